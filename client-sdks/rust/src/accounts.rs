@@ -0,0 +1,111 @@
+//! Fetchers for Superstream accounts.
+
+use anchor_lang::{prelude::Pubkey, AccountDeserialize, Discriminator};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+};
+use superstream::state::{
+    Activity, Distributor, StakePosition, Status, Stream, StreamRegistry, StreamRegistryPage, ZeroCopyStream,
+};
+
+use crate::filters::StreamFilters;
+
+/// Fetch a stream account.
+pub fn fetch_stream(client: &RpcClient, stream: &Pubkey) -> ClientResult<Stream> {
+    fetch(client, stream)
+}
+
+/// Fetch a stream account migrated to the zero-copy layout. Use [`ZeroCopyStream::to_stream`] to read it as a
+/// [`Stream`], e.g. for the zero-copy instruction builders.
+pub fn fetch_zero_copy_stream(client: &RpcClient, stream: &Pubkey) -> ClientResult<ZeroCopyStream> {
+    fetch(client, stream)
+}
+
+/// Fetch an activity account.
+pub fn fetch_activity(client: &RpcClient, activity: &Pubkey) -> ClientResult<Activity> {
+    fetch(client, activity)
+}
+
+/// Fetch a reward distributor account.
+pub fn fetch_distributor(client: &RpcClient, distributor: &Pubkey) -> ClientResult<Distributor> {
+    fetch(client, distributor)
+}
+
+/// Fetch a claim status account.
+pub fn fetch_status(client: &RpcClient, status: &Pubkey) -> ClientResult<Status> {
+    fetch(client, status)
+}
+
+/// Fetch the stream registry of a wallet, counting the streams in which the wallet is or was the sender or the
+/// recipient. The registry address is given by [`get_registry_public_key`](crate::pda::get_registry_public_key).
+pub fn fetch_registry(client: &RpcClient, registry: &Pubkey) -> ClientResult<StreamRegistry> {
+    fetch(client, registry)
+}
+
+/// Fetch a page of the stream registry of a wallet, listing the streams appended to it. The page address is given by
+/// [`get_registry_page_public_key`](crate::pda::get_registry_page_public_key), for pages up to
+/// [`StreamRegistry::page_count`].
+pub fn fetch_registry_page(client: &RpcClient, page: &Pubkey) -> ClientResult<StreamRegistryPage> {
+    fetch(client, page)
+}
+
+/// Fetch the stake position of a wallet in an activity. The position address is given by
+/// [`get_position_public_key`](crate::pda::get_position_public_key).
+pub fn fetch_position(client: &RpcClient, position: &Pubkey) -> ClientResult<StakePosition> {
+    fetch(client, position)
+}
+
+/// Fetch all stream accounts matching the given filters.
+pub fn fetch_streams(client: &RpcClient, filters: &StreamFilters) -> ClientResult<Vec<(Pubkey, Stream)>> {
+    fetch_all(client, filters.to_rpc_filters())
+}
+
+/// Fetch all activity accounts.
+pub fn fetch_activities(client: &RpcClient) -> ClientResult<Vec<(Pubkey, Activity)>> {
+    fetch_all(client, Vec::new())
+}
+
+fn fetch<T: AccountDeserialize>(client: &RpcClient, public_key: &Pubkey) -> ClientResult<T> {
+    let account = client.get_account(public_key)?;
+    deserialize(public_key, &account.data)
+}
+
+fn fetch_all<T: AccountDeserialize + Discriminator>(
+    client: &RpcClient,
+    mut filters: Vec<RpcFilterType>,
+) -> ClientResult<Vec<(Pubkey, T)>> {
+    filters.insert(
+        0,
+        RpcFilterType::Memcmp(Memcmp {
+            offset: 0,
+            bytes: MemcmpEncodedBytes::Base58(bs58::encode(T::discriminator()).into_string()),
+            encoding: None,
+        }),
+    );
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    client
+        .get_program_accounts_with_config(&superstream::ID, config)?
+        .into_iter()
+        .map(|(public_key, account)| Ok((public_key, deserialize(&public_key, &account.data)?)))
+        .collect()
+}
+
+fn deserialize<T: AccountDeserialize>(public_key: &Pubkey, mut data: &[u8]) -> ClientResult<T> {
+    T::try_deserialize(&mut data).map_err(|error| {
+        ClientError::from(ClientErrorKind::Custom(format!(
+            "Could not deserialize account {}: {}",
+            public_key, error
+        )))
+    })
+}
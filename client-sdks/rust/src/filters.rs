@@ -0,0 +1,214 @@
+//! `getProgramAccounts` filters for stream accounts. Mirrors `filters.ts` of the TypeScript SDK.
+
+use anchor_lang::prelude::Pubkey;
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use superstream::state::Stream;
+
+// Byte offsets of the filterable fields of a stream account, including the 8 byte Anchor discriminator.
+const IS_PREPAID_OFFSET: usize = 8;
+const MINT_OFFSET: usize = 9;
+const SENDER_OFFSET: usize = 41;
+const RECIPIENT_OFFSET: usize = 73;
+const IS_CANCELLED_OFFSET: usize = 153;
+const IS_CANCELLED_BEFORE_START_OFFSET: usize = 154;
+const IS_CANCELLED_BY_SENDER_OFFSET: usize = 155;
+const IS_PAUSED_OFFSET: usize = 182;
+const IS_PAUSED_BY_SENDER_OFFSET: usize = 183;
+const ACTIVITY_OFFSET: usize = 420;
+// The name is the last field. Its bytes start after the 4 byte string length prefix.
+const NAME_OFFSET: usize = Stream::BASE_LENGTH + 4;
+
+/// Stream filters that can be used to filter streams when fetching all streams.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamFilters {
+    /// Filter by stream type - prepaid or unbounded.
+    pub is_prepaid: Option<bool>,
+    /// Filter by the stream mint.
+    pub mint: Option<Pubkey>,
+    /// Filter by the stream sender.
+    pub sender: Option<Pubkey>,
+    /// Filter by the stream recipient.
+    pub recipient: Option<Pubkey>,
+    /// Filter by the stream cancellation status.
+    pub is_cancelled: Option<bool>,
+    /// Filter by the stream cancellation before start status.
+    pub is_cancelled_before_start: Option<bool>,
+    /// Filter by the stream cancellation by sender status.
+    pub is_cancelled_by_sender: Option<bool>,
+    /// Filter by the stream paused status.
+    pub is_paused: Option<bool>,
+    /// Filter by the stream paused by sender status.
+    pub is_paused_by_sender: Option<bool>,
+    /// Filter by the activity the stream belongs to.
+    pub activity: Option<Pubkey>,
+    /// Filter by the stream name.
+    pub name: Option<String>,
+}
+
+impl StreamFilters {
+    /// Convert the filters to RPC memcmp filters.
+    pub fn to_rpc_filters(&self) -> Vec<RpcFilterType> {
+        let mut filters = Vec::new();
+        let mut push = |offset: usize, bytes: &[u8]| {
+            filters.push(RpcFilterType::Memcmp(Memcmp {
+                offset,
+                bytes: MemcmpEncodedBytes::Base58(bs58::encode(bytes).into_string()),
+                encoding: None,
+            }))
+        };
+
+        if let Some(is_prepaid) = self.is_prepaid {
+            push(IS_PREPAID_OFFSET, &[is_prepaid as u8]);
+        }
+        if let Some(mint) = &self.mint {
+            push(MINT_OFFSET, mint.as_ref());
+        }
+        if let Some(sender) = &self.sender {
+            push(SENDER_OFFSET, sender.as_ref());
+        }
+        if let Some(recipient) = &self.recipient {
+            push(RECIPIENT_OFFSET, recipient.as_ref());
+        }
+        if let Some(is_cancelled) = self.is_cancelled {
+            push(IS_CANCELLED_OFFSET, &[is_cancelled as u8]);
+        }
+        if let Some(is_cancelled_before_start) = self.is_cancelled_before_start {
+            push(IS_CANCELLED_BEFORE_START_OFFSET, &[is_cancelled_before_start as u8]);
+        }
+        if let Some(is_cancelled_by_sender) = self.is_cancelled_by_sender {
+            push(IS_CANCELLED_BY_SENDER_OFFSET, &[is_cancelled_by_sender as u8]);
+        }
+        if let Some(is_paused) = self.is_paused {
+            push(IS_PAUSED_OFFSET, &[is_paused as u8]);
+        }
+        if let Some(is_paused_by_sender) = self.is_paused_by_sender {
+            push(IS_PAUSED_BY_SENDER_OFFSET, &[is_paused_by_sender as u8]);
+        }
+        if let Some(activity) = &self.activity {
+            push(ACTIVITY_OFFSET, activity.as_ref());
+        }
+        if let Some(name) = &self.name {
+            push(NAME_OFFSET, name.as_bytes());
+        }
+        filters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::AccountSerialize;
+
+    use super::*;
+    use crate::fixtures::{key, stream};
+
+    /// Check that the filter set by `set` matches `data` with `value` but not with `other`.
+    fn assert_filter<T>(data: &[u8], set: impl Fn(&mut StreamFilters, T), value: T, other: T) {
+        let matches = |value: T| {
+            let mut filters = StreamFilters::default();
+            set(&mut filters, value);
+            let rpc_filters = filters.to_rpc_filters();
+            assert_eq!(rpc_filters.len(), 1);
+            match &rpc_filters[0] {
+                RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+                _ => unreachable!(),
+            }
+        };
+        assert!(matches(value));
+        assert!(!matches(other));
+    }
+
+    #[test]
+    fn filters_match_the_serialized_stream() {
+        let stream = stream();
+        let mut data = Vec::new();
+        stream.try_serialize(&mut data).unwrap();
+
+        assert_filter(
+            &data,
+            |f, v| f.is_prepaid = Some(v),
+            stream.is_prepaid,
+            !stream.is_prepaid,
+        );
+        assert_filter(&data, |f, v| f.mint = Some(v), stream.mint, key(9));
+        assert_filter(&data, |f, v| f.sender = Some(v), stream.sender, key(9));
+        assert_filter(&data, |f, v| f.recipient = Some(v), stream.recipient, key(9));
+        assert_filter(
+            &data,
+            |f, v| f.is_cancelled = Some(v),
+            stream.is_cancelled,
+            !stream.is_cancelled,
+        );
+        assert_filter(
+            &data,
+            |f, v| f.is_cancelled_before_start = Some(v),
+            stream.is_cancelled_before_start,
+            !stream.is_cancelled_before_start,
+        );
+        assert_filter(
+            &data,
+            |f, v| f.is_cancelled_by_sender = Some(v),
+            stream.is_cancelled_by_sender,
+            !stream.is_cancelled_by_sender,
+        );
+        assert_filter(&data, |f, v| f.is_paused = Some(v), stream.is_paused, !stream.is_paused);
+        assert_filter(
+            &data,
+            |f, v| f.is_paused_by_sender = Some(v),
+            stream.is_paused_by_sender,
+            !stream.is_paused_by_sender,
+        );
+        assert_filter(&data, |f, v| f.activity = Some(v), stream.activity, key(9));
+        assert_filter(
+            &data,
+            |f, v| f.name = Some(v),
+            stream.name.clone(),
+            "other name".to_string(),
+        );
+        assert_filter(&data, |f, v| f.name = Some(v), "stream".to_string(), "name".to_string());
+    }
+
+    #[test]
+    fn filters_are_encoded_like_the_ts_sdk() {
+        assert!(StreamFilters::default().to_rpc_filters().is_empty());
+
+        // Same offsets as `filters.ts`, in the order of the fields, with the values encoded in base58.
+        let filters = StreamFilters {
+            is_prepaid: Some(true),
+            mint: Some(key(1)),
+            sender: Some(key(2)),
+            recipient: Some(key(3)),
+            is_cancelled: Some(false),
+            is_cancelled_before_start: Some(true),
+            is_cancelled_by_sender: Some(false),
+            is_paused: Some(true),
+            is_paused_by_sender: Some(false),
+            activity: Some(key(4)),
+            name: Some("stream".to_string()),
+        };
+        let expected = [
+            (8, "2"),
+            (9, "4vJ9JU1bJJE96FWSJKvHsmmFADCg4gpZQff4P3bkLKi"),
+            (41, "8qbHbw2BbbTHBW1sbeqakYXVKRQM8Ne7pLK7m6CVfeR"),
+            (73, "CktRuQ2mttgRGkXJtyksdKHjUdc2C4TgDzyB98oEzy8"),
+            (153, "1"),
+            (154, "2"),
+            (155, "1"),
+            (182, "2"),
+            (183, "1"),
+            (420, "GgBaCs3NCBuZN12kCJgAW63ydqohFkHEdfdEXBPzLHq"),
+            (575, "zVbyAVSk"),
+        ];
+        let rpc_filters = filters.to_rpc_filters();
+        assert_eq!(rpc_filters.len(), expected.len());
+        for (rpc_filter, (offset, bytes)) in rpc_filters.iter().zip(expected) {
+            assert_eq!(
+                rpc_filter,
+                &RpcFilterType::Memcmp(Memcmp {
+                    offset,
+                    bytes: MemcmpEncodedBytes::Base58(bytes.to_string()),
+                    encoding: None,
+                }),
+            );
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Account fixtures for the unit tests.
+
+use anchor_lang::prelude::Pubkey;
+use superstream::state::{AccrualMode, ManagerPermissions, Stream, STREAM_VERSION};
+
+/// Get a public key with all bytes set to `byte`.
+pub(crate) fn key(byte: u8) -> Pubkey {
+    Pubkey::new_from_array([byte; 32])
+}
+
+/// Get a non-prepaid stream with distinct values in its fields, created by its sender.
+pub(crate) fn stream() -> Stream {
+    Stream {
+        is_prepaid: true,
+        mint: key(1),
+        sender: key(2),
+        recipient: key(3),
+        created_at: 1,
+        starts_at: 2,
+        ends_at: 3,
+        initial_amount: 4,
+        flow_interval: 5,
+        flow_rate: 6,
+        is_cancelled: true,
+        is_cancelled_before_start: false,
+        is_cancelled_by_sender: true,
+        cancelled_at: 7,
+        sender_can_cancel: true,
+        sender_can_cancel_at: 8,
+        sender_can_change_sender: false,
+        sender_can_change_sender_at: 9,
+        is_paused: false,
+        is_paused_by_sender: true,
+        sender_can_pause: true,
+        sender_can_pause_at: 10,
+        recipient_can_resume_pause_by_sender: false,
+        recipient_can_resume_pause_by_sender_at: 11,
+        anyone_can_withdraw_for_recipient: true,
+        anyone_can_withdraw_for_recipient_at: 12,
+        last_resumed_at: 13,
+        accumulated_active_time: 14,
+        total_withdrawn_amount: 15,
+        last_withdrawn_at: 16,
+        last_withdrawn_amount: 17,
+        total_topup_amount: 18,
+        last_topup_at: 19,
+        last_topup_amount: 20,
+        deposit_needed: 21,
+        stake_weight: 0,
+        beneficiary: Pubkey::default(),
+        version: STREAM_VERSION,
+        reserved: [0; 10],
+        seed: 22,
+        bump: 23,
+        activity: key(4),
+        manager: key(5),
+        manager_permissions: ManagerPermissions::default(),
+        notice_period: 0,
+        cancellation_scheduled_at: 0,
+        is_cancellation_requested_by_sender: false,
+        creator: key(2),
+        accrual_mode: AccrualMode::Continuous,
+        rent_payer: key(2),
+        is_stake: false,
+        name: "stream name".to_string(),
+    }
+}
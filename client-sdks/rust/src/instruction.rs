@@ -0,0 +1,1700 @@
+//! Typed instruction builders for every Superstream instruction.
+//!
+//! Stream PDAs, escrow token accounts and the token accounts of the wallets involved are derived by the builders. Token
+//! accounts are assumed to be associated token accounts, which is what the official clients use.
+//!
+//! Builders for instructions on an existing stream take the deserialized [`Stream`] account, which can be fetched
+//! using [`fetch_stream`](crate::accounts::fetch_stream).
+//!
+//! Builders for instructions appending a stream to stream registries take the next page of each registry, given by
+//! [`StreamRegistry::next_page`](superstream::state::StreamRegistry::next_page) of the registry fetched using
+//! [`fetch_registry`](crate::accounts::fetch_registry), or `0` if the wallet has no registry yet.
+//!
+//! The rent of the accounts created by the `create*`, [`create_distributor`] and [`claim`] instructions is paid by the
+//! sender, the creator and the claimer respectively. Use [`with_rent_payer`] to have another wallet sponsor it, except
+//! for the legacy [`create_prepaid`], [`create_non_prepaid`] and [`create_stream`] instructions.
+
+use anchor_lang::{
+    prelude::Pubkey,
+    solana_program::instruction::{AccountMeta, Instruction},
+    system_program, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token;
+use superstream::state::{
+    Activity, CreateStreamParams, Distributor, LockMultiplier, ManagerPermissions, Status, Stream, StreamLayout,
+};
+
+use crate::pda::{
+    get_activity_public_key, get_distributor_public_key, get_position_public_key, get_registry_page_public_key,
+    get_registry_public_key, get_status_public_key, get_stream_public_key, get_token_public_key,
+};
+
+/// Next pages of the stream registries of the sender and the recipient of a new stream, see
+/// [`StreamRegistry::next_page`](superstream::state::StreamRegistry::next_page).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegistryPages {
+    pub sender: u64,
+    pub recipient: u64,
+}
+
+/// Parameters for creating an activity. See fields of the [`Activity`](superstream::state::Activity) struct for more
+/// information.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CreateActivityParams {
+    pub seed: u64,
+    pub name: String,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub reward_expires_at: u64,
+    pub duration: u64,
+    pub min_amount: u64,
+    pub flow_rate: u64,
+}
+
+/// Create a new prepaid stream using the legacy `create_prepaid` instruction.
+///
+/// Prefer [`create_prepaid_v2`]. The legacy instruction has no notice period and always accrues continuously, so
+/// `params.notice_period` and `params.accrual_mode` are ignored. The stream is not added to the stream registries.
+pub fn create_prepaid(sender: &Pubkey, activity: &Pubkey, mint: &Pubkey, params: CreateStreamParams) -> Instruction {
+    let accounts = legacy_create_accounts(sender, activity, mint, &params);
+    let permissions = params.permissions;
+    build(
+        accounts,
+        superstream::instruction::CreatePrepaid {
+            seed: params.seed,
+            name: params.name,
+            recipient: params.recipient,
+            starts_at: params.starts_at,
+            ends_at: params.ends_at,
+            initial_amount: params.initial_amount,
+            flow_interval: params.flow_interval,
+            flow_rate: params.flow_rate,
+            sender_can_cancel: permissions.sender_can_cancel,
+            sender_can_cancel_at: permissions.sender_can_cancel_at,
+            sender_can_change_sender: permissions.sender_can_change_sender,
+            sender_can_change_sender_at: permissions.sender_can_change_sender_at,
+            sender_can_pause: permissions.sender_can_pause,
+            sender_can_pause_at: permissions.sender_can_pause_at,
+            recipient_can_resume_pause_by_sender: permissions.recipient_can_resume_pause_by_sender,
+            recipient_can_resume_pause_by_sender_at: permissions.recipient_can_resume_pause_by_sender_at,
+            anyone_can_withdraw_for_recipient: permissions.anyone_can_withdraw_for_recipient,
+            anyone_can_withdraw_for_recipient_at: permissions.anyone_can_withdraw_for_recipient_at,
+        },
+    )
+}
+
+/// Create a new prepaid stream.
+///
+/// # Arguments
+///
+/// * `sender` - Sender wallet. Must sign the transaction.
+/// * `activity` - Activity the stream belongs to.
+/// * `mint` - SPL token mint of the stream.
+/// * `params` - Stream creation parameters.
+/// * `registry_pages` - Next pages of the stream registries of the sender and the recipient.
+pub fn create_prepaid_v2(
+    sender: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    params: CreateStreamParams,
+    registry_pages: RegistryPages,
+) -> Instruction {
+    build(
+        create_accounts(sender, sender, activity, mint, &params, registry_pages),
+        superstream::instruction::CreatePrepaidV2 { params },
+    )
+}
+
+/// Create a new non-prepaid stream using the legacy `create_non_prepaid` instruction.
+///
+/// Prefer [`create_non_prepaid_v2`]. The legacy instruction has no notice period and always accrues continuously, so
+/// `params.notice_period` and `params.accrual_mode` are ignored. The stream is not added to the stream registries.
+pub fn create_non_prepaid(
+    sender: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    params: CreateStreamParams,
+    topup_amount: u64,
+) -> Instruction {
+    let accounts = legacy_create_accounts(sender, activity, mint, &params);
+    let permissions = params.permissions;
+    build(
+        accounts,
+        superstream::instruction::CreateNonPrepaid {
+            seed: params.seed,
+            name: params.name,
+            recipient: params.recipient,
+            starts_at: params.starts_at,
+            ends_at: params.ends_at,
+            initial_amount: params.initial_amount,
+            flow_interval: params.flow_interval,
+            flow_rate: params.flow_rate,
+            sender_can_cancel: permissions.sender_can_cancel,
+            sender_can_cancel_at: permissions.sender_can_cancel_at,
+            sender_can_change_sender: permissions.sender_can_change_sender,
+            sender_can_change_sender_at: permissions.sender_can_change_sender_at,
+            sender_can_pause: permissions.sender_can_pause,
+            sender_can_pause_at: permissions.sender_can_pause_at,
+            recipient_can_resume_pause_by_sender: permissions.recipient_can_resume_pause_by_sender,
+            recipient_can_resume_pause_by_sender_at: permissions.recipient_can_resume_pause_by_sender_at,
+            anyone_can_withdraw_for_recipient: permissions.anyone_can_withdraw_for_recipient,
+            anyone_can_withdraw_for_recipient_at: permissions.anyone_can_withdraw_for_recipient_at,
+            topup_amount,
+        },
+    )
+}
+
+/// Create a new non-prepaid stream.
+///
+/// # Arguments
+///
+/// * `sender` - Sender wallet. Must sign the transaction.
+/// * `activity` - Activity the stream belongs to.
+/// * `mint` - SPL token mint of the stream.
+/// * `params` - Stream creation parameters.
+/// * `topup_amount` - Initial topup amount for the stream.
+/// * `registry_pages` - Next pages of the stream registries of the sender and the recipient.
+pub fn create_non_prepaid_v2(
+    sender: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    params: CreateStreamParams,
+    topup_amount: u64,
+    registry_pages: RegistryPages,
+) -> Instruction {
+    build(
+        create_accounts(sender, sender, activity, mint, &params, registry_pages),
+        superstream::instruction::CreateNonPrepaidV2 { params, topup_amount },
+    )
+}
+
+/// Create a new activity stream using the legacy `create_stream` instruction.
+///
+/// Prefer [`create_stream_v2`]. The legacy instruction derives the stream times and flow rate from the activity, has
+/// no notice period and always accrues continuously, so `params.starts_at`, `params.ends_at`, `params.flow_rate`,
+/// `params.notice_period` and `params.accrual_mode` are ignored. The stake is owned by the sender and locked for
+/// `params.flow_interval` seconds, without a staker allowlist proof. The stream is not added to the stream registries.
+pub fn create_stream(sender: &Pubkey, activity: &Pubkey, mint: &Pubkey, params: CreateStreamParams) -> Instruction {
+    let accounts = legacy_create_accounts(sender, activity, mint, &params);
+    let permissions = params.permissions;
+    let instruction = build(
+        accounts,
+        superstream::instruction::CreateStream {
+            seed: params.seed,
+            name: params.name,
+            recipient: params.recipient,
+            initial_amount: params.initial_amount,
+            flow_interval: params.flow_interval,
+            flow_rate: params.flow_rate,
+            sender_can_cancel: permissions.sender_can_cancel,
+            sender_can_cancel_at: permissions.sender_can_cancel_at,
+            sender_can_change_sender: permissions.sender_can_change_sender,
+            sender_can_change_sender_at: permissions.sender_can_change_sender_at,
+            sender_can_pause: permissions.sender_can_pause,
+            sender_can_pause_at: permissions.sender_can_pause_at,
+            recipient_can_resume_pause_by_sender: permissions.recipient_can_resume_pause_by_sender,
+            recipient_can_resume_pause_by_sender_at: permissions.recipient_can_resume_pause_by_sender_at,
+            anyone_can_withdraw_for_recipient: permissions.anyone_can_withdraw_for_recipient,
+            anyone_can_withdraw_for_recipient_at: permissions.anyone_can_withdraw_for_recipient_at,
+        },
+    );
+    with_position(instruction, activity, sender)
+}
+
+/// Create a new activity stream. The stream times and flow rate are derived from the activity, so
+/// `params.starts_at`, `params.ends_at` and `params.flow_rate` are ignored.
+///
+/// # Arguments
+///
+/// * `sender` - Sender wallet. Must sign the transaction.
+/// * `beneficiary` - Wallet owning the stake, which receives the withdrawals and the rewards. Pass the sender unless the
+///   stake is funded on behalf of another wallet.
+/// * `activity` - Activity the stream belongs to.
+/// * `mint` - SPL token mint of the stream. Must be the stake mint of the activity.
+/// * `params` - Stream creation parameters. `initial_amount` needs to be at least the minimum amount of the activity.
+/// * `lock_duration` - Duration in seconds the stake is locked for. Must be one of the lock durations of the activity
+///   if it has lock multipliers.
+/// * `allowlist_proof` - Merkle proof that the beneficiary is in the staker allowlist of the activity. Empty if the activity
+///   has no allowlist.
+/// * `registry_pages` - Next pages of the stream registries of the sender and the recipient.
+#[allow(clippy::too_many_arguments)]
+pub fn create_stream_v2(
+    sender: &Pubkey,
+    beneficiary: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    params: CreateStreamParams,
+    lock_duration: u64,
+    allowlist_proof: Vec<[u8; 32]>,
+    registry_pages: RegistryPages,
+) -> Instruction {
+    let instruction = build(
+        create_accounts(sender, beneficiary, activity, mint, &params, registry_pages),
+        superstream::instruction::CreateStreamV2 {
+            params,
+            lock_duration,
+            allowlist_proof,
+        },
+    );
+    with_position(instruction, activity, beneficiary)
+}
+
+/// Create a new activity.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction.
+/// * `stake_mint` - SPL token mint that is staked in the activity.
+/// * `reward_mint` - SPL token mint of the rewards.
+/// * `opt_reward_mint` - SPL token mint of the optional rewards.
+/// * `params` - Activity creation parameters.
+pub fn create_activity(
+    creator: &Pubkey,
+    stake_mint: &Pubkey,
+    reward_mint: &Pubkey,
+    opt_reward_mint: &Pubkey,
+    params: CreateActivityParams,
+) -> Instruction {
+    let (activity, _) = get_activity_public_key(params.seed, stake_mint, &params.name);
+    build(
+        superstream::accounts::CreateActivity {
+            activity,
+            creator: *creator,
+            stake_mint: *stake_mint,
+            reward_mint: *reward_mint,
+            opt_reward_mint: *opt_reward_mint,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::CreateActivity {
+            seed: params.seed,
+            name: params.name,
+            starts_at: params.starts_at,
+            ends_at: params.ends_at,
+            reward_expires_at: params.reward_expires_at,
+            duration: params.duration,
+            min_amount: params.min_amount,
+            flow_rate: params.flow_rate,
+        },
+    )
+}
+
+/// Update the end time, reward expiry time, minimum stake amount and flow rate of an active activity.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+/// * `ends_at` - New end time. Cannot be in the past.
+/// * `reward_expires_at` - New reward expiry time. Should be >= `ends_at`.
+/// * `min_amount` - New minimum stake amount. Should be > 0.
+/// * `flow_rate` - New number of reward tokens emitted per second. Should be > 0.
+pub fn update_activity(
+    creator: &Pubkey,
+    activity: &Pubkey,
+    ends_at: u64,
+    reward_expires_at: u64,
+    min_amount: u64,
+    flow_rate: u64,
+) -> Instruction {
+    build(
+        superstream::accounts::UpdateActivity {
+            activity: *activity,
+            creator: *creator,
+        },
+        superstream::instruction::UpdateActivity {
+            ends_at,
+            reward_expires_at,
+            min_amount,
+            flow_rate,
+        },
+    )
+}
+
+/// Quote the stake totals of an activity. The [`ActivityTotals`](superstream::state::ActivityTotals) are returned
+/// through the return data of the simulated transaction.
+///
+/// # Arguments
+///
+/// * `activity` - Activity address.
+pub fn quote_activity(activity: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::QuoteActivity { activity: *activity },
+        superstream::instruction::QuoteActivity {},
+    )
+}
+
+/// Set the maximum amount staked in an activity and by a single wallet. A cap of 0 means no cap.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+/// * `max_total_stake` - Maximum amount staked in the activity.
+/// * `max_stake_per_wallet` - Maximum amount staked by a single wallet in the activity.
+pub fn set_stake_caps(
+    creator: &Pubkey,
+    activity: &Pubkey,
+    max_total_stake: u64,
+    max_stake_per_wallet: u64,
+) -> Instruction {
+    build(
+        superstream::accounts::UpdateActivity {
+            activity: *activity,
+            creator: *creator,
+        },
+        superstream::instruction::SetStakeCaps {
+            max_total_stake,
+            max_stake_per_wallet,
+        },
+    )
+}
+
+/// Set the lock durations allowed for the stakes of an activity and their reward multipliers.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction and pays for resizing the activity account.
+/// * `activity` - Activity address.
+/// * `lock_multipliers` - Lock durations and their multipliers, sorted by strictly increasing duration.
+pub fn set_lock_multipliers(creator: &Pubkey, activity: &Pubkey, lock_multipliers: Vec<LockMultiplier>) -> Instruction {
+    build(
+        superstream::accounts::SetLockMultipliers {
+            activity: *activity,
+            creator: *creator,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::SetLockMultipliers { lock_multipliers },
+    )
+}
+
+/// Set the penalty charged on stakes of an activity unstaked early and the wallet receiving it.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+/// * `early_unstake_penalty` - Penalty in basis points of the unstaked amount.
+/// * `penalty_treasury` - Wallet receiving the penalties, or the default pubkey to send them to the reward vault.
+pub fn set_early_unstake_penalty(
+    creator: &Pubkey,
+    activity: &Pubkey,
+    early_unstake_penalty: u64,
+    penalty_treasury: &Pubkey,
+) -> Instruction {
+    build(
+        superstream::accounts::UpdateActivity {
+            activity: *activity,
+            creator: *creator,
+        },
+        superstream::instruction::SetEarlyUnstakePenalty {
+            early_unstake_penalty,
+            penalty_treasury: *penalty_treasury,
+        },
+    )
+}
+
+/// Set the merkle root of the wallets allowed to stake in an activity.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+/// * `staker_allowlist_root` - Merkle root with the keccak hashes of the wallet addresses as leaves, or all zeros to
+///   remove the allowlist.
+pub fn set_staker_allowlist_root(creator: &Pubkey, activity: &Pubkey, staker_allowlist_root: [u8; 32]) -> Instruction {
+    build(
+        superstream::accounts::UpdateActivity {
+            activity: *activity,
+            creator: *creator,
+        },
+        superstream::instruction::SetStakerAllowlistRoot { staker_allowlist_root },
+    )
+}
+
+/// Deactivate an activity so that no new streams can be created for it.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+pub fn deactivate_activity(creator: &Pubkey, activity: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::UpdateActivity {
+            activity: *activity,
+            creator: *creator,
+        },
+        superstream::instruction::DeactivateActivity {},
+    )
+}
+
+/// Close an activity whose streams, distributors and stake positions are all closed. The rewards left in the reward
+/// vault go to the creator's token account, and the rent of the activity and the vault goes back to the creator.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+/// * `reward_mint` - SPL token mint of the rewards of the activity.
+pub fn close_activity(creator: &Pubkey, activity: &Pubkey, reward_mint: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::CloseActivity {
+            activity: *activity,
+            creator: *creator,
+            reward_mint: *reward_mint,
+            creator_token: get_token_public_key(reward_mint, creator),
+            reward_vault: get_token_public_key(reward_mint, activity),
+            token_program: token::ID,
+        },
+        superstream::instruction::CloseActivity {},
+    )
+}
+
+/// Migrate an activity created with the original account layout to the current layout in place.
+///
+/// # Arguments
+///
+/// * `creator` - Activity creator wallet. Must sign the transaction and pays for any extra rent.
+/// * `activity` - Activity address.
+pub fn migrate_activity(creator: &Pubkey, activity: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::MigrateActivity {
+            activity: *activity,
+            creator: *creator,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::MigrateActivity {},
+    )
+}
+
+/// Fund the reward vault of an activity from the funder's token account. The reward vault is the associated token
+/// account of the activity for the reward mint, and needs to exist.
+///
+/// # Arguments
+///
+/// * `funder` - Funder wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+/// * `reward_mint` - SPL token mint of the rewards of the activity.
+/// * `amount` - Amount of rewards to fund.
+pub fn fund_activity_rewards(funder: &Pubkey, activity: &Pubkey, reward_mint: &Pubkey, amount: u64) -> Instruction {
+    build(
+        superstream::accounts::FundActivityRewards {
+            activity: *activity,
+            funder: *funder,
+            reward_mint: *reward_mint,
+            funder_token: get_token_public_key(reward_mint, funder),
+            reward_vault: get_token_public_key(reward_mint, activity),
+            token_program: token::ID,
+        },
+        superstream::instruction::FundActivityRewards { amount },
+    )
+}
+
+/// Claim the rewards accrued to the stake position of the staker in an activity.
+///
+/// # Arguments
+///
+/// * `staker` - Staker wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+/// * `reward_mint` - SPL token mint of the rewards of the activity.
+pub fn claim_activity_rewards(staker: &Pubkey, activity: &Pubkey, reward_mint: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::ClaimActivityRewards {
+            activity: *activity,
+            position: get_position_public_key(activity, staker).0,
+            staker: *staker,
+            reward_mint: *reward_mint,
+            staker_token: get_token_public_key(reward_mint, staker),
+            reward_vault: get_token_public_key(reward_mint, activity),
+            token_program: token::ID,
+        },
+        superstream::instruction::ClaimActivityRewards {},
+    )
+}
+
+/// Close the stake position of the staker in an activity once it holds no stake and its rewards are claimed. The rent
+/// goes back to the staker.
+///
+/// # Arguments
+///
+/// * `staker` - Staker wallet. Must sign the transaction.
+/// * `activity` - Activity address.
+pub fn close_position(staker: &Pubkey, activity: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::ClosePosition {
+            activity: *activity,
+            position: get_position_public_key(activity, staker).0,
+            staker: *staker,
+        },
+        superstream::instruction::ClosePosition {},
+    )
+}
+
+/// Unstake an activity stream before it ends, paying the early unstake penalty of the activity.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account. Its staker, i.e. its beneficiary or its creator, must sign the transaction.
+/// * `activity` - Activity account of the stream. The token account receiving the penalty is only passed if the
+///   activity has an early unstake penalty.
+pub fn unstake_early(stream: &Stream, activity: &Activity) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    let mut instruction = build(
+        superstream::accounts::UnstakeEarly {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            staker: stream.staker(),
+            staker_token: get_token_public_key(&stream.mint, &stream.staker()),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::UnstakeEarly {},
+    );
+    push_penalty_token(&mut instruction, stream, activity);
+    instruction
+}
+
+/// Pass the token account receiving the penalty of an early unstake if the activity has an early unstake penalty.
+fn push_penalty_token(instruction: &mut Instruction, stream: &Stream, activity: &Activity) {
+    if activity.early_unstake_penalty > 0 {
+        let penalty_token = get_token_public_key(&stream.mint, &activity.penalty_owner(stream.activity));
+        instruction.accounts.push(AccountMeta::new(penalty_token, false));
+    }
+}
+
+/// Create a new reward distributor for an activity and fund it from the creator's token account.
+///
+/// # Arguments
+///
+/// * `creator` - Distributor creator wallet. Must sign the transaction.
+/// * `activity` - Activity the rewards are distributed for.
+/// * `mint` - SPL token mint of the rewards.
+/// * `root` - Merkle root of the reward allocations.
+/// * `total_supply` - Total amount of rewards that can be claimed.
+pub fn create_distributor(
+    creator: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    root: [u8; 32],
+    total_supply: u64,
+) -> Instruction {
+    let (distributor, bump) = get_distributor_public_key(activity, mint);
+    build(
+        superstream::accounts::NewDistributor {
+            distributor,
+            activity: *activity,
+            creator: *creator,
+            sender_token: get_token_public_key(mint, creator),
+            mint: *mint,
+            reward_escrow_token: get_token_public_key(mint, &distributor),
+            token_program: token::ID,
+            system_program: system_program::ID,
+            payer: *creator,
+        },
+        superstream::instruction::CreateDistributor {
+            _bump: bump,
+            root,
+            total_supply,
+        },
+    )
+}
+
+/// Claim rewards from a reward distributor.
+///
+/// # Arguments
+///
+/// * `claimer` - Claimer wallet. Must sign the transaction.
+/// * `activity` - Activity the rewards are distributed for.
+/// * `mint` - SPL token mint of the rewards.
+/// * `index` - Index of the claim in the merkle tree.
+/// * `amount` - Amount to claim.
+/// * `leaf` - Merkle leaf of the claim.
+/// * `proof` - Merkle proof of the leaf.
+pub fn claim(
+    claimer: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    index: u64,
+    amount: u64,
+    leaf: [u8; 32],
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    let (distributor, _) = get_distributor_public_key(activity, mint);
+    build(
+        superstream::accounts::Claim {
+            distributor,
+            escrow_token: get_token_public_key(mint, &distributor),
+            recipent_token: get_token_public_key(mint, claimer),
+            claimer: *claimer,
+            mint: *mint,
+            status: get_status_public_key(&distributor, claimer).0,
+            token_program: token::ID,
+            system_program: system_program::ID,
+            payer: *claimer,
+        },
+        superstream::instruction::Claim {
+            index,
+            amount,
+            leaf,
+            proof,
+        },
+    )
+}
+
+/// Recycle the unclaimed rewards of a reward distributor back to the sender.
+///
+/// # Arguments
+///
+/// * `sender` - Wallet receiving the unclaimed rewards. Must sign the transaction.
+/// * `activity` - Activity the rewards are distributed for.
+/// * `mint` - SPL token mint of the rewards.
+pub fn recycle_reward(sender: &Pubkey, activity: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (distributor, _) = get_distributor_public_key(activity, mint);
+    build(
+        superstream::accounts::RecycleReward {
+            distributor,
+            escrow_token: get_token_public_key(mint, &distributor),
+            recipent_token: get_token_public_key(mint, sender),
+            sender: *sender,
+            mint: *mint,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::RecycleReward {},
+    )
+}
+
+/// Close a reward distributor whose escrow is empty. The rent is refunded to the wallet which paid for it.
+///
+/// # Arguments
+///
+/// * `distributor` - Distributor account.
+pub fn close_distributor(distributor: &Distributor) -> Instruction {
+    let (distributor_public_key, _) = get_distributor_public_key(&distributor.activity_key, &distributor.mint);
+    build(
+        superstream::accounts::CloseDistributor {
+            distributor: distributor_public_key,
+            activity: distributor.activity_key,
+            creator: distributor.creater_key,
+            rent_payer: distributor.rent_payer,
+            escrow_token: get_token_public_key(&distributor.mint, &distributor_public_key),
+            token_program: token::ID,
+        },
+        superstream::instruction::CloseDistributor {},
+    )
+}
+
+/// Close a claim status of a closed reward distributor. The rent is refunded to the wallet which paid for it.
+///
+/// # Arguments
+///
+/// * `distributor` - Address of the closed distributor.
+/// * `status` - Status account.
+pub fn close_status(distributor: &Pubkey, status: &Status) -> Instruction {
+    build(
+        superstream::accounts::CloseStatus {
+            distributor: *distributor,
+            status: get_status_public_key(distributor, &status.claimer).0,
+            rent_payer: status.rent_payer,
+        },
+        superstream::instruction::CloseStatus {},
+    )
+}
+
+/// Cancel a stream.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn cancel(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::Cancel {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            signer: *signer,
+            sender: stream.sender,
+            mint: stream.mint,
+            signer_token: get_token_public_key(&stream.mint, signer),
+            sender_token: get_token_public_key(&stream.mint, &stream.refundee()),
+            recipient_token: get_token_public_key(&stream.mint, &stream.payee()),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::Cancel {
+            _seed: stream.seed,
+            _name: stream.name.clone(),
+            recipient: stream.recipient,
+        },
+    )
+}
+
+/// Request the cancellation of a stream which has a notice period.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Sender, manager or recipient wallet. Must sign the transaction.
+pub fn request_cancel(stream: &Stream, signer: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::RequestCancel {
+            stream: stream_public_key(stream),
+            signer: *signer,
+        },
+        superstream::instruction::RequestCancel {},
+    )
+}
+
+/// Quote the amounts of a stream. The quote is returned through the return data of the simulated transaction.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `at` - Time to quote the stream at. If `None`, the current on-chain time is used.
+pub fn quote_stream(stream: &Stream, at: Option<u64>) -> Instruction {
+    build(
+        superstream::accounts::QuoteStream {
+            stream: stream_public_key(stream),
+        },
+        superstream::instruction::QuoteStream { at },
+    )
+}
+
+/// Withdraw the excess topup of a non-prepaid stream which has ended.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn withdraw_excess_topup_non_prepaid_ended(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::WithdrawExcessTopupNonPrepaidEnded {
+            stream: stream_public_key,
+            signer: *signer,
+            sender: stream.sender,
+            mint: stream.mint,
+            sender_token: get_token_public_key(&stream.mint, &stream.sender),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::WithdrawExcessTopupNonPrepaidEnded {
+            _seed: stream.seed,
+            _name: stream.name.clone(),
+        },
+    )
+}
+
+/// Topup a non-prepaid stream.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+/// * `topup_amount` - Topup amount.
+pub fn topup_non_prepaid(stream: &Stream, signer: &Pubkey, topup_amount: u64) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::TopupNonPrepaid {
+            stream: stream_public_key,
+            signer: *signer,
+            mint: stream.mint,
+            signer_token: get_token_public_key(&stream.mint, signer),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::TopupNonPrepaid {
+            _seed: stream.seed,
+            _name: stream.name.clone(),
+            topup_amount,
+        },
+    )
+}
+
+/// Change the sender of a non-prepaid stream. The stream is appended to the registry of the new sender.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `sender` - Current sender wallet. Must sign the transaction.
+/// * `new_sender` - New sender wallet.
+/// * `new_sender_registry_page` - Next page of the stream registry of the new sender.
+pub fn change_sender_non_prepaid(
+    stream: &Stream,
+    sender: &Pubkey,
+    new_sender: &Pubkey,
+    new_sender_registry_page: u64,
+) -> Instruction {
+    build(
+        superstream::accounts::ChangeSenderNonPrepaid {
+            stream: stream_public_key(stream),
+            sender: *sender,
+            mint: stream.mint,
+            new_sender_registry: get_registry_public_key(new_sender).0,
+            new_sender_registry_page: get_registry_page_public_key(new_sender, new_sender_registry_page).0,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::ChangeSenderNonPrepaid {
+            _seed: stream.seed,
+            _name: stream.name.clone(),
+            new_sender: *new_sender,
+        },
+    )
+}
+
+/// Set or clear the manager of a stream.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `manager` - New manager wallet. Pass `Pubkey::default()` to clear the manager.
+/// * `manager_permissions` - Rights delegated to the manager.
+pub fn set_manager(stream: &Stream, manager: &Pubkey, manager_permissions: ManagerPermissions) -> Instruction {
+    build(
+        superstream::accounts::SetManager {
+            stream: stream_public_key(stream),
+            sender: stream.sender,
+        },
+        superstream::instruction::SetManager {
+            manager: *manager,
+            manager_permissions,
+        },
+    )
+}
+
+/// Withdraw recipient funds from a stream.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn withdraw(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::Withdraw {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            signer: *signer,
+            mint: stream.mint,
+            recipient_token: get_token_public_key(&stream.mint, &stream.payee()),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::Withdraw {
+            _name: stream.name.clone(),
+            recipient: stream.recipient,
+            _sender: stream.sender,
+        },
+    )
+}
+
+/// Withdraw recipient funds from a stream and change the recipient of the stream. The stream is appended to the
+/// registry of the new recipient.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Current recipient wallet. Must sign the transaction.
+/// * `new_recipient` - New recipient wallet.
+/// * `new_recipient_registry_page` - Next page of the stream registry of the new recipient.
+pub fn withdraw_and_change_recipient(
+    stream: &Stream,
+    signer: &Pubkey,
+    new_recipient: &Pubkey,
+    new_recipient_registry_page: u64,
+) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::WithdrawAndChangeRecipient {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            signer: *signer,
+            mint: stream.mint,
+            recipient_token: get_token_public_key(&stream.mint, &stream.payee()),
+            escrow_token,
+            new_recipient_registry: get_registry_public_key(new_recipient).0,
+            new_recipient_registry_page: get_registry_page_public_key(new_recipient, new_recipient_registry_page).0,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::WithdrawAndChangeRecipient {
+            _name: stream.name.clone(),
+            recipient: stream.recipient,
+            _sender: stream.sender,
+            new_recipient: *new_recipient,
+        },
+    )
+}
+
+/// Pause a non-prepaid stream.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn pause_non_prepaid(stream: &Stream, signer: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::PauseNonPrepaid {
+            stream: stream_public_key(stream),
+            signer: *signer,
+            mint: stream.mint,
+        },
+        superstream::instruction::PauseNonPrepaid {
+            _seed: stream.seed,
+            _name: stream.name.clone(),
+        },
+    )
+}
+
+/// Resume a non-prepaid stream.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn resume_non_prepaid(stream: &Stream, signer: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::ResumeNonPrepaid {
+            stream: stream_public_key(stream),
+            signer: *signer,
+            mint: stream.mint,
+        },
+        superstream::instruction::ResumeNonPrepaid {
+            _seed: stream.seed,
+            _name: stream.name.clone(),
+        },
+    )
+}
+
+/// Close a stream which has stopped and whose escrow is empty. The rent is refunded to the wallet which paid for it.
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Sender or recipient wallet. Must sign the transaction.
+pub fn close_stream(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::CloseStream {
+            stream: stream_public_key,
+            activity: stream.activity,
+            signer: *signer,
+            rent_payer: stream.rent_payer,
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::CloseStream {},
+    )
+}
+
+/// Migrate a stream to the zero-copy layout in place. Afterwards, use the `*_zero_copy` builders with the stream
+/// converted back using [`ZeroCopyStream::to_stream`](superstream::state::ZeroCopyStream::to_stream).
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Sender or recipient wallet. Must sign the transaction and pays for any extra rent.
+pub fn migrate_stream(stream: &Stream, signer: &Pubkey) -> Instruction {
+    build(
+        superstream::accounts::MigrateStream {
+            stream: stream_public_key(stream),
+            activity: stream.activity,
+            signer: *signer,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::MigrateStream { creator: None },
+    )
+}
+
+/// Migrate a stream created with the original account layout, which cannot be deserialized as a [`Stream`], to the
+/// zero-copy layout in place. See [`migrate_stream`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account address.
+/// * `activity` - Activity address of the stream. The activity needs to be migrated first, see [`migrate_activity`].
+/// * `signer` - Sender or recipient wallet. Must sign the transaction and pays for any extra rent.
+/// * `creator` - Sender at the time of creation of the stream, if the sender has changed since.
+pub fn migrate_legacy_stream(
+    stream: &Pubkey,
+    activity: &Pubkey,
+    signer: &Pubkey,
+    creator: Option<Pubkey>,
+) -> Instruction {
+    build(
+        superstream::accounts::MigrateStream {
+            stream: *stream,
+            activity: *activity,
+            signer: *signer,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::MigrateStream { creator },
+    )
+}
+
+/// Cancel a zero-copy stream. See [`cancel`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn cancel_zero_copy(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::CancelZeroCopy {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            signer: *signer,
+            sender: stream.sender,
+            mint: stream.mint,
+            signer_token: get_token_public_key(&stream.mint, signer),
+            sender_token: get_token_public_key(&stream.mint, &stream.refundee()),
+            recipient_token: get_token_public_key(&stream.mint, &stream.payee()),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::CancelZeroCopy {},
+    )
+}
+
+/// Request the cancellation of a zero-copy stream. See [`request_cancel`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Sender, manager or recipient wallet. Must sign the transaction.
+pub fn request_cancel_zero_copy(stream: &Stream, signer: &Pubkey) -> Instruction {
+    build(
+        update_zero_copy_accounts(stream, signer),
+        superstream::instruction::RequestCancelZeroCopy {},
+    )
+}
+
+/// Quote the amounts of a zero-copy stream. See [`quote_stream`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `at` - Time to quote the stream at. If `None`, the current on-chain time is used.
+pub fn quote_zero_copy_stream(stream: &Stream, at: Option<u64>) -> Instruction {
+    build(
+        superstream::accounts::QuoteZeroCopyStream {
+            stream: stream_public_key(stream),
+        },
+        superstream::instruction::QuoteZeroCopyStream { at },
+    )
+}
+
+/// Withdraw the excess topup of a non-prepaid zero-copy stream which has ended. See
+/// [`withdraw_excess_topup_non_prepaid_ended`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn withdraw_excess_topup_non_prepaid_ended_zero_copy(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::WithdrawExcessTopupNonPrepaidEndedZeroCopy {
+            stream: stream_public_key,
+            signer: *signer,
+            sender: stream.sender,
+            mint: stream.mint,
+            sender_token: get_token_public_key(&stream.mint, &stream.sender),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::WithdrawExcessTopupNonPrepaidEndedZeroCopy {},
+    )
+}
+
+/// Topup a non-prepaid zero-copy stream. See [`topup_non_prepaid`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+/// * `topup_amount` - Topup amount.
+pub fn topup_non_prepaid_zero_copy(stream: &Stream, signer: &Pubkey, topup_amount: u64) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::TopupNonPrepaidZeroCopy {
+            stream: stream_public_key,
+            signer: *signer,
+            mint: stream.mint,
+            signer_token: get_token_public_key(&stream.mint, signer),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::TopupNonPrepaidZeroCopy { topup_amount },
+    )
+}
+
+/// Change the sender of a non-prepaid zero-copy stream. See [`change_sender_non_prepaid`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `sender` - Current sender or manager wallet. Must sign the transaction.
+/// * `new_sender` - New sender wallet.
+/// * `new_sender_registry_page` - Next page of the stream registry of the new sender.
+pub fn change_sender_non_prepaid_zero_copy(
+    stream: &Stream,
+    sender: &Pubkey,
+    new_sender: &Pubkey,
+    new_sender_registry_page: u64,
+) -> Instruction {
+    build(
+        superstream::accounts::ChangeSenderNonPrepaidZeroCopy {
+            stream: stream_public_key(stream),
+            signer: *sender,
+            new_sender_registry: get_registry_public_key(new_sender).0,
+            new_sender_registry_page: get_registry_page_public_key(new_sender, new_sender_registry_page).0,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::ChangeSenderNonPrepaidZeroCopy {
+            new_sender: *new_sender,
+        },
+    )
+}
+
+/// Set or clear the manager of a zero-copy stream. See [`set_manager`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `manager` - New manager wallet. Pass `Pubkey::default()` to clear the manager.
+/// * `manager_permissions` - Rights delegated to the manager.
+pub fn set_manager_zero_copy(
+    stream: &Stream,
+    manager: &Pubkey,
+    manager_permissions: ManagerPermissions,
+) -> Instruction {
+    build(
+        update_zero_copy_accounts(stream, &stream.sender),
+        superstream::instruction::SetManagerZeroCopy {
+            manager: *manager,
+            manager_permissions,
+        },
+    )
+}
+
+/// Withdraw recipient funds from a zero-copy stream. See [`withdraw`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn withdraw_zero_copy(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::WithdrawZeroCopy {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            signer: *signer,
+            mint: stream.mint,
+            recipient_token: get_token_public_key(&stream.mint, &stream.payee()),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::WithdrawZeroCopy {},
+    )
+}
+
+/// Withdraw recipient funds from a zero-copy stream and change the recipient of the stream. See
+/// [`withdraw_and_change_recipient`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Current recipient wallet. Must sign the transaction.
+/// * `new_recipient` - New recipient wallet.
+/// * `new_recipient_registry_page` - Next page of the stream registry of the new recipient.
+pub fn withdraw_and_change_recipient_zero_copy(
+    stream: &Stream,
+    signer: &Pubkey,
+    new_recipient: &Pubkey,
+    new_recipient_registry_page: u64,
+) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::WithdrawAndChangeRecipientZeroCopy {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            signer: *signer,
+            mint: stream.mint,
+            recipient_token: get_token_public_key(&stream.mint, &stream.payee()),
+            escrow_token,
+            new_recipient_registry: get_registry_public_key(new_recipient).0,
+            new_recipient_registry_page: get_registry_page_public_key(new_recipient, new_recipient_registry_page).0,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        },
+        superstream::instruction::WithdrawAndChangeRecipientZeroCopy {
+            new_recipient: *new_recipient,
+        },
+    )
+}
+
+/// Unstake a zero-copy activity stream before it ends. See [`unstake_early`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account. Its staker, i.e. its beneficiary or its creator, must sign the transaction.
+/// * `activity` - Activity account of the stream.
+pub fn unstake_early_zero_copy(stream: &Stream, activity: &Activity) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    let mut instruction = build(
+        superstream::accounts::UnstakeEarlyZeroCopy {
+            stream: stream_public_key,
+            activity: stream.activity,
+            position: get_position_public_key(&stream.activity, &stream.staker()).0,
+            staker: stream.staker(),
+            staker_token: get_token_public_key(&stream.mint, &stream.staker()),
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::UnstakeEarlyZeroCopy {},
+    );
+    push_penalty_token(&mut instruction, stream, activity);
+    instruction
+}
+
+/// Pause a non-prepaid zero-copy stream. See [`pause_non_prepaid`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn pause_non_prepaid_zero_copy(stream: &Stream, signer: &Pubkey) -> Instruction {
+    build(
+        update_zero_copy_accounts(stream, signer),
+        superstream::instruction::PauseNonPrepaidZeroCopy {},
+    )
+}
+
+/// Resume a non-prepaid zero-copy stream. See [`resume_non_prepaid`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Signer wallet. Must sign the transaction.
+pub fn resume_non_prepaid_zero_copy(stream: &Stream, signer: &Pubkey) -> Instruction {
+    build(
+        update_zero_copy_accounts(stream, signer),
+        superstream::instruction::ResumeNonPrepaidZeroCopy {},
+    )
+}
+
+/// Close a zero-copy stream. See [`close_stream`].
+///
+/// # Arguments
+///
+/// * `stream` - Stream account.
+/// * `signer` - Sender or recipient wallet. Must sign the transaction.
+pub fn close_zero_copy_stream(stream: &Stream, signer: &Pubkey) -> Instruction {
+    let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(stream);
+    build(
+        superstream::accounts::CloseZeroCopyStream {
+            stream: stream_public_key,
+            activity: stream.activity,
+            signer: *signer,
+            rent_payer: stream.rent_payer,
+            escrow_token,
+            token_program: token::ID,
+        },
+        superstream::instruction::CloseZeroCopyStream {},
+    )
+}
+
+/// Make `payer` pay for the rent of the accounts created by an instruction built with one of the `create*_v2`,
+/// [`create_distributor`] or [`claim`] builders, instead of the sender, the creator or the claimer. `payer` needs to
+/// sign the transaction as well. The legacy `create*` instructions always have the sender pay the rent.
+///
+/// The rent payer is recorded in the created accounts and gets the rent back when they are closed.
+pub fn with_rent_payer(mut instruction: Instruction, payer: &Pubkey) -> Instruction {
+    // The payer is the last signer of all these instructions. Only the stake position follows it.
+    if let Some(account) = instruction.accounts.iter_mut().rev().find(|account| account.is_signer) {
+        *account = AccountMeta::new(*payer, true);
+    }
+    instruction
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: superstream::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+fn legacy_create_accounts(
+    sender: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    params: &CreateStreamParams,
+) -> superstream::accounts::Create {
+    let (stream, _) = get_stream_public_key(activity, mint, sender, &params.name);
+    superstream::accounts::Create {
+        stream,
+        activity: *activity,
+        sender: *sender,
+        mint: *mint,
+        sender_token: get_token_public_key(mint, sender),
+        escrow_token: get_token_public_key(mint, &stream),
+        token_program: token::ID,
+        system_program: system_program::ID,
+    }
+}
+
+fn create_accounts(
+    sender: &Pubkey,
+    beneficiary: &Pubkey,
+    activity: &Pubkey,
+    mint: &Pubkey,
+    params: &CreateStreamParams,
+    registry_pages: RegistryPages,
+) -> superstream::accounts::CreateV2 {
+    let (stream, _) = get_stream_public_key(activity, mint, sender, &params.name);
+    superstream::accounts::CreateV2 {
+        stream,
+        activity: *activity,
+        sender: *sender,
+        mint: *mint,
+        sender_token: get_token_public_key(mint, sender),
+        escrow_token: get_token_public_key(mint, &stream),
+        token_program: token::ID,
+        system_program: system_program::ID,
+        sender_registry: get_registry_public_key(sender).0,
+        sender_registry_page: get_registry_page_public_key(sender, registry_pages.sender).0,
+        recipient_registry: get_registry_public_key(&params.recipient).0,
+        recipient_registry_page: get_registry_page_public_key(&params.recipient, registry_pages.recipient).0,
+        beneficiary: *beneficiary,
+        payer: *sender,
+    }
+}
+
+/// Append the stake position of `beneficiary`, which activity stakes take as their first remaining account.
+fn with_position(mut instruction: Instruction, activity: &Pubkey, beneficiary: &Pubkey) -> Instruction {
+    let position = get_position_public_key(activity, beneficiary).0;
+    instruction.accounts.push(AccountMeta::new(position, false));
+    instruction
+}
+
+fn update_zero_copy_accounts(stream: &Stream, signer: &Pubkey) -> superstream::accounts::UpdateZeroCopyStream {
+    superstream::accounts::UpdateZeroCopyStream {
+        stream: stream_public_key(stream),
+        signer: *signer,
+    }
+}
+
+fn stream_public_key(stream: &Stream) -> Pubkey {
+    get_stream_public_key(&stream.activity, &stream.mint, &stream.creator, &stream.name).0
+}
+
+fn stream_and_escrow_public_keys(stream: &Stream) -> (Pubkey, Pubkey) {
+    let stream_public_key = stream_public_key(stream);
+    (
+        stream_public_key,
+        get_token_public_key(&stream.mint, &stream_public_key),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use anchor_lang::solana_program::hash::hash;
+    use anchor_syn::idl::{Idl, IdlAccount, IdlAccountItem};
+
+    use super::*;
+    use crate::fixtures::{key, stream};
+
+    /// Parse the IDL of the program from its source, the same way `anchor build` does.
+    fn idl() -> Idl {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../programs/superstream/src/lib.rs");
+        anchor_syn::idl::file::parse(path, "0.0.0".to_string(), false, true, false)
+            .unwrap()
+            .unwrap()
+    }
+
+    fn flatten<'a>(items: &'a [IdlAccountItem], accounts: &mut Vec<&'a IdlAccount>) {
+        for item in items {
+            match item {
+                IdlAccountItem::IdlAccount(account) => accounts.push(account),
+                IdlAccountItem::IdlAccounts(nested) => flatten(&nested.accounts, accounts),
+            }
+        }
+    }
+
+    /// Get the Anchor discriminator of the instruction named `name` in the IDL.
+    fn discriminator(name: &str) -> Vec<u8> {
+        let mut snake_case_name = String::new();
+        for c in name.chars() {
+            if c.is_ascii_uppercase() {
+                snake_case_name.push('_');
+            }
+            snake_case_name.push(c.to_ascii_lowercase());
+        }
+        hash(format!("global:{}", snake_case_name).as_bytes()).to_bytes()[..8].to_vec()
+    }
+
+    /// Checks instructions against the instruction of the IDL they call and records which ones were checked.
+    struct IdlChecker {
+        idl: Idl,
+        keys: HashMap<&'static str, Pubkey>,
+        checked: HashSet<String>,
+    }
+
+    impl IdlChecker {
+        /// Check that `instruction` has the accounts of its IDL instruction in order, with the same signer and writable
+        /// flags and the keys in `keys` or `overrides` by account name, followed by `remaining_accounts`.
+        fn check(
+            &mut self,
+            instruction: Instruction,
+            overrides: &[(&'static str, Pubkey)],
+            remaining_accounts: &[AccountMeta],
+        ) {
+            assert_eq!(instruction.program_id, superstream::ID);
+            let idl_instruction = self
+                .idl
+                .instructions
+                .iter()
+                .find(|idl_instruction| instruction.data[..8] == discriminator(&idl_instruction.name))
+                .expect("instruction not found in the IDL");
+            let mut idl_accounts = Vec::new();
+            flatten(&idl_instruction.accounts, &mut idl_accounts);
+
+            let name = &idl_instruction.name;
+            let mut keys = self.keys.clone();
+            keys.extend(overrides.iter().copied());
+            assert!(instruction.accounts.len() >= idl_accounts.len(), "{}", name);
+            let (accounts, remaining) = instruction.accounts.split_at(idl_accounts.len());
+            for (account, idl_account) in accounts.iter().zip(&idl_accounts) {
+                let context = format!("{}.{}", name, idl_account.name);
+                assert_eq!(account.pubkey, keys[idl_account.name.as_str()], "{}", context);
+                assert_eq!(account.is_signer, idl_account.is_signer, "{}", context);
+                assert_eq!(account.is_writable, idl_account.is_mut, "{}", context);
+            }
+            assert_eq!(remaining, remaining_accounts, "{}", name);
+            self.checked.insert(name.clone());
+        }
+    }
+
+    #[test]
+    fn builders_match_the_idl() {
+        let stream = stream();
+        let (stream_public_key, escrow_token) = stream_and_escrow_public_keys(&stream);
+        let (activity, mint, sender, recipient) = (stream.activity, stream.mint, stream.sender, stream.recipient);
+        // Signer, creator, staker or claimer, depending on the instruction.
+        let wallet = key(9);
+        let new_wallet = key(7);
+        let (distributor_public_key, _) = get_distributor_public_key(&activity, &mint);
+        let distributor_escrow_token = get_token_public_key(&mint, &distributor_public_key);
+        let staker_position = get_position_public_key(&activity, &sender).0;
+
+        let mut checker = IdlChecker {
+            idl: idl(),
+            keys: HashMap::from([
+                ("stream", stream_public_key),
+                ("activity", activity),
+                ("position", staker_position),
+                ("mint", mint),
+                ("stakeMint", mint),
+                ("rewardMint", mint),
+                ("optRewardMint", mint),
+                ("sender", sender),
+                ("beneficiary", sender),
+                ("payer", sender),
+                ("rentPayer", stream.rent_payer),
+                ("signer", wallet),
+                ("creator", wallet),
+                ("funder", wallet),
+                ("staker", wallet),
+                ("claimer", wallet),
+                ("senderToken", get_token_public_key(&mint, &sender)),
+                ("signerToken", get_token_public_key(&mint, &wallet)),
+                ("recipientToken", get_token_public_key(&mint, &recipient)),
+                ("recipentToken", get_token_public_key(&mint, &wallet)),
+                ("funderToken", get_token_public_key(&mint, &wallet)),
+                ("stakerToken", get_token_public_key(&mint, &wallet)),
+                ("creatorToken", get_token_public_key(&mint, &wallet)),
+                ("escrowToken", escrow_token),
+                ("rewardVault", get_token_public_key(&mint, &activity)),
+                ("rewardEscrowToken", distributor_escrow_token),
+                ("senderRegistry", get_registry_public_key(&sender).0),
+                ("senderRegistryPage", get_registry_page_public_key(&sender, 2).0),
+                ("recipientRegistry", get_registry_public_key(&recipient).0),
+                ("recipientRegistryPage", get_registry_page_public_key(&recipient, 1).0),
+                ("newSenderRegistry", get_registry_public_key(&new_wallet).0),
+                ("newSenderRegistryPage", get_registry_page_public_key(&new_wallet, 3).0),
+                ("newRecipientRegistry", get_registry_public_key(&new_wallet).0),
+                (
+                    "newRecipientRegistryPage",
+                    get_registry_page_public_key(&new_wallet, 3).0,
+                ),
+                ("distributor", distributor_public_key),
+                ("status", get_status_public_key(&distributor_public_key, &wallet).0),
+                ("tokenProgram", token::ID),
+                ("systemProgram", system_program::ID),
+            ]),
+            checked: HashSet::new(),
+        };
+
+        // Stream creation. Activity stakes take the stake position of the beneficiary as a remaining account.
+        let params = CreateStreamParams {
+            seed: stream.seed,
+            name: stream.name.clone(),
+            recipient,
+            ..CreateStreamParams::default()
+        };
+        let position = [AccountMeta::new(staker_position, false)];
+        let pages = RegistryPages {
+            sender: 2,
+            recipient: 1,
+        };
+        checker.check(create_prepaid(&sender, &activity, &mint, params.clone()), &[], &[]);
+        let instruction = create_prepaid_v2(&sender, &activity, &mint, params.clone(), pages);
+        checker.check(instruction, &[], &[]);
+        let instruction = create_non_prepaid(&sender, &activity, &mint, params.clone(), 1);
+        checker.check(instruction, &[], &[]);
+        let instruction = create_non_prepaid_v2(&sender, &activity, &mint, params.clone(), 1, pages);
+        checker.check(instruction, &[], &[]);
+        checker.check(create_stream(&sender, &activity, &mint, params.clone()), &[], &position);
+        let instruction = create_stream_v2(&sender, &sender, &activity, &mint, params.clone(), 1, vec![], pages);
+        checker.check(instruction, &[], &position);
+        let instruction = create_stream_v2(&sender, &sender, &activity, &mint, params.clone(), 1, vec![], pages);
+        checker.check(with_rent_payer(instruction, &wallet), &[("payer", wallet)], &position);
+        let instruction = create_non_prepaid_v2(&sender, &activity, &mint, params, 1, pages);
+        checker.check(with_rent_payer(instruction, &wallet), &[("payer", wallet)], &[]);
+
+        // Activities.
+        let activity_params = CreateActivityParams {
+            name: "activity name".to_string(),
+            ..CreateActivityParams::default()
+        };
+        let created_activity = get_activity_public_key(0, &mint, &activity_params.name).0;
+        let instruction = create_activity(&wallet, &mint, &mint, &mint, activity_params);
+        checker.check(instruction, &[("activity", created_activity)], &[]);
+        checker.check(update_activity(&wallet, &activity, 1, 2, 3, 4), &[], &[]);
+        checker.check(quote_activity(&activity), &[], &[]);
+        checker.check(set_stake_caps(&wallet, &activity, 1, 2), &[], &[]);
+        checker.check(set_lock_multipliers(&wallet, &activity, vec![]), &[], &[]);
+        checker.check(set_early_unstake_penalty(&wallet, &activity, 1, &key(6)), &[], &[]);
+        checker.check(set_staker_allowlist_root(&wallet, &activity, [1; 32]), &[], &[]);
+        checker.check(deactivate_activity(&wallet, &activity), &[], &[]);
+        checker.check(close_activity(&wallet, &activity, &mint), &[], &[]);
+        checker.check(migrate_activity(&wallet, &activity), &[], &[]);
+        checker.check(fund_activity_rewards(&wallet, &activity, &mint, 1), &[], &[]);
+        let wallet_position = get_position_public_key(&activity, &wallet).0;
+        let instruction = claim_activity_rewards(&wallet, &activity, &mint);
+        checker.check(instruction, &[("position", wallet_position)], &[]);
+        let instruction = close_position(&wallet, &activity);
+        checker.check(instruction, &[("position", wallet_position)], &[]);
+
+        // Early unstakes take the penalty token account as a remaining account if the activity has a penalty.
+        let staker = [
+            ("staker", sender),
+            ("stakerToken", get_token_public_key(&mint, &sender)),
+        ];
+        checker.check(unstake_early(&stream, &Activity::default()), &staker, &[]);
+        let penalized_activity = Activity {
+            early_unstake_penalty: 1,
+            penalty_treasury: key(6),
+            ..Activity::default()
+        };
+        let penalty_token = [AccountMeta::new(get_token_public_key(&mint, &key(6)), false)];
+        checker.check(unstake_early(&stream, &penalized_activity), &staker, &penalty_token);
+
+        // Distributors.
+        let distributor = [("escrowToken", distributor_escrow_token)];
+        let instruction = create_distributor(&wallet, &activity, &mint, [1; 32], 1);
+        checker.check(
+            instruction,
+            &[("payer", wallet), ("senderToken", get_token_public_key(&mint, &wallet))],
+            &[],
+        );
+        let instruction = claim(&wallet, &activity, &mint, 0, 1, [1; 32], vec![]);
+        checker.check(instruction, &[distributor[0], ("payer", wallet)], &[]);
+        let instruction = claim(&wallet, &activity, &mint, 0, 1, [1; 32], vec![]);
+        checker.check(
+            with_rent_payer(instruction, &new_wallet),
+            &[distributor[0], ("payer", new_wallet)],
+            &[],
+        );
+        let instruction = recycle_reward(&wallet, &activity, &mint);
+        checker.check(instruction, &[distributor[0], ("sender", wallet)], &[]);
+        let distributor_account = Distributor {
+            distributor_key: distributor_public_key,
+            activity_key: activity,
+            creater_key: wallet,
+            bump: 0,
+            root: [0; 32],
+            total_supply: 0,
+            total_claimed: 0,
+            reward_expires_at: 0,
+            mint,
+            rent_payer: stream.rent_payer,
+        };
+        checker.check(close_distributor(&distributor_account), &distributor, &[]);
+        let status = Status {
+            claimer: wallet,
+            rent_payer: stream.rent_payer,
+            ..Status::default()
+        };
+        checker.check(close_status(&distributor_public_key, &status), &[], &[]);
+
+        // Streams.
+        let permissions = ManagerPermissions::default();
+        checker.check(cancel(&stream, &wallet), &[], &[]);
+        checker.check(request_cancel(&stream, &wallet), &[], &[]);
+        checker.check(quote_stream(&stream, None), &[], &[]);
+        checker.check(withdraw_excess_topup_non_prepaid_ended(&stream, &wallet), &[], &[]);
+        checker.check(topup_non_prepaid(&stream, &wallet, 1), &[], &[]);
+        checker.check(change_sender_non_prepaid(&stream, &sender, &new_wallet, 3), &[], &[]);
+        checker.check(set_manager(&stream, &wallet, permissions), &[], &[]);
+        checker.check(withdraw(&stream, &wallet), &[], &[]);
+        let instruction = withdraw_and_change_recipient(&stream, &wallet, &new_wallet, 3);
+        checker.check(instruction, &[], &[]);
+        checker.check(pause_non_prepaid(&stream, &wallet), &[], &[]);
+        checker.check(resume_non_prepaid(&stream, &wallet), &[], &[]);
+        checker.check(close_stream(&stream, &wallet), &[], &[]);
+        checker.check(migrate_stream(&stream, &wallet), &[], &[]);
+        let instruction = migrate_legacy_stream(&stream_public_key, &activity, &wallet, Some(sender));
+        checker.check(instruction, &[], &[]);
+
+        // Zero-copy streams.
+        let signed_by_sender = [("signer", sender)];
+        checker.check(cancel_zero_copy(&stream, &wallet), &[], &[]);
+        checker.check(request_cancel_zero_copy(&stream, &wallet), &[], &[]);
+        checker.check(quote_zero_copy_stream(&stream, None), &[], &[]);
+        let instruction = withdraw_excess_topup_non_prepaid_ended_zero_copy(&stream, &wallet);
+        checker.check(instruction, &[], &[]);
+        checker.check(topup_non_prepaid_zero_copy(&stream, &wallet, 1), &[], &[]);
+        let instruction = change_sender_non_prepaid_zero_copy(&stream, &sender, &new_wallet, 3);
+        checker.check(instruction, &signed_by_sender, &[]);
+        let instruction = set_manager_zero_copy(&stream, &wallet, permissions);
+        checker.check(instruction, &signed_by_sender, &[]);
+        checker.check(withdraw_zero_copy(&stream, &wallet), &[], &[]);
+        let instruction = withdraw_and_change_recipient_zero_copy(&stream, &wallet, &new_wallet, 3);
+        checker.check(instruction, &[], &[]);
+        checker.check(unstake_early_zero_copy(&stream, &Activity::default()), &staker, &[]);
+        let instruction = unstake_early_zero_copy(&stream, &penalized_activity);
+        checker.check(instruction, &staker, &penalty_token);
+        checker.check(pause_non_prepaid_zero_copy(&stream, &wallet), &[], &[]);
+        checker.check(resume_non_prepaid_zero_copy(&stream, &wallet), &[], &[]);
+        checker.check(close_zero_copy_stream(&stream, &wallet), &[], &[]);
+
+        // Every instruction of the program has a builder.
+        let instructions = checker.idl.instructions.iter();
+        let idl_names: HashSet<_> = instructions.map(|instruction| instruction.name.clone()).collect();
+        assert_eq!(checker.checked, idl_names);
+    }
+}
@@ -0,0 +1,30 @@
+//! Off-chain Rust client for [Superstream](https://superstream.finance/).
+//!
+//! - [`pda`] derives the addresses of stream, activity, distributor and claim status accounts.
+//! - [`instruction`] builds every Superstream instruction.
+//! - [`accounts`] fetches and deserializes Superstream accounts, with [`StreamFilters`] to filter streams.
+//!
+//! The account types are re-exported from the on-chain program crate through [`superstream::state`].
+//!
+//! ```rust ignore
+//! let client = RpcClient::new("https://api.devnet.solana.com".to_string());
+//! let streams = superstream_client::accounts::fetch_streams(
+//!     &client,
+//!     &StreamFilters { recipient: Some(wallet), is_cancelled: Some(false), ..StreamFilters::default() },
+//! )?;
+//! for (_, stream) in streams {
+//!     let instruction = superstream_client::instruction::withdraw(&stream, &wallet);
+//!     // ... send the instruction
+//! }
+//! ```
+
+pub mod accounts;
+pub mod filters;
+pub mod instruction;
+pub mod pda;
+
+#[cfg(test)]
+mod fixtures;
+
+pub use filters::StreamFilters;
+pub use superstream;
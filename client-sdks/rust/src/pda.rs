@@ -0,0 +1,179 @@
+//! PDA (Program Derived Address) helpers for Superstream accounts.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address;
+use superstream::{
+    get_name_seed, ACTIVITY_ACCOUNT_SEED, DISTRIBUTOR_ACCOUNT_SEED, POSITION_ACCOUNT_SEED, REGISTRY_ACCOUNT_SEED,
+    STATUS_ACCOUNT_SEED, STREAM_ACCOUNT_SEED,
+};
+
+/// Get the stream PDA public key and bump.
+///
+/// # Arguments
+///
+/// * `activity` - Activity the stream belongs to.
+/// * `mint` - SPL token mint of the stream.
+/// * `creator` - Wallet that created the stream. This is the original sender and does not change when the sender is
+///   changed.
+/// * `name` - Stream name. Names longer than 32 bytes are seeded on their hash, see [`get_name_seed`].
+pub fn get_stream_public_key(activity: &Pubkey, mint: &Pubkey, creator: &Pubkey, name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            STREAM_ACCOUNT_SEED,
+            activity.as_ref(),
+            mint.as_ref(),
+            creator.as_ref(),
+            get_name_seed(name).as_ref(),
+        ],
+        &superstream::ID,
+    )
+}
+
+/// Get the activity PDA public key and bump.
+///
+/// # Arguments
+///
+/// * `seed` - Activity seed.
+/// * `stake_mint` - SPL token mint that is staked in the activity.
+/// * `name` - Activity name. Names longer than 32 bytes are seeded on their hash, see [`get_name_seed`].
+pub fn get_activity_public_key(seed: u64, stake_mint: &Pubkey, name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            ACTIVITY_ACCOUNT_SEED,
+            seed.to_le_bytes().as_ref(),
+            stake_mint.as_ref(),
+            get_name_seed(name).as_ref(),
+        ],
+        &superstream::ID,
+    )
+}
+
+/// Get the reward distributor PDA public key and bump.
+///
+/// # Arguments
+///
+/// * `activity` - Activity the rewards are distributed for.
+/// * `mint` - SPL token mint of the rewards.
+pub fn get_distributor_public_key(activity: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DISTRIBUTOR_ACCOUNT_SEED, activity.as_ref(), mint.as_ref()],
+        &superstream::ID,
+    )
+}
+
+/// Get the claim status PDA public key and bump.
+///
+/// # Arguments
+///
+/// * `distributor` - Reward distributor.
+/// * `claimer` - Wallet claiming the rewards.
+pub fn get_status_public_key(distributor: &Pubkey, claimer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STATUS_ACCOUNT_SEED, distributor.as_ref(), claimer.as_ref()],
+        &superstream::ID,
+    )
+}
+
+/// Get the stream registry PDA public key and bump.
+///
+/// # Arguments
+///
+/// * `wallet` - Wallet whose streams, as the sender or the recipient, are listed in the registry.
+pub fn get_registry_public_key(wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REGISTRY_ACCOUNT_SEED, wallet.as_ref()], &superstream::ID)
+}
+
+/// Get the stream registry page PDA public key and bump.
+///
+/// # Arguments
+///
+/// * `wallet` - Wallet whose streams, as the sender or the recipient, are listed in the registry.
+/// * `page` - Index of the page. Instructions adding streams to the registry take its next page, given by
+///   [`StreamRegistry::next_page`](superstream::state::StreamRegistry::next_page).
+pub fn get_registry_page_public_key(wallet: &Pubkey, page: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[REGISTRY_ACCOUNT_SEED, wallet.as_ref(), page.to_le_bytes().as_ref()],
+        &superstream::ID,
+    )
+}
+
+/// Get the stake position PDA public key and bump.
+///
+/// # Arguments
+///
+/// * `activity` - Activity the wallet stakes in.
+/// * `wallet` - Staker wallet, i.e. the wallet that created the stakes.
+pub fn get_position_public_key(activity: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POSITION_ACCOUNT_SEED, activity.as_ref(), wallet.as_ref()],
+        &superstream::ID,
+    )
+}
+
+/// Get the associated token account of `owner` for `mint`. Escrow token accounts are the associated token accounts of
+/// the stream and distributor PDAs.
+pub fn get_token_public_key(mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+    get_associated_token_address(owner, mint)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::fixtures::key;
+
+    /// Check that `actual` is the PDA `address` with `bump`.
+    fn assert_pda(actual: (Pubkey, u8), address: &str, bump: u8) {
+        assert_eq!(actual, (Pubkey::from_str(address).unwrap(), bump));
+    }
+
+    // The addresses are derived independently of this crate, the same way the TypeScript SDK does.
+    #[test]
+    fn pdas_match_known_addresses() {
+        assert_pda(
+            get_stream_public_key(&key(4), &key(1), &key(2), "stream name"),
+            "3pBKaEGjoDGEj2cLDGgvA9rWb8QRBANoMzsoA6n5aw8J",
+            253,
+        );
+        assert_pda(
+            get_stream_public_key(&key(4), &key(1), &key(2), "a stream name which is longer than 32 bytes"),
+            "HsNMiuGXW1C2FdGo3iELXqpvZ2boHd14mtRPUhqXrLTr",
+            254,
+        );
+        assert_pda(
+            get_activity_public_key(1, &key(1), "activity name"),
+            "GVe69n89iFH4iPncU9yvxT96pY6ptQPhx9tdUk3UTV5f",
+            250,
+        );
+        assert_pda(
+            get_distributor_public_key(&key(4), &key(1)),
+            "J6EXhcQ4WTtQP14sRyg3Mg3sfqojEQeLC8ipJGCg9x71",
+            255,
+        );
+        assert_pda(
+            get_status_public_key(&key(5), &key(9)),
+            "Hqfuo7Zr1V7mYYBxJYPYazQ7ZVRsSVe3Az51cMSBnaH4",
+            253,
+        );
+        assert_pda(
+            get_registry_public_key(&key(9)),
+            "7iSxZ1ihsuFPDuTLrhdwELXzMBdnL4VZeLkXqJ8ffQLD",
+            254,
+        );
+        assert_pda(
+            get_registry_page_public_key(&key(9), 1),
+            "TFtxjRWh2984eiUK1jKupFhnxYNPwhTZ2k5ceZoWZ1k",
+            255,
+        );
+        assert_pda(
+            get_position_public_key(&key(4), &key(9)),
+            "7bzm1wzg4cKM1eWQJ4HsDfTsDiLrT3T7RG1fS188ML8o",
+            254,
+        );
+        assert_eq!(
+            get_token_public_key(&key(1), &key(9)),
+            Pubkey::from_str("bEQ2ZBPu3E5s8y4rzytQvpRGN5NqNsYBjWk7StNjKfi").unwrap(),
+        );
+    }
+}
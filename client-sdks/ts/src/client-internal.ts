@@ -1,4 +1,4 @@
-import { BN, Program, web3, AccountClient, ProgramAccount, AnchorProvider } from "@project-serum/anchor";
+import { BN, Program, web3, AccountClient, ProgramAccount, AnchorProvider, IdlTypes } from "@project-serum/anchor";
 import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
 
 import { Activity } from "./activity";
@@ -15,7 +15,7 @@ import idl from "./gen/idl.json";
 import { Superstream } from "./gen/types";
 import { StreamPagination } from "./pagination";
 import { Stream } from "./stream";
-import { BN_ONE, BN_TEN, BN_TWO, BN_ZERO } from "./utils/bn";
+import { BN_ONE, BN_TEN, BN_TWO, BN_ZERO, divCeil } from "./utils/bn";
 import { getErrorMessage } from "./utils/error";
 import { getOrCreateAssociatedTokenAccount, mustGetAssociatedTokenAccount } from "./utils/spl";
 import { getCurrentTimeInSecsBN, getCurrentTimeInSecsBNOrNull } from "./utils/time";
@@ -27,6 +27,8 @@ export type StreamAccount = ExtractGeneric<AccountClient<Superstream, Superstrea
 type ExtractGeneric0<T> = T extends AccountClient<Superstream, Superstream["accounts"][0], infer X> ? X : never;
 export type ActivityAccount = ExtractGeneric0<AccountClient<Superstream, Superstream["accounts"][0]>>;
 
+export type StreamQuote = IdlTypes<Superstream>["StreamQuote"];
+
 function hasKey<K extends string>(o: unknown, k: K): o is Record<K, unknown> {
   return typeof o === "object" && o != null && k in o;
 }
@@ -191,7 +193,8 @@ export class SuperstreamClientInternal implements SuperstreamClient {
     if (flowRate.lte(BN_ZERO) || endsAt.lte(startsAt) || flowInterval.lte(BN_ZERO)) {
       return initialAmount;
     } else {
-      return initialAmount.add(endsAt.sub(startsAt).mul(flowRate).div(flowInterval));
+      // Rounded up like the on-chain program, so that the prepaid amount covers any amount owed during the stream.
+      return initialAmount.add(divCeil(endsAt.sub(startsAt).mul(flowRate), flowInterval));
     }
   };
 
@@ -214,11 +217,12 @@ export class SuperstreamClientInternal implements SuperstreamClient {
       return BN_ZERO;
     } else {
       const diff = endsAt.sub(startsAt);
-      const depositNeeded = flowRate
-        .mul(
+      const depositNeeded = divCeil(
+        flowRate.mul(
           endsAt.lte(BN_ZERO) || DEPOSIT_AMOUNT_PERIOD_IN_SECS_BN.lte(diff) ? DEPOSIT_AMOUNT_PERIOD_IN_SECS_BN : diff,
-        )
-        .div(flowInterval);
+        ),
+        flowInterval,
+      );
       return depositNeeded.gte(BN_TEN) ? depositNeeded : depositNeeded.add(BN_ONE);
     }
   };
@@ -608,6 +612,17 @@ export class SuperstreamClientInternal implements SuperstreamClient {
       .rpc();
   };
 
+  readonly quoteStream = async (stream: Stream, at?: BN): Promise<StreamQuote> => {
+    const [streamKey] = await stream.getStreamPublicKey();
+
+    return await this.program.methods
+      .quoteStream(at ?? null)
+      .accounts({
+        stream: streamKey,
+      })
+      .view();
+  };
+
   readonly pauseNonPrepaidStream = async (stream: Stream) => {
     this.checkForValidWallet();
 
@@ -4,6 +4,12 @@ import { web3 } from "@project-serum/anchor";
 import { GetProgramAccountsFilter } from "@solana/web3.js";
 import bs58 from "bs58";
 
+/**
+ * Size of a stream account without its name, i.e. `Stream::BASE_LENGTH` in the program. The name is the last field and
+ * its bytes start after the 4 byte string length prefix.
+ */
+const STREAM_BASE_LENGTH = 571;
+
 /**
  * Stream filters that can be used to filter streams when fetching all streams.
  */
@@ -141,7 +147,7 @@ export function streamFiltersToAnchorFilters(filters?: StreamFilters): GetProgra
   if (filters.activity) {
     anchorFilters.push({
       memcmp: {
-        offset: 432,
+        offset: 420,
         bytes: filters.activity.toString(),
       },
     });
@@ -149,7 +155,7 @@ export function streamFiltersToAnchorFilters(filters?: StreamFilters): GetProgra
   if (filters.name) {
     anchorFilters.push({
       memcmp: {
-        offset: 456,
+        offset: STREAM_BASE_LENGTH + 4,
         bytes: bs58.encode(Buffer.from(filters.name)),
       },
     });
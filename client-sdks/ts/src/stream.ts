@@ -1,7 +1,7 @@
 import { BN, web3 } from "@project-serum/anchor";
 import { formatDistanceToNow } from "date-fns";
 
-import { StreamAccount, SuperstreamClientInternal } from "./client-internal";
+import { StreamAccount, StreamQuote, SuperstreamClientInternal } from "./client-internal";
 import { LOW_TOPUP_WARNING_PERIOD_IN_SECS } from "./constants";
 import { StreamPaymentStatus, StreamStatus } from "./stream-status";
 import { BN_ZERO } from "./utils/bn";
@@ -426,6 +426,20 @@ export class Stream {
     return await this.clientInternal.getStream(this.publicKey);
   };
 
+  /**
+   * Get a quote of the amounts of the stream computed by the on-chain program. This simulates the quote instruction and
+   * does not send a transaction.
+   *
+   * @param at The on-chain time. Defaults to the current on-chain time
+   *
+   * @returns The stream quote
+   *
+   * @throws An error is thrown if there is a Solana RPC issue
+   */
+  readonly quote = async (at?: BN): Promise<StreamQuote> => {
+    return await this.clientInternal.quoteStream(this, at);
+  };
+
   /**
    * Check is the client wallet address is the sender of this stream.
    *
@@ -514,8 +528,10 @@ export class Stream {
    * @param at The on-chain time
    *
    * @returns The payment status of the stream
+   *
+   * @throws An error is thrown if there is a Solana RPC issue
    */
-  readonly getPaymentStatus = (at: BN): StreamPaymentStatus => {
+  readonly getPaymentStatus = async (at: BN): Promise<StreamPaymentStatus> => {
     const status = this.getStatus(at);
     if (status != StreamStatus.STREAMING) {
       return StreamPaymentStatus.STREAMING;
@@ -526,11 +542,10 @@ export class Stream {
     } else if (!this.hasFlowPayments()) {
       return StreamPaymentStatus.FULLY_PAID;
     } else {
-      const { noLimit, maxAcceptableTopupAmount } = this.getMaxAcceptableTopupAmount(at);
-      if (!noLimit && maxAcceptableTopupAmount.lte(BN_ZERO)) {
+      const { hasUnlimitedTopup, maxAcceptableTopupAmount, amountOwed } = await this.quote(at);
+      if (!hasUnlimitedTopup && maxAcceptableTopupAmount.lte(BN_ZERO)) {
         return StreamPaymentStatus.FULLY_PAID;
       } else {
-        const amountOwed = this.getAmountOwed(at);
         const diff = this.totalTopupAmount.sub(amountOwed);
         if (diff.lte(BN_ZERO)) {
           return StreamPaymentStatus.NEEDS_TOPUP;
@@ -546,112 +561,63 @@ export class Stream {
     }
   };
 
-  private readonly unsafeGetActiveTimeAfterStart = (at: BN): BN => {
-    return this.isPaused
-      ? this.accumulatedActiveTime
-      : this.lastResumedAt.lte(BN_ZERO)
-      ? at.sub(this.startsAt)
-      : at.sub(this.lastResumedAt).add(this.accumulatedActiveTime);
-  };
-
   /**
-   * Get the maximum acceptable topup amount at the given on-chain time.
+   * Get the maximum acceptable topup amount at the given on-chain time, as computed by the on-chain program. See
+   * {@link Stream.quote}.
    *
-   * @param at The on-chain time
+   * @param at The on-chain time. Defaults to the current on-chain time
    *
    * @returns The maximum acceptable topup amount. If there is no maximum, the noLimit property is true
+   *
+   * @throws An error is thrown if there is a Solana RPC issue
    */
-  readonly getMaxAcceptableTopupAmount = (at: BN): { noLimit: boolean; maxAcceptableTopupAmount: BN } => {
-    if (this.isPrepaid || !this.hasFlowPayments()) {
-      return { noLimit: false, maxAcceptableTopupAmount: BN_ZERO };
-    }
-
-    const stopsAt = this.getStopsAt();
-    if (stopsAt.lte(BN_ZERO)) {
-      return { noLimit: true, maxAcceptableTopupAmount: BN_ZERO };
-    } else if (stopsAt.lt(this.startsAt)) {
-      return { noLimit: false, maxAcceptableTopupAmount: BN_ZERO };
-    }
-
-    const totalPossibleActiveTime = at.lt(this.startsAt)
-      ? this.endsAt.sub(this.startsAt)
-      : stopsAt.lte(at)
-      ? this.unsafeGetActiveTimeAfterStart(stopsAt)
-      : this.unsafeGetActiveTimeAfterStart(at).add(stopsAt.sub(at));
-    const totalPossibleTopup = totalPossibleActiveTime.lte(BN_ZERO)
-      ? this.initialAmount
-      : this.initialAmount.add(totalPossibleActiveTime.mul(this.flowRate).div(this.flowInterval));
-    if (totalPossibleTopup.lte(this.totalTopupAmount)) {
-      return { noLimit: false, maxAcceptableTopupAmount: BN_ZERO };
-    } else {
-      return { noLimit: false, maxAcceptableTopupAmount: totalPossibleTopup.sub(this.totalTopupAmount) };
-    }
+  readonly getMaxAcceptableTopupAmount = async (
+    at?: BN,
+  ): Promise<{ noLimit: boolean; maxAcceptableTopupAmount: BN }> => {
+    const { hasUnlimitedTopup, maxAcceptableTopupAmount } = await this.quote(at);
+    return { noLimit: hasUnlimitedTopup, maxAcceptableTopupAmount };
   };
 
   /**
-   * Get the total amount owed to the recipient at the given on-chain time. This includes any amount the recipient has
-   * already withdrawn. That need be subtracted to get the amount the recipient is eligible to withdraw. See
-   * {@link Stream.totalWithdrawnAmount} for more details.
+   * Get the total amount owed to the recipient at the given on-chain time, as computed by the on-chain program. This
+   * includes any amount the recipient has already withdrawn. See {@link Stream.quote}.
    *
-   * @param at The on-chain time
+   * @param at The on-chain time. Defaults to the current on-chain time
    *
    * @returns The total amount owed to the recipient at the given on-chain time
+   *
+   * @throws An error is thrown if there is a Solana RPC issue
    */
-  readonly getAmountOwed = (at: BN): BN => {
-    const stopsAt = this.getStopsAt();
-    if (stopsAt.gt(BN_ZERO) && stopsAt.lt(at)) {
-      at = stopsAt;
-    }
-
-    if (at.lt(this.startsAt)) {
-      return BN_ZERO;
-    } else if (!this.hasFlowPayments()) {
-      return this.initialAmount;
-    } else {
-      const activeTime = this.isPaused
-        ? this.accumulatedActiveTime
-        : this.lastResumedAt.eq(BN_ZERO)
-        ? at.sub(this.startsAt)
-        : at.sub(this.lastResumedAt).add(this.accumulatedActiveTime);
-      return this.initialAmount.add(activeTime.mul(this.flowRate).div(this.flowInterval));
-    }
+  readonly getAmountOwed = async (at?: BN): Promise<BN> => {
+    return (await this.quote(at)).amountOwed;
   };
 
   /**
-   * Get the total amount available to withdraw to the recipient at the given on-chain time.
+   * Get the amount the recipient can withdraw at the given on-chain time, as computed by the on-chain program. This is
+   * 0 until the stream has ended. See {@link Stream.quote}.
    *
-   * @param at The on-chain time
+   * @param at The on-chain time. Defaults to the current on-chain time
    *
-   * @returns The total amount available to withdraw to the recipient at the given on-chain time
-   */
-  readonly getAmountAvailableToWithdraw = (at: BN): BN => {
-    const amountOwed = this.getAmountOwed(at);
-    let amountAvailableToWithdraw = this.totalWithdrawnAmount.gte(amountOwed)
-      ? BN_ZERO
-      : amountOwed.sub(this.totalWithdrawnAmount);
-    if (amountAvailableToWithdraw.gt(BN_ZERO)) {
-      const totalEscrowAmount = this.totalTopupAmount.add(this.depositNeeded);
-      if (this.totalWithdrawnAmount.gte(totalEscrowAmount)) {
-        amountAvailableToWithdraw = BN_ZERO;
-      } else {
-        const remainingEscrowAmount = totalEscrowAmount.sub(this.totalWithdrawnAmount);
-        if (amountAvailableToWithdraw.gt(remainingEscrowAmount)) {
-          amountAvailableToWithdraw = remainingEscrowAmount;
-        }
-      }
-    }
-    return amountAvailableToWithdraw;
+   * @returns The amount available to withdraw to the recipient at the given on-chain time
+   *
+   * @throws An error is thrown if there is a Solana RPC issue
+   */
+  readonly getAmountAvailableToWithdraw = async (at?: BN): Promise<BN> => {
+    return (await this.quote(at)).amountAvailableToWithdraw;
   };
 
   /**
-   * Check is the stream is solvent at the given on-chain time.
+   * Check is the stream is solvent at the given on-chain time, as computed by the on-chain program. See
+   * {@link Stream.quote}.
    *
-   * @param at The on-chain time
+   * @param at The on-chain time. Defaults to the current on-chain time
    *
    * @returns true if the stream is solvent, false otherwise
+   *
+   * @throws An error is thrown if there is a Solana RPC issue
    */
-  readonly isSolvent = (at: BN): boolean => {
-    return this.getAmountOwed(at).lte(this.totalTopupAmount);
+  readonly isSolvent = async (at?: BN): Promise<boolean> => {
+    return (await this.quote(at)).isSolvent;
   };
 
   /**
@@ -668,10 +634,10 @@ export class Stream {
    *
    * @throws An error is thrown if the stream cannot be cancelled
    */
-  readonly validateCancel = (at: BN) => {
+  readonly validateCancel = async (at: BN) => {
     if (this.isCancelled) {
       throw new Error("The stream has already been cancelled");
-    } else if (this.isSolvent(at)) {
+    } else if (await this.isSolvent(at)) {
       const isSender = this.isSender();
       const isRecipient = this.isRecipient();
       if (!isSender && !isRecipient) {
@@ -701,7 +667,7 @@ export class Stream {
    *         client or there is a Solana RPC issue
    */
   readonly cancel = async (at: BN) => {
-    this.validateCancel(at);
+    await this.validateCancel(at);
     await this.clientInternal.cancelStream(this);
   };
 
@@ -718,15 +684,14 @@ export class Stream {
    *
    * @throws An error is thrown if the stream cannot be withdrawn from for excess sender topup
    */
-  readonly validateWithdrawExcessTopupNonPrepaidEnded = (at: BN) => {
+  readonly validateWithdrawExcessTopupNonPrepaidEnded = async (at: BN) => {
+    const { isSolvent, amountRefundable } = await this.quote(at);
     if (this.isCancelled) {
       throw new Error("The stream has already been cancelled");
-    } else if (!this.isSolvent(at)) {
+    } else if (!isSolvent) {
       throw new Error("The stream is insolvent");
     } else {
-      const amountOwed = this.getAmountOwed(at);
-      const amount = this.totalTopupAmount.add(this.depositNeeded).sub(amountOwed);
-      if (amount.lte(BN_ZERO)) {
+      if (amountRefundable.lte(BN_ZERO)) {
         throw new Error("Nothing to withdraw");
       }
     }
@@ -745,7 +710,7 @@ export class Stream {
    *         provided to the Superstream client or there is a Solana RPC issue
    */
   readonly withdrawExcessTopupNonPrepaidEnded = async (at: BN) => {
-    this.validateWithdrawExcessTopupNonPrepaidEnded(at);
+    await this.validateWithdrawExcessTopupNonPrepaidEnded(at);
     await this.clientInternal.withdrawExcessTopupFromNonPrepaidEndedStream(this);
   };
 
@@ -764,7 +729,7 @@ export class Stream {
    *
    * @throws An error is thrown if the stream cannot be topped up
    */
-  readonly validateTopupNonPrepaid = (at: BN, topupAmount?: BN) => {
+  readonly validateTopupNonPrepaid = async (at: BN, topupAmount?: BN) => {
     if (this.isPrepaid) {
       throw new Error("Cannot topup a prepaid stream");
     } else if (topupAmount != null && topupAmount.eq(BN_ZERO)) {
@@ -778,7 +743,7 @@ export class Stream {
     }
 
     if (topupAmount) {
-      const { noLimit, maxAcceptableTopupAmount } = this.getMaxAcceptableTopupAmount(at);
+      const { noLimit, maxAcceptableTopupAmount } = await this.getMaxAcceptableTopupAmount(at);
       if (!noLimit && topupAmount.gt(maxAcceptableTopupAmount)) {
         throw new Error("The topup amount is more than what is needed by the stream");
       }
@@ -795,7 +760,7 @@ export class Stream {
    *         client or there is a Solana RPC issue
    */
   readonly topupNonPrepaid = async (at: BN, topupAmount: BN) => {
-    this.validateTopupNonPrepaid(at, topupAmount);
+    await this.validateTopupNonPrepaid(at, topupAmount);
     await this.clientInternal.topupNonPrepaidStream(this, topupAmount);
   };
 
@@ -863,21 +828,21 @@ export class Stream {
    * Validate if the stream's recipient funds can be withdrawn at the given on-chain time.
    *
    * Conditions when validation fails:
-   * - Amount owed to the recipient is less than or equal to the total withdrawn amount
-   * - Total withdrawn amount is greater than or equal to the total topup amount plus the total deposit
+   * - Stream has not ended yet
+   * - Amount available to withdraw, as quoted by the on-chain program, is 0
    * - Wallet doesn't belong to the recipient of the stream and
    *   - Anyone cannot withdraw on behalf of the recipient
    *
    * @param at The on-chain time
    *
-   * @throws An error is thrown if the stream's recipient funds cannot be withdrawn
+   * @throws An error is thrown if the stream's recipient funds cannot be withdrawn or there is a Solana RPC issue
    */
-  readonly validateWithdraw = (at: BN) => {
-    const amountOwed = this.getAmountOwed(at);
-    if (amountOwed.lte(this.totalWithdrawnAmount)) {
+  readonly validateWithdraw = async (at: BN) => {
+    const { isWithdrawable, amountAvailableToWithdraw } = await this.quote(at);
+    if (!isWithdrawable) {
+      throw new Error("Stream has not ended yet. Can withdraw only after the stream has ended");
+    } else if (amountAvailableToWithdraw.lte(BN_ZERO)) {
       throw new Error("Amount owed has already been withdrawn. Nothing to withdraw");
-    } else if (this.totalWithdrawnAmount.gte(this.totalTopupAmount.add(this.depositNeeded))) {
-      throw new Error("Amount topped up has already been withdrawn. Nothing to withdraw");
     } else if (!this.isRecipient()) {
       if (!this.anyoneCanWithdrawForRecipient) {
         throw new Error("Only the recipient can withdraw");
@@ -900,7 +865,7 @@ export class Stream {
    *         the Superstream client or there is a Solana RPC issue
    */
   readonly withdraw = async (at: BN) => {
-    this.validateWithdraw(at);
+    await this.validateWithdraw(at);
     await this.clientInternal.withdrawFromStream(this);
   };
 
@@ -918,8 +883,8 @@ export class Stream {
    *
    * @throws An error is thrown if the stream's recipient funds cannot be withdrawn or the recipient cannot be changed
    */
-  readonly validateWithdrawAndChangeRecipient = (at: BN, newRecipient?: web3.PublicKey) => {
-    this.validateWithdraw(at);
+  readonly validateWithdrawAndChangeRecipient = async (at: BN, newRecipient?: web3.PublicKey) => {
+    await this.validateWithdraw(at);
     if (!this.isRecipient()) {
       throw new Error("Only the recipient can withdraw and change recipient");
     } else if (
@@ -944,7 +909,7 @@ export class Stream {
    *         or a user wallet wasn't provided to the Superstream client or there is a Solana RPC issue
    */
   readonly withdrawAndChangeRecipient = async (at: BN, newRecipient: web3.PublicKey) => {
-    this.validateWithdrawAndChangeRecipient(at, newRecipient);
+    await this.validateWithdrawAndChangeRecipient(at, newRecipient);
     await this.clientInternal.withdrawFromStreamAndChangeRecipient(this, newRecipient);
   };
 
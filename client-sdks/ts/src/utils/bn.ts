@@ -24,3 +24,15 @@ export const BN_HUNDRED = new BN(100);
  * The value 1000 as a BN
  */
 export const BN_THOUSAND = new BN(1000);
+
+/**
+ * Divide `a` by `b`, rounding up.
+ *
+ * @param a Dividend. Must not be negative
+ * @param b Divisor. Must be positive
+ *
+ * @returns The quotient rounded up
+ */
+export function divCeil(a: BN, b: BN): BN {
+  return a.add(b).sub(BN_ONE).div(b);
+}
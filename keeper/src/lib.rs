@@ -0,0 +1,601 @@
+//! Keeper that cancels insolvent [Superstream](https://superstream.finance/) streams.
+//!
+//! Senders of non-prepaid streams pay a deposit upfront. Once a stream becomes insolvent, anyone can cancel it and the
+//! deposit goes to whoever cancelled it as a reward. The keeper periodically scans all non-prepaid streams, evaluates
+//! their solvency with the program's own [`Stream::get_amount_owed`] and cancels the insolvent ones.
+//!
+//! ```rust ignore
+//! let client = RpcClient::new_with_commitment("http://localhost:8899".to_string(), CommitmentConfig::confirmed());
+//! let keeper = Keeper::new(client, payer, KeeperConfig { dry_run: true, ..KeeperConfig::default() });
+//! let report = keeper.run_once()?;
+//! ```
+
+use std::{
+    cmp,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+    rpc_client::RpcClient,
+};
+use solana_sdk::{
+    account::from_account,
+    clock::Clock,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    sysvar,
+    transaction::Transaction,
+};
+use spl_associated_token_account::instruction::create_associated_token_account;
+use superstream_client::{
+    accounts::{fetch_stream, fetch_streams},
+    instruction,
+    pda::get_token_public_key,
+    superstream::state::{Stream, StreamLayout},
+    StreamFilters,
+};
+
+/// Exponential backoff between retries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between retries.
+    pub max_delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Backoff {
+    /// Get the delay before retry number `attempt`, starting from 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        cmp::min(self.initial_delay.saturating_mul(factor), self.max_delay)
+    }
+}
+
+/// Keeper configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeeperConfig {
+    /// Maximum number of streams cancelled concurrently.
+    pub concurrency: usize,
+    /// Number of times a failed cancellation is retried before giving up on the stream until the next scan.
+    pub max_retries: u32,
+    /// Backoff between retries of failed RPC requests and cancellations.
+    pub backoff: Backoff,
+    /// If true, cancel transactions are simulated instead of being sent.
+    pub dry_run: bool,
+}
+
+impl Default for KeeperConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            max_retries: 3,
+            backoff: Backoff::default(),
+            dry_run: false,
+        }
+    }
+}
+
+/// Outcome of an attempt to cancel an insolvent stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The stream was cancelled by the given transaction.
+    Cancelled(Signature),
+    /// The cancel transaction was simulated successfully in dry-run mode.
+    Simulated,
+    /// The stream was not insolvent anymore when re-checked, e.g. because it was topped up or cancelled meanwhile.
+    Skipped,
+    /// The stream could not be cancelled after all retries.
+    Failed(String),
+}
+
+/// Summary of a single scan.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Time at which the streams were evaluated.
+    pub at: u64,
+    /// Number of non-prepaid streams which are not cancelled yet.
+    pub scanned: usize,
+    /// Insolvent streams found along with the outcome of cancelling them.
+    pub insolvent: Vec<(Pubkey, Outcome)>,
+}
+
+/// Check if a stream is insolvent at `at` and hence can be cancelled by anyone.
+///
+/// Only non-prepaid streams which have not stopped yet can become insolvent.
+pub fn is_insolvent(stream: &Stream, at: u64) -> anchor_lang::Result<bool> {
+    if stream.is_prepaid || stream.has_stopped(at) {
+        return Ok(false);
+    }
+    Ok(stream.total_topup_amount < stream.get_amount_owed(at)?)
+}
+
+/// Keeper that finds and cancels insolvent streams.
+pub struct Keeper {
+    client: RpcClient,
+    payer: Keypair,
+    config: KeeperConfig,
+}
+
+impl Keeper {
+    /// Create a new keeper. `payer` signs and pays for the cancel transactions and receives the deposit rewards.
+    pub fn new(client: RpcClient, payer: Keypair, config: KeeperConfig) -> Self {
+        Self { client, payer, config }
+    }
+
+    /// Get the current on-chain time from the clock sysvar.
+    pub fn current_time(&self) -> ClientResult<u64> {
+        let account = self.client.get_account(&sysvar::clock::ID)?;
+        let clock: Clock =
+            from_account(&account).ok_or_else(|| custom_error("Could not deserialize the clock sysvar".to_string()))?;
+        Ok(clock.unix_timestamp as u64)
+    }
+
+    /// Fetch all non-prepaid streams which are not cancelled yet and return the ones which are insolvent at `at`,
+    /// along with the number of streams scanned.
+    pub fn find_insolvent_streams(&self, at: u64) -> ClientResult<(usize, Vec<(Pubkey, Stream)>)> {
+        let streams = fetch_streams(
+            &self.client,
+            &StreamFilters {
+                is_prepaid: Some(false),
+                is_cancelled: Some(false),
+                ..StreamFilters::default()
+            },
+        )?;
+        let scanned = streams.len();
+        let mut insolvent = Vec::new();
+        for (public_key, stream) in streams {
+            match is_insolvent(&stream, at) {
+                Ok(true) => insolvent.push((public_key, stream)),
+                Ok(false) => {},
+                Err(error) => warn!("Unable to evaluate solvency [stream={}]: {}", public_key, error),
+            }
+        }
+        Ok((scanned, insolvent))
+    }
+
+    /// Scan all streams once and cancel the insolvent ones.
+    pub fn run_once(&self) -> ClientResult<Report> {
+        let at = self.current_time()?;
+        let (scanned, insolvent) = self.find_insolvent_streams(at)?;
+        let public_keys: Vec<Pubkey> = insolvent.into_iter().map(|(public_key, _)| public_key).collect();
+        Ok(Report {
+            at,
+            scanned,
+            insolvent: self.cancel_streams(&public_keys),
+        })
+    }
+
+    /// Scan streams forever, waiting `interval` between scans. Failed scans are retried with backoff.
+    pub fn run(&self, interval: Duration) -> ! {
+        let mut failures = 0;
+        loop {
+            match self.run_once() {
+                Ok(report) => {
+                    failures = 0;
+                    log_report(&report);
+                    thread::sleep(interval);
+                },
+                Err(error) => {
+                    let delay = self.config.backoff.delay(failures);
+                    error!(
+                        "Error scanning streams [try={}]: {}. Retrying in {:?}...",
+                        failures + 1,
+                        error,
+                        delay
+                    );
+                    failures = failures.saturating_add(1);
+                    thread::sleep(delay);
+                },
+            }
+        }
+    }
+
+    /// Cancel the given streams using at most `concurrency` threads.
+    pub fn cancel_streams(&self, public_keys: &[Pubkey]) -> Vec<(Pubkey, Outcome)> {
+        let next = AtomicUsize::new(0);
+        let outcomes = Mutex::new(Vec::with_capacity(public_keys.len()));
+        let workers = cmp::min(cmp::max(self.config.concurrency, 1), public_keys.len());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while let Some(public_key) = public_keys.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let outcome = self.cancel_stream(public_key);
+                        outcomes.lock().unwrap().push((*public_key, outcome));
+                    }
+                });
+            }
+        });
+        outcomes.into_inner().unwrap()
+    }
+
+    /// Cancel an insolvent stream, retrying with backoff on failure.
+    pub fn cancel_stream(&self, public_key: &Pubkey) -> Outcome {
+        let mut attempt = 0;
+        loop {
+            match self.try_cancel_stream(public_key) {
+                Ok(outcome) => return outcome,
+                Err(error) if attempt < self.config.max_retries => {
+                    let delay = self.config.backoff.delay(attempt);
+                    warn!(
+                        "Unable to cancel stream [stream={}, try={}]: {}. Retrying in {:?}...",
+                        public_key,
+                        attempt + 1,
+                        error,
+                        delay
+                    );
+                    attempt += 1;
+                    thread::sleep(delay);
+                },
+                Err(error) => return Outcome::Failed(error.to_string()),
+            }
+        }
+    }
+
+    fn try_cancel_stream(&self, public_key: &Pubkey) -> ClientResult<Outcome> {
+        // Re-check against fresh state as the stream may have been topped up or cancelled since the scan.
+        let stream = fetch_stream(&self.client, public_key)?;
+        let at = self.current_time()?;
+        if stream.is_cancelled || !is_insolvent(&stream, at).map_err(|error| custom_error(error.to_string()))? {
+            return Ok(Outcome::Skipped);
+        }
+
+        let transaction = Transaction::new_signed_with_payer(
+            &self.cancel_instructions(&stream)?,
+            Some(&self.payer.pubkey()),
+            &[&self.payer],
+            self.client.get_latest_blockhash()?,
+        );
+        if self.config.dry_run {
+            let result = self.client.simulate_transaction(&transaction)?;
+            return match result.value.err {
+                Some(error) => Err(ClientErrorKind::TransactionError(error).into()),
+                None => Ok(Outcome::Simulated),
+            };
+        }
+        Ok(Outcome::Cancelled(
+            self.client.send_and_confirm_transaction(&transaction)?,
+        ))
+    }
+
+    /// Build the cancel instruction, preceded by instructions creating the missing token accounts of the keeper, the
+    /// sender and the recipient since cancel requires all of them.
+    fn cancel_instructions(&self, stream: &Stream) -> ClientResult<Vec<Instruction>> {
+        let mut owners = vec![self.payer.pubkey()];
+        for owner in [stream.sender, stream.recipient] {
+            if !owners.contains(&owner) {
+                owners.push(owner);
+            }
+        }
+        let token_public_keys: Vec<Pubkey> = owners
+            .iter()
+            .map(|owner| get_token_public_key(&stream.mint, owner))
+            .collect();
+        let token_accounts = self.client.get_multiple_accounts(&token_public_keys)?;
+
+        let mut instructions: Vec<Instruction> = owners
+            .iter()
+            .zip(token_accounts)
+            .filter(|(_, account)| account.is_none())
+            .map(|(owner, _)| create_associated_token_account(&self.payer.pubkey(), owner, &stream.mint))
+            .collect();
+        instructions.push(instruction::cancel(stream, &self.payer.pubkey()));
+        Ok(instructions)
+    }
+}
+
+/// Log the outcome of a scan.
+pub fn log_report(report: &Report) {
+    info!(
+        "Scanned streams [at={}, scanned={}, insolvent={}]",
+        report.at,
+        report.scanned,
+        report.insolvent.len()
+    );
+    for (public_key, outcome) in &report.insolvent {
+        match outcome {
+            Outcome::Cancelled(signature) => {
+                info!(
+                    "Cancelled insolvent stream [stream={}, signature={}]",
+                    public_key, signature
+                )
+            },
+            Outcome::Simulated => info!("Simulated cancelling insolvent stream [stream={}]", public_key),
+            Outcome::Skipped => info!("Stream is not insolvent anymore [stream={}]", public_key),
+            Outcome::Failed(error) => error!("Unable to cancel insolvent stream [stream={}]: {}", public_key, error),
+        }
+    }
+}
+
+fn custom_error(message: String) -> ClientError {
+    ClientErrorKind::Custom(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use anchor_lang::AccountSerialize;
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use solana_account_decoder::{UiAccount, UiAccountEncoding};
+    use solana_client::{rpc_client::RpcClientConfig, rpc_request::RpcRequest, rpc_sender::*};
+    use solana_sdk::{
+        account::{create_account_for_test, Account},
+        commitment_config::CommitmentConfig,
+        hash::Hash,
+        transaction::TransactionError,
+    };
+    use superstream_client::superstream::{
+        self,
+        state::{AccrualMode, ManagerPermissions, STREAM_VERSION},
+    };
+
+    use super::*;
+
+    const NO_BACKOFF: Backoff = Backoff {
+        initial_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    /// Get a non-prepaid stream of 10 tokens per second starting at 100 without end time, topped up till 110.
+    fn stream() -> Stream {
+        Stream {
+            is_prepaid: false,
+            mint: key(1),
+            sender: key(2),
+            recipient: key(3),
+            created_at: 100,
+            starts_at: 100,
+            ends_at: 0,
+            initial_amount: 0,
+            flow_interval: 1,
+            flow_rate: 10,
+            is_cancelled: false,
+            is_cancelled_before_start: false,
+            is_cancelled_by_sender: false,
+            cancelled_at: 0,
+            sender_can_cancel: true,
+            sender_can_cancel_at: 0,
+            sender_can_change_sender: false,
+            sender_can_change_sender_at: 0,
+            is_paused: false,
+            is_paused_by_sender: false,
+            sender_can_pause: false,
+            sender_can_pause_at: 0,
+            recipient_can_resume_pause_by_sender: false,
+            recipient_can_resume_pause_by_sender_at: 0,
+            anyone_can_withdraw_for_recipient: false,
+            anyone_can_withdraw_for_recipient_at: 0,
+            last_resumed_at: 0,
+            accumulated_active_time: 0,
+            total_withdrawn_amount: 0,
+            last_withdrawn_at: 0,
+            last_withdrawn_amount: 0,
+            total_topup_amount: 100,
+            last_topup_at: 100,
+            last_topup_amount: 100,
+            deposit_needed: 50,
+            stake_weight: 0,
+            beneficiary: Pubkey::default(),
+            version: STREAM_VERSION,
+            reserved: [0; 10],
+            seed: 0,
+            bump: 255,
+            activity: key(4),
+            manager: Pubkey::default(),
+            manager_permissions: ManagerPermissions::default(),
+            notice_period: 0,
+            cancellation_scheduled_at: 0,
+            is_cancellation_requested_by_sender: false,
+            creator: key(2),
+            accrual_mode: AccrualMode::Continuous,
+            rent_payer: key(2),
+            is_stake: false,
+            name: "stream".to_string(),
+        }
+    }
+
+    #[test]
+    fn stream_is_insolvent_once_owed_more_than_topped_up() {
+        assert!(!is_insolvent(&stream(), 50).unwrap());
+        assert!(!is_insolvent(&stream(), 110).unwrap());
+        assert!(is_insolvent(&stream(), 111).unwrap());
+
+        let prepaid = Stream {
+            is_prepaid: true,
+            ..stream()
+        };
+        assert!(!is_insolvent(&prepaid, 111).unwrap());
+
+        // Nothing is owed once the stream has stopped, so stopped streams are left alone.
+        let ended = Stream {
+            ends_at: 120,
+            ..stream()
+        };
+        assert!(is_insolvent(&ended, 120).unwrap());
+        assert!(!is_insolvent(&ended, 121).unwrap());
+        let cancelled = Stream {
+            is_cancelled: true,
+            cancelled_at: 105,
+            ..stream()
+        };
+        assert!(!is_insolvent(&cancelled, 111).unwrap());
+
+        let paused = Stream {
+            is_paused: true,
+            accumulated_active_time: 10,
+            ..stream()
+        };
+        assert!(!is_insolvent(&paused, 1_000).unwrap());
+        let resumed = Stream {
+            is_paused: false,
+            last_resumed_at: 1_000,
+            ..paused
+        };
+        assert!(!is_insolvent(&resumed, 1_000).unwrap());
+        assert!(is_insolvent(&resumed, 1_001).unwrap());
+
+        let overflowing = Stream {
+            flow_rate: u64::MAX,
+            ..stream()
+        };
+        assert!(is_insolvent(&overflowing, 102).is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_the_delay_up_to_the_max() {
+        let backoff = Backoff {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(1), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(800));
+        assert_eq!(backoff.delay(4), Duration::from_secs(1));
+        for attempt in [31, 32, 64, u32::MAX] {
+            assert_eq!(backoff.delay(attempt), Duration::from_secs(1));
+        }
+
+        let default = Backoff::default();
+        assert_eq!(default.delay(0), Duration::from_millis(500));
+        assert_eq!(default.delay(5), Duration::from_secs(16));
+        assert_eq!(default.delay(6), Duration::from_secs(30));
+        assert_eq!(NO_BACKOFF.delay(10), Duration::ZERO);
+    }
+
+    /// RPC node serving `accounts` and the clock sysvar at `at`, in place of a validator. The methods called are
+    /// recorded in `methods`.
+    struct TestSender {
+        accounts: HashMap<Pubkey, Account>,
+        simulation_error: Option<TransactionError>,
+        methods: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl TestSender {
+        fn account(&self, public_key: &Value) -> Value {
+            let public_key = public_key.as_str().unwrap().parse::<Pubkey>().unwrap();
+            match self.accounts.get(&public_key) {
+                Some(account) => json!(UiAccount::encode(
+                    &public_key,
+                    account,
+                    UiAccountEncoding::Base64,
+                    None,
+                    None
+                )),
+                None => Value::Null,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl RpcSender for TestSender {
+        async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+            let method = request.to_string();
+            self.methods.lock().unwrap().push(method.clone());
+            let value = match method.as_str() {
+                "getVersion" => return Ok(json!({ "solana-core": "1.10.33" })),
+                "getAccountInfo" => self.account(&params[0]),
+                "getMultipleAccounts" => {
+                    let public_keys = params[0].as_array().unwrap();
+                    Value::Array(public_keys.iter().map(|public_key| self.account(public_key)).collect())
+                },
+                "getLatestBlockhash" => json!({ "blockhash": Hash::default().to_string(), "lastValidBlockHeight": 1 }),
+                "simulateTransaction" => json!({ "err": self.simulation_error, "logs": [] }),
+                _ => return Err(custom_error(format!("Unexpected RPC request {}", method))),
+            };
+            Ok(json!({ "context": { "slot": 1 }, "value": value }))
+        }
+
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            RpcTransportStats::default()
+        }
+
+        fn url(&self) -> String {
+            "test".to_string()
+        }
+    }
+
+    /// Cancel `stream` in dry-run mode at `at` and return the outcome along with the RPC methods called.
+    fn dry_run_cancel(stream: &Stream, at: i64, simulation_error: Option<TransactionError>) -> (Outcome, Vec<String>) {
+        let public_key = key(5);
+        let mut data = Vec::new();
+        stream.try_serialize(&mut data).unwrap();
+        let clock = Clock {
+            unix_timestamp: at,
+            ..Clock::default()
+        };
+        let account = Account {
+            lamports: 1,
+            data,
+            owner: superstream::ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        let accounts = HashMap::from([
+            (public_key, account),
+            (sysvar::clock::ID, create_account_for_test(&clock)),
+        ]);
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let sender = TestSender {
+            accounts,
+            simulation_error,
+            methods: methods.clone(),
+        };
+        let client = RpcClient::new_sender(sender, RpcClientConfig::with_commitment(CommitmentConfig::confirmed()));
+        let config = KeeperConfig {
+            max_retries: 1,
+            backoff: NO_BACKOFF,
+            dry_run: true,
+            ..KeeperConfig::default()
+        };
+        let outcome = Keeper::new(client, Keypair::new(), config).cancel_stream(&public_key);
+        let methods = methods.lock().unwrap().clone();
+        (outcome, methods)
+    }
+
+    #[test]
+    fn dry_run_simulates_the_cancellation_without_sending_it() {
+        let (outcome, methods) = dry_run_cancel(&stream(), 111, None);
+        assert_eq!(outcome, Outcome::Simulated);
+        assert!(methods.iter().any(|method| method == "simulateTransaction"));
+        assert!(!methods.iter().any(|method| method == "sendTransaction"));
+
+        // Failed simulations are retried like failed cancellations.
+        let (outcome, methods) = dry_run_cancel(&stream(), 111, Some(TransactionError::AccountNotFound));
+        assert!(matches!(outcome, Outcome::Failed(_)));
+        let simulations = methods.iter().filter(|method| *method == "simulateTransaction");
+        assert_eq!(simulations.count(), 2);
+        assert!(!methods.iter().any(|method| method == "sendTransaction"));
+
+        // Streams which are not insolvent anymore are skipped without simulating the cancellation.
+        let (outcome, methods) = dry_run_cancel(&stream(), 110, None);
+        assert_eq!(outcome, Outcome::Skipped);
+        assert!(!methods.iter().any(|method| method == "simulateTransaction"));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{process, time::Duration};
+
+use clap::{crate_description, crate_version, value_t_or_exit, App, Arg};
+use log::{error, info};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::read_keypair_file, signer::Signer};
+use superstream_keeper::{log_report, Backoff, Keeper, KeeperConfig};
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let matches = App::new("superstream-keeper")
+        .version(crate_version!())
+        .about(crate_description!())
+        .arg(
+            Arg::with_name("url")
+                .short("u")
+                .long("url")
+                .value_name("URL_OR_CLUSTER")
+                .default_value("devnet")
+                .help("RPC URL or cluster name: mainnet-beta, devnet, testnet or localnet"),
+        )
+        .arg(
+            Arg::with_name("wallet")
+                .short("w")
+                .long("wallet")
+                .value_name("PATH")
+                .required(true)
+                .help("Wallet keypair JSON file path. Pays for cancellations and receives the deposit rewards"),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .value_name("COUNT")
+                .default_value("4")
+                .help("Maximum number of streams cancelled concurrently"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .value_name("SECONDS")
+                .default_value("5")
+                .help("Delay between scans"),
+        )
+        .arg(
+            Arg::with_name("max_retries")
+                .long("max-retries")
+                .value_name("COUNT")
+                .default_value("3")
+                .help("Number of retries of a failed cancellation"),
+        )
+        .arg(
+            Arg::with_name("max_backoff")
+                .long("max-backoff")
+                .value_name("SECONDS")
+                .default_value("30")
+                .help("Maximum delay between retries of failed requests"),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .help("Simulate the cancel transactions instead of sending them"),
+        )
+        .arg(
+            Arg::with_name("once")
+                .long("once")
+                .help("Scan the streams once and exit"),
+        )
+        .get_matches();
+
+    let url = cluster_url(matches.value_of("url").unwrap());
+    let payer = read_keypair_file(matches.value_of("wallet").unwrap()).unwrap_or_else(|error| {
+        error!("Unable to read Solana wallet keypair JSON file: {}", error);
+        process::exit(1);
+    });
+    let config = KeeperConfig {
+        concurrency: value_t_or_exit!(matches, "concurrency", usize),
+        max_retries: value_t_or_exit!(matches, "max_retries", u32),
+        backoff: Backoff {
+            max_delay: Duration::from_secs(value_t_or_exit!(matches, "max_backoff", u64)),
+            ..Backoff::default()
+        },
+        dry_run: matches.is_present("dry_run"),
+    };
+    let interval = Duration::from_secs(value_t_or_exit!(matches, "interval", u64));
+
+    info!(
+        "Starting keeper [url={}, wallet={}, dry_run={}]...",
+        url,
+        payer.pubkey(),
+        config.dry_run
+    );
+    let keeper = Keeper::new(
+        RpcClient::new_with_commitment(url, CommitmentConfig::confirmed()),
+        payer,
+        config,
+    );
+    if matches.is_present("once") {
+        match keeper.run_once() {
+            Ok(report) => log_report(&report),
+            Err(error) => {
+                error!("Error scanning streams: {}", error);
+                process::exit(1);
+            },
+        }
+    } else {
+        keeper.run(interval);
+    }
+}
+
+fn cluster_url(url_or_cluster: &str) -> String {
+    match url_or_cluster {
+        "mainnet-beta" => "https://api.mainnet-beta.solana.com",
+        "devnet" => "https://api.devnet.solana.com",
+        "testnet" => "https://api.testnet.solana.com",
+        "localnet" => "http://localhost:8899",
+        url => url,
+    }
+    .to_string()
+}
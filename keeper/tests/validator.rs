@@ -0,0 +1,195 @@
+//! Tests against a local validator with the Superstream program deployed. They are ignored by default, run them with:
+//!
+//! ```shell
+//! anchor build
+//! solana-test-validator --reset --bpf-program 89XSrErdZFx8MpyohHFEievS7qqHDn9bZh33tV4xbz3K target/deploy/superstream.so
+//! cargo test -p superstream-keeper -- --ignored
+//! ```
+
+use std::{thread, time::Duration};
+
+use anchor_spl::token::spl_token::{self, state::Mint};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account::from_account,
+    clock::Clock,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::Transaction,
+};
+use spl_associated_token_account::instruction::create_associated_token_account;
+use superstream_client::{
+    accounts::fetch_stream,
+    instruction::{self, CreateActivityParams},
+    pda::{get_activity_public_key, get_stream_public_key, get_token_public_key},
+    superstream::state::{CreateStreamParams, DEPOSIT_AMOUNT_PERIOD_IN_SECS},
+};
+use superstream_keeper::{is_insolvent, Keeper, KeeperConfig, Outcome};
+
+const URL: &str = "http://localhost:8899";
+
+fn client() -> RpcClient {
+    RpcClient::new_with_commitment(URL.to_string(), CommitmentConfig::confirmed())
+}
+
+fn funded_keypair(client: &RpcClient) -> Keypair {
+    let keypair = Keypair::new();
+    let signature = client
+        .request_airdrop(&keypair.pubkey(), 10 * LAMPORTS_PER_SOL)
+        .unwrap();
+    while !client.confirm_transaction(&signature).unwrap() {
+        thread::sleep(Duration::from_millis(200));
+    }
+    keypair
+}
+
+fn send(client: &RpcClient, payer: &Keypair, signers: &[&Keypair], instructions: &[Instruction]) {
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&payer.pubkey()),
+        &all_signers,
+        client.get_latest_blockhash().unwrap(),
+    );
+    client.send_and_confirm_transaction(&transaction).unwrap();
+}
+
+fn create_mint(client: &RpcClient, payer: &Keypair) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = client.get_minimum_balance_for_rent_exemption(Mint::LEN).unwrap();
+    send(
+        client,
+        payer,
+        &[&mint],
+        &[
+            system_instruction::create_account(&payer.pubkey(), &mint.pubkey(), rent, Mint::LEN as u64, &spl_token::ID),
+            spl_token::instruction::initialize_mint(&spl_token::ID, &mint.pubkey(), &payer.pubkey(), None, 6).unwrap(),
+        ],
+    );
+    mint.pubkey()
+}
+
+/// Create an activity and an unbounded non-prepaid stream of 1 token per second from `sender` to a new recipient.
+fn create_non_prepaid_stream(client: &RpcClient, sender: &Keypair) -> Pubkey {
+    let mint = create_mint(client, sender);
+    let clock: Clock = from_account(&client.get_account(&sysvar::clock::ID).unwrap()).unwrap();
+    let now = clock.unix_timestamp as u64;
+
+    let activity_params = CreateActivityParams {
+        seed: 0,
+        name: "keeper".to_string(),
+        starts_at: now,
+        ends_at: now + 3600,
+        reward_expires_at: now + 7200,
+        duration: 1,
+        min_amount: 1,
+        flow_rate: 0,
+    };
+    let (activity, _) = get_activity_public_key(activity_params.seed, &mint, &activity_params.name);
+    let recipient = Pubkey::new_unique();
+    let name = "keeper".to_string();
+    let (stream, _) = get_stream_public_key(&activity, &mint, &sender.pubkey(), &name);
+    send(
+        client,
+        sender,
+        &[],
+        &[
+            instruction::create_activity(&sender.pubkey(), &mint, &mint, &mint, activity_params),
+            create_associated_token_account(&sender.pubkey(), &sender.pubkey(), &mint),
+            create_associated_token_account(&sender.pubkey(), &recipient, &mint),
+            create_associated_token_account(&sender.pubkey(), &stream, &mint),
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &mint,
+                &get_token_public_key(&mint, &sender.pubkey()),
+                &sender.pubkey(),
+                &[],
+                1_000_000_000,
+            )
+            .unwrap(),
+        ],
+    );
+
+    let params = CreateStreamParams {
+        seed: 0,
+        name,
+        recipient,
+        flow_interval: 1,
+        flow_rate: 1,
+        ..CreateStreamParams::default()
+    };
+    let topup_amount = 3 * DEPOSIT_AMOUNT_PERIOD_IN_SECS;
+    send(
+        client,
+        sender,
+        &[],
+        &[instruction::create_non_prepaid(
+            &sender.pubkey(),
+            &activity,
+            &mint,
+            params,
+            topup_amount,
+        )],
+    );
+    stream
+}
+
+#[test]
+#[ignore]
+fn finds_streams_insolvent_after_the_deposit_period() {
+    let client = client();
+    let sender = funded_keypair(&client);
+    let stream_public_key = create_non_prepaid_stream(&client, &sender);
+    let stream = fetch_stream(&client, &stream_public_key).unwrap();
+
+    let payer = funded_keypair(&client);
+    let keeper = Keeper::new(client, payer, KeeperConfig::default());
+    let at = keeper.current_time().unwrap();
+    assert!(!is_insolvent(&stream, at).unwrap());
+
+    let (scanned, insolvent) = keeper.find_insolvent_streams(at).unwrap();
+    assert!(scanned >= 1);
+    assert!(insolvent.iter().all(|(public_key, _)| *public_key != stream_public_key));
+
+    // The topup left after taking the deposit covers two deposit periods.
+    let later = at + 3 * DEPOSIT_AMOUNT_PERIOD_IN_SECS;
+    assert!(is_insolvent(&stream, later).unwrap());
+    let (_, insolvent) = keeper.find_insolvent_streams(later).unwrap();
+    assert!(insolvent.iter().any(|(public_key, _)| *public_key == stream_public_key));
+}
+
+#[test]
+#[ignore]
+fn does_not_cancel_solvent_streams() {
+    let client = client();
+    let sender = funded_keypair(&client);
+    let stream_public_key = create_non_prepaid_stream(&client, &sender);
+
+    for dry_run in [true, false] {
+        let keeper = Keeper::new(
+            self::client(),
+            funded_keypair(&client),
+            KeeperConfig {
+                dry_run,
+                ..KeeperConfig::default()
+            },
+        );
+        let report = keeper.run_once().unwrap();
+        assert!(report
+            .insolvent
+            .iter()
+            .all(|(public_key, _)| *public_key != stream_public_key));
+        // Solvency is re-checked against fresh state before cancelling.
+        assert_eq!(
+            keeper.cancel_streams(&[stream_public_key]),
+            vec![(stream_public_key, Outcome::Skipped)]
+        );
+    }
+    assert!(!fetch_stream(&client, &stream_public_key).unwrap().is_cancelled);
+}
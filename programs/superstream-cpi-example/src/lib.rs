@@ -0,0 +1,533 @@
+//! An example program which uses Superstream through CPI (Cross-Program Invocation).
+//!
+//! All the streams are sent, received or claimed by an authority PDA account owned by this program. The authority
+//! signs the Superstream instructions with its seeds, the same way a DAO treasury or a vault program would.
+//!
+//! Learn more about Superstream on [superstream.finance](https://superstream.finance/).
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token};
+use superstream::{
+    program::Superstream,
+    state::{CancelTransferParams, CreateStreamParams},
+};
+
+declare_id!("EeLdT7JNQ6rLjbKjQZtCAdpkND2k2sACFuLPzNT8r5L9");
+
+/// PDA account seed of the authority account which acts as the sender, recipient or claimer.
+pub const AUTHORITY_ACCOUNT_SEED: &[u8] = b"authority";
+
+#[program]
+pub mod superstream_cpi_example {
+    //! Module for superstream-cpi-example instructions.
+
+    use super::*;
+
+    /// Create a new prepaid stream with the authority as the sender.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Stream creation parameters. See [`CreateStreamParams`] for more information.
+    pub fn create_prepaid(ctx: Context<Create>, params: CreateStreamParams) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_ctx = ctx.accounts.create_ctx().with_signer(signer);
+
+        superstream::cpi::create_prepaid_v2(cpi_ctx, params)
+    }
+
+    /// Create a new non-prepaid stream with the authority as the sender.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Stream creation parameters. See [`CreateStreamParams`] for more information.
+    /// * `topup_amount` - Initial topup amount for the stream.
+    pub fn create_non_prepaid(ctx: Context<Create>, params: CreateStreamParams, topup_amount: u64) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_ctx = ctx.accounts.create_ctx().with_signer(signer);
+
+        superstream::cpi::create_non_prepaid_v2(cpi_ctx, params, topup_amount)
+    }
+
+    /// Topup a non-prepaid stream from the authority.
+    ///
+    /// # Arguments
+    ///
+    /// * `topup_amount` - Topup amount for the stream.
+    ///
+    /// For more information on the other arguments, see fields of the [`Stream`](superstream::state::Stream) struct.
+    pub fn topup_non_prepaid(ctx: Context<TopupNonPrepaid>, seed: u64, name: String, topup_amount: u64) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_program = ctx.accounts.superstream_program.to_account_info();
+        let cpi_accounts = superstream::cpi::accounts::TopupNonPrepaid {
+            stream: ctx.accounts.stream.to_account_info(),
+            signer: ctx.accounts.authority.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            signer_token: ctx.accounts.authority_token.to_account_info(),
+            escrow_token: ctx.accounts.escrow_token.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        superstream::cpi::topup_non_prepaid(cpi_ctx, seed, name, topup_amount)
+    }
+
+    /// Withdraw funds from a stream with the authority as the recipient.
+    ///
+    /// The withdrawn amount is set as the return data of the instruction.
+    ///
+    /// For more information on the arguments, see fields of the [`Stream`](superstream::state::Stream) struct.
+    pub fn withdraw(ctx: Context<Withdraw>, name: String, sender: Pubkey) -> Result<u64> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_program = ctx.accounts.superstream_program.to_account_info();
+        let cpi_accounts = superstream::cpi::accounts::Withdraw {
+            stream: ctx.accounts.stream.to_account_info(),
+            activity: ctx.accounts.activity.to_account_info(),
+            position: ctx.accounts.position.to_account_info(),
+            signer: ctx.accounts.authority.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            recipient_token: ctx.accounts.authority_token.to_account_info(),
+            escrow_token: ctx.accounts.escrow_token.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        let amount = superstream::cpi::withdraw(cpi_ctx, name, ctx.accounts.authority.key(), sender)?.get();
+        msg!("Withdrawn {} to the authority", amount);
+        Ok(amount)
+    }
+
+    /// Pause a non-prepaid stream with the authority as the sender or the recipient.
+    ///
+    /// For more information on the arguments, see fields of the [`Stream`](superstream::state::Stream) struct.
+    pub fn pause_non_prepaid(ctx: Context<PauseOrResume>, seed: u64, name: String) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_ctx = ctx.accounts.pause_ctx().with_signer(signer);
+
+        superstream::cpi::pause_non_prepaid(cpi_ctx, seed, name)
+    }
+
+    /// Resume a non-prepaid stream with the authority as the sender or the recipient.
+    ///
+    /// For more information on the arguments, see fields of the [`Stream`](superstream::state::Stream) struct.
+    pub fn resume_non_prepaid(ctx: Context<PauseOrResume>, seed: u64, name: String) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_ctx = ctx.accounts.resume_ctx().with_signer(signer);
+
+        superstream::cpi::resume_non_prepaid(cpi_ctx, seed, name)
+    }
+
+    /// Cancel a stream with the authority as the signer.
+    ///
+    /// The settled amounts are set as the return data of the instruction.
+    ///
+    /// For more information on the arguments, see fields of the [`Stream`](superstream::state::Stream) struct.
+    pub fn cancel(ctx: Context<Cancel>, seed: u64, name: String, recipient: Pubkey) -> Result<CancelTransferParams> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_program = ctx.accounts.superstream_program.to_account_info();
+        let cpi_accounts = superstream::cpi::accounts::Cancel {
+            stream: ctx.accounts.stream.to_account_info(),
+            activity: ctx.accounts.activity.to_account_info(),
+            position: ctx.accounts.position.to_account_info(),
+            signer: ctx.accounts.authority.to_account_info(),
+            sender: ctx.accounts.sender.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            signer_token: ctx.accounts.authority_token.to_account_info(),
+            sender_token: ctx.accounts.sender_token.to_account_info(),
+            recipient_token: ctx.accounts.recipient_token.to_account_info(),
+            escrow_token: ctx.accounts.escrow_token.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        let settled = superstream::cpi::cancel(cpi_ctx, seed, name, recipient)?.get();
+        msg!(
+            "Settled {} to the sender, {} to the signer and {} to the recipient",
+            settled.transfer_amount_sender,
+            settled.transfer_amount_signer,
+            settled.transfer_amount_recipient,
+        );
+        Ok(settled)
+    }
+
+    /// Claim distributor rewards with the authority as the claimer.
+    ///
+    /// The claimed amount is set as the return data of the instruction.
+    ///
+    /// For more information on the arguments, see [`superstream::superstream::claim`].
+    pub fn claim(ctx: Context<Claim>, index: u64, amount: u64, leaf: [u8; 32], proof: Vec<[u8; 32]>) -> Result<u64> {
+        let owner = ctx.accounts.owner.key();
+        let bump = [*ctx.bumps.get("authority").unwrap()];
+        let seeds = authority_seeds(&owner, &bump);
+        let signer = &[&seeds[..]];
+        let cpi_program = ctx.accounts.superstream_program.to_account_info();
+        let cpi_accounts = superstream::cpi::accounts::Claim {
+            distributor: ctx.accounts.distributor.to_account_info(),
+            escrow_token: ctx.accounts.escrow_token.to_account_info(),
+            recipent_token: ctx.accounts.authority_token.to_account_info(),
+            claimer: ctx.accounts.authority.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            status: ctx.accounts.status.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            payer: ctx.accounts.authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        let claimed = superstream::cpi::claim(cpi_ctx, index, amount, leaf, proof)?.get();
+        msg!("Claimed {} to the authority", claimed);
+        Ok(claimed)
+    }
+}
+
+fn authority_seeds<'a>(owner: &'a Pubkey, bump: &'a [u8; 1]) -> [&'a [u8]; 3] {
+    [AUTHORITY_ACCOUNT_SEED, owner.as_ref(), bump]
+}
+
+/// Accounts struct for creating a new stream.
+#[derive(Accounts)]
+pub struct Create<'info> {
+    /// Owner wallet of the authority.
+    pub owner: Signer<'info>,
+    /// Authority PDA account. It is the sender of the stream and pays for the stream account.
+    #[account(
+        mut,
+        seeds = [AUTHORITY_ACCOUNT_SEED, owner.key().as_ref()],
+        bump,
+    )]
+    pub authority: SystemAccount<'info>,
+
+    /// Stream PDA account. This is initialized by Superstream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub stream: UncheckedAccount<'info>,
+    /// Activity account of the stream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub activity: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the authority.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub authority_token: UncheckedAccount<'info>,
+    /// Associated token escrow account holding the funds for this stream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub escrow_token: UncheckedAccount<'info>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
+    /// Superstream program.
+    pub superstream_program: Program<'info, Superstream>,
+
+    /// Stream registry PDA account of the authority. Created by Superstream on first use.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub sender_registry: UncheckedAccount<'info>,
+    /// Next page of the stream registry of the authority. Created by Superstream on first use.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub sender_registry_page: UncheckedAccount<'info>,
+    /// Stream registry PDA account of the recipient. Created by Superstream on first use.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub recipient_registry: UncheckedAccount<'info>,
+    /// Next page of the stream registry of the recipient. Created by Superstream on first use.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub recipient_registry_page: UncheckedAccount<'info>,
+}
+
+/// Accounts struct for topping up a non-prepaid stream.
+#[derive(Accounts)]
+pub struct TopupNonPrepaid<'info> {
+    /// Owner wallet of the authority.
+    pub owner: Signer<'info>,
+    /// Authority PDA account. It funds the topup.
+    #[account(
+        seeds = [AUTHORITY_ACCOUNT_SEED, owner.key().as_ref()],
+        bump,
+    )]
+    pub authority: SystemAccount<'info>,
+
+    /// Stream PDA account.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub stream: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the authority.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub authority_token: UncheckedAccount<'info>,
+    /// Associated token escrow account holding the funds for this stream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub escrow_token: UncheckedAccount<'info>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Superstream program.
+    pub superstream_program: Program<'info, Superstream>,
+}
+
+/// Accounts struct for withdrawing funds from a stream.
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    /// Owner wallet of the authority.
+    pub owner: Signer<'info>,
+    /// Authority PDA account. It is the recipient of the stream.
+    #[account(
+        seeds = [AUTHORITY_ACCOUNT_SEED, owner.key().as_ref()],
+        bump,
+    )]
+    pub authority: SystemAccount<'info>,
+
+    /// Stream PDA account.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub stream: UncheckedAccount<'info>,
+    /// Activity account of the stream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub activity: UncheckedAccount<'info>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub position: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the authority.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub authority_token: UncheckedAccount<'info>,
+    /// Associated token escrow account holding the funds for this stream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub escrow_token: UncheckedAccount<'info>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Superstream program.
+    pub superstream_program: Program<'info, Superstream>,
+}
+
+/// Accounts struct for pausing or resuming a non-prepaid stream.
+#[derive(Accounts)]
+pub struct PauseOrResume<'info> {
+    /// Owner wallet of the authority.
+    pub owner: Signer<'info>,
+    /// Authority PDA account. It is either the sender or the recipient of the stream.
+    #[account(
+        seeds = [AUTHORITY_ACCOUNT_SEED, owner.key().as_ref()],
+        bump,
+    )]
+    pub authority: SystemAccount<'info>,
+
+    /// Stream PDA account.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub stream: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Superstream program.
+    pub superstream_program: Program<'info, Superstream>,
+}
+
+/// Accounts struct for cancelling a stream.
+#[derive(Accounts)]
+pub struct Cancel<'info> {
+    /// Owner wallet of the authority.
+    pub owner: Signer<'info>,
+    /// Authority PDA account. It is either the sender or the recipient of the stream.
+    #[account(
+        seeds = [AUTHORITY_ACCOUNT_SEED, owner.key().as_ref()],
+        bump,
+    )]
+    pub authority: SystemAccount<'info>,
+
+    /// Stream PDA account.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub stream: UncheckedAccount<'info>,
+    /// Activity account of the stream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub activity: UncheckedAccount<'info>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub position: UncheckedAccount<'info>,
+    /// Stream sender account.
+    ///
+    /// CHECK: Checked by Superstream.
+    pub sender: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the authority.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub authority_token: UncheckedAccount<'info>,
+    /// Associated token account of the sender.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub sender_token: UncheckedAccount<'info>,
+    /// Associated token account of the recipient.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub recipient_token: UncheckedAccount<'info>,
+    /// Associated token escrow account holding the funds for this stream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub escrow_token: UncheckedAccount<'info>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Superstream program.
+    pub superstream_program: Program<'info, Superstream>,
+}
+
+/// Accounts struct for claiming distributor rewards.
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    /// Owner wallet of the authority.
+    pub owner: Signer<'info>,
+    /// Authority PDA account. It is the claimer and pays for the claim status account.
+    #[account(
+        mut,
+        seeds = [AUTHORITY_ACCOUNT_SEED, owner.key().as_ref()],
+        bump,
+    )]
+    pub authority: SystemAccount<'info>,
+
+    /// Distributor PDA account.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub distributor: UncheckedAccount<'info>,
+    /// Claim status PDA account. This is initialized by Superstream.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub status: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the authority.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub authority_token: UncheckedAccount<'info>,
+    /// Associated token escrow account holding the rewards of the distributor.
+    ///
+    /// CHECK: Checked by Superstream.
+    #[account(mut)]
+    pub escrow_token: UncheckedAccount<'info>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
+    /// Superstream program.
+    pub superstream_program: Program<'info, Superstream>,
+}
+
+impl<'info> Create<'info> {
+    /// Superstream CPI context for creating a stream.
+    pub fn create_ctx(&self) -> CpiContext<'_, '_, '_, 'info, superstream::cpi::accounts::CreateV2<'info>> {
+        let cpi_program = self.superstream_program.to_account_info();
+        let cpi_accounts = superstream::cpi::accounts::CreateV2 {
+            stream: self.stream.to_account_info(),
+            activity: self.activity.to_account_info(),
+            sender: self.authority.to_account_info(),
+            mint: self.mint.to_account_info(),
+            sender_token: self.authority_token.to_account_info(),
+            escrow_token: self.escrow_token.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            sender_registry: self.sender_registry.to_account_info(),
+            sender_registry_page: self.sender_registry_page.to_account_info(),
+            recipient_registry: self.recipient_registry.to_account_info(),
+            recipient_registry_page: self.recipient_registry_page.to_account_info(),
+            beneficiary: self.authority.to_account_info(),
+            payer: self.authority.to_account_info(),
+        };
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
+
+impl<'info> PauseOrResume<'info> {
+    /// Superstream CPI context for pausing a stream.
+    pub fn pause_ctx(&self) -> CpiContext<'_, '_, '_, 'info, superstream::cpi::accounts::PauseNonPrepaid<'info>> {
+        let cpi_program = self.superstream_program.to_account_info();
+        let cpi_accounts = superstream::cpi::accounts::PauseNonPrepaid {
+            stream: self.stream.to_account_info(),
+            signer: self.authority.to_account_info(),
+            mint: self.mint.to_account_info(),
+        };
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+
+    /// Superstream CPI context for resuming a stream.
+    pub fn resume_ctx(&self) -> CpiContext<'_, '_, '_, 'info, superstream::cpi::accounts::ResumeNonPrepaid<'info>> {
+        let cpi_program = self.superstream_program.to_account_info();
+        let cpi_accounts = superstream::cpi::accounts::ResumeNonPrepaid {
+            stream: self.stream.to_account_info(),
+            signer: self.authority.to_account_info(),
+            mint: self.mint.to_account_info(),
+        };
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
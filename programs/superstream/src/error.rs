@@ -2,6 +2,8 @@
 
 use anchor_lang::prelude::*;
 
+use crate::stream_math::MathError;
+
 /// Enumeration of possible stream errors.
 #[error_code]
 pub enum StreamError {
@@ -151,4 +153,121 @@ pub enum StreamError {
 
     #[msg("Distributor not expire yet")]
     DistributorNotExpire,
+
+    /// The manager is invalid. Should be different from the sender and the recipient.
+    #[msg("The manager is invalid. Should be different from the sender and the recipient")]
+    InvalidManager,
+    /// The stream already has a requested cancellation.
+    #[msg("The stream already has a requested cancellation")]
+    CancellationAlreadyRequested,
+    /// The notice period is not over. Should request the cancellation and wait for the notice period to be over.
+    #[msg("The notice period is not over. Should request the cancellation and wait for the notice period to be over")]
+    NoticePeriodNotOver,
+    /// The stream is not funded till the end of the notice period. Should topup the stream first.
+    #[msg("The stream is not funded till the end of the notice period. Should topup the stream first")]
+    NoticePeriodNotFunded,
+    /// The user is not allowed to migrate the stream. Should be the sender or the recipient of the stream.
+    #[msg("The user is not allowed to migrate the stream. Should be the sender or the recipient of the stream")]
+    UserUnauthorizedToMigrate,
+    /// The user is not allowed to close the stream. Should be the sender or the recipient of the stream.
+    #[msg("The user is not allowed to close the stream. Should be the sender or the recipient of the stream")]
+    UserUnauthorizedToClose,
+    /// The stream has not stopped. Should be cancelled or ended.
+    #[msg("The stream has not stopped. Should be cancelled or ended")]
+    StreamHasNotStopped,
+    /// The escrow account still holds funds. Should withdraw all the funds first.
+    #[msg("The escrow account still holds funds. Should withdraw all the funds first")]
+    EscrowNotEmpty,
+    /// The user is not allowed to close the distributor. Should be the creator of the distributor.
+    #[msg("The user is not allowed to close the distributor. Should be the creator of the distributor")]
+    UserUnauthorizedToCloseDistributor,
+    /// The distributor has not been closed. Claim statuses can only be closed after their distributor.
+    #[msg("The distributor has not been closed. Claim statuses can only be closed after their distributor")]
+    DistributorNotClosed,
+    /// The user is not allowed to manage the activity. Should be the creator of the activity.
+    #[msg("The user is not allowed to manage the activity. Should be the creator of the activity")]
+    UserUnauthorizedToManageActivity,
+    /// The activity has been deactivated. Should be an active activity.
+    #[msg("The activity has been deactivated. Should be an active activity")]
+    ActivityNotActive,
+    /// The rewards expire before the activity ends. Should be >= ends_at.
+    #[msg("The rewards expire before the activity ends. Should be >= ends_at")]
+    InvalidRewardExpiresAt,
+    /// The activity still has open streams, distributors or stake positions. Should close them first.
+    #[msg("The activity still has open streams, distributors or stake positions. Should close them first")]
+    ActivityInUse,
+    /// The activity has not started yet. Should wait for the start time of the activity.
+    #[msg("The activity has not started yet. Should wait for the start time of the activity")]
+    ActivityNotStarted,
+    /// The stake amount is less than the minimum amount of the activity.
+    #[msg("The stake amount is less than the minimum amount of the activity")]
+    StakeAmountBelowMinimum,
+    /// The stake would exceed the maximum amount staked in the activity.
+    #[msg("The stake would exceed the maximum amount staked in the activity")]
+    ActivityStakeCapExceeded,
+    /// The stake would exceed the maximum amount staked by a single wallet in the activity.
+    #[msg("The stake would exceed the maximum amount staked by a single wallet in the activity")]
+    WalletStakeCapExceeded,
+    /// The rewards of the activity are out of bounds.
+    #[msg("The rewards of the activity are out of bounds")]
+    RewardAmountOutOfBounds,
+    /// The lock multipliers are invalid. Durations and multipliers should be > 0 and durations increasing.
+    #[msg("The lock multipliers are invalid. Durations and multipliers should be > 0 and durations increasing")]
+    InvalidLockMultipliers,
+    /// The lock duration is 0 or not one of the lock durations of the activity.
+    #[msg("The lock duration is 0 or not one of the lock durations of the activity")]
+    LockDurationNotAllowed,
+    /// The early unstake penalty is invalid. Should be <= 10000 basis points, with a treasury unless the stake and
+    /// reward mints are the same.
+    #[msg("The early unstake penalty is invalid. Should be <= 10000 basis points, with a treasury unless the stake and reward mints are the same")]
+    InvalidEarlyUnstakePenalty,
+    /// The stream is not a stake of an activity.
+    #[msg("The stream is not a stake of an activity")]
+    StreamIsNotStake,
+    /// The stake has already ended. Should withdraw it instead.
+    #[msg("The stake has already ended. Should withdraw it instead")]
+    StakeNotLocked,
+    /// The staker is not in the allowlist of the activity. Should provide a valid merkle proof.
+    #[msg("The staker is not in the allowlist of the activity. Should provide a valid merkle proof")]
+    StakerNotAllowed,
+    /// The creator does not match the stream PDA. Should provide the sender at stream creation.
+    #[msg("The creator does not match the stream PDA. Should provide the sender at stream creation")]
+    InvalidStreamCreator,
+    /// The stream, distributor or stake position count of the activity is out of bounds.
+    #[msg("The stream, distributor or stake position count of the activity is out of bounds")]
+    ActivityCountOutOfBounds,
+    /// The time the cancellation takes effect, i.e. the request time plus the notice period, is out of bounds.
+    #[msg("The cancellation time of the stream is out of bounds")]
+    CancellationScheduledAtOutOfBounds,
+    /// The amount returned to the staker by an early unstake, i.e. the stake minus the penalty, is out of bounds.
+    #[msg("The amount returned by the early unstake is out of bounds")]
+    UnstakeAmountOutOfBounds,
+    /// The flow rate of the activity is invalid. Should be > 0 and the rewards emitted between the start and end times
+    /// should fit in u64.
+    #[msg("The flow rate of the activity is invalid. Should be > 0 and the rewards emitted between the start and end times should fit in u64")]
+    InvalidActivityFlowRate,
+    /// The stream registry page is invalid. Should be the next page of the registry given by its stream count.
+    #[msg("The stream registry page is invalid. Should be the next page of the registry given by its stream count")]
+    InvalidRegistryPage,
+    /// The activity was migrated from the original layout and may still be used by streams or distributors which are
+    /// not counted, so it cannot be closed.
+    #[msg("The activity was migrated from the original layout and may still be used by streams or distributors which are not counted, so it cannot be closed")]
+    MigratedActivityNotClosable,
+    /// The user is not allowed to close the stake position. Should be the wallet of the position.
+    #[msg("The user is not allowed to close the stake position. Should be the wallet of the position")]
+    UserUnauthorizedToClosePosition,
+    /// The stake position still holds a stake or unclaimed rewards. Should unstake and claim the rewards first.
+    #[msg("The stake position still holds a stake or unclaimed rewards. Should unstake and claim the rewards first")]
+    PositionNotEmpty,
+}
+
+impl From<MathError> for StreamError {
+    fn from(error: MathError) -> Self {
+        match error {
+            MathError::PrepaidAmountNeeded => StreamError::PrepaidAmountNeededOutOfBounds,
+            MathError::DepositAmountNeeded => StreamError::DepositAmountNeededOutOfBounds,
+            MathError::AmountAvailableToWithdraw => StreamError::AmountAvailableToWithdrawOutOfBounds,
+            MathError::TopupAmount => StreamError::TopupAmountOutOfBounds,
+        }
+    }
 }
@@ -0,0 +1,433 @@
+//! Module for reading stream and activity accounts written with the original account layouts. See [`LegacyStream`]
+//! and [`LegacyActivity`].
+
+use anchor_lang::{prelude::*, Discriminator};
+
+use crate::{
+    error::StreamError,
+    state::{
+        AccrualMode, Activity, ManagerPermissions, Stream, ACTIVITY_VERSION, MAX_STREAM_NAME_LENGTH, STREAM_VERSION,
+    },
+    STREAM_ACCOUNT_SEED,
+};
+
+/// Offset of [`Stream::version`] in a stream account. The original layout has reserved space here, which is all
+/// zeroes.
+const STREAM_VERSION_OFFSET: usize = 323;
+
+/// Maximum length of an activity account in the original layout. Activities in the current layout are always longer.
+const LEGACY_ACTIVITY_MAX_LENGTH: usize = 205 + 4 + MAX_STREAM_NAME_LENGTH;
+
+/// Stream account in the original layout, i.e. before [`Stream::version`] existed. This is frozen and should never
+/// change: it is only used to read the streams created before the fields after `activity` were added, which moved
+/// `name` and broke deserializing them as a [`Stream`].
+///
+/// For more information on the fields, see fields of the [`Stream`] struct.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LegacyStream {
+    pub is_prepaid: bool,
+
+    pub mint: Pubkey,
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+
+    pub created_at: u64,
+    pub starts_at: u64,
+    pub ends_at: u64,
+
+    pub initial_amount: u64,
+    pub flow_interval: u64,
+    pub flow_rate: u64,
+
+    pub is_cancelled: bool,
+    pub is_cancelled_before_start: bool,
+    pub is_cancelled_by_sender: bool,
+
+    pub cancelled_at: u64,
+
+    pub sender_can_cancel: bool,
+    pub sender_can_cancel_at: u64,
+
+    pub sender_can_change_sender: bool,
+    pub sender_can_change_sender_at: u64,
+
+    pub is_paused: bool,
+    pub is_paused_by_sender: bool,
+
+    pub sender_can_pause: bool,
+    pub sender_can_pause_at: u64,
+
+    pub recipient_can_resume_pause_by_sender: bool,
+    pub recipient_can_resume_pause_by_sender_at: u64,
+
+    pub anyone_can_withdraw_for_recipient: bool,
+    pub anyone_can_withdraw_for_recipient_at: u64,
+
+    pub last_resumed_at: u64,
+    pub accumulated_active_time: u64,
+
+    pub total_withdrawn_amount: u64,
+    pub last_withdrawn_at: u64,
+    pub last_withdrawn_amount: u64,
+
+    pub total_topup_amount: u64,
+    pub last_topup_at: u64,
+    pub last_topup_amount: u64,
+
+    pub deposit_needed: u64,
+
+    pub reserved: [u64; 16],
+
+    pub seed: u64,
+    pub bump: u8,
+
+    pub activity: Pubkey,
+
+    pub name: String,
+}
+
+impl LegacyStream {
+    /// Convert to a [`Stream`] created by `creator`. The fields which did not exist in the original layout get the
+    /// values of a stream created without them: no manager, no notice period, continuous accrual, and not a stake,
+    /// since streams created before stakes were counted are not included in the totals of their activity.
+    pub fn into_stream(self, creator: Pubkey) -> Stream {
+        Stream {
+            is_prepaid: self.is_prepaid,
+            mint: self.mint,
+            sender: self.sender,
+            recipient: self.recipient,
+            created_at: self.created_at,
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+            initial_amount: self.initial_amount,
+            flow_interval: self.flow_interval,
+            flow_rate: self.flow_rate,
+            is_cancelled: self.is_cancelled,
+            is_cancelled_before_start: self.is_cancelled_before_start,
+            is_cancelled_by_sender: self.is_cancelled_by_sender,
+            cancelled_at: self.cancelled_at,
+            sender_can_cancel: self.sender_can_cancel,
+            sender_can_cancel_at: self.sender_can_cancel_at,
+            sender_can_change_sender: self.sender_can_change_sender,
+            sender_can_change_sender_at: self.sender_can_change_sender_at,
+            is_paused: self.is_paused,
+            is_paused_by_sender: self.is_paused_by_sender,
+            sender_can_pause: self.sender_can_pause,
+            sender_can_pause_at: self.sender_can_pause_at,
+            recipient_can_resume_pause_by_sender: self.recipient_can_resume_pause_by_sender,
+            recipient_can_resume_pause_by_sender_at: self.recipient_can_resume_pause_by_sender_at,
+            anyone_can_withdraw_for_recipient: self.anyone_can_withdraw_for_recipient,
+            anyone_can_withdraw_for_recipient_at: self.anyone_can_withdraw_for_recipient_at,
+            last_resumed_at: self.last_resumed_at,
+            accumulated_active_time: self.accumulated_active_time,
+            total_withdrawn_amount: self.total_withdrawn_amount,
+            last_withdrawn_at: self.last_withdrawn_at,
+            last_withdrawn_amount: self.last_withdrawn_amount,
+            total_topup_amount: self.total_topup_amount,
+            last_topup_at: self.last_topup_at,
+            last_topup_amount: self.last_topup_amount,
+            deposit_needed: self.deposit_needed,
+            stake_weight: 0,
+            beneficiary: Pubkey::default(),
+            version: STREAM_VERSION,
+            reserved: [0; 10],
+            seed: self.seed,
+            bump: self.bump,
+            activity: self.activity,
+            manager: Pubkey::default(),
+            manager_permissions: ManagerPermissions::default(),
+            notice_period: 0,
+            cancellation_scheduled_at: 0,
+            is_cancellation_requested_by_sender: false,
+            creator,
+            accrual_mode: AccrualMode::Continuous,
+            rent_payer: creator,
+            is_stake: false,
+            name: self.name,
+        }
+    }
+}
+
+/// Activity account in the original layout, i.e. before [`Activity::version`] existed. This is frozen and should never
+/// change: it is only used to read the activities created before the stake and reward fields were added, which moved
+/// `name` and broke deserializing them as an [`Activity`].
+///
+/// For more information on the fields, see fields of the [`Activity`] struct.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LegacyActivity {
+    pub is_active: bool,
+
+    pub creator: Pubkey,
+    pub stake_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub opt_reward_mint: Pubkey,
+
+    pub created_at: u64,
+    pub starts_at: u64,
+    pub ends_at: u64,
+
+    pub reward_expires_at: u64,
+
+    pub min_amount: u64,
+
+    pub duration: u64,
+
+    pub flow_rate: u64,
+
+    pub seed: u64,
+    pub bump: u8,
+
+    pub name: String,
+}
+
+impl LegacyActivity {
+    /// Deserialize an activity account in the original layout, including the discriminator.
+    pub fn try_deserialize(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() >= 8 && data[..8] == Activity::discriminator(),
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+        Self::deserialize(&mut &data[8..]).map_err(|_| error!(ErrorCode::AccountDidNotDeserialize))
+    }
+
+    /// Convert to an [`Activity`]. The fields which did not exist in the original layout get the values of a new
+    /// activity: no stakes, rewards, caps, lock multipliers, early unstake penalty or allowlist. Streams and
+    /// distributors created before they were counted are not included in `stream_count` and `distributor_count`, so the
+    /// activity is marked as migrated, which keeps it from being closed.
+    pub fn into_activity(self) -> Activity {
+        Activity {
+            is_active: self.is_active,
+            creator: self.creator,
+            stake_mint: self.stake_mint,
+            reward_mint: self.reward_mint,
+            opt_reward_mint: self.opt_reward_mint,
+            created_at: self.created_at,
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+            reward_expires_at: self.reward_expires_at,
+            min_amount: self.min_amount,
+            duration: self.duration,
+            flow_rate: self.flow_rate,
+            is_migrated: true,
+            version: ACTIVITY_VERSION,
+            seed: self.seed,
+            bump: self.bump,
+            name: self.name,
+            ..Activity::default()
+        }
+    }
+}
+
+/// Check if the activity account with data `data` was written with the original layout.
+pub(crate) fn is_legacy_activity(data: &[u8]) -> bool {
+    data.len() <= LEGACY_ACTIVITY_MAX_LENGTH
+}
+
+/// Check if the stream account with data `data` was written with the original layout.
+pub(crate) fn is_legacy_stream(data: &[u8]) -> bool {
+    data.get(STREAM_VERSION_OFFSET..STREAM_VERSION_OFFSET + 8) == Some(&[0; 8][..])
+}
+
+/// Read the stream account `stream` with data `data`, written with either the current or the original layout.
+///
+/// The original layout does not store the creator of the stream, which is part of the stream PDA seeds. For such
+/// streams, the creator is `creator` if provided, or else the current sender, and is checked against the stream PDA.
+/// `creator` is ignored for streams in the current layout.
+pub(crate) fn read_stream(stream: &Pubkey, data: &[u8], creator: Option<Pubkey>) -> Result<Stream> {
+    if !is_legacy_stream(data) {
+        return Stream::try_deserialize(&mut &data[..]);
+    }
+
+    if data.len() < 8 || data[..8] != Stream::discriminator() {
+        return err!(ErrorCode::AccountDiscriminatorMismatch);
+    }
+    let legacy_stream = LegacyStream::deserialize(&mut &data[8..]).map_err(|_| ErrorCode::AccountDidNotDeserialize)?;
+    let creator = creator.unwrap_or(legacy_stream.sender);
+    let expected_stream = Pubkey::create_program_address(
+        &[
+            STREAM_ACCOUNT_SEED,
+            legacy_stream.activity.as_ref(),
+            legacy_stream.mint.as_ref(),
+            creator.as_ref(),
+            legacy_stream.name.as_bytes(),
+            &[legacy_stream.bump],
+        ],
+        &crate::ID,
+    );
+    require!(expected_stream == Ok(*stream), StreamError::InvalidStreamCreator);
+    Ok(legacy_stream.into_stream(creator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_stream(creator: Pubkey) -> (Pubkey, LegacyStream) {
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        let name = "legacy stream".to_string();
+        let (stream, bump) = Pubkey::find_program_address(
+            &[
+                STREAM_ACCOUNT_SEED,
+                key(4).as_ref(),
+                key(1).as_ref(),
+                creator.as_ref(),
+                name.as_bytes(),
+            ],
+            &crate::ID,
+        );
+        let legacy_stream = LegacyStream {
+            is_prepaid: false,
+            mint: key(1),
+            sender: key(2),
+            recipient: key(3),
+            created_at: 1,
+            starts_at: 2,
+            ends_at: 3,
+            initial_amount: 4,
+            flow_interval: 5,
+            flow_rate: 6,
+            is_cancelled: false,
+            is_cancelled_before_start: false,
+            is_cancelled_by_sender: false,
+            cancelled_at: 0,
+            sender_can_cancel: true,
+            sender_can_cancel_at: 8,
+            sender_can_change_sender: true,
+            sender_can_change_sender_at: 9,
+            is_paused: true,
+            is_paused_by_sender: true,
+            sender_can_pause: true,
+            sender_can_pause_at: 10,
+            recipient_can_resume_pause_by_sender: false,
+            recipient_can_resume_pause_by_sender_at: 11,
+            anyone_can_withdraw_for_recipient: true,
+            anyone_can_withdraw_for_recipient_at: 12,
+            last_resumed_at: 13,
+            accumulated_active_time: 14,
+            total_withdrawn_amount: 15,
+            last_withdrawn_at: 16,
+            last_withdrawn_amount: 17,
+            total_topup_amount: 18,
+            last_topup_at: 19,
+            last_topup_amount: 20,
+            deposit_needed: 21,
+            reserved: [0; 16],
+            seed: 23,
+            bump,
+            activity: key(4),
+            name,
+        };
+        (stream, legacy_stream)
+    }
+
+    fn account_data(legacy_stream: &LegacyStream) -> Vec<u8> {
+        let mut data = Stream::discriminator().to_vec();
+        legacy_stream.serialize(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn legacy_stream_has_the_original_layout() {
+        let (_, legacy_stream) = legacy_stream(Pubkey::new_from_array([2; 32]));
+        let data = account_data(&legacy_stream);
+
+        // Original `Stream::space` was 8 + 448 + 4 + name.len(), with `activity` ending at 452.
+        assert_eq!(data.len(), 456 + legacy_stream.name.len());
+        assert_eq!(&data[420..452], legacy_stream.activity.as_ref());
+        assert_eq!(&data[456..], legacy_stream.name.as_bytes());
+    }
+
+    #[test]
+    fn read_stream_migrates_legacy_stream() {
+        let sender = Pubkey::new_from_array([2; 32]);
+        let (stream_key, legacy_stream) = legacy_stream(sender);
+        let data = account_data(&legacy_stream);
+
+        let stream = read_stream(&stream_key, &data, None).unwrap();
+        assert_eq!(stream, legacy_stream.clone().into_stream(sender));
+        assert_eq!(stream.version, STREAM_VERSION);
+        assert_eq!(stream.creator, sender);
+        assert_eq!(stream.rent_payer, sender);
+        assert_eq!(stream.name, legacy_stream.name);
+        assert_eq!(stream.total_topup_amount, legacy_stream.total_topup_amount);
+        assert!(!stream.is_stake);
+        assert_eq!(read_stream(&stream_key, &data, Some(sender)).unwrap(), stream);
+    }
+
+    #[test]
+    fn read_stream_migrates_legacy_stream_with_changed_sender() {
+        let creator = Pubkey::new_from_array([9; 32]);
+        let (stream_key, legacy_stream) = legacy_stream(creator);
+        let data = account_data(&legacy_stream);
+
+        assert_eq!(
+            read_stream(&stream_key, &data, None).unwrap_err(),
+            error!(StreamError::InvalidStreamCreator)
+        );
+        let stream = read_stream(&stream_key, &data, Some(creator)).unwrap();
+        assert_eq!(stream.creator, creator);
+        assert_eq!(stream.sender, legacy_stream.sender);
+    }
+
+    #[test]
+    fn legacy_activity_is_migrated() {
+        let legacy_activity = LegacyActivity {
+            is_active: true,
+            creator: Pubkey::new_from_array([1; 32]),
+            stake_mint: Pubkey::new_from_array([2; 32]),
+            reward_mint: Pubkey::new_from_array([3; 32]),
+            opt_reward_mint: Pubkey::new_from_array([4; 32]),
+            created_at: 1,
+            starts_at: 2,
+            ends_at: 3,
+            reward_expires_at: 4,
+            min_amount: 5,
+            duration: 6,
+            flow_rate: 7,
+            seed: 8,
+            bump: 9,
+            name: "a".repeat(MAX_STREAM_NAME_LENGTH),
+        };
+        let mut data = Activity::discriminator().to_vec();
+        legacy_activity.serialize(&mut data).unwrap();
+
+        // Original `Activity::space` was 205 + 4 + name.len(), 3 bytes more than the serialized activity.
+        assert_eq!(data.len() + 3, LEGACY_ACTIVITY_MAX_LENGTH);
+        data.resize(LEGACY_ACTIVITY_MAX_LENGTH, 0);
+        assert!(is_legacy_activity(&data));
+        let activity = LegacyActivity::try_deserialize(&data).unwrap().into_activity();
+        assert_eq!(activity.version, ACTIVITY_VERSION);
+        assert_eq!(activity.creator, legacy_activity.creator);
+        assert_eq!(activity.duration, legacy_activity.duration);
+        assert_eq!(activity.flow_rate, legacy_activity.flow_rate);
+        assert_eq!(activity.bump, legacy_activity.bump);
+        assert_eq!(activity.name, legacy_activity.name);
+        assert_eq!(activity.stream_count, 0);
+        assert!(activity.is_migrated);
+
+        let mut data = Vec::new();
+        Activity {
+            name: "ab".to_string(),
+            ..Activity::default()
+        }
+        .try_serialize(&mut data)
+        .unwrap();
+        assert!(!is_legacy_activity(&data));
+        assert!(LegacyActivity::try_deserialize(&Stream::discriminator()).is_err());
+    }
+
+    #[test]
+    fn read_stream_reads_current_stream() {
+        let sender = Pubkey::new_from_array([2; 32]);
+        let (stream_key, legacy_stream) = legacy_stream(sender);
+        let stream = legacy_stream.into_stream(sender);
+        let mut data = Vec::new();
+        stream.try_serialize(&mut data).unwrap();
+
+        assert_eq!(read_stream(&stream_key, &data, None).unwrap(), stream);
+        assert_eq!(
+            read_stream(&stream_key, &data, Some(Pubkey::default())).unwrap(),
+            stream
+        );
+    }
+}
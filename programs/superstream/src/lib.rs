@@ -27,9 +27,11 @@
 //!         let cpi_program = ctx.accounts.superstream_program.to_account_info();
 //!         let cpi_accounts = superstream::cpi::accounts::Cancel {
 //!             stream: ctx.accounts.stream.to_account_info(),
+//!             activity: ctx.accounts.activity.to_account_info(),
+//!             position: ctx.accounts.position.to_account_info(),
 //!             signer: ctx.accounts.signer.to_account_info(),
 //!             sender: ctx.accounts.sender.to_account_info(),
-//!             mint: ctx.accounts.sender.to_account_info(),
+//!             mint: ctx.accounts.mint.to_account_info(),
 //!             signer_token: ctx.accounts.signer_token.to_account_info(),
 //!             sender_token: ctx.accounts.sender_token.to_account_info(),
 //!             recipient_token: ctx.accounts.recipient_token.to_account_info(),
@@ -38,7 +40,11 @@
 //!         };
 //!         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
 //!
-//!         superstream::cpi::cancel(cpi_ctx, seed, name, recipient)
+//!         // The settled amounts are returned through the return data.
+//!         let settled = superstream::cpi::cancel(cpi_ctx, seed, name, recipient)?.get();
+//!         msg!("Refunded {} to the sender", settled.transfer_amount_sender);
+//!
+//!         Ok(())
 //!     }
 //!
 //!     // ... other stuff
@@ -50,6 +56,12 @@
 //!     /// Stream PDA account.
 //!     #[account(mut)]
 //!     pub stream: AccountInfo<'info>,
+//!     /// Activity PDA account of the stream.
+//!     #[account(mut)]
+//!     pub activity: AccountInfo<'info>,
+//!     /// Stake position PDA account of the staker in the activity.
+//!     #[account(mut)]
+//!     pub position: AccountInfo<'info>,
 //!
 //!     /// Signer wallet.
 //!     pub signer: Signer<'info>,
@@ -81,23 +93,59 @@
 //!
 //! // ... other stuff
 //! ```
+//!
+//! ## Off-chain stream math
+//!
+//! The stream accounting lives in the `superstream-math` crate, which has no dependencies and is re-exported as the
+//! [`stream_math`] module. Off-chain services can depend on it alone to compute the amounts of a stream at any time
+//! with the same code the program runs.
+//!
+//! ```toml ignore
+//! superstream-math = "0.2.0"
+//! ```
+//!
+//! ```rust ignore
+//! let amount_owed = stream.terms().amount_owed(at)?;
+//! ```
 
+mod legacy;
+mod position;
+mod registry;
 mod transfer;
 mod utils;
 
 pub mod error;
 pub mod state;
+pub use superstream_math as stream_math;
+
+use std::collections::BTreeMap;
 
-use anchor_lang::prelude::*;
+use anchor_lang::{
+    prelude::*,
+    solana_program::{hash::hash, pubkey::MAX_SEED_LEN},
+    system_program, Discriminator,
+};
 use anchor_spl::token::{self, TokenAccount, Mint, Token };
 use state::Activity;
 use state::Distributor;
+use state::StakePosition;
 use state::Status;
+use state::StreamRegistry;
+use state::StreamRegistryPage;
 
 use crate::{
     error::StreamError,
-    state::Stream,
-    transfer::{transfer_from_escrow, transfer_to_escrow},
+    legacy::LegacyActivity,
+    position::{record_early_unstake, record_stake, record_unstake},
+    registry::register_stream,
+    state::{
+        AccrualMode, ActivityTotals, CancelTransferParams, CreateStreamParams, LockMultiplier, ManagerPermissions,
+        Stream, StreamInstructions, StreamLayout, StreamPermissions, StreamQuote, StreamSeeds, ZeroCopyStream,
+    },
+    transfer::{
+        close_activity_vault, close_stream_escrow, transfer_from_activity_vault, transfer_from_escrow,
+        transfer_from_stream_escrow, transfer_to_escrow,
+    },
     utils::is_token_account_rent_exempt,
 };
 
@@ -112,6 +160,24 @@ pub const DISTRIBUTOR_ACCOUNT_SEED: &[u8] = b"distributor";
 
 pub const STATUS_ACCOUNT_SEED: &[u8] = b"status";
 
+/// PDA account seed to create stream registry PDA accounts of wallets and their pages.
+pub const REGISTRY_ACCOUNT_SEED: &[u8] = b"registry";
+
+/// PDA account seed to create stake position PDA accounts of wallets in activities.
+pub const POSITION_ACCOUNT_SEED: &[u8] = b"position";
+
+/// Get the PDA seed of a stream or activity name.
+///
+/// A PDA seed holds at most 32 bytes. Longer names are seeded on their SHA-256 hash and the full name is kept in the
+/// account data. Names which fit are used as is, so existing accounts keep their addresses.
+pub fn get_name_seed(name: &str) -> Vec<u8> {
+    if name.len() <= MAX_SEED_LEN {
+        name.as_bytes().to_vec()
+    } else {
+        hash(name.as_bytes()).to_bytes().to_vec()
+    }
+}
+
 #[event]
 pub struct CreateStreamEvent{
     sender: Pubkey,
@@ -128,11 +194,14 @@ pub mod superstream {
 
     /// Create a new prepaid stream.
     ///
+    /// Prefer [`create_prepaid_v2`] which takes a single [`CreateStreamParams`] struct. This legacy instruction keeps
+    /// the accounts of [`Create`]: the sender pays the rent and the stream is not added to the stream registries.
+    ///
     /// # Arguments
     ///
     /// For more information on the arguments, see fields of the [`Stream`] struct.
     pub fn create_prepaid(
-        mut ctx: Context<Create>,
+        ctx: Context<Create>,
         seed: u64,
         name: String,
         recipient: Pubkey,
@@ -152,43 +221,54 @@ pub mod superstream {
         anyone_can_withdraw_for_recipient: bool,
         anyone_can_withdraw_for_recipient_at: u64,
     ) -> Result<()> {
-        create(
-            &mut ctx,
-            true,
-            recipient,
+        let params = CreateStreamParams {
+            seed,
             name,
+            recipient,
             starts_at,
             ends_at,
             initial_amount,
             flow_interval,
             flow_rate,
-            sender_can_cancel,
-            sender_can_cancel_at,
-            sender_can_change_sender,
-            sender_can_change_sender_at,
-            sender_can_pause,
-            sender_can_pause_at,
-            recipient_can_resume_pause_by_sender,
-            recipient_can_resume_pause_by_sender_at,
-            anyone_can_withdraw_for_recipient,
-            anyone_can_withdraw_for_recipient_at,
-            seed,
-        )?;
+            permissions: StreamPermissions {
+                sender_can_cancel,
+                sender_can_cancel_at,
+                sender_can_change_sender,
+                sender_can_change_sender_at,
+                sender_can_pause,
+                sender_can_pause_at,
+                recipient_can_resume_pause_by_sender,
+                recipient_can_resume_pause_by_sender_at,
+                anyone_can_withdraw_for_recipient,
+                anyone_can_withdraw_for_recipient_at,
+            },
+            notice_period: 0,
+            accrual_mode: AccrualMode::Continuous,
+        };
+        let at = utils::get_current_timestamp()?;
+        let bump = *ctx.bumps.get("stream").unwrap();
+        let prepaid_amount_needed = ctx.accounts.stream_creation().create_prepaid(params, bump, at)?;
+        ctx.accounts.transfer_to_escrow(prepaid_amount_needed)
+    }
 
-        let stream = &mut ctx.accounts.stream;
-        msg!("3333 in create_prepaid, stream pubkey: {}", stream.key());
-        let prepaid_amount_needed = stream.initialize_prepaid()?;
-        emit!(CreateStreamEvent{
-            sender: ctx.accounts.sender.key(),
-            recipient: recipient.key(),
-            stream: stream.key(),
-            amount: initial_amount,
-        });
+    /// Create a new prepaid stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Stream creation parameters. See [`CreateStreamParams`] for more information.
+    pub fn create_prepaid_v2(ctx: Context<CreateV2>, params: CreateStreamParams) -> Result<()> {
+        let at = utils::get_current_timestamp()?;
+        let bump = *ctx.bumps.get("stream").unwrap();
+        let prepaid_amount_needed = ctx.accounts.stream_creation().create_prepaid(params, bump, at)?;
+        ctx.accounts.register(&ctx.bumps)?;
         ctx.accounts.transfer_to_escrow(prepaid_amount_needed)
     }
 
     /// Create a new non-prepaid stream.
     ///
+    /// Prefer [`create_non_prepaid_v2`] which takes a single [`CreateStreamParams`] struct. This legacy instruction
+    /// keeps the accounts of [`Create`]: the sender pays the rent and the stream is not added to the stream registries.
+    ///
     /// # Arguments
     ///
     /// * `topup_amount` - Initial topup amount for the stream. The topup amount should be >= minimum deposit required.
@@ -196,7 +276,7 @@ pub mod superstream {
     ///
     /// For more information on the other arguments, see fields of the [`Stream`] struct.
     pub fn create_non_prepaid(
-        mut ctx: Context<Create>,
+        ctx: Context<Create>,
         seed: u64,
         name: String,
         recipient: Pubkey,
@@ -217,37 +297,71 @@ pub mod superstream {
         anyone_can_withdraw_for_recipient_at: u64,
         topup_amount: u64,
     ) -> Result<()> {
-        create(
-            &mut ctx,
-            false,
-            recipient,
+        let params = CreateStreamParams {
+            seed,
             name,
+            recipient,
             starts_at,
             ends_at,
             initial_amount,
             flow_interval,
             flow_rate,
-            sender_can_cancel,
-            sender_can_cancel_at,
-            sender_can_change_sender,
-            sender_can_change_sender_at,
-            sender_can_pause,
-            sender_can_pause_at,
-            recipient_can_resume_pause_by_sender,
-            recipient_can_resume_pause_by_sender_at,
-            anyone_can_withdraw_for_recipient,
-            anyone_can_withdraw_for_recipient_at,
-            seed,
-        )?;
+            permissions: StreamPermissions {
+                sender_can_cancel,
+                sender_can_cancel_at,
+                sender_can_change_sender,
+                sender_can_change_sender_at,
+                sender_can_pause,
+                sender_can_pause_at,
+                recipient_can_resume_pause_by_sender,
+                recipient_can_resume_pause_by_sender_at,
+                anyone_can_withdraw_for_recipient,
+                anyone_can_withdraw_for_recipient_at,
+            },
+            notice_period: 0,
+            accrual_mode: AccrualMode::Continuous,
+        };
+        let at = utils::get_current_timestamp()?;
+        let bump = *ctx.bumps.get("stream").unwrap();
+        ctx.accounts.stream_creation().create_non_prepaid(params, topup_amount, bump, at)?;
+        ctx.accounts.transfer_to_escrow(topup_amount)
+    }
 
-        let stream = &mut ctx.accounts.stream;
-        msg!("in create_non_prepaid, stream pubkey: {}", stream.key());
-        stream.initialize_non_prepaid(topup_amount)?;
+    /// Create a new non-prepaid stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Stream creation parameters. See [`CreateStreamParams`] for more information.
+    /// * `topup_amount` - Initial topup amount for the stream. The topup amount should be >= minimum deposit required.
+    ///   See [`DEPOSIT_AMOUNT_PERIOD_IN_SECS`](crate::state::DEPOSIT_AMOUNT_PERIOD_IN_SECS) for more information.
+    pub fn create_non_prepaid_v2(
+        ctx: Context<CreateV2>,
+        params: CreateStreamParams,
+        topup_amount: u64,
+    ) -> Result<()> {
+        let at = utils::get_current_timestamp()?;
+        let bump = *ctx.bumps.get("stream").unwrap();
+        ctx.accounts.stream_creation().create_non_prepaid(params, topup_amount, bump, at)?;
+        ctx.accounts.register(&ctx.bumps)?;
         ctx.accounts.transfer_to_escrow(topup_amount)
     }
 
-    pub fn create_stream(
-        mut ctx: Context<Create>,
+    /// Create a new activity stream.
+    ///
+    /// Prefer [`create_stream_v2`] which takes a single [`CreateStreamParams`] struct, a lock duration and a staker
+    /// allowlist proof. This creates the stake with [`create_stream_v2`], locked for `flow_interval` seconds and without
+    /// an allowlist proof, so the stake fails if `flow_interval` is not one of the lock durations of an activity with
+    /// lock multipliers or if the activity has a staker allowlist.
+    ///
+    /// This legacy instruction keeps the accounts of [`Create`]: the sender pays the rent, owns the stake and the stream
+    /// is not added to the stream registries. As stakes are recorded in stake positions, it also takes the stake
+    /// position PDA account of the sender in the activity as the first remaining account, writable, which callers of
+    /// the original instruction need to add.
+    ///
+    /// `flow_rate` is ignored, stakes have no flow payments. For more information on the other arguments, see fields of
+    /// the [`Stream`] struct.
+    pub fn create_stream<'info>(
+        ctx: Context<'_, '_, '_, 'info, Create<'info>>,
         seed: u64,
         name: String,
         recipient: Pubkey,
@@ -265,35 +379,81 @@ pub mod superstream {
         anyone_can_withdraw_for_recipient: bool,
         anyone_can_withdraw_for_recipient_at: u64,
     ) -> Result<()> {
-        create1(
-            &mut ctx,
-            recipient,
+        let params = CreateStreamParams {
+            seed,
             name,
+            recipient,
+            starts_at: 0,
+            ends_at: 0,
             initial_amount,
             flow_interval,
             flow_rate,
-            sender_can_cancel,
-            sender_can_cancel_at,
-            sender_can_change_sender,
-            sender_can_change_sender_at,
-            sender_can_pause,
-            sender_can_pause_at,
-            recipient_can_resume_pause_by_sender,
-            recipient_can_resume_pause_by_sender_at,
-            anyone_can_withdraw_for_recipient,
-            anyone_can_withdraw_for_recipient_at,
-            seed,
+            permissions: StreamPermissions {
+                sender_can_cancel,
+                sender_can_cancel_at,
+                sender_can_change_sender,
+                sender_can_change_sender_at,
+                sender_can_pause,
+                sender_can_pause_at,
+                recipient_can_resume_pause_by_sender,
+                recipient_can_resume_pause_by_sender_at,
+                anyone_can_withdraw_for_recipient,
+                anyone_can_withdraw_for_recipient_at,
+            },
+            notice_period: 0,
+            accrual_mode: AccrualMode::Continuous,
+        };
+        // Legacy stakes end `flow_interval` seconds after their creation.
+        let at = utils::get_current_timestamp()?;
+        let bump = *ctx.bumps.get("stream").unwrap();
+        let prepaid_amount_needed = ctx.accounts.stream_creation().create_activity_stream(
+            ctx.remaining_accounts,
+            params,
+            flow_interval,
+            Vec::new(),
+            bump,
+            at,
         )?;
+        ctx.accounts.transfer_to_escrow(prepaid_amount_needed)
+    }
 
-        let stream = &mut ctx.accounts.stream;
-        msg!("4444 in create_stream, stream pubkey: {}", stream.key());
-        let prepaid_amount_needed = stream.initialize_prepaid()?;
-        emit!(CreateStreamEvent{
-            sender: ctx.accounts.sender.key(),
-            recipient: recipient.key(),
-            stream: stream.key(),
-            amount: initial_amount,
-        });
+    /// Create a new activity stream. Activity streams are prepaid and start immediately. The activity needs to be active
+    /// and open, i.e. between its start and end times, and the initial amount needs to be at least the minimum amount of
+    /// the activity.
+    ///
+    /// The stake can be funded on behalf of another wallet by passing it as the `beneficiary` account. The beneficiary
+    /// then owns the stake position, receives the withdrawals and the rewards, and can unstake early instead of the
+    /// sender. The stake position PDA account of the beneficiary needs to be passed as the first remaining account, and
+    /// is created on first use with its rent paid by the payer.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Stream creation parameters. `starts_at` and `ends_at` are ignored, `flow_interval` is
+    ///   overridden by the duration of the activity and `flow_rate` by 0, as the flow rate of the activity is emitted as
+    ///   rewards. See [`CreateStreamParams`] for more
+    ///   information.
+    /// * `lock_duration` - Duration in seconds the stake is locked for, i.e. the stream ends `lock_duration` seconds
+    ///   after its creation. Needs to be > 0. If the activity has lock multipliers, this needs to be one of their
+    ///   durations and the stake is weighted with its multiplier.
+    /// * `allowlist_proof` - Merkle proof that the beneficiary is in the staker allowlist of the activity. Ignored if
+    ///   the activity has no allowlist. See [`Activity::staker_allowlist_root`].
+    pub fn create_stream_v2<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateV2<'info>>,
+        params: CreateStreamParams,
+        lock_duration: u64,
+        allowlist_proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let at = utils::get_current_timestamp()?;
+        let bump = *ctx.bumps.get("stream").unwrap();
+        let prepaid_amount_needed = ctx.accounts.stream_creation().create_activity_stream(
+            ctx.remaining_accounts,
+            params,
+            lock_duration,
+            allowlist_proof,
+            bump,
+            at,
+        )?;
+        ctx.accounts.register(&ctx.bumps)?;
         ctx.accounts.transfer_to_escrow(prepaid_amount_needed)
     }
 
@@ -320,10 +480,233 @@ pub mod superstream {
             min_amount,
             flow_rate,
         )?;
-        msg!("activity pubkey {} ", ctx.accounts.activity.key());
         Ok(())
     }
 
+    /// Update the end time, reward expiry time, minimum stake amount and flow rate of an activity. The end time cannot
+    /// be in the past and the rewards cannot expire before the activity ends. Rewards are emitted at the new flow rate
+    /// from now on, see [`Activity::flow_rate`]. Distributors which already exist keep the
+    /// reward expiry time they were created with.
+    ///
+    /// Only the creator of the activity can update it, as long as it is active.
+    pub fn update_activity(
+        ctx: Context<UpdateActivity>,
+        ends_at: u64,
+        reward_expires_at: u64,
+        min_amount: u64,
+        flow_rate: u64,
+    ) -> Result<()> {
+        ctx.accounts.activity.update(
+            ctx.accounts.creator.key(),
+            ends_at,
+            reward_expires_at,
+            min_amount,
+            flow_rate,
+            utils::get_current_timestamp()?,
+        )
+    }
+
+    /// Get the stake totals of an activity. This does not modify the activity and is meant to be simulated by clients or
+    /// called by other programs to read the totals from the return data.
+    pub fn quote_activity(ctx: Context<QuoteActivity>) -> Result<ActivityTotals> {
+        Ok(ctx.accounts.activity.totals())
+    }
+
+    /// Set the maximum amount staked in an activity and by a single wallet. A cap of 0 means no cap. The caps are
+    /// enforced when stakes are created with [`create_stream_v2`].
+    ///
+    /// Only the creator of the activity can set the caps, as long as it is active.
+    pub fn set_stake_caps(ctx: Context<UpdateActivity>, max_total_stake: u64, max_stake_per_wallet: u64) -> Result<()> {
+        ctx.accounts
+            .activity
+            .set_stake_caps(ctx.accounts.creator.key(), max_total_stake, max_stake_per_wallet)
+    }
+
+    /// Set the lock durations allowed for the stakes of an activity and their reward multipliers, replacing the current
+    /// ones. The activity account is resized and the creator pays for any extra rent. See
+    /// [`Activity::lock_multipliers`].
+    ///
+    /// Only the creator of the activity can set the lock multipliers, as long as it is active.
+    pub fn set_lock_multipliers(ctx: Context<SetLockMultipliers>, lock_multipliers: Vec<LockMultiplier>) -> Result<()> {
+        ctx.accounts
+            .activity
+            .set_lock_multipliers(ctx.accounts.creator.key(), lock_multipliers)
+    }
+
+    /// Set the penalty charged on stakes of an activity unstaked before they end with [`unstake_early`], in basis points
+    /// of the unstaked amount. The penalty goes to `penalty_treasury`, or to the reward vault if it is the default
+    /// pubkey, in which case it is emitted as rewards to the remaining stakes.
+    ///
+    /// Only the creator of the activity can set the penalty, as long as it is active.
+    pub fn set_early_unstake_penalty(
+        ctx: Context<UpdateActivity>,
+        early_unstake_penalty: u64,
+        penalty_treasury: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.activity.set_early_unstake_penalty(
+            ctx.accounts.creator.key(),
+            early_unstake_penalty,
+            penalty_treasury,
+        )
+    }
+
+    /// Set the merkle root of the wallets allowed to stake in an activity, e.g. to restrict it to invited wallets. The
+    /// leaves are the keccak hashes of the wallet addresses. All zeros removes the allowlist. See
+    /// [`Activity::staker_allowlist_root`].
+    ///
+    /// Only the creator of the activity can set the allowlist, as long as it is active.
+    pub fn set_staker_allowlist_root(ctx: Context<UpdateActivity>, staker_allowlist_root: [u8; 32]) -> Result<()> {
+        ctx.accounts
+            .activity
+            .set_staker_allowlist_root(ctx.accounts.creator.key(), staker_allowlist_root)
+    }
+
+    /// Deactivate an activity so that no new streams can be created for it. Existing streams are not affected.
+    ///
+    /// Only the creator of the activity can deactivate it.
+    pub fn deactivate_activity(ctx: Context<UpdateActivity>) -> Result<()> {
+        ctx.accounts.activity.deactivate(ctx.accounts.creator.key())
+    }
+
+    /// Close an activity and refund its rent to the creator. All the streams, distributors and stake positions of the
+    /// activity need to be closed first, so all the rewards accrued to the stakes have been claimed. The rewards left
+    /// in the reward vault, i.e. never emitted or rounding dust, are refunded to the creator and the vault is closed.
+    ///
+    /// Activities migrated from the original layout cannot be closed, as the streams and distributors created before
+    /// the migration are not counted.
+    ///
+    /// Only the creator of the activity can close it.
+    pub fn close_activity(ctx: Context<CloseActivity>) -> Result<()> {
+        let activity = &ctx.accounts.activity;
+        activity.close(ctx.accounts.creator.key())?;
+        transfer_from_activity_vault(
+            activity.to_account_info(),
+            activity,
+            &ctx.accounts.creator_token,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.token_program,
+            ctx.accounts.reward_vault.amount,
+        )?;
+        close_activity_vault(
+            activity.to_account_info(),
+            activity,
+            &ctx.accounts.reward_vault,
+            ctx.accounts.creator.to_account_info(),
+            &ctx.accounts.token_program,
+        )
+    }
+
+    /// Migrate an activity created with the original account layout, i.e. before [`Activity::version`] existed, to the
+    /// current layout in place. The activity keeps its address and vaults. Activities already in the current layout are
+    /// left as is.
+    ///
+    /// Only the creator of the activity can migrate it, and pays for the extra rent.
+    pub fn migrate_activity(ctx: Context<MigrateActivity>) -> Result<()> {
+        let activity_info = ctx.accounts.activity.to_account_info();
+        if !legacy::is_legacy_activity(&activity_info.try_borrow_data()?) {
+            return Ok(());
+        }
+        let activity = LegacyActivity::try_deserialize(&activity_info.try_borrow_data()?)?.into_activity();
+        require!(
+            ctx.accounts.creator.key() == activity.creator,
+            StreamError::UserUnauthorizedToManageActivity
+        );
+
+        let space = Activity::space(&activity.name, 0);
+        ctx.accounts.fund_rent(space)?;
+        activity_info.realloc(space, false)?;
+        let mut data = activity_info.try_borrow_mut_data()?;
+        activity.try_serialize(&mut &mut data[..])
+    }
+
+    /// Fund the reward vault of an activity with `amount` reward tokens. The rewards are emitted to the stakers at the
+    /// flow rate of the activity from now on, until the activity ends or the funded rewards are used up. Anyone can
+    /// fund the rewards.
+    pub fn fund_activity_rewards(ctx: Context<FundActivityRewards>, amount: u64) -> Result<()> {
+        ctx.accounts
+            .activity
+            .fund_rewards(amount, utils::get_current_timestamp()?)?;
+        transfer_to_escrow(
+            &ctx.accounts.funder,
+            &ctx.accounts.funder_token,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.token_program,
+            amount,
+        )
+    }
+
+    /// Claim the activity rewards accrued to the stake position of the signer. Rewards accrue to each stake in
+    /// proportion to its amount, so no distributor or merkle proof is needed.
+    ///
+    /// The claimed amount is set as the return data of the instruction, so CPI callers can act on it.
+    pub fn claim_activity_rewards(ctx: Context<ClaimActivityRewards>) -> Result<u64> {
+        let at = utils::get_current_timestamp()?;
+        let amount = ctx.accounts.activity.claim_rewards(&mut ctx.accounts.position, at)?;
+        transfer_from_activity_vault(
+            ctx.accounts.activity.to_account_info(),
+            &ctx.accounts.activity,
+            &ctx.accounts.staker_token,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        Ok(amount)
+    }
+
+    /// Close the stake position of the signer in an activity and refund its rent to the signer. The position needs to
+    /// hold no stake and all the rewards accrued to it need to be claimed first. A new position is created if the
+    /// signer stakes in the activity again.
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        ctx.accounts.activity.close_position(
+            ctx.accounts.staker.key(),
+            &mut ctx.accounts.position,
+            utils::get_current_timestamp()?,
+        )
+    }
+
+    /// Unstake an activity stream before it ends. The stream is cancelled and the stake is returned to the staker minus
+    /// the early unstake penalty of the activity, which goes to the penalty treasury or the reward vault. The stake
+    /// position forfeits its pending rewards in proportion to the unstaked amount.
+    ///
+    /// Only the staker, i.e. the beneficiary of the stake or its creator, can unstake early. The amount returned to the
+    /// staker is set as the return data of the instruction, so CPI callers can act on it.
+    ///
+    /// The token account receiving the penalty is passed as the first remaining account. It is only needed if the
+    /// penalty is not 0.
+    pub fn unstake_early<'info>(ctx: Context<'_, '_, '_, 'info, UnstakeEarly<'info>>) -> Result<u64> {
+        let at = utils::get_current_timestamp()?;
+        let amount = ctx.accounts.stream.unstake_early(ctx.accounts.staker.key(), at)?;
+        let penalty = record_early_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            ctx.accounts.stream.stake(),
+            amount,
+            at,
+        )?;
+        let amount_returned = amount
+            .checked_sub(penalty)
+            .ok_or(StreamError::UnstakeAmountOutOfBounds)?;
+        transfer_from_escrow(
+            &ctx.accounts.stream,
+            &ctx.accounts.staker_token,
+            &ctx.accounts.escrow_token,
+            &ctx.accounts.token_program,
+            amount_returned,
+        )?;
+        if penalty > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.stream,
+                &penalty_token(ctx.remaining_accounts, &ctx.accounts.activity, ctx.accounts.stream.mint)?,
+                &ctx.accounts.escrow_token,
+                &ctx.accounts.token_program,
+                penalty,
+            )?;
+        }
+
+        Ok(amount_returned)
+    }
+
     pub fn create_distributor(
         ctx: Context<NewDistributor>,
         _bump:u8,
@@ -340,6 +723,13 @@ pub mod superstream {
         distributor.total_supply = total_supply;
         distributor.reward_expires_at = ctx.accounts.activity.reward_expires_at;
         distributor.mint = ctx.accounts.mint.key();
+        distributor.rent_payer = ctx.accounts.payer.key();
+        ctx.accounts.activity.distributor_count = ctx
+            .accounts
+            .activity
+            .distributor_count
+            .checked_add(1)
+            .ok_or(StreamError::ActivityCountOutOfBounds)?;
 
         transfer_to_escrow(
             &ctx.accounts.creator,
@@ -350,7 +740,10 @@ pub mod superstream {
         )
     }
 
-    pub fn claim(ctx: Context<Claim>, index: u64, amount: u64, leaf: [u8; 32], proof: Vec<[u8; 32]>) -> Result<()> {
+    /// Claim rewards from a distributor.
+    ///
+    /// The claimed amount is set as the return data of the instruction, so CPI callers can act on it.
+    pub fn claim(ctx: Context<Claim>, index: u64, amount: u64, leaf: [u8; 32], proof: Vec<[u8; 32]>) -> Result<u64> {
         //Check claim status
         let claimer = &ctx.accounts.claimer;
         let status = &mut ctx.accounts.status;
@@ -391,6 +784,7 @@ pub mod superstream {
         status.amount = amount;
         status.is_claimed = true;
         status.claimer = claimer.key();
+        status.rent_payer = ctx.accounts.payer.key();
         distributor.total_claimed = distributor.total_claimed + amount;
         let seeds  = [
             DISTRIBUTOR_ACCOUNT_SEED.as_ref(),
@@ -411,7 +805,7 @@ pub mod superstream {
             amount,
         )?;
 
-        Ok(())
+        Ok(amount)
     }
 
     pub fn recycle_reward(ctx: Context<RecycleReward>) -> Result<()> {
@@ -451,24 +845,104 @@ pub mod superstream {
         )
     }
 
-    /// Cancel a stream.
+    /// Close a reward distributor whose escrow is empty, i.e. all the rewards were claimed or recycled. The rent of the
+    /// distributor and escrow accounts is refunded to the wallet which paid for the distributor. The claim statuses of
+    /// the distributor can be closed afterwards with [`close_status`].
+    ///
+    /// Only the creator of the distributor can close it.
+    pub fn close_distributor(ctx: Context<CloseDistributor>) -> Result<()> {
+        let distributor = &ctx.accounts.distributor;
+        require!(
+            ctx.accounts.creator.key() == distributor.creater_key,
+            StreamError::UserUnauthorizedToCloseDistributor
+        );
+        require!(ctx.accounts.escrow_token.amount == 0, StreamError::EscrowNotEmpty);
+        ctx.accounts.activity.distributor_count = ctx
+            .accounts
+            .activity
+            .distributor_count
+            .checked_sub(1)
+            .ok_or(StreamError::ActivityCountOutOfBounds)?;
+
+        let seeds = [
+            DISTRIBUTOR_ACCOUNT_SEED,
+            distributor.activity_key.as_ref(),
+            distributor.mint.as_ref(),
+            &[distributor.bump],
+        ];
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.escrow_token.to_account_info(),
+                destination: ctx.accounts.rent_payer.to_account_info(),
+                authority: distributor.to_account_info(),
+            },
+            &[&seeds[..]],
+        ))
+    }
+
+    /// Close a claim status and refund its rent to the wallet which paid for it. The distributor needs to be closed
+    /// first, so that the claimer cannot claim again. Anyone can close a claim status.
+    pub fn close_status(ctx: Context<CloseStatus>) -> Result<()> {
+        require!(
+            ctx.accounts.distributor.data_is_empty(),
+            StreamError::DistributorNotClosed
+        );
+        Ok(())
+    }
+
+    /// Cancel a stream. If the stream has a notice period, the sender needs to [`request_cancel`] first and the
+    /// cancellation can only be settled once the notice period is over.
+    ///
+    /// The settled amounts are set as the return data of the instruction, so CPI callers can act on them.
     ///
     /// # Arguments
     ///
     /// For more information on the arguments, see fields of the [`Stream`] struct.
-    pub fn cancel(ctx: Context<Cancel>, seed: u64, name: String, recipient: Pubkey) -> Result<()> {
+    pub fn cancel(
+        ctx: Context<Cancel>,
+        _seed: u64,
+        _name: String,
+        recipient: Pubkey,
+    ) -> Result<CancelTransferParams> {
+        let at = utils::get_current_timestamp()?;
         let stream = &mut ctx.accounts.stream;
         let stream_key = stream.to_account_info().key;
-        let bump = stream.bump;
-        let params = stream.cancel(*stream_key, &ctx.accounts.signer, recipient)?;
-        ctx.accounts
-            .transfer_from_escrow_to_sender(seed, &name, bump, params.transfer_amount_sender)?;
-        ctx.accounts
-            .transfer_from_escrow_to_signer(seed, &name, bump, params.transfer_amount_signer)?;
-        ctx.accounts
-            .transfer_from_escrow_to_recipient(seed, &name, bump, params.transfer_amount_recipient)?;
+        let params = stream.cancel(*stream_key, ctx.accounts.signer.key(), recipient, at)?;
+        ctx.accounts.transfer_from_escrow_to_sender(params.transfer_amount_sender)?;
+        ctx.accounts.transfer_from_escrow_to_signer(params.transfer_amount_signer)?;
+        ctx.accounts.transfer_from_escrow_to_recipient(params.transfer_amount_recipient)?;
+        record_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            ctx.accounts.stream.stake(),
+            params.total_amount(),
+            at,
+        )?;
 
-        Ok(())
+        Ok(params)
+    }
+
+    /// Request cancellation of a stream with a notice period. The cancellation takes effect after the notice period,
+    /// and can then be settled by anyone calling [`cancel`]. A stream paused by the sender is resumed, since the
+    /// recipient is paid through the notice period.
+    pub fn request_cancel(ctx: Context<RequestCancel>) -> Result<()> {
+        let stream = &mut ctx.accounts.stream;
+        stream.request_cancel(ctx.accounts.signer.key(), utils::get_current_timestamp()?)
+    }
+
+    /// Get a quote of the amounts of a stream. This does not modify the stream and is meant to be simulated by clients
+    /// to read the quote from the return data.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - Time at which to compute the quote. Defaults to the current time.
+    pub fn quote_stream(ctx: Context<QuoteStream>, at: Option<u64>) -> Result<StreamQuote> {
+        let at = match at {
+            Some(at) => at,
+            None => utils::get_current_timestamp()?,
+        };
+        ctx.accounts.stream.quote(at)
     }
 
     /// Withdraw excess sender topup from a non-prepaid stream.
@@ -478,16 +952,12 @@ pub mod superstream {
     /// For more information on the arguments, see fields of the [`Stream`] struct.
     pub fn withdraw_excess_topup_non_prepaid_ended(
         ctx: Context<WithdrawExcessTopupNonPrepaidEnded>,
-        seed: u64,
-        name: String,
+        _seed: u64,
+        _name: String,
     ) -> Result<()> {
         let stream = &mut ctx.accounts.stream;
-        let amount = stream.withdraw_excess_topup_non_prepaid_ended()?;
-        if amount > 0 {
-            let bump = stream.bump;
-            ctx.accounts.transfer_from_escrow(seed, &name, bump, amount)?;
-        }
-        Ok(())
+        let amount = stream.withdraw_excess_topup_non_prepaid_ended(utils::get_current_timestamp()?)?;
+        ctx.accounts.transfer_from_escrow(amount)
     }
 
     /// Topup a non-prepaid stream.
@@ -504,15 +974,15 @@ pub mod superstream {
         topup_amount: u64,
     ) -> Result<()> {
         let stream = &mut ctx.accounts.stream;
-        stream.topup_non_prepaid(topup_amount)?;
+        stream.topup_non_prepaid(topup_amount, utils::get_current_timestamp()?)?;
         ctx.accounts.transfer_to_escrow(topup_amount)
     }
 
-    /// Change sender of a non-prepaid stream.
+    /// Change sender of a non-prepaid stream. The stream is appended to the registry of the new sender.
     ///
     /// # Arguments
     ///
-    /// * `new_sender` - The new sender
+    /// * `new_sender` - The new sender. A manager cannot make itself the sender.
     ///
     /// For more information on the other arguments, see fields of the [`Stream`] struct.
     pub fn change_sender_non_prepaid(
@@ -522,24 +992,67 @@ pub mod superstream {
         new_sender: Pubkey,
     ) -> Result<()> {
         let stream = &mut ctx.accounts.stream;
-        stream.change_sender_non_prepaid(&ctx.accounts.sender, new_sender)
+        stream.change_sender_non_prepaid(ctx.accounts.sender.key(), new_sender, utils::get_current_timestamp()?)?;
+
+        let stream_key = ctx.accounts.stream.key();
+        register_stream(
+            &mut ctx.accounts.new_sender_registry,
+            *ctx.bumps.get("new_sender_registry").unwrap(),
+            &mut ctx.accounts.new_sender_registry_page,
+            *ctx.bumps.get("new_sender_registry_page").unwrap(),
+            new_sender,
+            stream_key,
+        )
+    }
+
+    /// Set or remove the manager of a stream. Only the sender can set the manager.
+    ///
+    /// # Arguments
+    ///
+    /// * `manager` - The new manager. Pass the default (all zeroes) address to remove the manager.
+    /// * `manager_permissions` - Rights delegated to the manager. See [`ManagerPermissions`] for more information.
+    pub fn set_manager(
+        ctx: Context<SetManager>,
+        manager: Pubkey,
+        manager_permissions: ManagerPermissions,
+    ) -> Result<()> {
+        let stream = &mut ctx.accounts.stream;
+        stream.set_manager(
+            ctx.accounts.sender.key(),
+            manager,
+            manager_permissions,
+            utils::get_current_timestamp()?,
+        )
     }
 
     /// Withdraw recipient funds from a stream.
     ///
+    /// The withdrawn amount is set as the return data of the instruction, so CPI callers can act on it.
+    ///
     /// # Arguments
     ///
     /// For more information on the arguments, see fields of the [`Stream`] struct.
-    pub fn withdraw(
-        ctx: Context<WithdrawAndChangeRecipient>,
-        name: String,
-        recipient: Pubkey,
-        sender: Pubkey,
-    ) -> Result<()> {
-        withdraw_and_change_recipient(ctx, name, recipient, sender,Pubkey::default())
+    pub fn withdraw(ctx: Context<Withdraw>, _name: String, recipient: Pubkey, _sender: Pubkey) -> Result<u64> {
+        let at = utils::get_current_timestamp()?;
+        let stream = &mut ctx.accounts.stream;
+        let amount_available_to_withdraw =
+            stream.withdraw_and_change_recipient(ctx.accounts.signer.key(), recipient, Pubkey::default(), at)?;
+        ctx.accounts.transfer_from_escrow(amount_available_to_withdraw)?;
+        record_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            ctx.accounts.stream.stake(),
+            amount_available_to_withdraw,
+            at,
+        )?;
+
+        Ok(amount_available_to_withdraw)
     }
 
-    /// Withdraw recipient funds from a stream and change recipient of a stream.
+    /// Withdraw recipient funds from a stream and change recipient of a stream. The stream is appended to the registry of
+    /// the new recipient.
+    ///
+    /// The withdrawn amount is set as the return data of the instruction, so CPI callers can act on it.
     ///
     /// # Arguments
     ///
@@ -548,35 +1061,38 @@ pub mod superstream {
     /// For more information on the other arguments, see fields of the [`Stream`] struct.
     pub fn withdraw_and_change_recipient(
         ctx: Context<WithdrawAndChangeRecipient>,
-        name: String,
+        _name: String,
         recipient: Pubkey,
-        creator: Pubkey,
+        _sender: Pubkey,
         new_recipient: Pubkey,
-    ) -> Result<()> {
+    ) -> Result<u64> {
+        let at = utils::get_current_timestamp()?;
         let stream = &mut ctx.accounts.stream;
         let amount_available_to_withdraw =
-            stream.withdraw_and_change_recipient(&ctx.accounts.signer, recipient, new_recipient)?;
-        let bump = stream.bump;
-        let seeds  = [
-            STREAM_ACCOUNT_SEED.as_ref(),
-            &stream.activity.to_bytes(),
-            &stream.mint.to_bytes(),
-            &creator.to_bytes(),
-            name.as_bytes(),
-            &[bump],
-            ];
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.escrow_token.to_account_info(),
-                    to: ctx.accounts.recipient_token.to_account_info(),
-                    authority: ctx.accounts.stream.to_account_info(),
-                },
-            )
-            .with_signer(&[&seeds[..]]),
+            stream.withdraw_and_change_recipient(ctx.accounts.signer.key(), recipient, new_recipient, at)?;
+        ctx.accounts.transfer_from_escrow(amount_available_to_withdraw)?;
+        record_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            ctx.accounts.stream.stake(),
             amount_available_to_withdraw,
-        )
+            at,
+        )?;
+
+        // The recipient is not changed if the stream was cancelled.
+        if ctx.accounts.stream.recipient != recipient {
+            let stream_key = ctx.accounts.stream.key();
+            register_stream(
+                &mut ctx.accounts.new_recipient_registry,
+                *ctx.bumps.get("new_recipient_registry").unwrap(),
+                &mut ctx.accounts.new_recipient_registry_page,
+                *ctx.bumps.get("new_recipient_registry_page").unwrap(),
+                new_recipient,
+                stream_key,
+            )?;
+        }
+
+        Ok(amount_available_to_withdraw)
     }
 
     /// Pause a non-prepaid stream.
@@ -586,7 +1102,7 @@ pub mod superstream {
     /// For more information on the arguments, see fields of the [`Stream`] struct.
     pub fn pause_non_prepaid(ctx: Context<PauseNonPrepaid>, _seed: u64, _name: String) -> Result<()> {
         let stream = &mut ctx.accounts.stream;
-        stream.pause_non_prepaid(&ctx.accounts.signer)
+        stream.pause_non_prepaid(ctx.accounts.signer.key(), utils::get_current_timestamp()?)
     }
 
     /// Resume a non-prepaid stream.
@@ -596,129 +1112,463 @@ pub mod superstream {
     /// For more information on the arguments, see fields of the [`Stream`] struct.
     pub fn resume_non_prepaid(ctx: Context<ResumeNonPrepaid>, _seed: u64, _name: String) -> Result<()> {
         let stream = &mut ctx.accounts.stream;
-        stream.resume_non_prepaid(&ctx.accounts.signer)
+        stream.resume_non_prepaid(ctx.accounts.signer.key(), utils::get_current_timestamp()?)
     }
-}
-
-pub(crate) fn create(
-    ctx: &mut Context<Create>,
-    is_prepaid: bool,
-    recipient: Pubkey,
-    name: String,
-    starts_at: u64,
-    ends_at: u64,
-    initial_amount: u64,
-    flow_interval: u64,
-    flow_rate: u64,
-    sender_can_cancel: bool,
-    sender_can_cancel_at: u64,
-    sender_can_change_sender: bool,
-    sender_can_change_sender_at: u64,
-    sender_can_pause: bool,
-    sender_can_pause_at: u64,
-    recipient_can_resume_pause_by_sender: bool,
-    recipient_can_resume_pause_by_sender_at: u64,
-    anyone_can_withdraw_for_recipient: bool,
-    anyone_can_withdraw_for_recipient_at: u64,
-    seed: u64,
-) -> Result<()> {
-    let escrow_token_account = &ctx.accounts.escrow_token;
-    require!(
-        is_token_account_rent_exempt(escrow_token_account)?,
-        StreamError::EscrowNotRentExempt,
-    );
-    msg!("In fn create!!!");
-    let stream = &mut ctx.accounts.stream;
-    stream.initialize(
-        is_prepaid,
-        ctx.accounts.mint.key(),
-        ctx.accounts.sender.key(),
-        recipient,
-        name,
-        starts_at,
-        ends_at,
-        initial_amount,
-        flow_interval,
-        flow_rate,
-        sender_can_cancel,
-        sender_can_cancel_at,
-        sender_can_change_sender,
-        sender_can_change_sender_at,
-        sender_can_pause,
-        sender_can_pause_at,
-        recipient_can_resume_pause_by_sender,
-        recipient_can_resume_pause_by_sender_at,
-        anyone_can_withdraw_for_recipient,
-        anyone_can_withdraw_for_recipient_at,
-        seed,
-        *ctx.bumps.get("stream").unwrap(),
-    )
-}
 
-pub(crate) fn create1(
-    ctx: &mut Context<Create>,
-    recipient: Pubkey,
-    name: String,
-    initial_amount: u64,
-    flow_interval: u64,
-    flow_rate: u64,
-    sender_can_cancel: bool,
-    sender_can_cancel_at: u64,
-    sender_can_change_sender: bool,
-    sender_can_change_sender_at: u64,
-    sender_can_pause: bool,
-    sender_can_pause_at: u64,
-    recipient_can_resume_pause_by_sender: bool,
-    recipient_can_resume_pause_by_sender_at: u64,
-    anyone_can_withdraw_for_recipient: bool,
-    anyone_can_withdraw_for_recipient_at: u64,
-    seed: u64,
-) -> Result<()> {
-    let escrow_token_account = &ctx.accounts.escrow_token;
-    require!(
-        is_token_account_rent_exempt(escrow_token_account)?,
-        StreamError::EscrowNotRentExempt,
-    );
-    let activity_account = &ctx.accounts.activity;
-    require!(
-        is_token_account_rent_exempt(activity_account)?,
-        StreamError::EscrowNotRentExempt,
-    );
-    msg!("In fn create!!!");
-    let starts_at = utils::get_current_timestamp()?;
+    /// Close a stream which has stopped and whose escrow is empty, e.g. an ended stream which was fully withdrawn. The
+    /// rent of the stream and escrow accounts is refunded to the wallet which paid for the stream. The stream stays listed
+    /// in the append-only stream registries, see [`StreamRegistry`].
+    ///
+    /// Only the sender or the recipient can close a stream.
+    pub fn close_stream(ctx: Context<CloseStream>) -> Result<()> {
+        let stream = &ctx.accounts.stream;
+        stream.close(
+            ctx.accounts.signer.key(),
+            ctx.accounts.escrow_token.amount,
+            utils::get_current_timestamp()?,
+        )?;
+        ctx.accounts.close_escrow(&stream.seeds())?;
+        ctx.accounts.activity.stream_count = ctx
+            .accounts
+            .activity
+            .stream_count
+            .checked_sub(1)
+            .ok_or(StreamError::ActivityCountOutOfBounds)?;
+        Ok(())
+    }
 
-    require!(
-        starts_at <= activity_account.ends_at,
-        StreamError::ActivityEnded,
-    );
+    /// Migrate a stream to the zero-copy layout in place. The stream keeps its address, escrow account and amounts,
+    /// and is operated with the `*_zero_copy` instructions afterwards. See [`ZeroCopyStream`] for more information.
+    ///
+    /// Streams created with the original account layout, i.e. before [`Stream::version`] existed, can only be operated
+    /// after being migrated. Their creator is not stored and needs to be provided if the sender has changed since. They
+    /// were not counted in their activity, which needs to be migrated first, so they are counted when migrated.
+    ///
+    /// The signer needs to be the sender or the recipient of the stream, and pays for the extra rent if the zero-copy
+    /// account is larger.
+    ///
+    /// # Arguments
+    ///
+    /// * `creator` - Sender at the time of creation of a stream with the original layout. Defaults to the sender.
+    pub fn migrate_stream(ctx: Context<MigrateStream>, creator: Option<Pubkey>) -> Result<()> {
+        let stream_info = ctx.accounts.stream.to_account_info();
+        let is_legacy = legacy::is_legacy_stream(&stream_info.try_borrow_data()?);
+        let stream = legacy::read_stream(stream_info.key, &stream_info.try_borrow_data()?, creator)?;
+        let signer = ctx.accounts.signer.key();
+        require!(
+            signer == stream.sender || signer == stream.recipient,
+            StreamError::UserUnauthorizedToMigrate
+        );
+        require_keys_eq!(stream.activity, ctx.accounts.activity.key(), ErrorCode::ConstraintRaw);
+        if is_legacy {
+            ctx.accounts.activity.stream_count = ctx
+                .accounts
+                .activity
+                .stream_count
+                .checked_add(1)
+                .ok_or(StreamError::ActivityCountOutOfBounds)?;
+        }
 
-    let ends_at = starts_at + flow_interval;
+        ctx.accounts.fund_rent(ZeroCopyStream::LENGTH)?;
+        stream_info.realloc(ZeroCopyStream::LENGTH, false)?;
+        stream_info.try_borrow_mut_data()?[..8].copy_from_slice(&ZeroCopyStream::discriminator());
+        *AccountLoader::<ZeroCopyStream>::try_from(&stream_info)?.load_mut()? = ZeroCopyStream::from_stream(&stream);
+        Ok(())
+    }
 
-    let stream = &mut ctx.accounts.stream;
-    stream.initialize1(
-        activity_account.key(),
-        ctx.accounts.mint.key(),
-        ctx.accounts.sender.key(),
-        recipient,
-        name,
-        starts_at,
-        ends_at,
-        initial_amount,
-        activity_account.duration,
-        flow_rate,
-        sender_can_cancel,
-        sender_can_cancel_at,
-        sender_can_change_sender,
-        sender_can_change_sender_at,
-        sender_can_pause,
-        sender_can_pause_at,
-        recipient_can_resume_pause_by_sender,
-        recipient_can_resume_pause_by_sender_at,
-        anyone_can_withdraw_for_recipient,
-        anyone_can_withdraw_for_recipient_at,
-        seed,
-        *ctx.bumps.get("stream").unwrap(),
-    )
+    /// Cancel a zero-copy stream. See [`cancel`].
+    pub fn cancel_zero_copy(ctx: Context<CancelZeroCopy>) -> Result<CancelTransferParams> {
+        let stream_key = ctx.accounts.stream.key();
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        let (params, seeds, stake) = {
+            let mut stream = ctx.accounts.stream.load_mut()?;
+            let recipient = stream.recipient;
+            let params = stream.cancel(stream_key, signer, recipient, at)?;
+            (params, stream.seeds(), stream.stake())
+        };
+        ctx.accounts
+            .transfer_from_escrow(&seeds, &ctx.accounts.sender_token, params.transfer_amount_sender)?;
+        ctx.accounts
+            .transfer_from_escrow(&seeds, &ctx.accounts.signer_token, params.transfer_amount_signer)?;
+        ctx.accounts
+            .transfer_from_escrow(&seeds, &ctx.accounts.recipient_token, params.transfer_amount_recipient)?;
+        record_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            stake,
+            params.total_amount(),
+            at,
+        )?;
+
+        Ok(params)
+    }
+
+    /// Request cancellation of a zero-copy stream. See [`request_cancel`].
+    pub fn request_cancel_zero_copy(ctx: Context<UpdateZeroCopyStream>) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        ctx.accounts.stream.load_mut()?.request_cancel(signer, at)
+    }
+
+    /// Get a quote of the amounts of a zero-copy stream. See [`quote_stream`].
+    pub fn quote_zero_copy_stream(ctx: Context<QuoteZeroCopyStream>, at: Option<u64>) -> Result<StreamQuote> {
+        let at = match at {
+            Some(at) => at,
+            None => utils::get_current_timestamp()?,
+        };
+        ctx.accounts.stream.load()?.quote(at)
+    }
+
+    /// Withdraw excess sender topup from a non-prepaid zero-copy stream. See
+    /// [`withdraw_excess_topup_non_prepaid_ended`].
+    pub fn withdraw_excess_topup_non_prepaid_ended_zero_copy(
+        ctx: Context<WithdrawExcessTopupNonPrepaidEndedZeroCopy>,
+    ) -> Result<()> {
+        let at = utils::get_current_timestamp()?;
+        let (amount, seeds) = {
+            let mut stream = ctx.accounts.stream.load_mut()?;
+            (stream.withdraw_excess_topup_non_prepaid_ended(at)?, stream.seeds())
+        };
+        ctx.accounts.transfer_from_escrow(&seeds, amount)
+    }
+
+    /// Topup a non-prepaid zero-copy stream. See [`topup_non_prepaid`].
+    ///
+    /// # Arguments
+    ///
+    /// * `topup_amount` - Topup amount for the stream. The topup amount should be <= maximum acceptable topup amount.
+    pub fn topup_non_prepaid_zero_copy(ctx: Context<TopupNonPrepaidZeroCopy>, topup_amount: u64) -> Result<()> {
+        let at = utils::get_current_timestamp()?;
+        ctx.accounts.stream.load_mut()?.topup_non_prepaid(topup_amount, at)?;
+        ctx.accounts.transfer_to_escrow(topup_amount)
+    }
+
+    /// Change sender of a non-prepaid zero-copy stream. See [`change_sender_non_prepaid`].
+    ///
+    /// # Arguments
+    ///
+    /// * `new_sender` - The new sender
+    pub fn change_sender_non_prepaid_zero_copy(
+        ctx: Context<ChangeSenderNonPrepaidZeroCopy>,
+        new_sender: Pubkey,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        ctx.accounts
+            .stream
+            .load_mut()?
+            .change_sender_non_prepaid(signer, new_sender, at)?;
+
+        let stream_key = ctx.accounts.stream.key();
+        register_stream(
+            &mut ctx.accounts.new_sender_registry,
+            *ctx.bumps.get("new_sender_registry").unwrap(),
+            &mut ctx.accounts.new_sender_registry_page,
+            *ctx.bumps.get("new_sender_registry_page").unwrap(),
+            new_sender,
+            stream_key,
+        )
+    }
+
+    /// Set or remove the manager of a zero-copy stream. See [`set_manager`].
+    ///
+    /// # Arguments
+    ///
+    /// * `manager` - The new manager. Pass the default (all zeroes) address to remove the manager.
+    /// * `manager_permissions` - Rights delegated to the manager. See [`ManagerPermissions`] for more information.
+    pub fn set_manager_zero_copy(
+        ctx: Context<UpdateZeroCopyStream>,
+        manager: Pubkey,
+        manager_permissions: ManagerPermissions,
+    ) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        let mut stream = ctx.accounts.stream.load_mut()?;
+        stream.set_manager(signer, manager, manager_permissions, at)
+    }
+
+    /// Withdraw recipient funds from a zero-copy stream. See [`withdraw`].
+    pub fn withdraw_zero_copy(ctx: Context<WithdrawZeroCopy>) -> Result<u64> {
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        let (amount_available_to_withdraw, seeds, stake) = {
+            let mut stream = ctx.accounts.stream.load_mut()?;
+            let recipient = stream.recipient;
+            let amount = stream.withdraw_and_change_recipient(signer, recipient, Pubkey::default(), at)?;
+            (amount, stream.seeds(), stream.stake())
+        };
+        ctx.accounts
+            .transfer_from_escrow(&seeds, amount_available_to_withdraw)?;
+        record_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            stake,
+            amount_available_to_withdraw,
+            at,
+        )?;
+
+        Ok(amount_available_to_withdraw)
+    }
+
+    /// Withdraw recipient funds from a zero-copy stream and change recipient of the stream. See
+    /// [`withdraw_and_change_recipient`].
+    ///
+    /// # Arguments
+    ///
+    /// * `new_recipient` - The new recipient
+    pub fn withdraw_and_change_recipient_zero_copy(
+        ctx: Context<WithdrawAndChangeRecipientZeroCopy>,
+        new_recipient: Pubkey,
+    ) -> Result<u64> {
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        let (recipient, amount_available_to_withdraw, stream) = {
+            let mut stream = ctx.accounts.stream.load_mut()?;
+            let recipient = stream.recipient;
+            let amount = stream.withdraw_and_change_recipient(signer, recipient, new_recipient, at)?;
+            (recipient, amount, *stream)
+        };
+        ctx.accounts
+            .transfer_from_escrow(&stream.seeds(), amount_available_to_withdraw)?;
+        record_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            stream.stake(),
+            amount_available_to_withdraw,
+            at,
+        )?;
+
+        if stream.recipient != recipient {
+            let stream_key = ctx.accounts.stream.key();
+            register_stream(
+                &mut ctx.accounts.new_recipient_registry,
+                *ctx.bumps.get("new_recipient_registry").unwrap(),
+                &mut ctx.accounts.new_recipient_registry_page,
+                *ctx.bumps.get("new_recipient_registry_page").unwrap(),
+                new_recipient,
+                stream_key,
+            )?;
+        }
+
+        Ok(amount_available_to_withdraw)
+    }
+
+    /// Unstake a zero-copy activity stream before it ends. See [`unstake_early`].
+    pub fn unstake_early_zero_copy<'info>(ctx: Context<'_, '_, '_, 'info, UnstakeEarlyZeroCopy<'info>>) -> Result<u64> {
+        let at = utils::get_current_timestamp()?;
+        let (amount, stream) = {
+            let mut stream = ctx.accounts.stream.load_mut()?;
+            let amount = stream.unstake_early(ctx.accounts.staker.key(), at)?;
+            (amount, *stream)
+        };
+        let penalty = record_early_unstake(
+            &mut ctx.accounts.activity,
+            &ctx.accounts.position,
+            stream.stake(),
+            amount,
+            at,
+        )?;
+        let amount_returned = amount
+            .checked_sub(penalty)
+            .ok_or(StreamError::UnstakeAmountOutOfBounds)?;
+        let seeds = stream.seeds();
+        transfer_from_stream_escrow(
+            ctx.accounts.stream.to_account_info(),
+            &seeds,
+            &ctx.accounts.staker_token,
+            &ctx.accounts.escrow_token,
+            &ctx.accounts.token_program,
+            amount_returned,
+        )?;
+        if penalty > 0 {
+            transfer_from_stream_escrow(
+                ctx.accounts.stream.to_account_info(),
+                &seeds,
+                &penalty_token(ctx.remaining_accounts, &ctx.accounts.activity, stream.mint)?,
+                &ctx.accounts.escrow_token,
+                &ctx.accounts.token_program,
+                penalty,
+            )?;
+        }
+
+        Ok(amount_returned)
+    }
+
+    /// Pause a non-prepaid zero-copy stream. See [`pause_non_prepaid`].
+    pub fn pause_non_prepaid_zero_copy(ctx: Context<UpdateZeroCopyStream>) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        ctx.accounts.stream.load_mut()?.pause_non_prepaid(signer, at)
+    }
+
+    /// Resume a non-prepaid zero-copy stream. See [`resume_non_prepaid`].
+    pub fn resume_non_prepaid_zero_copy(ctx: Context<UpdateZeroCopyStream>) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let at = utils::get_current_timestamp()?;
+        ctx.accounts.stream.load_mut()?.resume_non_prepaid(signer, at)
+    }
+
+    /// Close a zero-copy stream. See [`close_stream`].
+    pub fn close_zero_copy_stream(ctx: Context<CloseZeroCopyStream>) -> Result<()> {
+        let stream = ctx.accounts.stream.load()?.to_stream();
+        stream.close(
+            ctx.accounts.signer.key(),
+            ctx.accounts.escrow_token.amount,
+            utils::get_current_timestamp()?,
+        )?;
+        ctx.accounts.close_escrow(&stream.seeds())?;
+        ctx.accounts.activity.stream_count = ctx
+            .accounts
+            .activity
+            .stream_count
+            .checked_sub(1)
+            .ok_or(StreamError::ActivityCountOutOfBounds)?;
+        Ok(())
+    }
+}
+
+/// Accounts shared by the legacy and v2 instructions creating a stream, see [`Create::stream_creation`] and
+/// [`CreateV2::stream_creation`].
+pub(crate) struct StreamCreation<'a, 'info> {
+    stream: &'a mut Account<'info, Stream>,
+    activity: &'a mut Account<'info, Activity>,
+    mint: &'a Account<'info, Mint>,
+    sender: &'a Signer<'info>,
+    escrow_token: &'a Account<'info, TokenAccount>,
+    beneficiary: Pubkey,
+    payer: &'a Signer<'info>,
+    system_program: &'a Program<'info, System>,
+}
+
+impl<'a, 'info> StreamCreation<'a, 'info> {
+    fn create(&mut self, is_prepaid: bool, params: CreateStreamParams, bump: u8, at: u64) -> Result<()> {
+        require!(
+            is_token_account_rent_exempt(self.escrow_token)?,
+            StreamError::EscrowNotRentExempt,
+        );
+        self.activity.stream_count = self
+            .activity
+            .stream_count
+            .checked_add(1)
+            .ok_or(StreamError::ActivityCountOutOfBounds)?;
+        self.stream.initialize(
+            is_prepaid,
+            self.activity.key(),
+            self.mint.key(),
+            self.sender.key(),
+            self.payer.key(),
+            params,
+            bump,
+            at,
+        )
+    }
+
+    fn emit_create(&self, initial_amount: u64) {
+        emit!(CreateStreamEvent{
+            sender: self.sender.key(),
+            recipient: self.stream.recipient,
+            stream: self.stream.key(),
+            amount: initial_amount,
+        });
+    }
+
+    /// Create a prepaid stream. Returns the prepaid amount needed.
+    pub fn create_prepaid(&mut self, params: CreateStreamParams, bump: u8, at: u64) -> Result<u64> {
+        let initial_amount = params.initial_amount;
+        self.create(true, params, bump, at)?;
+        let prepaid_amount_needed = self.stream.initialize_prepaid(at)?;
+        self.emit_create(initial_amount);
+        Ok(prepaid_amount_needed)
+    }
+
+    /// Create a non-prepaid stream.
+    pub fn create_non_prepaid(
+        &mut self,
+        params: CreateStreamParams,
+        topup_amount: u64,
+        bump: u8,
+        at: u64,
+    ) -> Result<()> {
+        self.create(false, params, bump, at)?;
+        self.stream.initialize_non_prepaid(topup_amount, at)
+    }
+
+    /// Create a prepaid stake in the activity of the stream and record it in the stake position of the beneficiary,
+    /// which is passed as the first remaining account and created on first use. Returns the prepaid amount needed.
+    pub fn create_activity_stream(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        params: CreateStreamParams,
+        lock_duration: u64,
+        allowlist_proof: Vec<[u8; 32]>,
+        bump: u8,
+        at: u64,
+    ) -> Result<u64> {
+        require!(
+            is_token_account_rent_exempt(self.activity)?,
+            StreamError::EscrowNotRentExempt,
+        );
+        self.activity.validate_stake(params.initial_amount, at)?;
+        let beneficiary = self.beneficiary;
+        self.activity.verify_staker(beneficiary, allowlist_proof)?;
+        require!(lock_duration > 0, StreamError::LockDurationNotAllowed);
+        let stake_weight = self.activity.stake_weight(lock_duration)?;
+        let starts_at = at;
+        let initial_amount = params.initial_amount;
+
+        // Activity streams start right away and last for the lock duration. They have no flow payments: the flow rate
+        // of the activity is emitted as rewards from the reward vault, so stakes only return their principal. The
+        // requested flow interval and flow rate are overridden.
+        let params = CreateStreamParams {
+            starts_at,
+            ends_at: starts_at.checked_add(lock_duration).ok_or(StreamError::InvalidEndsAt)?,
+            flow_interval: self.activity.duration,
+            flow_rate: 0,
+            ..params
+        };
+        self.create(true, params, bump, at)?;
+        self.stream.is_stake = true;
+        self.stream.stake_weight = stake_weight;
+        if beneficiary != self.sender.key() {
+            self.stream.beneficiary = beneficiary;
+        }
+        let prepaid_amount_needed = self.stream.initialize_prepaid(at)?;
+
+        let activity_key = self.activity.key();
+        let position_info = remaining_accounts.first().ok_or(ErrorCode::AccountNotEnoughKeys)?;
+        record_stake(
+            self.activity,
+            activity_key,
+            position_info,
+            beneficiary,
+            self.payer,
+            self.system_program,
+            prepaid_amount_needed,
+            stake_weight,
+            at,
+        )?;
+        self.emit_create(initial_amount);
+        Ok(prepaid_amount_needed)
+    }
+}
+
+/// Get the token account receiving the early unstake penalty, passed as the first remaining account of
+/// [`superstream::unstake_early`] and [`superstream::unstake_early_zero_copy`]. It is either the reward vault of the
+/// activity or a token account of the penalty treasury.
+fn penalty_token<'info>(
+    remaining_accounts: &[AccountInfo<'info>],
+    activity: &Account<'info, Activity>,
+    mint: Pubkey,
+) -> Result<Account<'info, TokenAccount>> {
+    let penalty_token_info = remaining_accounts.first().ok_or(ErrorCode::AccountNotEnoughKeys)?;
+    require!(penalty_token_info.is_writable, ErrorCode::ConstraintMut);
+    let penalty_token = Account::<TokenAccount>::try_from(penalty_token_info)?;
+    require!(
+        penalty_token.mint == mint && penalty_token.owner == activity.penalty_owner(activity.key()),
+        ErrorCode::ConstraintRaw,
+    );
+    Ok(penalty_token)
 }
 
 pub(crate) fn create_activity_internal(
@@ -733,7 +1583,6 @@ pub(crate) fn create_activity_internal(
     min_amount: u64,
     flow_rate: u64,
 ) -> Result<()> {
-    msg!("In fn create_activity_internal!!!");
     let activity = &mut ctx.accounts.activity;
     activity.initialize(
         is_active,
@@ -750,10 +1599,16 @@ pub(crate) fn create_activity_internal(
         seed,
         *ctx.bumps.get("activity").unwrap(),
         name,
+        utils::get_current_timestamp()?,
     )
 }
 
-/// Accounts struct for creating a new stream.
+/// Accounts struct for creating a new stream with the legacy [`create_prepaid`](superstream::create_prepaid),
+/// [`create_non_prepaid`](superstream::create_non_prepaid) and [`create_stream`](superstream::create_stream)
+/// instructions. The sender pays the rent and the stream is not added to the stream registries.
+///
+/// Stakes created with [`create_stream`](superstream::create_stream) also take the stake position PDA account of the
+/// sender in the activity as the first remaining account, writable. It is created on first use.
 #[derive(Accounts)]
 #[instruction(seed: u64, name: String)]
 pub struct Create<'info> {
@@ -765,7 +1620,7 @@ pub struct Create<'info> {
             activity.key().as_ref(),
             mint.key().as_ref(),
             sender.key().as_ref(),
-            name.as_bytes(),
+            get_name_seed(&name).as_ref(),
         ],
         payer = sender,
         space = Stream::space(&name),
@@ -811,6 +1666,121 @@ pub struct Create<'info> {
     pub system_program: Program<'info, System>,
 }
 
+/// Accounts struct for creating a new stream with the [`create_prepaid_v2`](superstream::create_prepaid_v2),
+/// [`create_non_prepaid_v2`](superstream::create_non_prepaid_v2) and [`create_stream_v2`](superstream::create_stream_v2)
+/// instructions.
+///
+/// Stakes created with [`create_stream_v2`](superstream::create_stream_v2) also take the stake position PDA account of
+/// the beneficiary in the activity as the first remaining account, writable. It is created on first use.
+#[derive(Accounts)]
+#[instruction(params: CreateStreamParams)]
+pub struct CreateV2<'info> {
+    /// Stream PDA account. This is initialized by the program.
+    #[account(
+        init,
+        seeds = [
+            STREAM_ACCOUNT_SEED,
+            activity.key().as_ref(),
+            mint.key().as_ref(),
+            sender.key().as_ref(),
+            get_name_seed(&params.name).as_ref(),
+        ],
+        payer = payer,
+        space = Stream::space(&params.name),
+        bump,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// Activity of the stream.
+    #[account(
+        mut,
+        constraint =
+            activity.stake_mint == mint.key(),
+        )]
+    pub activity: Account<'info, Activity>,
+
+    /// Stream sender wallet.
+    pub sender: Signer<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the sender.
+    #[account(
+        mut,
+        constraint =
+            sender_token.mint == mint.key()
+            && sender_token.owner == sender.key(),
+    )]
+    pub sender_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
+
+    /// Stream registry PDA account of the sender. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = StreamRegistry::LENGTH,
+        seeds = [REGISTRY_ACCOUNT_SEED, sender.key().as_ref()],
+        bump,
+    )]
+    pub sender_registry: Box<Account<'info, StreamRegistry>>,
+    /// Next page of the stream registry of the sender, see [`StreamRegistry::next_page`]. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = StreamRegistryPage::LENGTH,
+        seeds = [
+            REGISTRY_ACCOUNT_SEED,
+            sender.key().as_ref(),
+            sender_registry.next_page().to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub sender_registry_page: Box<Account<'info, StreamRegistryPage>>,
+    /// Stream registry PDA account of the recipient. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = StreamRegistry::LENGTH,
+        seeds = [REGISTRY_ACCOUNT_SEED, params.recipient.as_ref()],
+        bump,
+    )]
+    pub recipient_registry: Box<Account<'info, StreamRegistry>>,
+    /// Next page of the stream registry of the recipient, see [`StreamRegistry::next_page`]. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = StreamRegistryPage::LENGTH,
+        seeds = [
+            REGISTRY_ACCOUNT_SEED,
+            params.recipient.as_ref(),
+            recipient_registry.next_page().to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub recipient_registry_page: Box<Account<'info, StreamRegistryPage>>,
+    /// Wallet owning the stake of an activity stream, i.e. its stake position. Pass the sender unless the stake is
+    /// funded on behalf of another wallet, which then also receives the withdrawals. Ignored for other streams.
+    ///
+    /// CHECK: Any wallet can be the beneficiary.
+    pub beneficiary: UncheckedAccount<'info>,
+
+    /// Wallet paying for the rent of the stream, registry and position accounts. Pass the sender unless the rent is
+    /// sponsored.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
 
 // Accounts struct for creating a new stream.
 #[derive(Accounts)]
@@ -823,10 +1793,10 @@ pub struct CreateActivity<'info> {
             ACTIVITY_ACCOUNT_SEED,
             seed.to_le_bytes().as_ref(),
             stake_mint.key().as_ref(),
-            name.as_bytes(),
+            get_name_seed(&name).as_ref(),
         ],
         payer = creator,
-        space = Activity::space(&name),
+        space = Activity::space(&name, 0),
         bump,
     )]
     pub activity: Account<'info, Activity>,
@@ -847,53 +1817,283 @@ pub struct CreateActivity<'info> {
     pub system_program: Program<'info, System>,
 }
 
+/// Accounts struct for updating or deactivating an activity.
 #[derive(Accounts)]
-pub struct NewDistributor<'info> {
-    #[account(
-        init,
-        payer = creator,
-        space = 3000,     //TODO: implement space()
-        seeds = [
-            DISTRIBUTOR_ACCOUNT_SEED.as_ref(),
-            activity.key().to_bytes().as_ref(),
-            mint.key().to_bytes().as_ref()
-            ],
-        bump
-    )]
-    pub distributor: Account<'info, Distributor>,
-
+pub struct UpdateActivity<'info> {
+    /// Activity PDA account.
+    #[account(mut)]
     pub activity: Account<'info, Activity>,
 
-    /// Stream sender wallet.
-    #[account(mut)]
+    /// Creator of the activity.
     pub creator: Signer<'info>,
+}
 
-    /// Associated token account of the sender.
-    #[account(
-        mut,
-        constraint =
-            sender_token.mint == mint.key()
-            && sender_token.owner == creator.key(),
-    )]
-    pub sender_token: Box<Account<'info, TokenAccount>>,
-
-    pub mint: Account<'info, Mint>,
-
-    /// distributor is a PDA of this program, distributor.key() is this program.
+/// Accounts struct for setting the lock multipliers of an activity.
+#[derive(Accounts)]
+#[instruction(lock_multipliers: Vec<LockMultiplier>)]
+pub struct SetLockMultipliers<'info> {
+    /// Activity PDA account. It is resized to fit the lock multipliers.
     #[account(
         mut,
-        constraint =
-        reward_escrow_token.mint == mint.key()
-            && reward_escrow_token.owner == distributor.key(),
+        realloc = Activity::space(&activity.name, lock_multipliers.len()),
+        realloc::payer = creator,
+        realloc::zero = false,
     )]
-    pub reward_escrow_token: Box<Account<'info, TokenAccount>>,
+    pub activity: Account<'info, Activity>,
 
-    /// SPL token program.
-    pub token_program: Program<'info, Token>,
+    /// Creator of the activity.
+    #[account(mut)]
+    pub creator: Signer<'info>,
 
+    /// Solana system program.
     pub system_program: Program<'info, System>,
 }
 
+/// Accounts struct for quoting an activity.
+#[derive(Accounts)]
+pub struct QuoteActivity<'info> {
+    /// Activity PDA account.
+    pub activity: Account<'info, Activity>,
+}
+
+/// Accounts struct for closing an activity.
+#[derive(Accounts)]
+pub struct CloseActivity<'info> {
+    /// Activity PDA account. The rent goes back to the creator.
+    #[account(mut, close = creator, has_one = reward_mint)]
+    pub activity: Account<'info, Activity>,
+
+    /// Creator of the activity.
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    /// SPL token mint account of the rewards.
+    pub reward_mint: Box<Account<'info, Mint>>,
+    /// Associated token account of the creator. Receives the rewards left in the reward vault.
+    #[account(
+        mut,
+        constraint =
+            creator_token.mint == reward_mint.key()
+            && creator_token.owner == creator.key(),
+    )]
+    pub creator_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token account of the activity holding the rewards. Closed along with the activity, with its rent
+    /// going to the creator.
+    #[account(
+        mut,
+        constraint =
+            reward_vault.mint == reward_mint.key()
+            && reward_vault.owner == activity.key(),
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for migrating an activity to the current layout.
+#[derive(Accounts)]
+pub struct MigrateActivity<'info> {
+    /// Activity PDA account.
+    ///
+    /// CHECK: The account is deserialized as an [`Activity`] in the original layout in the instruction, which checks
+    /// the discriminator. The owner check in the constraint makes sure that it was created by this program.
+    #[account(mut, owner = crate::ID)]
+    pub activity: UncheckedAccount<'info>,
+
+    /// Creator of the activity. Pays for the extra rent.
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts struct for funding the rewards of an activity.
+#[derive(Accounts)]
+pub struct FundActivityRewards<'info> {
+    /// Activity PDA account.
+    #[account(mut, has_one = reward_mint)]
+    pub activity: Account<'info, Activity>,
+
+    /// Wallet funding the rewards.
+    pub funder: Signer<'info>,
+    /// SPL token mint account of the rewards.
+    pub reward_mint: Box<Account<'info, Mint>>,
+    /// Associated token account of the funder.
+    #[account(
+        mut,
+        constraint =
+            funder_token.mint == reward_mint.key()
+            && funder_token.owner == funder.key(),
+    )]
+    pub funder_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token account of the activity holding the rewards.
+    #[account(
+        mut,
+        constraint =
+            reward_vault.mint == reward_mint.key()
+            && reward_vault.owner == activity.key(),
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for closing a stake position.
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    /// Activity PDA account.
+    #[account(mut)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity. The rent goes back to the staker.
+    #[account(
+        mut,
+        close = staker,
+        seeds = [POSITION_ACCOUNT_SEED, activity.key().as_ref(), staker.key().as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Box<Account<'info, StakePosition>>,
+
+    /// Staker wallet.
+    #[account(mut)]
+    pub staker: Signer<'info>,
+}
+
+/// Accounts struct for claiming the rewards of a stake position.
+#[derive(Accounts)]
+pub struct ClaimActivityRewards<'info> {
+    /// Activity PDA account.
+    #[account(mut, has_one = reward_mint)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    #[account(
+        mut,
+        seeds = [POSITION_ACCOUNT_SEED, activity.key().as_ref(), staker.key().as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Box<Account<'info, StakePosition>>,
+
+    /// Staker wallet.
+    pub staker: Signer<'info>,
+    /// SPL token mint account of the rewards.
+    pub reward_mint: Box<Account<'info, Mint>>,
+    /// Associated token account of the staker.
+    #[account(
+        mut,
+        constraint =
+            staker_token.mint == reward_mint.key()
+            && staker_token.owner == staker.key(),
+    )]
+    pub staker_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token account of the activity holding the rewards.
+    #[account(
+        mut,
+        constraint =
+            reward_vault.mint == reward_mint.key()
+            && reward_vault.owner == activity.key(),
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for unstaking an activity stream early.
+#[derive(Accounts)]
+pub struct UnstakeEarly<'info> {
+    /// Stream PDA account.
+    #[account(mut)]
+    pub stream: Account<'info, Stream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
+    #[account(
+        mut,
+        seeds = [POSITION_ACCOUNT_SEED, stream.activity.as_ref(), stream.staker().as_ref()],
+        bump,
+    )]
+    pub position: UncheckedAccount<'info>,
+
+    /// Wallet owning the stake, i.e. the beneficiary of the stream or its creator. See [`Stream::staker`].
+    pub staker: Signer<'info>,
+
+    /// Associated token account of the staker.
+    #[account(
+        mut,
+        constraint =
+            staker_token.mint == stream.mint
+            && staker_token.owner == staker.key(),
+    )]
+    pub staker_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == stream.mint
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct NewDistributor<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 3000,     //TODO: implement space()
+        seeds = [
+            DISTRIBUTOR_ACCOUNT_SEED.as_ref(),
+            activity.key().to_bytes().as_ref(),
+            mint.key().to_bytes().as_ref()
+            ],
+        bump
+    )]
+    pub distributor: Account<'info, Distributor>,
+
+    #[account(mut)]
+    pub activity: Account<'info, Activity>,
+
+    /// Stream sender wallet.
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    /// Associated token account of the sender.
+    #[account(
+        mut,
+        constraint =
+            sender_token.mint == mint.key()
+            && sender_token.owner == creator.key(),
+    )]
+    pub sender_token: Box<Account<'info, TokenAccount>>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// distributor is a PDA of this program, distributor.key() is this program.
+    #[account(
+        mut,
+        constraint =
+        reward_escrow_token.mint == mint.key()
+            && reward_escrow_token.owner == distributor.key(),
+    )]
+    pub reward_escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Wallet paying for the rent of the distributor account. Pass the creator unless the rent is sponsored.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct Claim<'info> {
     #[account(mut, has_one = mint)] //state for account
@@ -915,7 +2115,6 @@ pub struct Claim<'info> {
     )]
     pub recipent_token: Account<'info, TokenAccount>,
 
-    #[account(mut)]
     pub claimer: Signer<'info>,
 
     /// SPL token mint account.
@@ -923,19 +2122,23 @@ pub struct Claim<'info> {
 
     #[account(
         init,
-        payer = claimer,
+        payer = payer,
         seeds = [
             STATUS_ACCOUNT_SEED.as_ref(),
             distributor.to_account_info().key().to_bytes().as_ref(),
             claimer.key().to_bytes().as_ref(),
             ],
         bump,
-        space = 50)]
+        space = Status::LENGTH)]
     pub status: Account<'info, Status>,
 
     pub token_program: Program<'info, Token>,
 
     pub system_program: Program<'info, System>,
+
+    /// Wallet paying for the rent of the status account. Pass the claimer unless the rent is sponsored.
+    #[account(mut)]
+    pub payer: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -970,6 +2173,63 @@ pub struct RecycleReward<'info> {
     pub system_program: Program<'info, System>,
 }
 
+/// Accounts struct for closing a reward distributor.
+#[derive(Accounts)]
+pub struct CloseDistributor<'info> {
+    /// Reward distributor PDA account. The rent is refunded to the rent payer.
+    #[account(mut, close = rent_payer)]
+    pub distributor: Account<'info, Distributor>,
+
+    /// Activity PDA account of the distributor.
+    #[account(mut, constraint = activity.key() == distributor.activity_key)]
+    pub activity: Account<'info, Activity>,
+
+    /// Creator of the distributor.
+    pub creator: Signer<'info>,
+    /// Wallet which paid the rent of the distributor.
+    ///
+    /// CHECK: Only 1 check is needed which is in the constraint. That is enough to verify that we are refunding the
+    /// rent to the rent payer.
+    #[account(mut, constraint = rent_payer.key() == distributor.rent_payer)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Associated token escrow account holding the rewards of the distributor.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == distributor.mint
+            && escrow_token.owner == distributor.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for closing a claim status.
+#[derive(Accounts)]
+pub struct CloseStatus<'info> {
+    /// Reward distributor PDA account the status belongs to. It needs to be closed.
+    ///
+    /// CHECK: The instruction checks that the account is closed. The status seeds tie it to the status.
+    pub distributor: UncheckedAccount<'info>,
+
+    /// Claim status PDA account. The rent is refunded to the rent payer.
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [STATUS_ACCOUNT_SEED, distributor.key().as_ref(), status.claimer.as_ref()],
+        bump,
+    )]
+    pub status: Account<'info, Status>,
+    /// Wallet which paid the rent of the status.
+    ///
+    /// CHECK: Only 1 check is needed which is in the constraint. That is enough to verify that we are refunding the
+    /// rent to the rent payer.
+    #[account(mut, constraint = rent_payer.key() == status.rent_payer)]
+    pub rent_payer: UncheckedAccount<'info>,
+}
+
 /// Accounts struct for cancelling a stream.
 #[derive(Accounts)]
 #[instruction(seed: u64, name: String, recipient: Pubkey)]
@@ -979,16 +2239,29 @@ pub struct Cancel<'info> {
         mut,
         seeds = [
             STREAM_ACCOUNT_SEED,
-            seed.to_le_bytes().as_ref(),
+            stream.activity.as_ref(),
             mint.key().as_ref(),
-            name.as_bytes(),
+            stream.creator.as_ref(),
+            get_name_seed(&name).as_ref(),
         ],
-        bump,
+        bump = stream.bump,
     )]
     pub stream: Account<'info, Stream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
+    #[account(
+        mut,
+        seeds = [POSITION_ACCOUNT_SEED, stream.activity.as_ref(), stream.staker().as_ref()],
+        bump,
+    )]
+    pub position: UncheckedAccount<'info>,
 
-    /// Signer wallet. Either the sender or the receiver can cancel the stream till it's solvent. After insolvency,
-    /// anyone can cancel.
+    /// Signer wallet. Either the sender, the manager (if allowed to) or the receiver can cancel the stream till it's
+    /// solvent. After insolvency, anyone can cancel.
     pub signer: Signer<'info>,
 
     /// Stream sender account.
@@ -1008,20 +2281,21 @@ pub struct Cancel<'info> {
             && signer_token.owner == signer.key(),
     )]
     pub signer_token: Box<Account<'info, TokenAccount>>,
-    /// Associated token account of the sender.
+    /// Associated token account of the sender, or of the beneficiary of a stake, which gets the refund. See
+    /// [`Stream::refundee`].
     #[account(
         mut,
         constraint =
             sender_token.mint == mint.key()
-            && sender_token.owner == sender.key(),
+            && sender_token.owner == stream.refundee(),
     )]
     pub sender_token: Box<Account<'info, TokenAccount>>,
-    /// Associated token account of the recipient.
+    /// Associated token account of the recipient, or of the beneficiary of a stake. See [`Stream::payee`].
     #[account(
         mut,
         constraint =
             recipient_token.mint == mint.key()
-            && recipient_token.owner == recipient,
+            && recipient_token.owner == stream.payee(),
     )]
     pub recipient_token: Box<Account<'info, TokenAccount>>,
     /// Associated token escrow account holding the funds for this stream.
@@ -1037,6 +2311,24 @@ pub struct Cancel<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+/// Accounts struct for requesting cancellation of a stream.
+#[derive(Accounts)]
+pub struct RequestCancel<'info> {
+    /// Stream PDA account.
+    #[account(mut)]
+    pub stream: Account<'info, Stream>,
+
+    /// Signer wallet. Either the sender, the manager (if allowed to) or the receiver can request cancellation.
+    pub signer: Signer<'info>,
+}
+
+/// Accounts struct for quoting a stream.
+#[derive(Accounts)]
+pub struct QuoteStream<'info> {
+    /// Stream PDA account.
+    pub stream: Account<'info, Stream>,
+}
+
 /// Accounts struct for withdrawing excess sender topup from a non-prepaid stream.
 #[derive(Accounts)]
 #[instruction(seed: u64, name: String)]
@@ -1046,11 +2338,12 @@ pub struct WithdrawExcessTopupNonPrepaidEnded<'info> {
         mut,
         seeds = [
             STREAM_ACCOUNT_SEED,
-            seed.to_le_bytes().as_ref(),
+            stream.activity.as_ref(),
             mint.key().as_ref(),
-            name.as_bytes(),
+            stream.creator.as_ref(),
+            get_name_seed(&name).as_ref(),
         ],
-        bump,
+        bump = stream.bump,
     )]
     pub stream: Account<'info, Stream>,
 
@@ -1066,14 +2359,571 @@ pub struct WithdrawExcessTopupNonPrepaidEnded<'info> {
     /// SPL token mint account.
     pub mint: Box<Account<'info, Mint>>,
 
-    /// Associated token account of the sender.
+    /// Associated token account of the sender.
+    #[account(
+        mut,
+        constraint =
+            sender_token.mint == mint.key()
+            && sender_token.owner == sender.key(),
+    )]
+    pub sender_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for topping up a non-prepaid stream.
+#[derive(Accounts)]
+#[instruction(seed: u64, name: String)]
+pub struct TopupNonPrepaid<'info> {
+    /// Stream PDA account.
+    #[account(
+        mut,
+        seeds = [
+            STREAM_ACCOUNT_SEED,
+            stream.activity.as_ref(),
+            mint.key().as_ref(),
+            stream.creator.as_ref(),
+            get_name_seed(&name).as_ref(),
+        ],
+        bump = stream.bump,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// Signer wallet. Anyone can topup a stream. But the refund when the stream gets cancelled will only go to the
+    /// stream sender.
+    pub signer: Signer<'info>,
+    /// SPL token mint account.
+    pub mint: Account<'info, Mint>,
+
+    /// Associated token account of the signer.
+    #[account(
+        mut,
+        constraint =
+            signer_token.mint == mint.key()
+            && signer_token.owner == signer.key(),
+    )]
+    pub signer_token: Account<'info, TokenAccount>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Account<'info, TokenAccount>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for changing the sender of a non-prepaid stream.
+#[derive(Accounts)]
+#[instruction(seed: u64, name: String, new_sender: Pubkey)]
+pub struct ChangeSenderNonPrepaid<'info> {
+    /// Stream PDA account.
+    #[account(
+        mut,
+        seeds = [
+            STREAM_ACCOUNT_SEED,
+            stream.activity.as_ref(),
+            mint.key().as_ref(),
+            stream.creator.as_ref(),
+            get_name_seed(&name).as_ref(),
+        ],
+        bump = stream.bump,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// Signer wallet. Signer needs to be either the sender or the manager (if they are allowed to).
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// SPL token mint account.
+    pub mint: Account<'info, Mint>,
+
+    /// Stream registry PDA account of the new sender. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = StreamRegistry::LENGTH,
+        seeds = [REGISTRY_ACCOUNT_SEED, new_sender.as_ref()],
+        bump,
+    )]
+    pub new_sender_registry: Box<Account<'info, StreamRegistry>>,
+    /// Next page of the stream registry of the new sender, see [`StreamRegistry::next_page`]. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = StreamRegistryPage::LENGTH,
+        seeds = [
+            REGISTRY_ACCOUNT_SEED,
+            new_sender.as_ref(),
+            new_sender_registry.next_page().to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub new_sender_registry_page: Box<Account<'info, StreamRegistryPage>>,
+
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts struct for setting the manager of a stream.
+#[derive(Accounts)]
+pub struct SetManager<'info> {
+    /// Stream PDA account.
+    #[account(
+        mut,
+        has_one = sender,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// Stream sender wallet.
+    pub sender: Signer<'info>,
+}
+
+/// Accounts struct for withdrawing recipient funds from a stream.
+#[derive(Accounts)]
+#[instruction(name: String, recipient: Pubkey, sender: Pubkey)]
+pub struct Withdraw<'info> {
+    /// Stream PDA account.
+    #[account(
+        mut,
+        has_one = sender,
+    )]
+    pub stream: Account<'info, Stream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
+    #[account(
+        mut,
+        seeds = [POSITION_ACCOUNT_SEED, stream.activity.as_ref(), stream.staker().as_ref()],
+        bump,
+    )]
+    pub position: UncheckedAccount<'info>,
+
+    /// Signer wallet. Anybody can call the withdraw method. The recipient of the withdrawn amount is not related to the
+    /// signer. Recipient is passed as an argument, based on which the stream PDA is accessed, so if a malicious user
+    /// tries to send themselves as a recipient, but a different stream account, the constraint for the stream account
+    /// will fail.
+    pub signer: Signer<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the recipient, or of the beneficiary of a stake. See [`Stream::payee`].
+    #[account(
+        mut,
+        constraint =
+            recipient_token.mint == mint.key()
+            && recipient_token.owner == stream.payee(),
+    )]
+    pub recipient_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for withdrawing recipient funds from a stream and changing recipient of a stream.
+#[derive(Accounts)]
+#[instruction(name: String, recipient: Pubkey, sender: Pubkey, new_recipient: Pubkey)]
+pub struct WithdrawAndChangeRecipient<'info> {
+    /// Stream PDA account.
+    #[account(
+        mut,
+        has_one = sender,
+    )]
+    pub stream: Account<'info, Stream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
+    #[account(
+        mut,
+        seeds = [POSITION_ACCOUNT_SEED, stream.activity.as_ref(), stream.staker().as_ref()],
+        bump,
+    )]
+    pub position: UncheckedAccount<'info>,
+
+    /// Signer wallet. Anybody can call the withdraw method. The recipient of the withdrawn amount is not related to the
+    /// signer. Recipient is passed as an argument, based on which the stream PDA is accessed, so if a malicious user
+    /// tries to send themselves as a recipient, but a different stream account, the constraint for the stream account
+    /// will fail.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// SPL token mint account.
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the recipient, or of the beneficiary of a stake. See [`Stream::payee`].
+    #[account(
+        mut,
+        constraint =
+            recipient_token.mint == mint.key()
+            && recipient_token.owner == stream.payee(),
+    )]
+    pub recipient_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// Stream registry PDA account of the new recipient. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = StreamRegistry::LENGTH,
+        seeds = [REGISTRY_ACCOUNT_SEED, new_recipient.as_ref()],
+        bump,
+    )]
+    pub new_recipient_registry: Box<Account<'info, StreamRegistry>>,
+    /// Next page of the stream registry of the new recipient, see [`StreamRegistry::next_page`]. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = StreamRegistryPage::LENGTH,
+        seeds = [
+            REGISTRY_ACCOUNT_SEED,
+            new_recipient.as_ref(),
+            new_recipient_registry.next_page().to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub new_recipient_registry_page: Box<Account<'info, StreamRegistryPage>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts struct for pausing a non-prepaid stream.
+#[derive(Accounts)]
+#[instruction(seed: u64, name: String)]
+pub struct PauseNonPrepaid<'info> {
+    /// Stream PDA account.
+    #[account(
+        mut,
+        seeds = [
+            STREAM_ACCOUNT_SEED,
+            stream.activity.as_ref(),
+            mint.key().as_ref(),
+            stream.creator.as_ref(),
+            get_name_seed(&name).as_ref(),
+        ],
+        bump = stream.bump,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// Signer wallet. Signer needs to be either the sender or the manager (if they are allowed to) or the recipient.
+    pub signer: Signer<'info>,
+    /// SPL token mint account.
+    pub mint: Account<'info, Mint>,
+}
+
+/// Accounts struct for resuming a non-prepaid stream.
+#[derive(Accounts)]
+#[instruction(seed: u64, name: String)]
+pub struct ResumeNonPrepaid<'info> {
+    /// Stream PDA account.
+    #[account(
+        mut,
+        seeds = [
+            STREAM_ACCOUNT_SEED,
+            stream.activity.as_ref(),
+            mint.key().as_ref(),
+            stream.creator.as_ref(),
+            get_name_seed(&name).as_ref(),
+        ],
+        bump = stream.bump,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    /// Signer wallet. Signer needs to be either the sender or the manager (if they are allowed to) or the recipient
+    /// (exception is if the stream was paused by the sender and recipient is not allowed to resume a stream paused by
+    /// sender).
+    pub signer: Signer<'info>,
+    /// SPL token mint account.
+    pub mint: Account<'info, Mint>,
+}
+
+/// Accounts struct for closing a stream.
+#[derive(Accounts)]
+pub struct CloseStream<'info> {
+    /// Stream PDA account. The rent is refunded to the rent payer.
+    #[account(mut, close = rent_payer)]
+    pub stream: Account<'info, Stream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.activity)]
+    pub activity: Account<'info, Activity>,
+
+    /// Signer wallet. Either the sender or the recipient can close the stream.
+    pub signer: Signer<'info>,
+    /// Wallet which paid the rent of the stream.
+    ///
+    /// CHECK: Only 1 check is needed which is in the constraint. That is enough to verify that we are refunding the
+    /// rent to the rent payer.
+    #[account(mut, constraint = rent_payer.key() == stream.rent_payer)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == stream.mint
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for migrating a stream to the zero-copy layout.
+#[derive(Accounts)]
+pub struct MigrateStream<'info> {
+    /// Stream PDA account.
+    ///
+    /// CHECK: The account is deserialized as a [`Stream`] in the current or the original layout in the instruction,
+    /// which checks the discriminator. The owner check in the constraint makes sure that it was created by this program.
+    #[account(mut, owner = crate::ID)]
+    pub stream: UncheckedAccount<'info>,
+    /// Activity PDA account of the stream.
+    #[account(mut)]
+    pub activity: Account<'info, Activity>,
+
+    /// Signer wallet. Either the sender or the recipient can migrate the stream.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts struct for cancelling a zero-copy stream.
+///
+/// The zero-copy stream accounts structs do not check the stream PDA seeds. Only this program can create accounts
+/// with the zero-copy stream discriminator, and it only does so in place of existing stream PDAs.
+#[derive(Accounts)]
+pub struct CancelZeroCopy<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.load()?.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
+    #[account(
+        mut,
+        seeds = [POSITION_ACCOUNT_SEED, stream.load()?.activity.as_ref(), stream.load()?.staker().as_ref()],
+        bump,
+    )]
+    pub position: UncheckedAccount<'info>,
+
+    /// Signer wallet. Either the sender, the manager (if allowed to) or the receiver can cancel the stream till it's
+    /// solvent. After insolvency, anyone can cancel.
+    pub signer: Signer<'info>,
+
+    /// Stream sender account.
+    ///
+    /// CHECK: Only 1 check is needed which is in the constraint. That is enough to verify that we are sending the funds
+    /// to the stream sender.
+    #[account(constraint = sender.key() == stream.load()?.sender)]
+    pub sender: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    #[account(constraint = mint.key() == stream.load()?.mint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the signer.
+    #[account(
+        mut,
+        constraint =
+            signer_token.mint == mint.key()
+            && signer_token.owner == signer.key(),
+    )]
+    pub signer_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token account of the sender, or of the beneficiary of a stake, which gets the refund. See
+    /// [`Stream::refundee`].
+    #[account(
+        mut,
+        constraint =
+            sender_token.mint == mint.key()
+            && sender_token.owner == stream.load()?.refundee(),
+    )]
+    pub sender_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token account of the recipient, or of the beneficiary of a stake. See [`Stream::payee`].
+    #[account(
+        mut,
+        constraint =
+            recipient_token.mint == mint.key()
+            && recipient_token.owner == stream.load()?.payee(),
+    )]
+    pub recipient_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for updating a zero-copy stream without any transfers, e.g. pausing it.
+#[derive(Accounts)]
+pub struct UpdateZeroCopyStream<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+
+    /// Signer wallet. The instruction checks if the signer is allowed to update the stream.
+    pub signer: Signer<'info>,
+}
+
+/// Accounts struct for getting a quote of the amounts of a zero-copy stream.
+#[derive(Accounts)]
+pub struct QuoteZeroCopyStream<'info> {
+    /// Zero-copy stream PDA account.
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+}
+
+/// Accounts struct for withdrawing excess sender topup from a non-prepaid zero-copy stream.
+#[derive(Accounts)]
+pub struct WithdrawExcessTopupNonPrepaidEndedZeroCopy<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+
+    /// Signer wallet.
+    pub signer: Signer<'info>,
+
+    /// Stream sender account.
+    ///
+    /// CHECK: Only 1 check is needed which is in the constraint. That is enough to verify that we are sending the funds
+    /// to the stream sender.
+    #[account(constraint = sender.key() == stream.load()?.sender)]
+    pub sender: UncheckedAccount<'info>,
+    /// SPL token mint account.
+    #[account(constraint = mint.key() == stream.load()?.mint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the sender.
+    #[account(
+        mut,
+        constraint =
+            sender_token.mint == mint.key()
+            && sender_token.owner == sender.key(),
+    )]
+    pub sender_token: Box<Account<'info, TokenAccount>>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for topping up a non-prepaid zero-copy stream.
+#[derive(Accounts)]
+pub struct TopupNonPrepaidZeroCopy<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+
+    /// Signer wallet. Anyone can topup a stream. But the refund when the stream gets cancelled will only go to the
+    /// stream sender.
+    pub signer: Signer<'info>,
+    /// SPL token mint account.
+    #[account(constraint = mint.key() == stream.load()?.mint)]
+    pub mint: Account<'info, Mint>,
+
+    /// Associated token account of the signer.
+    #[account(
+        mut,
+        constraint =
+            signer_token.mint == mint.key()
+            && signer_token.owner == signer.key(),
+    )]
+    pub signer_token: Account<'info, TokenAccount>,
+    /// Associated token escrow account holding the funds for this stream.
+    #[account(
+        mut,
+        constraint =
+            escrow_token.mint == mint.key()
+            && escrow_token.owner == stream.key(),
+    )]
+    pub escrow_token: Account<'info, TokenAccount>,
+
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts struct for withdrawing recipient funds from a zero-copy stream.
+#[derive(Accounts)]
+pub struct WithdrawZeroCopy<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.load()?.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
+    #[account(
+        mut,
+        seeds = [POSITION_ACCOUNT_SEED, stream.load()?.activity.as_ref(), stream.load()?.staker().as_ref()],
+        bump,
+    )]
+    pub position: UncheckedAccount<'info>,
+
+    /// Signer wallet. Anybody can call the withdraw method. The withdrawn amount always goes to the stream recipient.
+    pub signer: Signer<'info>,
+    /// SPL token mint account.
+    #[account(constraint = mint.key() == stream.load()?.mint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// Associated token account of the recipient, or of the beneficiary of a stake. See [`Stream::payee`].
     #[account(
         mut,
         constraint =
-            sender_token.mint == mint.key()
-            && sender_token.owner == sender.key(),
+            recipient_token.mint == mint.key()
+            && recipient_token.owner == stream.load()?.payee(),
     )]
-    pub sender_token: Box<Account<'info, TokenAccount>>,
+    pub recipient_token: Box<Account<'info, TokenAccount>>,
     /// Associated token escrow account holding the funds for this stream.
     #[account(
         mut,
@@ -1087,37 +2937,41 @@ pub struct WithdrawExcessTopupNonPrepaidEnded<'info> {
     pub token_program: Program<'info, Token>,
 }
 
-/// Accounts struct for topping up a non-prepaid stream.
+/// Accounts struct for withdrawing recipient funds from a zero-copy stream and changing recipient of the stream.
 #[derive(Accounts)]
-#[instruction(seed: u64, name: String)]
-pub struct TopupNonPrepaid<'info> {
-    /// Stream PDA account.
+#[instruction(new_recipient: Pubkey)]
+pub struct WithdrawAndChangeRecipientZeroCopy<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.load()?.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
     #[account(
         mut,
-        seeds = [
-            STREAM_ACCOUNT_SEED,
-            seed.to_le_bytes().as_ref(),
-            mint.key().as_ref(),
-            name.as_bytes(),
-        ],
+        seeds = [POSITION_ACCOUNT_SEED, stream.load()?.activity.as_ref(), stream.load()?.staker().as_ref()],
         bump,
     )]
-    pub stream: Account<'info, Stream>,
+    pub position: UncheckedAccount<'info>,
 
-    /// Signer wallet. Anyone can topup a stream. But the refund when the stream gets cancelled will only go to the
-    /// stream sender.
+    /// Signer wallet. Anybody can call the withdraw method. The withdrawn amount always goes to the stream recipient.
+    #[account(mut)]
     pub signer: Signer<'info>,
     /// SPL token mint account.
-    pub mint: Account<'info, Mint>,
+    #[account(constraint = mint.key() == stream.load()?.mint)]
+    pub mint: Box<Account<'info, Mint>>,
 
-    /// Associated token account of the signer.
+    /// Associated token account of the recipient, or of the beneficiary of a stake. See [`Stream::payee`].
     #[account(
         mut,
         constraint =
-            signer_token.mint == mint.key()
-            && signer_token.owner == signer.key(),
+            recipient_token.mint == mint.key()
+            && recipient_token.owner == stream.load()?.payee(),
     )]
-    pub signer_token: Account<'info, TokenAccount>,
+    pub recipient_token: Box<Account<'info, TokenAccount>>,
     /// Associated token escrow account holding the funds for this stream.
     #[account(
         mut,
@@ -1125,67 +2979,72 @@ pub struct TopupNonPrepaid<'info> {
             escrow_token.mint == mint.key()
             && escrow_token.owner == stream.key(),
     )]
-    pub escrow_token: Account<'info, TokenAccount>,
-
-    /// SPL token program.
-    pub token_program: Program<'info, Token>,
-}
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
 
-/// Accounts struct for changing the sender of a non-prepaid stream.
-#[derive(Accounts)]
-#[instruction(seed: u64, name: String)]
-pub struct ChangeSenderNonPrepaid<'info> {
-    /// Stream PDA account.
+    /// Stream registry PDA account of the new recipient. Created on first use.
     #[account(
-        mut,
+        init_if_needed,
+        payer = signer,
+        space = StreamRegistry::LENGTH,
+        seeds = [REGISTRY_ACCOUNT_SEED, new_recipient.as_ref()],
+        bump,
+    )]
+    pub new_recipient_registry: Box<Account<'info, StreamRegistry>>,
+    /// Next page of the stream registry of the new recipient, see [`StreamRegistry::next_page`]. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = StreamRegistryPage::LENGTH,
         seeds = [
-            STREAM_ACCOUNT_SEED,
-            seed.to_le_bytes().as_ref(),
-            mint.key().as_ref(),
-            name.as_bytes(),
+            REGISTRY_ACCOUNT_SEED,
+            new_recipient.as_ref(),
+            new_recipient_registry.next_page().to_le_bytes().as_ref(),
         ],
         bump,
     )]
-    pub stream: Account<'info, Stream>,
+    pub new_recipient_registry_page: Box<Account<'info, StreamRegistryPage>>,
 
-    // Stream sender wallet.
-    pub sender: Signer<'info>,
-    /// SPL token mint account.
-    pub mint: Account<'info, Mint>,
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
 }
 
-/// Accounts struct for withdrawing recipient funds from a stream and changing recipient of a stream.
+/// Accounts struct for unstaking a zero-copy activity stream early.
 #[derive(Accounts)]
-#[instruction(name: String, recipient: Pubkey, sender: Pubkey)]
-pub struct WithdrawAndChangeRecipient<'info> {
-    /// Stream PDA account.
+pub struct UnstakeEarlyZeroCopy<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.load()?.activity)]
+    pub activity: Account<'info, Activity>,
+    /// Stake position PDA account of the staker in the activity.
+    ///
+    /// CHECK: The seeds constraint checks the address. The position is only updated if it exists.
     #[account(
         mut,
-        has_one = sender,
+        seeds = [POSITION_ACCOUNT_SEED, stream.load()?.activity.as_ref(), stream.load()?.staker().as_ref()],
+        bump,
     )]
-    pub stream: Account<'info, Stream>,
+    pub position: UncheckedAccount<'info>,
 
-    /// Signer wallet. Anybody can call the withdraw method. The recipient of the withdrawn amount is not related to the
-    /// signer. Recipient is passed as an argument, based on which the stream PDA is accessed, so if a malicious user
-    /// tries to send themselves as a recipient, but a different stream account, the constraint for the stream account
-    /// will fail.
-    pub signer: Signer<'info>,
-    /// SPL token mint account.
-    pub mint: Box<Account<'info, Mint>>,
+    /// Wallet owning the stake, i.e. the beneficiary of the stream or its creator. See [`Stream::staker`].
+    pub staker: Signer<'info>,
 
-    /// Associated token account of the recipient.
+    /// Associated token account of the staker.
     #[account(
         mut,
         constraint =
-            recipient_token.mint == mint.key()
-            && recipient_token.owner == recipient,
+            staker_token.mint == stream.load()?.mint
+            && staker_token.owner == staker.key(),
     )]
-    pub recipient_token: Box<Account<'info, TokenAccount>>,
+    pub staker_token: Box<Account<'info, TokenAccount>>,
     /// Associated token escrow account holding the funds for this stream.
     #[account(
         mut,
         constraint =
-            escrow_token.mint == mint.key()
+            escrow_token.mint == stream.load()?.mint
             && escrow_token.owner == stream.key(),
     )]
     pub escrow_token: Box<Account<'info, TokenAccount>>,
@@ -1194,54 +3053,141 @@ pub struct WithdrawAndChangeRecipient<'info> {
     pub token_program: Program<'info, Token>,
 }
 
-/// Accounts struct for pausing a non-prepaid stream.
+/// Accounts struct for changing the sender of a non-prepaid zero-copy stream.
 #[derive(Accounts)]
-#[instruction(seed: u64, name: String)]
-pub struct PauseNonPrepaid<'info> {
-    /// Stream PDA account.
+#[instruction(new_sender: Pubkey)]
+pub struct ChangeSenderNonPrepaidZeroCopy<'info> {
+    /// Zero-copy stream PDA account.
+    #[account(mut)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+
+    /// Signer wallet. Signer needs to be either the sender or the manager (if they are allowed to).
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// Stream registry PDA account of the new sender. Created on first use.
     #[account(
-        mut,
+        init_if_needed,
+        payer = signer,
+        space = StreamRegistry::LENGTH,
+        seeds = [REGISTRY_ACCOUNT_SEED, new_sender.as_ref()],
+        bump,
+    )]
+    pub new_sender_registry: Box<Account<'info, StreamRegistry>>,
+    /// Next page of the stream registry of the new sender, see [`StreamRegistry::next_page`]. Created on first use.
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = StreamRegistryPage::LENGTH,
         seeds = [
-            STREAM_ACCOUNT_SEED,
-            seed.to_le_bytes().as_ref(),
-            mint.key().as_ref(),
-            name.as_bytes(),
+            REGISTRY_ACCOUNT_SEED,
+            new_sender.as_ref(),
+            new_sender_registry.next_page().to_le_bytes().as_ref(),
         ],
         bump,
     )]
-    pub stream: Account<'info, Stream>,
+    pub new_sender_registry_page: Box<Account<'info, StreamRegistryPage>>,
 
-    /// Signer wallet. Signer needs to be either the sender (if they are allowed to) or the recipient.
-    pub signer: Signer<'info>,
-    /// SPL token mint account.
-    pub mint: Account<'info, Mint>,
+    /// Solana system program.
+    pub system_program: Program<'info, System>,
 }
 
-/// Accounts struct for resuming a non-prepaid stream.
+/// Accounts struct for closing a zero-copy stream.
 #[derive(Accounts)]
-#[instruction(seed: u64, name: String)]
-pub struct ResumeNonPrepaid<'info> {
-    /// Stream PDA account.
+pub struct CloseZeroCopyStream<'info> {
+    /// Zero-copy stream PDA account. The rent is refunded to the rent payer.
+    #[account(mut, close = rent_payer)]
+    pub stream: AccountLoader<'info, ZeroCopyStream>,
+    /// Activity PDA account of the stream.
+    #[account(mut, constraint = activity.key() == stream.load()?.activity)]
+    pub activity: Account<'info, Activity>,
+
+    /// Signer wallet. Either the sender or the recipient can close the stream.
+    pub signer: Signer<'info>,
+    /// Wallet which paid the rent of the stream.
+    ///
+    /// CHECK: Only 1 check is needed which is in the constraint. That is enough to verify that we are refunding the
+    /// rent to the rent payer.
+    #[account(mut, constraint = rent_payer.key() == stream.load()?.rent_payer)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Associated token escrow account holding the funds for this stream.
     #[account(
         mut,
-        seeds = [
-            STREAM_ACCOUNT_SEED,
-            seed.to_le_bytes().as_ref(),
-            mint.key().as_ref(),
-            name.as_bytes(),
-        ],
-        bump,
+        constraint =
+            escrow_token.mint == stream.load()?.mint
+            && escrow_token.owner == stream.key(),
     )]
-    pub stream: Account<'info, Stream>,
+    pub escrow_token: Box<Account<'info, TokenAccount>>,
 
-    /// Signer wallet. Signer needs to be either the sender (if they are allowed to) or the recipient (exception is if
-    /// the stream was paused by the sender and recipient is not allowed to resume a stream paused by sender).
-    pub signer: Signer<'info>,
-    /// SPL token mint account.
-    pub mint: Account<'info, Mint>,
+    /// SPL token program.
+    pub token_program: Program<'info, Token>,
 }
 
 impl<'info> Create<'info> {
+    /// Get the accounts needed to create the stream. The sender pays the rent and owns the stake of activity streams.
+    pub(crate) fn stream_creation(&mut self) -> StreamCreation<'_, 'info> {
+        StreamCreation {
+            stream: &mut self.stream,
+            activity: &mut self.activity,
+            mint: &self.mint,
+            sender: &self.sender,
+            escrow_token: &self.escrow_token,
+            beneficiary: self.sender.key(),
+            payer: &self.sender,
+            system_program: &self.system_program,
+        }
+    }
+
+    /// Transfer funds from the associated token account of the sender to associated token escrow account holding the
+    /// funds for this stream.
+    pub fn transfer_to_escrow(&self, amount: u64) -> Result<()> {
+        transfer_to_escrow(
+            &self.sender,
+            &self.sender_token,
+            &self.escrow_token,
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+impl<'info> CreateV2<'info> {
+    /// Get the accounts needed to create the stream.
+    pub(crate) fn stream_creation(&mut self) -> StreamCreation<'_, 'info> {
+        StreamCreation {
+            stream: &mut self.stream,
+            activity: &mut self.activity,
+            mint: &self.mint,
+            sender: &self.sender,
+            escrow_token: &self.escrow_token,
+            beneficiary: self.beneficiary.key(),
+            payer: &self.payer,
+            system_program: &self.system_program,
+        }
+    }
+
+    /// Append the created stream to the stream registries of its sender and recipient.
+    pub fn register(&mut self, bumps: &BTreeMap<String, u8>) -> Result<()> {
+        let stream_key = self.stream.key();
+        register_stream(
+            &mut self.sender_registry,
+            *bumps.get("sender_registry").unwrap(),
+            &mut self.sender_registry_page,
+            *bumps.get("sender_registry_page").unwrap(),
+            self.sender.key(),
+            stream_key,
+        )?;
+        register_stream(
+            &mut self.recipient_registry,
+            *bumps.get("recipient_registry").unwrap(),
+            &mut self.recipient_registry_page,
+            *bumps.get("recipient_registry_page").unwrap(),
+            self.stream.recipient,
+            stream_key,
+        )
+    }
+
     /// Transfer funds from the associated token account of the sender to associated token escrow account holding the
     /// funds for this stream.
     pub fn transfer_to_escrow(&self, amount: u64) -> Result<()> {
@@ -1258,28 +3204,25 @@ impl<'info> Create<'info> {
 impl<'info> Cancel<'info> {
     /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
     /// token account of the sender.
-    pub fn transfer_from_escrow_to_sender(&self, seed: u64, name: &str, bump: u8, amount: u64) -> Result<()> {
-        self.transfer_from_escrow(&self.sender_token, seed, name, bump, amount)
+    pub fn transfer_from_escrow_to_sender(&self, amount: u64) -> Result<()> {
+        self.transfer_from_escrow(&self.sender_token, amount)
     }
 
     /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
     /// token account of the signer.
-    pub fn transfer_from_escrow_to_signer(&self, seed: u64, name: &str, bump: u8, amount: u64) -> Result<()> {
-        self.transfer_from_escrow(&self.signer_token, seed, name, bump, amount)
+    pub fn transfer_from_escrow_to_signer(&self, amount: u64) -> Result<()> {
+        self.transfer_from_escrow(&self.signer_token, amount)
     }
 
     /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
     /// token account of the recipient.
-    pub fn transfer_from_escrow_to_recipient(&self, seed: u64, name: &str, bump: u8, amount: u64) -> Result<()> {
-        self.transfer_from_escrow(&self.recipient_token, seed, name, bump, amount)
+    pub fn transfer_from_escrow_to_recipient(&self, amount: u64) -> Result<()> {
+        self.transfer_from_escrow(&self.recipient_token, amount)
     }
 
     fn transfer_from_escrow(
         &self,
         destination_token: &Account<'info, TokenAccount>,
-        seed: u64,
-        name: &str,
-        bump: u8,
         amount: u64,
     ) -> Result<()> {
         transfer_from_escrow(
@@ -1287,10 +3230,6 @@ impl<'info> Cancel<'info> {
             destination_token,
             &self.escrow_token,
             &self.token_program,
-            seed,
-            &self.mint.key(),
-            name,
-            bump,
             amount,
         )
     }
@@ -1299,16 +3238,12 @@ impl<'info> Cancel<'info> {
 impl<'info> WithdrawExcessTopupNonPrepaidEnded<'info> {
     /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
     /// token account of the sender.
-    fn transfer_from_escrow(&self, seed: u64, name: &str, bump: u8, amount: u64) -> Result<()> {
+    fn transfer_from_escrow(&self, amount: u64) -> Result<()> {
         transfer_from_escrow(
             &self.stream,
             &self.sender_token,
             &self.escrow_token,
             &self.token_program,
-            seed,
-            &self.mint.key(),
-            name,
-            bump,
             amount,
         )
     }
@@ -1328,20 +3263,181 @@ impl<'info> TopupNonPrepaid<'info> {
     }
 }
 
+impl<'info> Withdraw<'info> {
+    /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
+    /// token account of the recipient.
+    pub fn transfer_from_escrow(&self, amount: u64) -> Result<()> {
+        transfer_from_escrow(
+            &self.stream,
+            &self.recipient_token,
+            &self.escrow_token,
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
 impl<'info> WithdrawAndChangeRecipient<'info> {
     /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
     /// token account of the recipient.
-    pub fn transfer_from_escrow(&self, seed: u64, name: &str, bump: u8, amount: u64) -> Result<()> {
+    pub fn transfer_from_escrow(&self, amount: u64) -> Result<()> {
         transfer_from_escrow(
             &self.stream,
             &self.recipient_token,
             &self.escrow_token,
             &self.token_program,
-            seed,
-            &self.mint.key(),
-            name,
-            bump,
             amount,
         )
     }
 }
+
+impl<'info> CloseStream<'info> {
+    /// Close the associated token escrow account holding the funds for this stream and refund its rent to the rent
+    /// payer.
+    fn close_escrow(&self, seeds: &StreamSeeds) -> Result<()> {
+        close_stream_escrow(
+            self.stream.to_account_info(),
+            seeds,
+            &self.escrow_token,
+            self.rent_payer.to_account_info(),
+            &self.token_program,
+        )
+    }
+}
+
+impl<'info> MigrateActivity<'info> {
+    /// Transfer lamports from the creator to the activity account so that it stays rent exempt with `length` bytes.
+    fn fund_rent(&self, length: usize) -> Result<()> {
+        let rent_needed = Rent::get()?.minimum_balance(length);
+        let lamports = self.activity.lamports();
+        if rent_needed <= lamports {
+            return Ok(());
+        }
+
+        system_program::transfer(
+            CpiContext::new(
+                self.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: self.creator.to_account_info(),
+                    to: self.activity.to_account_info(),
+                },
+            ),
+            rent_needed - lamports,
+        )
+    }
+}
+
+impl<'info> MigrateStream<'info> {
+    /// Transfer lamports from the signer to the stream account so that it stays rent exempt with `length` bytes.
+    fn fund_rent(&self, length: usize) -> Result<()> {
+        let rent_needed = Rent::get()?.minimum_balance(length);
+        let lamports = self.stream.lamports();
+        if rent_needed <= lamports {
+            return Ok(());
+        }
+
+        system_program::transfer(
+            CpiContext::new(
+                self.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: self.signer.to_account_info(),
+                    to: self.stream.to_account_info(),
+                },
+            ),
+            rent_needed - lamports,
+        )
+    }
+}
+
+impl<'info> CancelZeroCopy<'info> {
+    /// Transfer funds from the associated token escrow account holding the funds for this stream to
+    /// `destination_token`.
+    fn transfer_from_escrow(
+        &self,
+        seeds: &StreamSeeds,
+        destination_token: &Account<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        transfer_from_stream_escrow(
+            self.stream.to_account_info(),
+            seeds,
+            destination_token,
+            &self.escrow_token,
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+impl<'info> WithdrawExcessTopupNonPrepaidEndedZeroCopy<'info> {
+    /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
+    /// token account of the sender.
+    fn transfer_from_escrow(&self, seeds: &StreamSeeds, amount: u64) -> Result<()> {
+        transfer_from_stream_escrow(
+            self.stream.to_account_info(),
+            seeds,
+            &self.sender_token,
+            &self.escrow_token,
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+impl<'info> TopupNonPrepaidZeroCopy<'info> {
+    /// Transfer funds from the associated token account of the sender to associated token escrow account holding the
+    /// funds for this stream.
+    pub fn transfer_to_escrow(&self, amount: u64) -> Result<()> {
+        transfer_to_escrow(
+            &self.signer,
+            &self.signer_token,
+            &self.escrow_token,
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+impl<'info> WithdrawZeroCopy<'info> {
+    /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
+    /// token account of the recipient.
+    fn transfer_from_escrow(&self, seeds: &StreamSeeds, amount: u64) -> Result<()> {
+        transfer_from_stream_escrow(
+            self.stream.to_account_info(),
+            seeds,
+            &self.recipient_token,
+            &self.escrow_token,
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+impl<'info> WithdrawAndChangeRecipientZeroCopy<'info> {
+    /// Transfer funds from the associated token escrow account holding the funds for this stream to the associated
+    /// token account of the recipient.
+    fn transfer_from_escrow(&self, seeds: &StreamSeeds, amount: u64) -> Result<()> {
+        transfer_from_stream_escrow(
+            self.stream.to_account_info(),
+            seeds,
+            &self.recipient_token,
+            &self.escrow_token,
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+impl<'info> CloseZeroCopyStream<'info> {
+    /// Close the associated token escrow account holding the funds for this stream and refund its rent to the rent
+    /// payer.
+    fn close_escrow(&self, seeds: &StreamSeeds) -> Result<()> {
+        close_stream_escrow(
+            self.stream.to_account_info(),
+            seeds,
+            &self.escrow_token,
+            self.rent_payer.to_account_info(),
+            &self.token_program,
+        )
+    }
+}
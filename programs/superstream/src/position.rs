@@ -0,0 +1,153 @@
+//! Module for maintaining the stake positions of wallets in activities. See [`StakePosition`].
+
+use anchor_lang::{prelude::*, system_program};
+
+use crate::{
+    state::{Activity, StakePosition},
+    POSITION_ACCOUNT_SEED,
+};
+
+/// Record a new stake of `amount` with weight `weight` at time `at` in `activity` at address `activity_key` and in the
+/// stake position PDA `position_info` of `wallet`. See [`Activity::stake`].
+///
+/// The position is created on first use, with its rent paid by `payer`, and counted in the activity. Only stakes need
+/// a position, so it is passed in the remaining accounts of the create instructions rather than created for every
+/// stream.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_stake<'info>(
+    activity: &mut Activity,
+    activity_key: Pubkey,
+    position_info: &AccountInfo<'info>,
+    wallet: Pubkey,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    amount: u64,
+    weight: u64,
+    at: u64,
+) -> Result<()> {
+    let (position_key, bump) = Pubkey::find_program_address(
+        &[POSITION_ACCOUNT_SEED, activity_key.as_ref(), wallet.as_ref()],
+        &crate::ID,
+    );
+    require_keys_eq!(position_info.key(), position_key, ErrorCode::ConstraintSeeds);
+    if position_info.owner == &system_program::ID {
+        create_position(activity_key, position_info, wallet, bump, payer, system_program)?;
+        activity.open_position()?;
+    }
+
+    let mut position = Account::<StakePosition>::try_from(position_info)?;
+    activity.stake(&mut position, amount, weight, at)?;
+    position.exit(&crate::ID)
+}
+
+/// Create the stake position PDA `position_info` of `wallet` in the activity at address `activity_key`, with its rent
+/// paid by `payer`. The account may already hold lamports, as anyone can transfer lamports to an address.
+fn create_position<'info>(
+    activity_key: Pubkey,
+    position_info: &AccountInfo<'info>,
+    wallet: Pubkey,
+    bump: u8,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let seeds = [POSITION_ACCOUNT_SEED, activity_key.as_ref(), wallet.as_ref(), &[bump]];
+    let signer = &[&seeds[..]];
+    let rent_needed = Rent::get()?.minimum_balance(StakePosition::LENGTH);
+    let lamports = position_info.lamports();
+    if lamports == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                system_program::CreateAccount {
+                    from: payer.to_account_info(),
+                    to: position_info.clone(),
+                },
+                signer,
+            ),
+            rent_needed,
+            StakePosition::LENGTH as u64,
+            &crate::ID,
+        )?;
+    } else {
+        if rent_needed > lamports {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: payer.to_account_info(),
+                        to: position_info.clone(),
+                    },
+                ),
+                rent_needed - lamports,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                system_program::Allocate {
+                    account_to_allocate: position_info.clone(),
+                },
+                signer,
+            ),
+            StakePosition::LENGTH as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                system_program::Assign {
+                    account_to_assign: position_info.clone(),
+                },
+                signer,
+            ),
+            &crate::ID,
+        )?;
+    }
+
+    let position = StakePosition {
+        activity: activity_key,
+        wallet,
+        bump,
+        ..StakePosition::default()
+    };
+    let mut data = position_info.try_borrow_mut_data()?;
+    position.try_serialize(&mut &mut data[..])
+}
+
+/// Record `amount` withdrawn or refunded from a stream with stake `stake` at time `at` in the totals of `activity` and in
+/// the stake position `position_info` of the staker. See [`Stream::stake`](crate::Stream::stake).
+///
+/// The position is not updated if it does not exist, e.g. because the stake was created before positions existed.
+pub(crate) fn record_unstake(
+    activity: &mut Activity,
+    position_info: &AccountInfo,
+    stake: Option<u64>,
+    amount: u64,
+    at: u64,
+) -> Result<()> {
+    if stake.is_none() || position_info.owner != &crate::ID {
+        return activity.unstake(None, stake, amount, at);
+    }
+    let mut position = Account::<StakePosition>::try_from(position_info)?;
+    activity.unstake(Some(&mut position), stake, amount, at)?;
+    position.exit(&crate::ID)
+}
+
+/// Record `amount` unstaked early from a stream with stake `stake` at time `at` in `activity` and in the stake position
+/// `position_info` of the staker. Returns the early unstake penalty. See [`Activity::unstake_early`].
+///
+/// The position is not updated if it does not exist, e.g. because the stake was created before positions existed.
+pub(crate) fn record_early_unstake(
+    activity: &mut Activity,
+    position_info: &AccountInfo,
+    stake: Option<u64>,
+    amount: u64,
+    at: u64,
+) -> Result<u64> {
+    if position_info.owner != &crate::ID {
+        return activity.unstake_early(None, stake, amount, at);
+    }
+    let mut position = Account::<StakePosition>::try_from(position_info)?;
+    let penalty = activity.unstake_early(Some(&mut position), stake, amount, at)?;
+    position.exit(&crate::ID)?;
+    Ok(penalty)
+}
@@ -0,0 +1,29 @@
+//! Module for maintaining the stream registries of wallets. See [`StreamRegistry`].
+
+use anchor_lang::prelude::*;
+
+use crate::state::{StreamRegistry, StreamRegistryPage};
+
+/// Append a stream to the registry of `wallet`, in its next page.
+///
+/// The registry and the page may just have been created by `init_if_needed`, in which case their wallet, index and
+/// bump are set here. The page PDA seeds are checked against the next page of the registry by the accounts structs.
+pub(crate) fn register_stream(
+    registry: &mut StreamRegistry,
+    registry_bump: u8,
+    page: &mut StreamRegistryPage,
+    page_bump: u8,
+    wallet: Pubkey,
+    stream: Pubkey,
+) -> Result<()> {
+    if registry.wallet == Pubkey::default() {
+        registry.wallet = wallet;
+        registry.bump = registry_bump;
+    }
+    if page.wallet == Pubkey::default() {
+        page.wallet = wallet;
+        page.index = registry.next_page();
+        page.bump = page_bump;
+    }
+    registry.add(page, stream)
+}
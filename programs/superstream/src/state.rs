@@ -1,28 +1,43 @@
 //! Module for superstream state management.
 
-use std::cmp::min;
+use std::cmp::{max, min};
 
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, solana_program::keccak};
 
-use crate::{error::StreamError, utils::get_current_timestamp};
+pub use crate::stream_math::DEPOSIT_AMOUNT_PERIOD_IN_SECS;
+use crate::{
+    error::StreamError,
+    get_name_seed,
+    stream_math::{MathError, StreamTerms},
+    utils,
+};
 
 const ANCHOR_DISCRIMINATOR_LENGTH: usize = 8;
 
 const BOOL_LENGTH: usize = 1;
 const U8_LENGTH: usize = 1;
 const U64_LENGTH: usize = 8;
+const U128_LENGTH: usize = 16;
 const PUBLIC_KEY_LENGTH: usize = 32;
 const STRING_LENGTH_PREFIX: usize = 4;
+const VEC_LENGTH_PREFIX: usize = 4;
 
 /// Minimum length of a stream name.
 pub const MIN_STREAM_NAME_LENGTH: usize = 2;
 /// Maximum length of a stream name.
 pub const MAX_STREAM_NAME_LENGTH: usize = 100;
-
-/// Deposit amount period (in seconds) for a non-prepaid stream. If a non-prepaid stream has unlimited lifetime or
-/// lifetime >= DEPOSIT_AMOUNT_PERIOD_IN_SECS, a security deposit is taken from the sender which would not be returned
-/// in case the stream becomes insolvent. This is done to make sure users keep topping up their streams on time.
-pub const DEPOSIT_AMOUNT_PERIOD_IN_SECS: u64 = 8 * 60 * 60; // 8 hrs
+/// Scale of [`Activity::reward_per_token`], so that rewards per staked token smaller than 1 are not lost.
+pub const REWARD_PER_TOKEN_PRECISION: u128 = 1_000_000_000_000;
+/// Weight of a stake without a lock multiplier. Stake weights are in basis points of the staked amount.
+pub const BASE_STAKE_WEIGHT: u64 = 10_000;
+/// Maximum number of lock durations of an activity.
+pub const MAX_LOCK_MULTIPLIERS: usize = 16;
+/// Maximum early unstake penalty of an activity, i.e. 100% in basis points of the unstaked amount.
+pub const MAX_EARLY_UNSTAKE_PENALTY: u64 = 10_000;
+/// Version of the [`Stream`] account layout written by this program. See [`Stream::version`].
+pub const STREAM_VERSION: u64 = 1;
+/// Version of the [`Activity`] account layout written by this program. See [`Activity::version`].
+pub const ACTIVITY_VERSION: u64 = 1;
 
 #[account]
 pub struct Distributor {
@@ -44,6 +59,10 @@ pub struct Distributor {
 
     // the mint to distribute
     pub mint: Pubkey,
+
+    /// Wallet which paid the rent of the distributor account. The rent is refunded to it when the distributor is
+    /// closed.
+    pub rent_payer: Pubkey,
 }   
 
 #[account]
@@ -54,13 +73,25 @@ pub struct Status {
     pub claimer: Pubkey,
 
     pub amount: u64,
+
+    /// Wallet which paid the rent of the status account. The rent is refunded to it when the status is closed.
+    pub rent_payer: Pubkey,
+}
+
+impl Status {
+    /// Total size of a Status account.
+    pub const LENGTH: usize = ANCHOR_DISCRIMINATOR_LENGTH
+        + 1 * BOOL_LENGTH       // is_claimed - 9
+        + 1 * PUBLIC_KEY_LENGTH // claimer - 41
+        + 1 * U64_LENGTH        // amount - 49
+        + 1 * PUBLIC_KEY_LENGTH // rent_payer - 81
+    ;
 }
 
 #[account]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct Activity {
-    /// If true, the stream is prepaid - all the required amount needs to be deposited on creation. Prepaid streams
-    /// cannot have unlimited lifetime.
+    /// If true, the activity accepts new streams. The creator can deactivate the activity to stop new stakes.
     pub is_active: bool,
 
     /// creator address.
@@ -91,9 +122,81 @@ pub struct Activity {
     // required stake time
     pub duration: u64,
 
-    // should be ZERO, unless you want to use superstream original reward function
+    /// Number of reward tokens emitted per second to the stakes between `starts_at` and `ends_at`, shared in proportion
+    /// to their weighted amount and paid from the reward vault. Stakes only return their principal, they have no flow
+    /// payments of their own.
     pub flow_rate: u64,
 
+    /// Number of open streams of the activity. The activity can only be closed once all its streams are closed.
+    pub stream_count: u64,
+    /// Number of open reward distributors of the activity. The activity can only be closed once all its distributors
+    /// are closed.
+    pub distributor_count: u64,
+    /// Number of open stake positions of the activity. The activity can only be closed once all its positions are
+    /// closed, i.e. all the rewards accrued to them are claimed.
+    pub position_count: u64,
+    /// If true, the activity was migrated from the original layout with
+    /// [`migrate_activity`](crate::superstream::migrate_activity). The streams and distributors created before then are
+    /// not counted, so the activity can never be closed.
+    pub is_migrated: bool,
+
+    /// Total amount ever staked in the activity with `create_stream`.
+    pub total_staked: u64,
+    /// Total amount ever withdrawn or refunded from the stakes of the activity.
+    ///
+    /// INVARIANT: <= total_staked
+    pub total_unstaked: u64,
+    /// Number of wallets currently staking in the activity, i.e. with a [`StakePosition`] holding a stake.
+    pub participant_count: u64,
+    /// Sum of the weighted stakes currently in the activity. Rewards are shared in proportion to the weighted stakes.
+    /// See [`Activity::lock_multipliers`].
+    pub total_weighted_stake: u64,
+
+    /// Maximum amount staked in the activity at any time. If 0, there is no cap.
+    pub max_total_stake: u64,
+    /// Maximum amount staked by a single wallet in the activity at any time. If 0, there is no cap.
+    pub max_stake_per_wallet: u64,
+
+    /// Penalty charged on stakes unstaked before they end, in basis points of the unstaked amount.
+    ///
+    /// INVARIANT: <= MAX_EARLY_UNSTAKE_PENALTY
+    pub early_unstake_penalty: u64,
+    /// Wallet receiving the early unstake penalties. If it is the default pubkey, the penalties go to the reward vault
+    /// and are emitted as rewards to the remaining stakes.
+    pub penalty_treasury: Pubkey,
+    /// Merkle root of the wallets allowed to stake in the activity, with the keccak hash of each wallet address as
+    /// leaf. If all zeros, anyone can stake.
+    pub staker_allowlist_root: [u8; 32],
+
+    /// Rewards accrued per staked token since the activity started, scaled by [`REWARD_PER_TOKEN_PRECISION`].
+    pub reward_per_token: u128,
+    /// Time up to which the rewards are accrued in `reward_per_token`.
+    pub rewards_updated_at: u64,
+    /// Total amount of `reward_mint` tokens ever funded into the reward vault of the activity.
+    pub total_reward_funded: u64,
+    /// Total amount of rewards accrued to the stakers.
+    ///
+    /// INVARIANT: <= total_reward_funded
+    pub total_reward_accrued: u64,
+    /// Total amount of rewards claimed by the stakers.
+    ///
+    /// INVARIANT: <= total_reward_accrued
+    pub total_reward_claimed: u64,
+    /// Total amount of accrued rewards forfeited by early unstakes. Forfeited rewards are emitted again to the
+    /// remaining stakes.
+    ///
+    /// INVARIANT: <= total_reward_accrued
+    pub total_reward_forfeited: u64,
+    /// Total amount of penalties charged on early unstakes.
+    pub total_early_unstake_penalty: u64,
+
+    /// Version of the account layout, i.e. [`ACTIVITY_VERSION`]. Activities created with the original layout, which
+    /// ended with `name`, do not have this field and need to be migrated with
+    /// [`migrate_activity`](crate::superstream::migrate_activity) before they can be used.
+    pub version: u64,
+    /// Extra space for program upgrades.
+    pub reserved: [u64; 16],
+
     /// Seed of the stream PDA. It's upto the client how they choose the seed. Each tuple (seed, mint, name) corresponds
     /// to a unique stream.
     pub seed: u64,
@@ -104,6 +207,12 @@ pub struct Activity {
     ///
     /// INVARIANT: Length <= 100 unicode chars or 400 bytes
     pub name: String,
+
+    /// Lock durations allowed for the stakes of the activity and their reward multipliers, by increasing duration. If
+    /// empty, stakes can be locked for any duration and have the base weight.
+    ///
+    /// INVARIANT: Length <= MAX_LOCK_MULTIPLIERS
+    pub lock_multipliers: Vec<LockMultiplier>,
 }
 
 impl Activity {
@@ -115,10 +224,28 @@ impl Activity {
         + 1 * U64_LENGTH        // min_amount - 196
         + 1 * U64_LENGTH        // seed - 204
         + 1 * U8_LENGTH         // bump - 205
+        + 2 * U64_LENGTH        // stream_count, distributor_count - 221
+        + 3 * U64_LENGTH        // total_staked, total_unstaked, participant_count - 245
+        + 2 * U64_LENGTH        // max_total_stake, max_stake_per_wallet - 261
+        + 1 * U128_LENGTH       // reward_per_token - 277
+        + 4 * U64_LENGTH        // rewards_updated_at, total_reward_funded, total_reward_accrued, total_reward_claimed - 309
+        + 1 * U64_LENGTH        // total_weighted_stake - 317
+        + 1 * U64_LENGTH        // early_unstake_penalty - 325
+        + 1 * PUBLIC_KEY_LENGTH // penalty_treasury - 357
+        + 2 * U64_LENGTH        // total_reward_forfeited, total_early_unstake_penalty - 373
+        + 32 * U8_LENGTH        // staker_allowlist_root - 405
+        + 1 * U64_LENGTH        // position_count - 413
+        + 1 * BOOL_LENGTH       // is_migrated - 414
+        + 1 * U64_LENGTH        // version - 422
+        + 16 * U64_LENGTH       // reserved - 550
     ;
 
-    pub fn space(name: &str) -> usize {
-        Self::BASE_LENGTH + STRING_LENGTH_PREFIX + name.len()
+    pub fn space(name: &str, lock_multiplier_count: usize) -> usize {
+        Self::BASE_LENGTH
+            + STRING_LENGTH_PREFIX
+            + name.len()
+            + VEC_LENGTH_PREFIX
+            + lock_multiplier_count * LockMultiplier::LENGTH
     }
 
     pub fn initialize(
@@ -137,19 +264,20 @@ impl Activity {
         seed: u64,
         bump: u8,
         name: String,
+        at: u64,
     ) -> Result<()> {
         require!(name.len() >= MIN_STREAM_NAME_LENGTH, StreamError::StreamNameTooShort);
         require!(name.len() <= MAX_STREAM_NAME_LENGTH, StreamError::StreamNameTooLong);
-        require!(is_active == true,StreamError::ZeroFlowInterval);
 
-        let at = get_current_timestamp()?;
         let starts_at = if starts_at < at { at } else { starts_at };
 
         require!(ends_at >= starts_at,
             StreamError::InvalidEndsAt,
         );
+        Self::validate_flow_rate(starts_at, ends_at, flow_rate)?;
 
         self.is_active = is_active;
+        self.version = ACTIVITY_VERSION;
         self.creator = creator;
         self.stake_mint = stake_mint;
         self.reward_mint = reward_mint;
@@ -171,6 +299,373 @@ impl Activity {
         );
         Ok(())
     }
+
+    /// Update the end time, reward expiry time, minimum stake amount and flow rate of an active activity. The end time
+    /// cannot be moved into the past and the rewards cannot expire before the activity ends.
+    pub(crate) fn update(
+        &mut self,
+        signer: Pubkey,
+        ends_at: u64,
+        reward_expires_at: u64,
+        min_amount: u64,
+        flow_rate: u64,
+        at: u64,
+    ) -> Result<()> {
+        require!(signer == self.creator, StreamError::UserUnauthorizedToManageActivity);
+        require!(self.is_active, StreamError::ActivityNotActive);
+        require!(ends_at >= self.starts_at && ends_at >= at, StreamError::InvalidEndsAt);
+        require!(reward_expires_at >= ends_at, StreamError::InvalidRewardExpiresAt);
+        require!(min_amount > 0, StreamError::ZeroLifetimeAmount);
+        Self::validate_flow_rate(self.starts_at, ends_at, flow_rate)?;
+
+        // Rewards up to now are accrued at the current flow rate.
+        self.accrue_rewards(at)?;
+        self.ends_at = ends_at;
+        self.reward_expires_at = reward_expires_at;
+        self.min_amount = min_amount;
+        self.flow_rate = flow_rate;
+        Ok(())
+    }
+
+    /// Check that rewards are emitted at `flow_rate` tokens per second, i.e. it is > 0, and that the rewards emitted
+    /// between `starts_at` and `ends_at` fit in u64.
+    fn validate_flow_rate(starts_at: u64, ends_at: u64, flow_rate: u64) -> Result<()> {
+        require!(
+            flow_rate > 0 && flow_rate.checked_mul(ends_at - starts_at).is_some(),
+            StreamError::InvalidActivityFlowRate
+        );
+        Ok(())
+    }
+
+    /// Check that a new stream with `initial_amount` can be created for the activity at time `at`, i.e. the activity is
+    /// active and open and the amount is at least the minimum amount.
+    pub(crate) fn validate_stake(&self, initial_amount: u64, at: u64) -> Result<()> {
+        require!(self.is_active, StreamError::ActivityNotActive);
+        require!(at >= self.starts_at, StreamError::ActivityNotStarted);
+        require!(at <= self.ends_at, StreamError::ActivityEnded);
+        require!(initial_amount >= self.min_amount, StreamError::StakeAmountBelowMinimum);
+        Ok(())
+    }
+
+    /// Set the maximum amount staked in the activity and by a single wallet. A cap of 0 means no cap. Lowering a cap
+    /// below the current stake only prevents new stakes.
+    pub(crate) fn set_stake_caps(
+        &mut self,
+        signer: Pubkey,
+        max_total_stake: u64,
+        max_stake_per_wallet: u64,
+    ) -> Result<()> {
+        require!(signer == self.creator, StreamError::UserUnauthorizedToManageActivity);
+        require!(self.is_active, StreamError::ActivityNotActive);
+        self.max_total_stake = max_total_stake;
+        self.max_stake_per_wallet = max_stake_per_wallet;
+        Ok(())
+    }
+
+    /// Set the lock durations allowed for new stakes and their reward multipliers. Existing stakes keep their weight.
+    pub(crate) fn set_lock_multipliers(&mut self, signer: Pubkey, lock_multipliers: Vec<LockMultiplier>) -> Result<()> {
+        require!(signer == self.creator, StreamError::UserUnauthorizedToManageActivity);
+        require!(self.is_active, StreamError::ActivityNotActive);
+        let is_positive = |lock: &LockMultiplier| lock.duration > 0 && lock.multiplier > 0;
+        let is_sorted = |locks: &[LockMultiplier]| locks[0].duration < locks[1].duration;
+        require!(
+            lock_multipliers.len() <= MAX_LOCK_MULTIPLIERS
+                && lock_multipliers.iter().all(is_positive)
+                && lock_multipliers.windows(2).all(is_sorted),
+            StreamError::InvalidLockMultipliers
+        );
+        self.lock_multipliers = lock_multipliers;
+        Ok(())
+    }
+
+    /// Set the early unstake penalty in basis points and the wallet receiving it. A penalty can only go to the reward
+    /// vault if the stake and reward mints are the same.
+    pub(crate) fn set_early_unstake_penalty(
+        &mut self,
+        signer: Pubkey,
+        early_unstake_penalty: u64,
+        penalty_treasury: Pubkey,
+    ) -> Result<()> {
+        require!(signer == self.creator, StreamError::UserUnauthorizedToManageActivity);
+        require!(self.is_active, StreamError::ActivityNotActive);
+        require!(
+            early_unstake_penalty <= MAX_EARLY_UNSTAKE_PENALTY
+                && (penalty_treasury != Pubkey::default() || self.stake_mint == self.reward_mint),
+            StreamError::InvalidEarlyUnstakePenalty
+        );
+        self.early_unstake_penalty = early_unstake_penalty;
+        self.penalty_treasury = penalty_treasury;
+        Ok(())
+    }
+
+    /// Set the merkle root of the wallets allowed to stake, replacing the current one. All zeros removes the allowlist.
+    /// Existing stakes are not affected.
+    pub(crate) fn set_staker_allowlist_root(&mut self, signer: Pubkey, staker_allowlist_root: [u8; 32]) -> Result<()> {
+        require!(signer == self.creator, StreamError::UserUnauthorizedToManageActivity);
+        require!(self.is_active, StreamError::ActivityNotActive);
+        self.staker_allowlist_root = staker_allowlist_root;
+        Ok(())
+    }
+
+    /// Check that `staker` is allowed to stake in the activity, i.e. the activity has no allowlist or `proof` proves
+    /// that the keccak hash of the staker address is a leaf of the allowlist.
+    pub(crate) fn verify_staker(&self, staker: Pubkey, proof: Vec<[u8; 32]>) -> Result<()> {
+        if self.staker_allowlist_root == [0; 32] {
+            return Ok(());
+        }
+        let leaf = keccak::hashv(&[&staker.to_bytes()]).0;
+        require!(
+            utils::verify(proof, self.staker_allowlist_root, leaf),
+            StreamError::StakerNotAllowed
+        );
+        Ok(())
+    }
+
+    /// Get the owner of the token accounts receiving the early unstake penalties, i.e. the penalty treasury or the
+    /// activity itself at address `key` if the penalties go to the reward vault.
+    pub fn penalty_owner(&self, key: Pubkey) -> Pubkey {
+        if self.penalty_treasury == Pubkey::default() {
+            key
+        } else {
+            self.penalty_treasury
+        }
+    }
+
+    /// Get the weight of a new stake locked for `lock_duration` seconds. Fails if the activity has lock multipliers and
+    /// none of them is for `lock_duration`.
+    pub fn stake_weight(&self, lock_duration: u64) -> Result<u64> {
+        if self.lock_multipliers.is_empty() {
+            return Ok(BASE_STAKE_WEIGHT);
+        }
+        self.lock_multipliers
+            .iter()
+            .find(|lock| lock.duration == lock_duration)
+            .map(|lock| lock.multiplier)
+            .ok_or_else(|| error!(StreamError::LockDurationNotAllowed))
+    }
+
+    /// Record a new stake of `amount` with weight `weight` at time `at` in the activity and in the stake position of
+    /// the staker, enforcing the stake caps.
+    pub(crate) fn stake(&mut self, position: &mut StakePosition, amount: u64, weight: u64, at: u64) -> Result<()> {
+        let staked_amount = self
+            .total_staked
+            .saturating_sub(self.total_unstaked)
+            .checked_add(amount)
+            .ok_or(StreamError::ActivityStakeCapExceeded)?;
+        require!(
+            self.max_total_stake == 0 || staked_amount <= self.max_total_stake,
+            StreamError::ActivityStakeCapExceeded
+        );
+        let position_amount = position
+            .staked_amount
+            .checked_add(amount)
+            .ok_or(StreamError::WalletStakeCapExceeded)?;
+        require!(
+            self.max_stake_per_wallet == 0 || position_amount <= self.max_stake_per_wallet,
+            StreamError::WalletStakeCapExceeded
+        );
+
+        self.accrue_rewards(at)?;
+        self.checkpoint(position, at)?;
+        if position.staked_amount == 0 && amount > 0 {
+            self.participant_count = self.participant_count.saturating_add(1);
+        }
+        let weighted_amount = weighted_amount(amount, weight)?;
+        position.staked_amount = position_amount;
+        position.weighted_stake = position.weighted_stake.saturating_add(weighted_amount);
+        self.total_staked = self.total_staked.saturating_add(amount);
+        self.total_weighted_stake = self.total_weighted_stake.saturating_add(weighted_amount);
+        Ok(())
+    }
+
+    /// Record `amount` withdrawn or refunded from a stream at time `at` in the activity and in the stake position of
+    /// the staker, if any. Only stakes are counted, `stake` is the weight of the stake of the stream or `None` if the
+    /// stream is not a stake. See [`Stream::stake`].
+    pub(crate) fn unstake(
+        &mut self,
+        position: Option<&mut StakePosition>,
+        stake: Option<u64>,
+        amount: u64,
+        at: u64,
+    ) -> Result<()> {
+        let stake_weight = match stake {
+            Some(stake_weight) => stake_weight,
+            None => return Ok(()),
+        };
+        self.accrue_rewards(at)?;
+        let mut weighted_amount = weighted_amount(amount, stake_weight)?;
+        self.total_unstaked = self.total_unstaked.saturating_add(amount);
+        if let Some(position) = position {
+            self.checkpoint(position, at)?;
+            if position.staked_amount > 0 && position.staked_amount <= amount {
+                self.participant_count = self.participant_count.saturating_sub(1);
+            }
+            position.staked_amount = position.staked_amount.saturating_sub(amount);
+            // Rounding can leave some weight behind, which is dropped once nothing is staked anymore.
+            if position.staked_amount == 0 {
+                weighted_amount = position.weighted_stake;
+            }
+            position.weighted_stake = position.weighted_stake.saturating_sub(weighted_amount);
+        }
+        self.total_weighted_stake = self.total_weighted_stake.saturating_sub(weighted_amount);
+        Ok(())
+    }
+
+    /// Record `amount` unstaked early from a stream with stake `stake` at time `at` in the activity and in the stake
+    /// position of the staker, if any. The position forfeits the share of its pending rewards of the unstaked amount. Returns the
+    /// penalty to transfer to the reward vault or the penalty treasury, which is funded as rewards in the former case.
+    pub(crate) fn unstake_early(
+        &mut self,
+        position: Option<&mut StakePosition>,
+        stake: Option<u64>,
+        amount: u64,
+        at: u64,
+    ) -> Result<u64> {
+        if let Some(position) = position {
+            let staked_amount = position.staked_amount;
+            self.unstake(Some(position), stake, amount, at)?;
+            if staked_amount > 0 {
+                // Cannot overflow, the forfeited rewards are at most the pending rewards.
+                let forfeited = (u128::from(position.pending_rewards) * u128::from(min(amount, staked_amount))
+                    / u128::from(staked_amount)) as u64;
+                position.pending_rewards -= forfeited;
+                position.forfeited_rewards = position.forfeited_rewards.saturating_add(forfeited);
+                self.total_reward_forfeited = self.total_reward_forfeited.saturating_add(forfeited);
+            }
+        } else {
+            self.unstake(None, stake, amount, at)?;
+        }
+
+        // Cannot overflow, the penalty is at most the amount.
+        let penalty = (u128::from(amount) * u128::from(self.early_unstake_penalty)
+            / u128::from(MAX_EARLY_UNSTAKE_PENALTY)) as u64;
+        self.total_early_unstake_penalty = self.total_early_unstake_penalty.saturating_add(penalty);
+        if self.penalty_treasury == Pubkey::default() {
+            self.fund_rewards(penalty, at)?;
+        }
+        Ok(penalty)
+    }
+
+    /// Accrue the rewards emitted up to time `at` in `reward_per_token`. Rewards are emitted at `flow_rate` tokens per
+    /// second between `starts_at` and `ends_at` and shared by the stakes in proportion to their weighted amount.
+    /// Nothing is emitted while nothing is staked or once the funded and forfeited rewards are used up.
+    pub(crate) fn accrue_rewards(&mut self, at: u64) -> Result<()> {
+        let from = max(self.rewards_updated_at, self.starts_at);
+        let to = min(at, self.ends_at);
+        if to <= from {
+            return Ok(());
+        }
+        self.rewards_updated_at = to;
+
+        let accrued = self.total_reward_accrued.saturating_sub(self.total_reward_forfeited);
+        let available = self.total_reward_funded.saturating_sub(accrued);
+        let emitted = min((to - from).saturating_mul(self.flow_rate), available);
+        if self.total_weighted_stake == 0 || emitted == 0 {
+            return Ok(());
+        }
+        // Cannot overflow, u64::MAX * REWARD_PER_TOKEN_PRECISION < u128::MAX.
+        let increment = u128::from(emitted) * REWARD_PER_TOKEN_PRECISION / u128::from(self.total_weighted_stake);
+        self.reward_per_token = self
+            .reward_per_token
+            .checked_add(increment)
+            .ok_or(StreamError::RewardAmountOutOfBounds)?;
+        self.total_reward_accrued += emitted;
+        Ok(())
+    }
+
+    /// Update the rewards and the stake-seconds of `position` up to time `at`. The rewards need to be accrued up to `at`
+    /// first. This needs to be called before the staked amount of the position changes.
+    fn checkpoint(&self, position: &mut StakePosition, at: u64) -> Result<()> {
+        position.update_rewards(self.reward_per_token)?;
+        position.update_stake_seconds(min(at, self.ends_at));
+        Ok(())
+    }
+
+    /// Get the stake-seconds of `position` up to time `at`. Stake-seconds stop accumulating at `ends_at`, so once the
+    /// activity has ended this is the final stake-seconds of the position, e.g. to build a reward distribution from.
+    pub fn stake_seconds(&self, position: &StakePosition, at: u64) -> u128 {
+        position.stake_seconds_at(min(at, self.ends_at))
+    }
+
+    /// Record `amount` reward tokens funded into the reward vault at time `at`. Rewards before `at` are accrued first,
+    /// so that the new funds are only emitted from `at` on.
+    pub(crate) fn fund_rewards(&mut self, amount: u64, at: u64) -> Result<()> {
+        self.accrue_rewards(at)?;
+        self.total_reward_funded = self
+            .total_reward_funded
+            .checked_add(amount)
+            .ok_or(StreamError::RewardAmountOutOfBounds)?;
+        Ok(())
+    }
+
+    /// Claim the rewards accrued to `position` up to time `at`. Returns the amount to transfer from the reward vault.
+    pub(crate) fn claim_rewards(&mut self, position: &mut StakePosition, at: u64) -> Result<u64> {
+        self.accrue_rewards(at)?;
+        self.checkpoint(position, at)?;
+        let amount = position.pending_rewards;
+        position.pending_rewards = 0;
+        position.claimed_rewards = position.claimed_rewards.saturating_add(amount);
+        self.total_reward_claimed = self.total_reward_claimed.saturating_add(amount);
+        Ok(amount)
+    }
+
+    /// Get the stake totals of the activity.
+    pub fn totals(&self) -> ActivityTotals {
+        ActivityTotals {
+            total_staked: self.total_staked,
+            total_unstaked: self.total_unstaked,
+            staked_amount: self.total_staked.saturating_sub(self.total_unstaked),
+            weighted_stake: self.total_weighted_stake,
+            participant_count: self.participant_count,
+            stream_count: self.stream_count,
+        }
+    }
+
+    /// Deactivate the activity so that it does not accept new streams. Existing streams are not affected.
+    pub(crate) fn deactivate(&mut self, signer: Pubkey) -> Result<()> {
+        require!(signer == self.creator, StreamError::UserUnauthorizedToManageActivity);
+        require!(self.is_active, StreamError::ActivityNotActive);
+        self.is_active = false;
+        Ok(())
+    }
+
+    /// Check that the activity can be closed, i.e. the signer is the creator, no streams, distributors or stake
+    /// positions reference the activity anymore and it was not migrated from the original layout.
+    pub(crate) fn close(&self, signer: Pubkey) -> Result<()> {
+        require!(signer == self.creator, StreamError::UserUnauthorizedToManageActivity);
+        require!(!self.is_migrated, StreamError::MigratedActivityNotClosable);
+        require!(
+            self.stream_count == 0 && self.distributor_count == 0 && self.position_count == 0,
+            StreamError::ActivityInUse
+        );
+        Ok(())
+    }
+
+    /// Record a new stake position of the activity.
+    pub(crate) fn open_position(&mut self) -> Result<()> {
+        self.position_count = self
+            .position_count
+            .checked_add(1)
+            .ok_or(StreamError::ActivityCountOutOfBounds)?;
+        Ok(())
+    }
+
+    /// Check that `position` can be closed at time `at`, i.e. the signer is its wallet, it holds no stake and all the
+    /// rewards accrued to it are claimed, and remove it from the activity.
+    pub(crate) fn close_position(&mut self, signer: Pubkey, position: &mut StakePosition, at: u64) -> Result<()> {
+        require!(signer == position.wallet, StreamError::UserUnauthorizedToClosePosition);
+        self.accrue_rewards(at)?;
+        self.checkpoint(position, at)?;
+        require!(
+            position.staked_amount == 0 && position.pending_rewards == 0,
+            StreamError::PositionNotEmpty
+        );
+        self.position_count = self
+            .position_count
+            .checked_sub(1)
+            .ok_or(StreamError::ActivityCountOutOfBounds)?;
+        Ok(())
+    }
 }
 
 /// A payment stream with support for SPL tokens, prepaid and limited upfront payment, unlimited lifetime, cliffs and
@@ -324,18 +819,68 @@ pub struct Stream {
     /// INVARIANT: unbounded: == DEPOSIT_AMOUNT_PERIOD_IN_SECS of streaming payments
     pub deposit_needed: u64,
 
+    /// Reward weight of the stake in basis points of the staked amount, from the lock multipliers of the activity. 0
+    /// if the stream is not a stake or was created before stake weights existed.
+    pub stake_weight: u64,
+    /// Wallet owning the stake if it was funded on behalf of another wallet than the sender. The beneficiary owns the
+    /// stake position of the stake and receives the withdrawals instead of the recipient. If the stream is not such a
+    /// stake, this is the default (all zeroes) address. See [`Stream::staker`] and [`Stream::payee`].
+    pub beneficiary: Pubkey,
+    /// Version of the account layout, i.e. [`STREAM_VERSION`]. The fields after `activity` were added in version 1,
+    /// which does not fit in the reserved space and moves `name`. Streams created with the original layout have 0 here,
+    /// as this was reserved space, and need to be migrated with
+    /// [`migrate_stream`](crate::superstream::migrate_stream) before they can be used.
+    pub version: u64,
+
     /// Extra space for program upgrades.
-    pub reserved: [u64; 16],
+    pub reserved: [u64; 10],
 
-    /// Seed of the stream PDA. It's upto the client how they choose the seed. Each tuple (seed, mint, name) corresponds
-    /// to a unique stream.
+    /// Seed chosen by the client on creation. Each tuple (activity, mint, creator, name) corresponds to a unique
+    /// stream PDA.
     pub seed: u64,
     /// The PDA bump.
     pub bump: u8,
 
-    /// SPL token mint address.
+    /// Activity address.
     pub activity: Pubkey,
 
+    /// Manager address. The manager can act on behalf of the sender with the rights in `manager_permissions`. If
+    /// there is no manager, this is the default (all zeroes) address.
+    ///
+    /// INVARIANT: != sender && != recipient
+    pub manager: Pubkey,
+    /// Rights delegated by the sender to the manager.
+    ///
+    /// INVARIANT: manager == Pubkey::default() => all false
+    pub manager_permissions: ManagerPermissions,
+
+    /// Notice period (in seconds) for cancellation. If > 0, the sender cannot cancel a solvent stream immediately and
+    /// has to request the cancellation, which takes effect after the notice period.
+    pub notice_period: u64,
+    /// Time at which a requested cancellation takes effect. If the stream has no requested cancellation, this is 0.
+    ///
+    /// INVARIANT: == 0 || >= created_at + notice_period
+    pub cancellation_scheduled_at: u64,
+    /// If true, the requested cancellation was requested by the sender.
+    ///
+    /// INVARIANT: cancellation_scheduled_at == 0 => == false
+    pub is_cancellation_requested_by_sender: bool,
+
+    /// Sender address at the time of creation. This is part of the stream PDA seeds, so unlike `sender`, it never
+    /// changes.
+    pub creator: Pubkey,
+
+    /// How the flow payments accrue - continuously every second or at once at the end of every flow interval.
+    pub accrual_mode: AccrualMode,
+
+    /// Wallet which paid the rent of the stream account. This is the sender unless the rent was sponsored. The rent is
+    /// refunded to it when the stream is closed.
+    pub rent_payer: Pubkey,
+
+    /// If true, the stream is a stake in its activity, i.e. it was created with `create_stream`. Withdrawals and
+    /// cancellations of stakes are counted in the totals of the activity.
+    pub is_stake: bool,
+
     /// Name of the stream. Should be unique for a particular set of (seed, mint).
     ///
     /// INVARIANT: Length <= 100 unicode chars or 400 bytes
@@ -380,7 +925,7 @@ pub struct Stream {
 ///                0
 impl Stream {
     /// Total size of a Stream account excluding space taken up by the name
-    const BASE_LENGTH: usize = ANCHOR_DISCRIMINATOR_LENGTH
+    pub const BASE_LENGTH: usize = ANCHOR_DISCRIMINATOR_LENGTH
         + 1 * BOOL_LENGTH       // is_prepaid - 9
         + 3 * PUBLIC_KEY_LENGTH // sender, recipient, mint - 105
         + 3 * U64_LENGTH        // created_at, starts_at, ends_at - 129
@@ -402,10 +947,21 @@ impl Stream {
         + 3 * U64_LENGTH        // total_withdrawn_amount, last_withdrawn_at, last_withdrawn_amount - 251
         + 3 * U64_LENGTH        // total_topup_amount, last_topup_at, last_topup_amount - 275
         + 1 * U64_LENGTH        // deposit_needed - 283
-        + 16 * U64_LENGTH       // reserved - 411
+        + 1 * U64_LENGTH        // stake_weight - 291
+        + 1 * PUBLIC_KEY_LENGTH // beneficiary - 323
+        + 1 * U64_LENGTH        // version - 331
+        + 10 * U64_LENGTH       // reserved - 411
         + 1 * U64_LENGTH        // seed - 419
         + 1 * U8_LENGTH         // bump - 420
         + 1 * PUBLIC_KEY_LENGTH         // activity - 452
+        + 1 * PUBLIC_KEY_LENGTH         // manager - 484
+        + ManagerPermissions::LENGTH    // manager_permissions - 488
+        + 2 * U64_LENGTH                // notice_period, cancellation_scheduled_at - 504
+        + 1 * BOOL_LENGTH               // is_cancellation_requested_by_sender - 505
+        + 1 * PUBLIC_KEY_LENGTH         // creator - 537
+        + 1 * U8_LENGTH                 // accrual_mode - 538
+        + 1 * PUBLIC_KEY_LENGTH         // rent_payer - 570
+        + 1 * BOOL_LENGTH               // is_stake - 571
     ;
 
     pub fn space(name: &str) -> usize {
@@ -414,256 +970,53 @@ impl Stream {
 
     // --- Utility functions --- BEGIN ---
 
-    pub fn has_flow_payments(&self) -> bool {
-        self.flow_rate > 0 && (self.ends_at == 0 || self.ends_at > self.starts_at)
-    }
-
-    /// Calculate the amount of prepaid needed for a prepaid stream. This is called when creating the stream.
-    pub fn get_prepaid_amount_needed(&self) -> Result<u64> {
-        msg!("flow_rate {}, flow_interval{}, starts_at {}, ends_at {}",self.flow_rate, self.flow_interval, self.starts_at, self.ends_at);
-        if !self.is_prepaid || self.ends_at == 0 {
-            Ok(0)
-        } else if !self.has_flow_payments() {
-            Ok(self.initial_amount)
-        } else {
-            self.initial_amount
-                .checked_add(
-                    ((self.ends_at - self.starts_at)
-                        .checked_mul(self.flow_rate)
-                        .ok_or(error!(StreamError::PrepaidAmountNeededOutOfBounds))?)
-                        / self.flow_interval,
-                )
-                .ok_or(error!(StreamError::PrepaidAmountNeededOutOfBounds))
-        }
-    }
-
-    /// Calculate the amount of deposit needed for the streaming payments excluding the initial amount. This is called
-    /// when creating the stream.
-    pub fn get_deposit_needed(&self) -> Result<u64> {
-        Ok(if self.is_prepaid || !self.has_flow_payments() {
-            0
-        } else {
-            let deposit_needed = if self.ends_at == 0 {
-                DEPOSIT_AMOUNT_PERIOD_IN_SECS
-                    .checked_mul(self.flow_rate)
-                    .ok_or(error!(StreamError::DepositAmountNeededOutOfBounds))?
-                    / self.flow_interval
-            } else {
-                min(DEPOSIT_AMOUNT_PERIOD_IN_SECS, self.ends_at - self.starts_at)
-                    .checked_mul(self.flow_rate)
-                    .ok_or(error!(StreamError::DepositAmountNeededOutOfBounds))?
-                    / self.flow_interval
-            };
-
-            if deposit_needed >= 10 {
-                deposit_needed
-            } else {
-                deposit_needed + 1
-            }
-        })
-    }
-
-    pub fn get_stops_at(&self) -> u64 {
-        let cancelled_at = self.cancelled_at;
-        let ends_at = self.ends_at;
-        if cancelled_at == 0 {
-            ends_at
-        } else if ends_at == 0 {
-            cancelled_at
-        } else {
-            min(ends_at, cancelled_at)
-        }
-    }
-
-    /// Check if the stream has stooped.
-    pub fn has_stopped(&self, at: u64) -> bool {
-        let stops_at = self.get_stops_at();
-        return stops_at > 0 && at > stops_at;
-    }
-
-    fn min_with_stopped_at(&self, at: u64) -> u64 {
-        let stops_at = self.get_stops_at();
-        if stops_at > 0 && at > stops_at {
-            // If the stream has been stopped for some reason - either ending or being cancelled - make at = stopped_at
-            // if the stream stopped before at. This will make sure, the amount is calculated only till the time the
-            // stream was active.
-            stops_at
-        } else {
-            at
-        }
-    }
-
-    // INVARIANT: (stops_at == 0 || at <= stops_at) && at >= self.starts_at && self.has_flow_payments()
-    fn unsafe_get_active_time_after_start(&self, at: u64) -> Result<u64> {
-        Ok(if self.is_paused {
-            // INVARIANT: The stream is paused => accumulated time is the total time.
-            self.accumulated_active_time
-        } else if self.last_resumed_at == 0 {
-            // INVARIANT: The stream is not paused and was never resumed => stream was never paused.
-            at - self.starts_at
-        } else {
-            // SAFETY: INVARIANT: last_resumed_at != 0 =>
-            //     last_resumed_at >= starts_at && (ends_at == 0 || < ends_at) =>
-            //     last_resumed_at will never be > ends_at if ends_at > 0
-            (at - self.last_resumed_at)
-                .checked_add(self.accumulated_active_time)
-                .ok_or(error!(StreamError::AmountAvailableToWithdrawOutOfBounds))?
-        })
-    }
-
-    /// Get the maximum acceptable topup amount.
-    pub fn get_max_acceptable_topup_amount(&self, at: u64) -> Result<(bool, u64)> {
-        Ok(if self.is_prepaid || !self.has_flow_payments() {
-            (false, 0)
-        } else {
-            // Streams: non-prepaid, with flow payments.
-            let stops_at = self.get_stops_at();
-            if stops_at == 0 {
-                // Streams: non-prepaid, with flow payments, non-cancelled with no end time.
-                (true, 0)
-            } else if stops_at < self.starts_at {
-                // Streams: non-prepaid, with flow payments, stopped before start.
-                (false, 0)
-            } else {
-                // Streams: non-prepaid, with flow payments, cancelled after start time and/or with set end time after
-                // start time.
-                let total_possible_active_time = if at < self.starts_at {
-                    // Streams: non-prepaid, with flow payments, not-started, with set end time after start time. The
-                    // stream cannot be cancelled before current time.
-                    self.ends_at - self.starts_at
-                } else {
-                    // Streams: non-prepaid, with flow payments, started, cancelled after start time and/or with set end
-                    // time after start time.
-                    if stops_at <= at {
-                        // Streams: non-prepaid, with flow payments, started, stopped after start time.
-                        self.unsafe_get_active_time_after_start(stops_at)?
-                    } else {
-                        // Streams: non-prepaid, with flow payments, started, not already stopped => with set end time
-                        // after start time in the future.
-                        self.unsafe_get_active_time_after_start(at)?
-                            .checked_add(stops_at - at)
-                            .ok_or(error!(StreamError::TopupAmountOutOfBounds))?
-                    }
-                };
-
-                let total_possible_topup = if total_possible_active_time == 0 {
-                    self.initial_amount
-                } else {
-                    self.initial_amount
-                        .checked_add(
-                            (total_possible_active_time
-                                .checked_mul(self.flow_rate)
-                                .ok_or(error!(StreamError::TopupAmountOutOfBounds))?)
-                                / self.flow_interval,
-                        )
-                        .ok_or(error!(StreamError::TopupAmountOutOfBounds))?
-                };
-
-                (
-                    false,
-                    if total_possible_topup <= self.total_topup_amount {
-                        0
-                    } else {
-                        total_possible_topup - self.total_topup_amount
-                    },
-                )
-            }
-        })
-    }
-
-    /// Get the total amount owed to the recipient.
-    pub fn get_amount_owed(&self, at: u64) -> Result<u64> {
-        let at = self.min_with_stopped_at(at);
-
-        Ok(if at < self.starts_at {
-            0
-        } else if !self.has_flow_payments() {
-            self.initial_amount
-        } else {
-            let active_time = self.unsafe_get_active_time_after_start(at)?;
-            if active_time == 0 {
-                self.initial_amount
-            } else {
-                self.initial_amount
-                    .checked_add(
-                        (active_time
-                            .checked_mul(self.flow_rate)
-                            .ok_or(error!(StreamError::AmountAvailableToWithdrawOutOfBounds))?)
-                            / self.flow_interval,
-                    )
-                    .ok_or(error!(StreamError::AmountAvailableToWithdrawOutOfBounds))?
-            }
-        })
-    }
-
-    fn mark_cancelled(&mut self, at: u64, signer: &Signer) {
-        self.is_cancelled = true;
-        self.is_cancelled_before_start = at < self.starts_at;
-        self.is_cancelled_by_sender = signer.key() == self.sender;
-        self.cancelled_at = at;
-    }
-
-    fn add_topup_amount(&mut self, at: u64, latest_topup_amount: u64) -> Result<()> {
-        self.total_topup_amount = self
-            .total_topup_amount
-            .checked_add(latest_topup_amount)
-            .ok_or(error!(StreamError::TopupAmountOutOfBounds))?;
-        self.last_topup_at = at;
-        self.last_topup_amount = latest_topup_amount;
-        Ok(())
-    }
-
-    fn add_withdrawn_amount(&mut self, at: u64, latest_withdrawn_amount: u64) -> Result<()> {
-        if latest_withdrawn_amount == 0 {
-            return Ok(());
+    /// Get the seeds of the stream PDA, with which the stream signs for its escrow.
+    pub(crate) fn seeds(&self) -> StreamSeeds {
+        StreamSeeds {
+            activity: self.activity,
+            mint: self.mint,
+            creator: self.creator,
+            name: get_name_seed(&self.name),
+            bump: self.bump,
         }
-
-        self.total_withdrawn_amount = self
-            .total_withdrawn_amount
-            .checked_add(latest_withdrawn_amount)
-            .ok_or(error!(StreamError::WithdrawAmountOutOfBounds))?;
-        self.last_withdrawn_at = at;
-        self.last_withdrawn_amount = latest_withdrawn_amount;
-        Ok(())
     }
 
     // --- Utility functions --- END ---
 
     // --- Instruction functions --- BEGIN ---
 
-    /// Initialize a stream.
+    /// Initialize a stream. All stream creation instructions are routed through this method.
     pub fn initialize(
         &mut self,
         is_prepaid: bool,
+        activity: Pubkey,
         mint: Pubkey,
         sender: Pubkey,
-        recipient: Pubkey,
-        name: String,
-        starts_at: u64,
-        ends_at: u64,
-        initial_amount: u64,
-        flow_interval: u64,
-        flow_rate: u64,
-        sender_can_cancel: bool,
-        sender_can_cancel_at: u64,
-        sender_can_change_sender: bool,
-        sender_can_change_sender_at: u64,
-        sender_can_pause: bool,
-        sender_can_pause_at: u64,
-        recipient_can_resume_pause_by_sender: bool,
-        recipient_can_resume_pause_by_sender_at: u64,
-        anyone_can_withdraw_for_recipient: bool,
-        anyone_can_withdraw_for_recipient_at: u64,
-        seed: u64,
+        rent_payer: Pubkey,
+        params: CreateStreamParams,
         bump: u8,
+        at: u64,
     ) -> Result<()> {
+        let CreateStreamParams {
+            seed,
+            name,
+            recipient,
+            starts_at,
+            ends_at,
+            initial_amount,
+            flow_interval,
+            flow_rate,
+            permissions,
+            notice_period,
+            accrual_mode,
+        } = params;
+
         require!(recipient != Pubkey::default(), StreamError::EmptyRecipient);
         require!(name.len() >= MIN_STREAM_NAME_LENGTH, StreamError::StreamNameTooShort);
         require!(name.len() <= MAX_STREAM_NAME_LENGTH, StreamError::StreamNameTooLong);
         require!(recipient != sender, StreamError::SameSenderAndRecipient);
         require!(flow_interval > 0, StreamError::ZeroFlowInterval);
 
-        let at = get_current_timestamp()?;
         let starts_at = if starts_at < at { at } else { starts_at };
 
         require!(
@@ -671,31 +1024,7 @@ impl Stream {
             StreamError::InvalidEndsAt,
         );
 
-        let sender_can_cancel_at = if sender_can_cancel {
-            min(sender_can_cancel_at, at)
-        } else {
-            0
-        };
-        let sender_can_change_sender_at = if sender_can_change_sender {
-            min(sender_can_change_sender_at, at)
-        } else {
-            0
-        };
-        let sender_can_pause_at = if sender_can_pause {
-            min(sender_can_pause_at, at)
-        } else {
-            0
-        };
-        let recipient_can_resume_pause_by_sender_at = if recipient_can_resume_pause_by_sender {
-            min(recipient_can_resume_pause_by_sender_at, at)
-        } else {
-            0
-        };
-        let anyone_can_withdraw_for_recipient_at = if anyone_can_withdraw_for_recipient {
-            min(anyone_can_withdraw_for_recipient_at, at)
-        } else {
-            0
-        };
+        let permissions = permissions.clamp(at);
 
         self.is_prepaid = is_prepaid;
         self.is_cancelled = false;
@@ -703,8 +1032,17 @@ impl Stream {
         self.is_cancelled_by_sender = false;
         self.is_paused = false;
         self.is_paused_by_sender = false;
+        self.activity = activity;
+        self.manager = Pubkey::default();
+        self.manager_permissions = ManagerPermissions::default();
+        self.notice_period = notice_period;
+        self.cancellation_scheduled_at = 0;
+        self.is_cancellation_requested_by_sender = false;
         self.mint = mint;
         self.sender = sender;
+        self.creator = sender;
+        self.rent_payer = rent_payer;
+        self.accrual_mode = accrual_mode;
         self.recipient = recipient;
         self.created_at = at;
         self.starts_at = starts_at;
@@ -712,122 +1050,17 @@ impl Stream {
         self.initial_amount = initial_amount;
         self.flow_interval = flow_interval;
         self.flow_rate = flow_rate;
-        self.sender_can_cancel = sender_can_cancel;
-        self.sender_can_cancel_at = sender_can_cancel_at;
+        self.sender_can_cancel = permissions.sender_can_cancel;
+        self.sender_can_cancel_at = permissions.sender_can_cancel_at;
         self.cancelled_at = 0;
-        self.sender_can_change_sender = sender_can_change_sender;
-        self.sender_can_change_sender_at = sender_can_change_sender_at;
-        self.sender_can_pause = sender_can_pause;
-        self.sender_can_pause_at = sender_can_pause_at;
-        self.recipient_can_resume_pause_by_sender = recipient_can_resume_pause_by_sender;
-        self.recipient_can_resume_pause_by_sender_at = recipient_can_resume_pause_by_sender_at;
-        self.anyone_can_withdraw_for_recipient = anyone_can_withdraw_for_recipient;
-        self.anyone_can_withdraw_for_recipient_at = anyone_can_withdraw_for_recipient_at;
-        self.last_resumed_at = 0;
-        self.accumulated_active_time = 0;
-        self.total_withdrawn_amount = 0;
-        self.last_withdrawn_at = 0;
-        self.last_withdrawn_amount = 0;
-        self.total_topup_amount = 0;
-        self.last_topup_at = 0;
-        self.last_topup_amount = 0;
-        self.deposit_needed = self.get_deposit_needed()?;
-        self.seed = seed;
-        self.bump = bump;
-        self.name = name;
-
-        require!(
-            self.initial_amount > 0 || self.has_flow_payments(),
-            StreamError::ZeroLifetimeAmount
-        );
-        Ok(())
-    }
-
-    pub fn initialize1(
-        &mut self,
-        activity: Pubkey,
-        mint: Pubkey,
-        sender: Pubkey,
-        recipient: Pubkey,
-        name: String,
-        starts_at: u64,
-        ends_at: u64,
-        initial_amount: u64,
-        flow_interval: u64,
-        flow_rate: u64,
-        sender_can_cancel: bool,
-        sender_can_cancel_at: u64,
-        sender_can_change_sender: bool,
-        sender_can_change_sender_at: u64,
-        sender_can_pause: bool,
-        sender_can_pause_at: u64,
-        recipient_can_resume_pause_by_sender: bool,
-        recipient_can_resume_pause_by_sender_at: u64,
-        anyone_can_withdraw_for_recipient: bool,
-        anyone_can_withdraw_for_recipient_at: u64,
-        seed: u64,
-        bump: u8,
-    ) -> Result<()> {
-        require!(recipient != Pubkey::default(), StreamError::EmptyRecipient);
-        require!(name.len() >= MIN_STREAM_NAME_LENGTH, StreamError::StreamNameTooShort);
-        require!(name.len() <= MAX_STREAM_NAME_LENGTH, StreamError::StreamNameTooLong);
-        require!(recipient != sender, StreamError::SameSenderAndRecipient);
-        require!(flow_interval > 0, StreamError::ZeroFlowInterval);
-
-
-        let sender_can_cancel_at = if sender_can_cancel {
-            min(sender_can_cancel_at, starts_at)
-        } else {
-            0
-        };
-        let sender_can_change_sender_at = if sender_can_change_sender {
-            min(sender_can_change_sender_at, starts_at)
-        } else {
-            0
-        };
-        let sender_can_pause_at = if sender_can_pause {
-            min(sender_can_pause_at, starts_at)
-        } else {
-            0
-        };
-        let recipient_can_resume_pause_by_sender_at = if recipient_can_resume_pause_by_sender {
-            min(recipient_can_resume_pause_by_sender_at, starts_at)
-        } else {
-            0
-        };
-        let anyone_can_withdraw_for_recipient_at = if anyone_can_withdraw_for_recipient {
-            min(anyone_can_withdraw_for_recipient_at, starts_at)
-        } else {
-            0
-        };
-
-        self.is_prepaid = true;
-        self.is_cancelled = false;
-        self.is_cancelled_before_start = false;
-        self.is_cancelled_by_sender = false;
-        self.is_paused = false;
-        self.is_paused_by_sender = false;
-        self.activity = activity;
-        self.mint = mint;
-        self.sender = sender;
-        self.recipient = recipient;
-        self.created_at = starts_at;
-        self.starts_at = starts_at;
-        self.ends_at = ends_at;
-        self.initial_amount = initial_amount;
-        self.flow_interval = flow_interval;
-        self.flow_rate = flow_rate;
-        self.sender_can_cancel = sender_can_cancel;
-        self.sender_can_cancel_at = sender_can_cancel_at;
-        self.cancelled_at = 0;
-        self.sender_can_change_sender = sender_can_change_sender;
-        self.sender_can_change_sender_at = sender_can_change_sender_at;
-        self.sender_can_pause = sender_can_pause;
-        self.sender_can_pause_at = sender_can_pause_at;
-        self.recipient_can_resume_pause_by_sender = recipient_can_resume_pause_by_sender;
-        self.recipient_can_resume_pause_by_sender_at = recipient_can_resume_pause_by_sender_at;
-        self.anyone_can_withdraw_for_recipient = anyone_can_withdraw_for_recipient;
-        self.anyone_can_withdraw_for_recipient_at = anyone_can_withdraw_for_recipient_at;
+        self.sender_can_change_sender = permissions.sender_can_change_sender;
+        self.sender_can_change_sender_at = permissions.sender_can_change_sender_at;
+        self.sender_can_pause = permissions.sender_can_pause;
+        self.sender_can_pause_at = permissions.sender_can_pause_at;
+        self.recipient_can_resume_pause_by_sender = permissions.recipient_can_resume_pause_by_sender;
+        self.recipient_can_resume_pause_by_sender_at = permissions.recipient_can_resume_pause_by_sender_at;
+        self.anyone_can_withdraw_for_recipient = permissions.anyone_can_withdraw_for_recipient;
+        self.anyone_can_withdraw_for_recipient_at = permissions.anyone_can_withdraw_for_recipient_at;
         self.last_resumed_at = 0;
         self.accumulated_active_time = 0;
         self.total_withdrawn_amount = 0;
@@ -837,6 +1070,7 @@ impl Stream {
         self.last_topup_at = 0;
         self.last_topup_amount = 0;
         self.deposit_needed = self.get_deposit_needed()?;
+        self.version = STREAM_VERSION;
         self.seed = seed;
         self.bump = bump;
         self.name = name;
@@ -849,16 +1083,15 @@ impl Stream {
     }
 
     /// Initialize a prepaid stream.
-    pub fn initialize_prepaid(&mut self) -> Result<u64> {
+    pub fn initialize_prepaid(&mut self, at: u64) -> Result<u64> {
         let prepaid_amount_needed = self.get_prepaid_amount_needed()?;
-        msg!("get_prepaid_amount_needed {}", prepaid_amount_needed);
         require!(prepaid_amount_needed > 0, StreamError::ZeroLifetimeAmount);
-        self.add_topup_amount(get_current_timestamp()?, prepaid_amount_needed)?;
+        self.add_topup_amount(at, prepaid_amount_needed)?;
         Ok(prepaid_amount_needed)
     }
 
     /// Initialize a non-prepaid stream.
-    pub fn initialize_non_prepaid(&mut self, topup_amount: u64) -> Result<()> {
+    pub fn initialize_non_prepaid(&mut self, topup_amount: u64, at: u64) -> Result<()> {
         require!(topup_amount > 0, StreamError::ZeroAmount);
 
         // Amount needed = initial_amount + 2 * deposit_amount.
@@ -874,28 +1107,676 @@ impl Stream {
             )
             .ok_or(error!(StreamError::DepositAmountNeededOutOfBounds))?;
         require!(topup_amount >= amount_needed, StreamError::AmountLessThanAmountNeeded);
-        self.add_topup_amount(get_current_timestamp()?, topup_amount - self.deposit_needed)
+        self.add_topup_amount(at, topup_amount - self.deposit_needed)
+    }
+
+    // --- Instruction functions --- END ---
+}
+
+/// Convert a stream math error to the corresponding program error.
+fn math_error(error: MathError) -> Error {
+    error!(StreamError::from(error))
+}
+
+/// Get the weighted amount of a stake of `amount` with weight `weight`.
+fn weighted_amount(amount: u64, weight: u64) -> Result<u64> {
+    let weighted_amount = u128::from(amount) * u128::from(weight) / u128::from(BASE_STAKE_WEIGHT);
+    u64::try_from(weighted_amount).map_err(|_| error!(StreamError::RewardAmountOutOfBounds))
+}
+
+/// Zero-copy layout of a [`Stream`].
+///
+/// [`Stream`] accounts are Borsh-deserialized and serialized back on every instruction, which is costly for a struct
+/// of this size with a variable-length name. A zero-copy stream is read in place instead: it has a fixed-size name
+/// buffer and bit-packed flags, so it has a fixed length and no padding.
+///
+/// Existing streams are converted in place with the [`migrate_stream`](crate::superstream::migrate_stream)
+/// instruction. The stream keeps its address, escrow account and amounts. Migrated streams are operated with the
+/// `*_zero_copy` instructions, which update the fields in place with the same logic as their [`Stream`] counterparts,
+/// as both layouts implement [`StreamLayout`].
+/// The compute units used by both layouts for `withdraw`, `withdraw_excess_topup_non_prepaid_ended`, `cancel`,
+/// `topup_non_prepaid` and `unstake_early` are measured and printed by the typescript tests.
+///
+/// For more information on the fields, see fields of the [`Stream`] struct.
+#[account(zero_copy)]
+pub struct ZeroCopyStream {
+    pub created_at: u64,
+    pub starts_at: u64,
+    pub ends_at: u64,
+
+    pub initial_amount: u64,
+    pub flow_interval: u64,
+    pub flow_rate: u64,
+
+    pub cancelled_at: u64,
+
+    pub sender_can_cancel_at: u64,
+    pub sender_can_change_sender_at: u64,
+    pub sender_can_pause_at: u64,
+    pub recipient_can_resume_pause_by_sender_at: u64,
+    pub anyone_can_withdraw_for_recipient_at: u64,
+
+    pub last_resumed_at: u64,
+    pub accumulated_active_time: u64,
+
+    pub total_withdrawn_amount: u64,
+    pub last_withdrawn_at: u64,
+    pub last_withdrawn_amount: u64,
+
+    pub total_topup_amount: u64,
+    pub last_topup_at: u64,
+    pub last_topup_amount: u64,
+
+    pub deposit_needed: u64,
+
+    pub seed: u64,
+
+    pub notice_period: u64,
+    pub cancellation_scheduled_at: u64,
+
+    pub stake_weight: u64,
+    pub version: u64,
+
+    /// Extra space for program upgrades.
+    pub reserved: [u64; 10],
+
+    pub mint: Pubkey,
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub activity: Pubkey,
+    pub manager: Pubkey,
+    pub creator: Pubkey,
+    pub rent_payer: Pubkey,
+    pub beneficiary: Pubkey,
+
+    /// Boolean fields of the stream, one bit each. See the `ZeroCopyStream::IS_*`, `ZeroCopyStream::SENDER_*`, etc.
+    /// constants.
+    pub flags: u32,
+
+    pub bump: u8,
+
+    /// Length of the name in bytes.
+    pub name_length: u8,
+    /// Name of the stream, padded with zeroes up to [`MAX_STREAM_NAME_LENGTH`] bytes.
+    pub name: [u8; 100],
+
+    /// Explicit padding to a multiple of 8 bytes, as zero-copy accounts must not have implicit padding.
+    pub padding: [u8; 6],
+}
+
+impl ZeroCopyStream {
+    /// Total size of a zero-copy stream account.
+    pub const LENGTH: usize = ANCHOR_DISCRIMINATOR_LENGTH + std::mem::size_of::<ZeroCopyStream>();
+
+    pub const IS_PREPAID: u32 = 1 << 0;
+    pub const IS_CANCELLED: u32 = 1 << 1;
+    pub const IS_CANCELLED_BEFORE_START: u32 = 1 << 2;
+    pub const IS_CANCELLED_BY_SENDER: u32 = 1 << 3;
+    pub const SENDER_CAN_CANCEL: u32 = 1 << 4;
+    pub const SENDER_CAN_CHANGE_SENDER: u32 = 1 << 5;
+    pub const IS_PAUSED: u32 = 1 << 6;
+    pub const IS_PAUSED_BY_SENDER: u32 = 1 << 7;
+    pub const SENDER_CAN_PAUSE: u32 = 1 << 8;
+    pub const RECIPIENT_CAN_RESUME_PAUSE_BY_SENDER: u32 = 1 << 9;
+    pub const ANYONE_CAN_WITHDRAW_FOR_RECIPIENT: u32 = 1 << 10;
+    pub const IS_CANCELLATION_REQUESTED_BY_SENDER: u32 = 1 << 11;
+    pub const MANAGER_CAN_CANCEL: u32 = 1 << 12;
+    pub const MANAGER_CAN_CHANGE_SENDER: u32 = 1 << 13;
+    pub const MANAGER_CAN_PAUSE: u32 = 1 << 14;
+    pub const MANAGER_CAN_RESUME: u32 = 1 << 15;
+    pub const IS_DISCRETE: u32 = 1 << 16;
+    pub const IS_STAKE: u32 = 1 << 17;
+
+    /// Convert a stream to the zero-copy layout.
+    pub fn from_stream(stream: &Stream) -> Self {
+        let flag = |is_set: bool, flag: u32| if is_set { flag } else { 0 };
+        let flags = flag(stream.is_prepaid, Self::IS_PREPAID)
+            | flag(stream.is_cancelled, Self::IS_CANCELLED)
+            | flag(stream.is_cancelled_before_start, Self::IS_CANCELLED_BEFORE_START)
+            | flag(stream.is_cancelled_by_sender, Self::IS_CANCELLED_BY_SENDER)
+            | flag(stream.sender_can_cancel, Self::SENDER_CAN_CANCEL)
+            | flag(stream.sender_can_change_sender, Self::SENDER_CAN_CHANGE_SENDER)
+            | flag(stream.is_paused, Self::IS_PAUSED)
+            | flag(stream.is_paused_by_sender, Self::IS_PAUSED_BY_SENDER)
+            | flag(stream.sender_can_pause, Self::SENDER_CAN_PAUSE)
+            | flag(
+                stream.recipient_can_resume_pause_by_sender,
+                Self::RECIPIENT_CAN_RESUME_PAUSE_BY_SENDER,
+            )
+            | flag(
+                stream.anyone_can_withdraw_for_recipient,
+                Self::ANYONE_CAN_WITHDRAW_FOR_RECIPIENT,
+            )
+            | flag(
+                stream.is_cancellation_requested_by_sender,
+                Self::IS_CANCELLATION_REQUESTED_BY_SENDER,
+            )
+            | flag(stream.manager_permissions.can_cancel, Self::MANAGER_CAN_CANCEL)
+            | flag(
+                stream.manager_permissions.can_change_sender,
+                Self::MANAGER_CAN_CHANGE_SENDER,
+            )
+            | flag(stream.manager_permissions.can_pause, Self::MANAGER_CAN_PAUSE)
+            | flag(stream.manager_permissions.can_resume, Self::MANAGER_CAN_RESUME)
+            | flag(stream.accrual_mode == AccrualMode::Discrete, Self::IS_DISCRETE)
+            | flag(stream.is_stake, Self::IS_STAKE);
+
+        let mut name = [0; MAX_STREAM_NAME_LENGTH];
+        name[..stream.name.len()].copy_from_slice(stream.name.as_bytes());
+
+        Self {
+            created_at: stream.created_at,
+            starts_at: stream.starts_at,
+            ends_at: stream.ends_at,
+            initial_amount: stream.initial_amount,
+            flow_interval: stream.flow_interval,
+            flow_rate: stream.flow_rate,
+            cancelled_at: stream.cancelled_at,
+            sender_can_cancel_at: stream.sender_can_cancel_at,
+            sender_can_change_sender_at: stream.sender_can_change_sender_at,
+            sender_can_pause_at: stream.sender_can_pause_at,
+            recipient_can_resume_pause_by_sender_at: stream.recipient_can_resume_pause_by_sender_at,
+            anyone_can_withdraw_for_recipient_at: stream.anyone_can_withdraw_for_recipient_at,
+            last_resumed_at: stream.last_resumed_at,
+            accumulated_active_time: stream.accumulated_active_time,
+            total_withdrawn_amount: stream.total_withdrawn_amount,
+            last_withdrawn_at: stream.last_withdrawn_at,
+            last_withdrawn_amount: stream.last_withdrawn_amount,
+            total_topup_amount: stream.total_topup_amount,
+            last_topup_at: stream.last_topup_at,
+            last_topup_amount: stream.last_topup_amount,
+            deposit_needed: stream.deposit_needed,
+            seed: stream.seed,
+            notice_period: stream.notice_period,
+            cancellation_scheduled_at: stream.cancellation_scheduled_at,
+            stake_weight: stream.stake_weight,
+            version: stream.version,
+            reserved: stream.reserved,
+            mint: stream.mint,
+            sender: stream.sender,
+            recipient: stream.recipient,
+            activity: stream.activity,
+            manager: stream.manager,
+            creator: stream.creator,
+            rent_payer: stream.rent_payer,
+            beneficiary: stream.beneficiary,
+            flags,
+            bump: stream.bump,
+            name_length: stream.name.len() as u8,
+            name,
+            padding: [0; 6],
+        }
+    }
+
+    /// Convert the stream back to the Borsh layout, e.g. to read it in clients.
+    pub fn to_stream(&self) -> Stream {
+        Stream {
+            is_prepaid: self.has_flag(Self::IS_PREPAID),
+            mint: self.mint,
+            sender: self.sender,
+            recipient: self.recipient,
+            created_at: self.created_at,
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+            initial_amount: self.initial_amount,
+            flow_interval: self.flow_interval,
+            flow_rate: self.flow_rate,
+            is_cancelled: self.has_flag(Self::IS_CANCELLED),
+            is_cancelled_before_start: self.has_flag(Self::IS_CANCELLED_BEFORE_START),
+            is_cancelled_by_sender: self.has_flag(Self::IS_CANCELLED_BY_SENDER),
+            cancelled_at: self.cancelled_at,
+            sender_can_cancel: self.has_flag(Self::SENDER_CAN_CANCEL),
+            sender_can_cancel_at: self.sender_can_cancel_at,
+            sender_can_change_sender: self.has_flag(Self::SENDER_CAN_CHANGE_SENDER),
+            sender_can_change_sender_at: self.sender_can_change_sender_at,
+            is_paused: self.has_flag(Self::IS_PAUSED),
+            is_paused_by_sender: self.has_flag(Self::IS_PAUSED_BY_SENDER),
+            sender_can_pause: self.has_flag(Self::SENDER_CAN_PAUSE),
+            sender_can_pause_at: self.sender_can_pause_at,
+            recipient_can_resume_pause_by_sender: self.has_flag(Self::RECIPIENT_CAN_RESUME_PAUSE_BY_SENDER),
+            recipient_can_resume_pause_by_sender_at: self.recipient_can_resume_pause_by_sender_at,
+            anyone_can_withdraw_for_recipient: self.has_flag(Self::ANYONE_CAN_WITHDRAW_FOR_RECIPIENT),
+            anyone_can_withdraw_for_recipient_at: self.anyone_can_withdraw_for_recipient_at,
+            last_resumed_at: self.last_resumed_at,
+            accumulated_active_time: self.accumulated_active_time,
+            total_withdrawn_amount: self.total_withdrawn_amount,
+            last_withdrawn_at: self.last_withdrawn_at,
+            last_withdrawn_amount: self.last_withdrawn_amount,
+            total_topup_amount: self.total_topup_amount,
+            last_topup_at: self.last_topup_at,
+            last_topup_amount: self.last_topup_amount,
+            deposit_needed: self.deposit_needed,
+            stake_weight: self.stake_weight,
+            version: self.version,
+            reserved: self.reserved,
+            seed: self.seed,
+            bump: self.bump,
+            activity: self.activity,
+            manager: self.manager,
+            manager_permissions: ManagerPermissions {
+                can_cancel: self.has_flag(Self::MANAGER_CAN_CANCEL),
+                can_change_sender: self.has_flag(Self::MANAGER_CAN_CHANGE_SENDER),
+                can_pause: self.has_flag(Self::MANAGER_CAN_PAUSE),
+                can_resume: self.has_flag(Self::MANAGER_CAN_RESUME),
+            },
+            notice_period: self.notice_period,
+            cancellation_scheduled_at: self.cancellation_scheduled_at,
+            is_cancellation_requested_by_sender: self.has_flag(Self::IS_CANCELLATION_REQUESTED_BY_SENDER),
+            creator: self.creator,
+            accrual_mode: if self.has_flag(Self::IS_DISCRETE) {
+                AccrualMode::Discrete
+            } else {
+                AccrualMode::Continuous
+            },
+            rent_payer: self.rent_payer,
+            beneficiary: self.beneficiary,
+            is_stake: self.has_flag(Self::IS_STAKE),
+            name: String::from_utf8_lossy(&self.name[..self.name_length as usize]).into_owned(),
+        }
     }
 
-    pub(crate) fn cancel(&mut self, key: Pubkey, signer: &Signer, recipient: Pubkey) -> Result<CancelTransferParams> {
-        require!(!self.is_cancelled, StreamError::StreamAlreadyCancelled);
-        require!(recipient == self.recipient, StreamError::InvalidRecipient);
+    /// Get the seeds of the stream PDA. See [`Stream::seeds`].
+    pub(crate) fn seeds(&self) -> StreamSeeds {
+        let name = String::from_utf8_lossy(&self.name[..self.name_length as usize]);
+        StreamSeeds {
+            activity: self.activity,
+            mint: self.mint,
+            creator: self.creator,
+            name: get_name_seed(&name),
+            bump: self.bump,
+        }
+    }
+}
+
+/// Generates [`StreamFields`] for the fields stored the same way by both stream layouts.
+macro_rules! stream_fields {
+    ($($field:ident, $set_field:ident: $type:ty;)*) => {
+        /// Accessors of the fields stored the same way by [`Stream`] and [`ZeroCopyStream`], through which
+        /// [`StreamLayout`] runs the same stream logic on both layouts. See fields of the [`Stream`] struct.
+        pub trait StreamFields {
+            $(
+                fn $field(&self) -> $type;
+                fn $set_field(&mut self, $field: $type);
+            )*
+        }
+
+        impl StreamFields for Stream {
+            $(
+                fn $field(&self) -> $type {
+                    self.$field
+                }
+
+                fn $set_field(&mut self, $field: $type) {
+                    self.$field = $field;
+                }
+            )*
+        }
+
+        impl StreamFields for ZeroCopyStream {
+            $(
+                fn $field(&self) -> $type {
+                    self.$field
+                }
 
-        let at = get_current_timestamp()?;
+                fn $set_field(&mut self, $field: $type) {
+                    self.$field = $field;
+                }
+            )*
+        }
+    };
+}
+
+stream_fields! {
+    starts_at, set_starts_at: u64;
+    ends_at, set_ends_at: u64;
+    initial_amount, set_initial_amount: u64;
+    flow_interval, set_flow_interval: u64;
+    flow_rate, set_flow_rate: u64;
+    cancelled_at, set_cancelled_at: u64;
+    sender_can_cancel_at, set_sender_can_cancel_at: u64;
+    sender_can_change_sender_at, set_sender_can_change_sender_at: u64;
+    sender_can_pause_at, set_sender_can_pause_at: u64;
+    recipient_can_resume_pause_by_sender_at, set_recipient_can_resume_pause_by_sender_at: u64;
+    anyone_can_withdraw_for_recipient_at, set_anyone_can_withdraw_for_recipient_at: u64;
+    last_resumed_at, set_last_resumed_at: u64;
+    accumulated_active_time, set_accumulated_active_time: u64;
+    total_withdrawn_amount, set_total_withdrawn_amount: u64;
+    last_withdrawn_at, set_last_withdrawn_at: u64;
+    last_withdrawn_amount, set_last_withdrawn_amount: u64;
+    total_topup_amount, set_total_topup_amount: u64;
+    last_topup_at, set_last_topup_at: u64;
+    last_topup_amount, set_last_topup_amount: u64;
+    deposit_needed, set_deposit_needed: u64;
+    notice_period, set_notice_period: u64;
+    cancellation_scheduled_at, set_cancellation_scheduled_at: u64;
+    stake_weight, set_stake_weight: u64;
+    sender, set_sender: Pubkey;
+    recipient, set_recipient: Pubkey;
+    manager, set_manager_key: Pubkey;
+    creator, set_creator: Pubkey;
+    beneficiary, set_beneficiary: Pubkey;
+}
+
+/// Stream logic shared by both stream layouts, [`Stream`] and [`ZeroCopyStream`]. The boolean fields are accessed
+/// through the `ZeroCopyStream::IS_*`, `ZeroCopyStream::SENDER_*`, etc. flags, which [`Stream`] maps to its fields.
+pub trait StreamLayout: StreamFields {
+    /// Check if a flag is set.
+    fn has_flag(&self, flag: u32) -> bool;
+
+    /// Set or clear a flag.
+    fn set_flag(&mut self, flag: u32, is_set: bool);
+
+    /// Get the fields of the stream which determine its amounts. See [`StreamTerms`] for the math.
+    fn terms(&self) -> StreamTerms {
+        StreamTerms {
+            is_prepaid: self.has_flag(ZeroCopyStream::IS_PREPAID),
+            starts_at: self.starts_at(),
+            ends_at: self.ends_at(),
+            initial_amount: self.initial_amount(),
+            flow_interval: self.flow_interval(),
+            flow_rate: self.flow_rate(),
+            notice_period: self.notice_period(),
+            cancelled_at: self.cancelled_at(),
+            cancellation_scheduled_at: self.cancellation_scheduled_at(),
+            is_paused: self.has_flag(ZeroCopyStream::IS_PAUSED),
+            last_resumed_at: self.last_resumed_at(),
+            accumulated_active_time: self.accumulated_active_time(),
+            total_topup_amount: self.total_topup_amount(),
+            is_discrete: self.has_flag(ZeroCopyStream::IS_DISCRETE),
+        }
+    }
+
+    fn has_flow_payments(&self) -> bool {
+        self.terms().has_flow_payments()
+    }
+
+    /// Calculate the amount of prepaid needed for a prepaid stream. This is called when creating the stream.
+    fn get_prepaid_amount_needed(&self) -> Result<u64> {
+        self.terms().prepaid_amount_needed().map_err(math_error)
+    }
+
+    /// Calculate the amount of deposit needed for the streaming payments excluding the initial amount. This is called
+    /// when creating the stream.
+    fn get_deposit_needed(&self) -> Result<u64> {
+        self.terms().deposit_needed().map_err(math_error)
+    }
+
+    fn get_stops_at(&self) -> u64 {
+        self.terms().stops_at()
+    }
+
+    /// Check if a requested cancellation has taken effect.
+    fn is_notice_over(&self, at: u64) -> bool {
+        self.terms().is_notice_over(at)
+    }
+
+    /// Get the wallet owning the stake of the stream, i.e. whose stake position is updated with it. This is the
+    /// beneficiary if the stake was funded on behalf of another wallet, otherwise the creator.
+    fn staker(&self) -> Pubkey {
+        if self.beneficiary() == Pubkey::default() {
+            self.creator()
+        } else {
+            self.beneficiary()
+        }
+    }
+
+    /// Get the wallet receiving the withdrawals of the stream. This is the beneficiary if the stream is a stake funded
+    /// on behalf of another wallet, otherwise the recipient.
+    fn payee(&self) -> Pubkey {
+        if self.beneficiary() == Pubkey::default() {
+            self.recipient()
+        } else {
+            self.beneficiary()
+        }
+    }
+
+    /// Get the wallet receiving the refund when the stream is cancelled. This is the beneficiary if the stream is a
+    /// stake funded on behalf of another wallet, since the stake belongs to it, otherwise the sender.
+    fn refundee(&self) -> Pubkey {
+        if self.beneficiary() == Pubkey::default() {
+            self.sender()
+        } else {
+            self.beneficiary()
+        }
+    }
+
+    /// Get the weight of the stake of the stream, or `None` if the stream is not a stake. See [`Stream::is_stake`].
+    fn stake(&self) -> Option<u64> {
+        if self.has_flag(ZeroCopyStream::IS_STAKE) {
+            Some(self.stake_weight())
+        } else {
+            None
+        }
+    }
+
+    /// Check if the stream has stooped.
+    fn has_stopped(&self, at: u64) -> bool {
+        self.terms().has_stopped(at)
+    }
+
+    /// Check if the stream has ended, i.e. if the recipient can withdraw.
+    fn has_ended(&self, at: u64) -> bool {
+        self.ends_at() > 0 && self.ends_at() < at
+    }
+
+    /// Get the maximum acceptable topup amount.
+    fn get_max_acceptable_topup_amount(&self, at: u64) -> Result<(bool, u64)> {
+        self.terms().max_acceptable_topup_amount(at).map_err(math_error)
+    }
+
+    /// Get the total amount owed to the recipient.
+    fn get_amount_owed(&self, at: u64) -> Result<u64> {
+        self.terms().amount_owed(at).map_err(math_error)
+    }
+
+    /// Get a quote of the amounts owed, withdrawable and refundable at `at`, along with the solvency and stop time of
+    /// the stream.
+    fn quote(&self, at: u64) -> Result<StreamQuote> {
+        let amount_owed = self.get_amount_owed(at)?;
+        let total_topup_amount = self.total_topup_amount();
+        let is_solvent = total_topup_amount >= amount_owed;
+
+        // Same as what withdraw_and_change_recipient pays out, which is nothing until the stream has ended. An insolvent
+        // stream which is not cancelled yet also owes the deposit to the recipient.
+        let is_withdrawable = self.has_ended(at);
+        let amount_payable = if !is_withdrawable {
+            0
+        } else if is_solvent {
+            amount_owed
+        } else if self.has_flag(ZeroCopyStream::IS_CANCELLED) {
+            total_topup_amount
+        } else {
+            total_topup_amount
+                .checked_add(self.deposit_needed())
+                .ok_or(error!(StreamError::WithdrawAmountOutOfBounds))?
+        };
+        let amount_available_to_withdraw = amount_payable.saturating_sub(self.total_withdrawn_amount());
+
+        // Same as what cancel refunds the sender. Nothing is refunded for an insolvent stream.
+        let amount_refundable = if is_solvent {
+            total_topup_amount
+                .checked_add(self.deposit_needed())
+                .ok_or(error!(StreamError::CancellationRefundOutOfBounds))?
+                - amount_owed
+        } else {
+            0
+        };
+
+        let (has_unlimited_topup, max_acceptable_topup_amount) = self.get_max_acceptable_topup_amount(at)?;
+
+        Ok(StreamQuote {
+            at,
+            amount_owed,
+            amount_available_to_withdraw,
+            amount_refundable,
+            is_withdrawable,
+            is_solvent,
+            stops_at: self.get_stops_at(),
+            has_stopped: self.has_stopped(at),
+            has_unlimited_topup,
+            max_acceptable_topup_amount,
+        })
+    }
+}
+
+impl StreamLayout for Stream {
+    fn has_flag(&self, flag: u32) -> bool {
+        match flag {
+            ZeroCopyStream::IS_PREPAID => self.is_prepaid,
+            ZeroCopyStream::IS_CANCELLED => self.is_cancelled,
+            ZeroCopyStream::IS_CANCELLED_BEFORE_START => self.is_cancelled_before_start,
+            ZeroCopyStream::IS_CANCELLED_BY_SENDER => self.is_cancelled_by_sender,
+            ZeroCopyStream::SENDER_CAN_CANCEL => self.sender_can_cancel,
+            ZeroCopyStream::SENDER_CAN_CHANGE_SENDER => self.sender_can_change_sender,
+            ZeroCopyStream::IS_PAUSED => self.is_paused,
+            ZeroCopyStream::IS_PAUSED_BY_SENDER => self.is_paused_by_sender,
+            ZeroCopyStream::SENDER_CAN_PAUSE => self.sender_can_pause,
+            ZeroCopyStream::RECIPIENT_CAN_RESUME_PAUSE_BY_SENDER => self.recipient_can_resume_pause_by_sender,
+            ZeroCopyStream::ANYONE_CAN_WITHDRAW_FOR_RECIPIENT => self.anyone_can_withdraw_for_recipient,
+            ZeroCopyStream::IS_CANCELLATION_REQUESTED_BY_SENDER => self.is_cancellation_requested_by_sender,
+            ZeroCopyStream::MANAGER_CAN_CANCEL => self.manager_permissions.can_cancel,
+            ZeroCopyStream::MANAGER_CAN_CHANGE_SENDER => self.manager_permissions.can_change_sender,
+            ZeroCopyStream::MANAGER_CAN_PAUSE => self.manager_permissions.can_pause,
+            ZeroCopyStream::MANAGER_CAN_RESUME => self.manager_permissions.can_resume,
+            ZeroCopyStream::IS_DISCRETE => self.accrual_mode == AccrualMode::Discrete,
+            ZeroCopyStream::IS_STAKE => self.is_stake,
+            _ => unreachable!("Unknown stream flag {}", flag),
+        }
+    }
+
+    fn set_flag(&mut self, flag: u32, is_set: bool) {
+        let field = match flag {
+            ZeroCopyStream::IS_PREPAID => &mut self.is_prepaid,
+            ZeroCopyStream::IS_CANCELLED => &mut self.is_cancelled,
+            ZeroCopyStream::IS_CANCELLED_BEFORE_START => &mut self.is_cancelled_before_start,
+            ZeroCopyStream::IS_CANCELLED_BY_SENDER => &mut self.is_cancelled_by_sender,
+            ZeroCopyStream::SENDER_CAN_CANCEL => &mut self.sender_can_cancel,
+            ZeroCopyStream::SENDER_CAN_CHANGE_SENDER => &mut self.sender_can_change_sender,
+            ZeroCopyStream::IS_PAUSED => &mut self.is_paused,
+            ZeroCopyStream::IS_PAUSED_BY_SENDER => &mut self.is_paused_by_sender,
+            ZeroCopyStream::SENDER_CAN_PAUSE => &mut self.sender_can_pause,
+            ZeroCopyStream::RECIPIENT_CAN_RESUME_PAUSE_BY_SENDER => &mut self.recipient_can_resume_pause_by_sender,
+            ZeroCopyStream::ANYONE_CAN_WITHDRAW_FOR_RECIPIENT => &mut self.anyone_can_withdraw_for_recipient,
+            ZeroCopyStream::IS_CANCELLATION_REQUESTED_BY_SENDER => &mut self.is_cancellation_requested_by_sender,
+            ZeroCopyStream::MANAGER_CAN_CANCEL => &mut self.manager_permissions.can_cancel,
+            ZeroCopyStream::MANAGER_CAN_CHANGE_SENDER => &mut self.manager_permissions.can_change_sender,
+            ZeroCopyStream::MANAGER_CAN_PAUSE => &mut self.manager_permissions.can_pause,
+            ZeroCopyStream::MANAGER_CAN_RESUME => &mut self.manager_permissions.can_resume,
+            ZeroCopyStream::IS_STAKE => &mut self.is_stake,
+            ZeroCopyStream::IS_DISCRETE => {
+                self.accrual_mode = if is_set {
+                    AccrualMode::Discrete
+                } else {
+                    AccrualMode::Continuous
+                };
+                return;
+            },
+            _ => unreachable!("Unknown stream flag {}", flag),
+        };
+        *field = is_set;
+    }
+}
+
+impl StreamLayout for ZeroCopyStream {
+    fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: u32, is_set: bool) {
+        if is_set {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+}
+
+/// Instruction functions of a stream, run in place on both stream layouts.
+pub(crate) trait StreamInstructions: StreamLayout {
+    // --- Utility functions --- BEGIN ---
+
+    /// Check if `key` can act as the sender, either by being the sender or the manager with the right `manager_flag`.
+    fn acts_as_sender(&self, key: Pubkey, manager_flag: u32) -> bool {
+        key == self.sender()
+            || (self.manager() != Pubkey::default() && key == self.manager() && self.has_flag(manager_flag))
+    }
+
+    fn set_manager_permissions(&mut self, manager_permissions: ManagerPermissions) {
+        self.set_flag(ZeroCopyStream::MANAGER_CAN_CANCEL, manager_permissions.can_cancel);
+        self.set_flag(
+            ZeroCopyStream::MANAGER_CAN_CHANGE_SENDER,
+            manager_permissions.can_change_sender,
+        );
+        self.set_flag(ZeroCopyStream::MANAGER_CAN_PAUSE, manager_permissions.can_pause);
+        self.set_flag(ZeroCopyStream::MANAGER_CAN_RESUME, manager_permissions.can_resume);
+    }
+
+    fn mark_cancelled(&mut self, at: u64, signer: Pubkey) {
+        let (at, is_cancelled_by_sender) = if self.is_notice_over(at) {
+            // A requested cancellation takes effect at the scheduled time, irrespective of who settles it.
+            let is_requested_by_sender = self.has_flag(ZeroCopyStream::IS_CANCELLATION_REQUESTED_BY_SENDER);
+            (self.cancellation_scheduled_at(), is_requested_by_sender)
+        } else {
+            (at, self.acts_as_sender(signer, ZeroCopyStream::MANAGER_CAN_CANCEL))
+        };
+        self.set_flag(ZeroCopyStream::IS_CANCELLED, true);
+        self.set_flag(ZeroCopyStream::IS_CANCELLED_BEFORE_START, at < self.starts_at());
+        self.set_flag(ZeroCopyStream::IS_CANCELLED_BY_SENDER, is_cancelled_by_sender);
+        self.set_cancelled_at(at);
+    }
+
+    fn add_topup_amount(&mut self, at: u64, latest_topup_amount: u64) -> Result<()> {
+        self.set_total_topup_amount(
+            self.total_topup_amount()
+                .checked_add(latest_topup_amount)
+                .ok_or(error!(StreamError::TopupAmountOutOfBounds))?,
+        );
+        self.set_last_topup_at(at);
+        self.set_last_topup_amount(latest_topup_amount);
+        Ok(())
+    }
+
+    fn add_withdrawn_amount(&mut self, at: u64, latest_withdrawn_amount: u64) -> Result<()> {
+        if latest_withdrawn_amount == 0 {
+            return Ok(());
+        }
+
+        self.set_total_withdrawn_amount(
+            self.total_withdrawn_amount()
+                .checked_add(latest_withdrawn_amount)
+                .ok_or(error!(StreamError::WithdrawAmountOutOfBounds))?,
+        );
+        self.set_last_withdrawn_at(at);
+        self.set_last_withdrawn_amount(latest_withdrawn_amount);
+        Ok(())
+    }
+
+    // --- Utility functions --- END ---
+
+    // --- Instruction functions --- BEGIN ---
+
+    fn cancel(&mut self, key: Pubkey, signer: Pubkey, recipient: Pubkey, at: u64) -> Result<CancelTransferParams> {
+        require!(
+            !self.has_flag(ZeroCopyStream::IS_CANCELLED),
+            StreamError::StreamAlreadyCancelled
+        );
+        require!(recipient == self.recipient(), StreamError::InvalidRecipient);
+
+        let is_notice_over = self.is_notice_over(at);
         self.mark_cancelled(at, signer);
 
-        let total_topup_amount = self.total_topup_amount;
+        let total_topup_amount = self.total_topup_amount();
         let amount_owed = self.get_amount_owed(at)?;
         if total_topup_amount < amount_owed {
             // The stream is insolvent. Anyone can cancel.
-            let transfer_amount_recipient = if total_topup_amount > self.total_withdrawn_amount {
-                total_topup_amount - self.total_withdrawn_amount
+            let transfer_amount_recipient = if total_topup_amount > self.total_withdrawn_amount() {
+                total_topup_amount - self.total_withdrawn_amount()
             } else {
                 0
             };
             self.add_withdrawn_amount(at, transfer_amount_recipient)?;
 
-            if self.is_prepaid {
+            if self.has_flag(ZeroCopyStream::IS_PREPAID) {
                 msg!("Prepaid stream [{}] is insolvent. THIS SHOULD NEVER HAPPEN!!!", key);
                 Ok(CancelTransferParams {
                     transfer_amount_sender: 0,
@@ -905,8 +1786,8 @@ impl Stream {
             } else {
                 // The deposit is given as reward to the signer. The remaining topup amount can be withdrawn by the
                 // recipient.
-                let transfer_amount_signer = self.deposit_needed;
-                self.deposit_needed = 0;
+                let transfer_amount_signer = self.deposit_needed();
+                self.set_deposit_needed(0);
                 Ok(CancelTransferParams {
                     transfer_amount_sender: 0,
                     transfer_amount_signer,
@@ -914,29 +1795,37 @@ impl Stream {
                 })
             }
         } else {
-            // The stream is still solvent. Only the sender and recipient can cancel.
-            let signer_key = signer.key();
-            require!(
-                signer_key == self.sender || signer_key == self.recipient,
-                StreamError::UserUnauthorizedToCancel,
-            );
-            require!(
-                signer_key != self.sender || (self.sender_can_cancel && self.sender_can_cancel_at <= at),
-                StreamError::SenderCannotCancel,
-            );
+            // The stream is still solvent. Only the sender (or the manager) and recipient can cancel, unless a
+            // requested cancellation has taken effect, in which case anyone can settle it.
+            if !is_notice_over {
+                let is_sender = self.acts_as_sender(signer, ZeroCopyStream::MANAGER_CAN_CANCEL);
+                require!(
+                    is_sender || signer == self.recipient(),
+                    StreamError::UserUnauthorizedToCancel,
+                );
+                require!(
+                    !is_sender
+                        || (self.has_flag(ZeroCopyStream::SENDER_CAN_CANCEL) && self.sender_can_cancel_at() <= at),
+                    StreamError::SenderCannotCancel,
+                );
+                require!(
+                    !is_sender || self.notice_period() == 0,
+                    StreamError::NoticePeriodNotOver
+                );
+            }
 
             // Return anything the sender paid - topup or deposit that is not owed to the recipient. The stream has been
             // cancelled and stopped, so the deposit is no longer needed.
             let transfer_amount_sender = total_topup_amount
-                .checked_add(self.deposit_needed)
+                .checked_add(self.deposit_needed())
                 .ok_or(error!(StreamError::CancellationRefundOutOfBounds))?
                 - amount_owed;
 
-            self.total_topup_amount = amount_owed;
-            self.deposit_needed = 0;
+            self.set_total_topup_amount(amount_owed);
+            self.set_deposit_needed(0);
 
-            let transfer_amount_recipient = if amount_owed > self.total_withdrawn_amount {
-                amount_owed - self.total_withdrawn_amount
+            let transfer_amount_recipient = if amount_owed > self.total_withdrawn_amount() {
+                amount_owed - self.total_withdrawn_amount()
             } else {
                 0
             };
@@ -950,22 +1839,71 @@ impl Stream {
         }
     }
 
-    pub(crate) fn withdraw_excess_topup_non_prepaid_ended(&mut self) -> Result<u64> {
-        require!(!self.is_cancelled, StreamError::StreamAlreadyCancelled);
+    fn request_cancel(&mut self, signer: Pubkey, at: u64) -> Result<()> {
+        require!(
+            !self.has_flag(ZeroCopyStream::IS_CANCELLED),
+            StreamError::StreamAlreadyCancelled
+        );
+        require!(
+            self.cancellation_scheduled_at() == 0,
+            StreamError::CancellationAlreadyRequested
+        );
+
+        require!(!self.has_stopped(at), StreamError::StreamHasStopped);
+
+        let is_sender = self.acts_as_sender(signer, ZeroCopyStream::MANAGER_CAN_CANCEL);
+        require!(
+            is_sender || signer == self.recipient(),
+            StreamError::UserUnauthorizedToCancel,
+        );
+        require!(
+            !is_sender || (self.has_flag(ZeroCopyStream::SENDER_CAN_CANCEL) && self.sender_can_cancel_at() <= at),
+            StreamError::SenderCannotCancel,
+        );
+
+        self.set_cancellation_scheduled_at(
+            at.checked_add(self.notice_period())
+                .ok_or(error!(StreamError::CancellationScheduledAtOutOfBounds))?,
+        );
+        self.set_flag(ZeroCopyStream::IS_CANCELLATION_REQUESTED_BY_SENDER, is_sender);
+
+        // The sender cannot cut the payments during the notice period, so a stream paused by the sender is resumed.
+        if self.has_flag(ZeroCopyStream::IS_PAUSED) && self.has_flag(ZeroCopyStream::IS_PAUSED_BY_SENDER) {
+            self.set_flag(ZeroCopyStream::IS_PAUSED, false);
+            self.set_flag(ZeroCopyStream::IS_PAUSED_BY_SENDER, false);
+            if at > self.starts_at() {
+                self.set_last_resumed_at(at);
+            }
+        }
+
+        // The recipient is guaranteed to be paid through the notice period, so the stream needs to be funded till the
+        // cancellation takes effect. Prepaid streams are always funded till they end.
+        let amount_owed = self.get_amount_owed(self.cancellation_scheduled_at())?;
+        require!(
+            self.total_topup_amount() >= amount_owed,
+            StreamError::NoticePeriodNotFunded
+        );
+        Ok(())
+    }
+
+    fn withdraw_excess_topup_non_prepaid_ended(&mut self, at: u64) -> Result<u64> {
+        require!(
+            !self.has_flag(ZeroCopyStream::IS_CANCELLED),
+            StreamError::StreamAlreadyCancelled
+        );
 
-        let at = get_current_timestamp()?;
-        require!(self.ends_at > 0 && self.ends_at < at, StreamError::StreamNotEnded);
+        require!(self.has_ended(at), StreamError::StreamNotEnded);
 
-        let total_topup_amount = self.total_topup_amount;
+        let total_topup_amount = self.total_topup_amount();
         let amount_owed = self.get_amount_owed(at)?;
         Ok(if total_topup_amount < amount_owed {
             // The stream is insolvent. Nothing to do.
             0
         } else {
-            let deposit_needed = self.deposit_needed;
+            let deposit_needed = self.deposit_needed();
 
-            self.total_topup_amount = amount_owed;
-            self.deposit_needed = 0;
+            self.set_total_topup_amount(amount_owed);
+            self.set_deposit_needed(0);
 
             total_topup_amount
                 .checked_add(deposit_needed)
@@ -974,12 +1912,11 @@ impl Stream {
         })
     }
 
-    pub(crate) fn topup_non_prepaid(&mut self, topup_amount: u64) -> Result<()> {
+    fn topup_non_prepaid(&mut self, topup_amount: u64, at: u64) -> Result<()> {
         require!(topup_amount > 0, StreamError::ZeroAmount);
-        require!(!self.is_prepaid, StreamError::StreamIsPrepaid);
+        require!(!self.has_flag(ZeroCopyStream::IS_PREPAID), StreamError::StreamIsPrepaid);
         require!(self.has_flow_payments(), StreamError::StreamHasNoFlowPayments);
 
-        let at = get_current_timestamp()?;
         require!(!self.has_stopped(at), StreamError::StreamHasStopped);
 
         let (no_limit, max_acceptable_topup) = self.get_max_acceptable_topup_amount(at)?;
@@ -987,135 +1924,558 @@ impl Stream {
             require!(!self.has_stopped(at), StreamError::TopupAmountMoreThanMaxAcceptable);
         }
 
-        self.add_topup_amount(get_current_timestamp()?, topup_amount)
+        self.add_topup_amount(at, topup_amount)
+    }
+
+    fn change_sender_non_prepaid(&mut self, sender: Pubkey, new_sender: Pubkey, at: u64) -> Result<()> {
+        require!(!self.has_flag(ZeroCopyStream::IS_PREPAID), StreamError::StreamIsPrepaid);
+        require!(
+            self.acts_as_sender(sender, ZeroCopyStream::MANAGER_CAN_CHANGE_SENDER),
+            StreamError::InvalidSender
+        );
+        require!(new_sender != Pubkey::default(), StreamError::InvalidNewSender);
+        require!(new_sender != self.sender(), StreamError::SameSenders);
+        // The manager cannot take over the stream by making itself the sender. Only the sender can hand it over.
+        require!(
+            sender == self.sender() || new_sender != self.manager(),
+            StreamError::InvalidNewSender
+        );
+
+        require!(
+            self.has_flag(ZeroCopyStream::SENDER_CAN_CHANGE_SENDER) && self.sender_can_change_sender_at() <= at,
+            StreamError::SenderCannotChangeSender
+        );
+        require!(!self.has_stopped(at), StreamError::StreamHasStopped);
+
+        // The manager was delegated by the previous sender, so the new sender starts without one.
+        self.set_sender(new_sender);
+        self.set_manager_key(Pubkey::default());
+        self.set_manager_permissions(ManagerPermissions::default());
+        Ok(())
     }
 
-    pub(crate) fn change_sender_non_prepaid(&mut self, sender: &Signer, new_sender: Pubkey) -> Result<()> {
-        require!(!self.is_prepaid, StreamError::StreamIsPrepaid);
-        require!(sender.key() == self.sender, StreamError::InvalidSender);
-        require!(new_sender != Pubkey::default(), StreamError::InvalidNewSender);
-        require!(new_sender != self.sender, StreamError::SameSenders);
-
-        let at = get_current_timestamp()?;
+    fn set_manager(
+        &mut self,
+        sender: Pubkey,
+        manager: Pubkey,
+        manager_permissions: ManagerPermissions,
+        at: u64,
+    ) -> Result<()> {
+        require!(sender == self.sender(), StreamError::InvalidSender);
         require!(
-            self.sender_can_change_sender && self.sender_can_change_sender_at <= at,
-            StreamError::SenderCannotChangeSender
+            manager != self.sender() && manager != self.recipient(),
+            StreamError::InvalidManager
         );
+
         require!(!self.has_stopped(at), StreamError::StreamHasStopped);
 
-        self.sender = new_sender;
+        self.set_manager_key(manager);
+        self.set_manager_permissions(if manager == Pubkey::default() {
+            ManagerPermissions::default()
+        } else {
+            manager_permissions
+        });
         Ok(())
     }
 
-    pub(crate) fn withdraw_and_change_recipient(
+    fn withdraw_and_change_recipient(
         &mut self,
-        signer: &Signer,
+        signer: Pubkey,
         recipient: Pubkey,
         new_recipient: Pubkey,
+        at: u64,
     ) -> Result<u64> {
-        require!(recipient == self.recipient, StreamError::InvalidRecipient);
-        let at = get_current_timestamp()?;
-        require!(self.ends_at > 0 && self.ends_at < at, StreamError::StreamNotEnded);
+        require!(recipient == self.recipient(), StreamError::InvalidRecipient);
+        require!(self.has_ended(at), StreamError::StreamNotEnded);
         require!(
-            signer.key() == self.recipient
-                || (self.anyone_can_withdraw_for_recipient && self.anyone_can_withdraw_for_recipient_at <= at),
+            signer == self.recipient()
+                || signer == self.payee()
+                || (self.has_flag(ZeroCopyStream::ANYONE_CAN_WITHDRAW_FOR_RECIPIENT)
+                    && self.anyone_can_withdraw_for_recipient_at() <= at),
             StreamError::UserUnauthorizedToWithdraw,
         );
 
-        let total_topup_amount = self.total_topup_amount;
+        let total_topup_amount = self.total_topup_amount();
         let mut amount_owed = self.get_amount_owed(at)?;
         if amount_owed > total_topup_amount {
             // The stream is insolvent. Cancel the stream if not already cancelled. Recipient is owed the whole topup
             // amount and if the stream is not cancelled yet, also the deposit amount.
-            amount_owed = if self.is_cancelled {
+            amount_owed = if self.has_flag(ZeroCopyStream::IS_CANCELLED) {
                 total_topup_amount
             } else {
                 self.mark_cancelled(at, signer);
                 total_topup_amount
-                    .checked_add(self.deposit_needed)
+                    .checked_add(self.deposit_needed())
                     .ok_or(error!(StreamError::WithdrawAmountOutOfBounds))?
             }
         }
 
         require!(
-            amount_owed >= self.total_withdrawn_amount,
+            amount_owed >= self.total_withdrawn_amount(),
             StreamError::WithdrawnAmountGreaterThanAmountOwed,
         );
-        let amount_available_to_withdraw = amount_owed - self.total_withdrawn_amount;
+        let amount_available_to_withdraw = amount_owed - self.total_withdrawn_amount();
         self.add_withdrawn_amount(at, amount_available_to_withdraw)?;
-        if !self.is_cancelled && new_recipient != Pubkey::default() {
+        if !self.has_flag(ZeroCopyStream::IS_CANCELLED) && new_recipient != Pubkey::default() {
             // Only the recipient can change the recipient.
-            require!(signer.key() == self.recipient, StreamError::UserUnauthorizedToWithdraw);
-            require!(new_recipient != self.recipient, StreamError::SameRecipients);
-            self.recipient = new_recipient;
+            require!(signer == self.recipient(), StreamError::UserUnauthorizedToWithdraw);
+            require!(new_recipient != self.recipient(), StreamError::SameRecipients);
+            require!(new_recipient != self.manager(), StreamError::InvalidRecipient);
+            self.set_recipient(new_recipient);
         }
 
         Ok(amount_available_to_withdraw)
     }
 
-    pub(crate) fn pause_non_prepaid(&mut self, signer: &Signer) -> Result<()> {
-        require!(!self.is_prepaid, StreamError::StreamIsPrepaid);
-        require!(!self.is_paused, StreamError::StreamIsPaused);
+    /// Unstake an activity stream before it ends. The stream is cancelled and everything left in the escrow is returned
+    /// to the staker, who needs to be the signer. Returns the unstaked amount, from which the early unstake penalty of
+    /// the activity is deducted.
+    fn unstake_early(&mut self, signer: Pubkey, at: u64) -> Result<u64> {
+        require!(self.has_flag(ZeroCopyStream::IS_STAKE), StreamError::StreamIsNotStake);
+        require!(
+            !self.has_flag(ZeroCopyStream::IS_CANCELLED),
+            StreamError::StreamAlreadyCancelled
+        );
+        require!(signer == self.staker(), StreamError::UserUnauthorizedToWithdraw);
+        require!(at < self.ends_at(), StreamError::StakeNotLocked);
+
+        self.mark_cancelled(at, signer);
+        let amount = self.total_topup_amount().saturating_sub(self.total_withdrawn_amount());
+        self.set_total_topup_amount(self.total_withdrawn_amount());
+        Ok(amount)
+    }
+
+    fn pause_non_prepaid(&mut self, signer: Pubkey, at: u64) -> Result<()> {
+        require!(!self.has_flag(ZeroCopyStream::IS_PREPAID), StreamError::StreamIsPrepaid);
+        require!(!self.has_flag(ZeroCopyStream::IS_PAUSED), StreamError::StreamIsPaused);
         require!(self.has_flow_payments(), StreamError::StreamHasNoFlowPayments);
 
-        let signer_key = signer.key();
-        let is_sender = signer_key == self.sender;
-        let is_recipient = signer_key == self.recipient;
+        let is_sender = self.acts_as_sender(signer, ZeroCopyStream::MANAGER_CAN_PAUSE);
+        let is_recipient = signer == self.recipient();
         require!(is_sender || is_recipient, StreamError::UserUnauthorizedToPause);
 
-        let at = get_current_timestamp()?;
         require!(
-            is_recipient || (self.sender_can_pause && self.sender_can_pause_at <= at),
+            is_recipient || (self.has_flag(ZeroCopyStream::SENDER_CAN_PAUSE) && self.sender_can_pause_at() <= at),
+            StreamError::SenderCannotPause
+        );
+        // The sender cannot cut the payments during the notice period by pausing the stream.
+        require!(
+            is_recipient || self.cancellation_scheduled_at() == 0,
             StreamError::SenderCannotPause
         );
 
         require!(!self.has_stopped(at), StreamError::StreamHasStopped);
 
         // Update accumulated_active_time if there has been any flow till `at`.
-        if at > self.starts_at {
-            self.accumulated_active_time = self.unsafe_get_active_time_after_start(at)?;
+        if at > self.starts_at() {
+            let accumulated_active_time = self
+                .terms()
+                .unsafe_get_active_time_after_start(at)
+                .map_err(math_error)?;
+            self.set_accumulated_active_time(accumulated_active_time);
         }
 
-        self.is_paused = true;
-        self.is_paused_by_sender = is_sender;
+        self.set_flag(ZeroCopyStream::IS_PAUSED, true);
+        self.set_flag(ZeroCopyStream::IS_PAUSED_BY_SENDER, is_sender);
 
         Ok(())
     }
 
-    pub(crate) fn resume_non_prepaid(&mut self, signer: &Signer) -> Result<()> {
-        require!(!self.is_prepaid, StreamError::StreamIsPrepaid);
-        require!(self.is_paused, StreamError::StreamIsNotPaused);
+    fn resume_non_prepaid(&mut self, signer: Pubkey, at: u64) -> Result<()> {
+        require!(!self.has_flag(ZeroCopyStream::IS_PREPAID), StreamError::StreamIsPrepaid);
+        require!(self.has_flag(ZeroCopyStream::IS_PAUSED), StreamError::StreamIsNotPaused);
 
-        let signer_key = signer.key();
-        let is_sender = signer_key == self.sender;
-        let is_recipient = signer_key == self.recipient;
+        let is_sender = self.acts_as_sender(signer, ZeroCopyStream::MANAGER_CAN_RESUME);
+        let is_recipient = signer == self.recipient();
         require!(is_sender || is_recipient, StreamError::UserUnauthorizedToResume);
 
-        let at = get_current_timestamp()?;
         require!(
             is_sender
-                || !self.is_paused_by_sender
-                || (self.recipient_can_resume_pause_by_sender && self.recipient_can_resume_pause_by_sender_at <= at),
+                || !self.has_flag(ZeroCopyStream::IS_PAUSED_BY_SENDER)
+                || (self.has_flag(ZeroCopyStream::RECIPIENT_CAN_RESUME_PAUSE_BY_SENDER)
+                    && self.recipient_can_resume_pause_by_sender_at() <= at),
             StreamError::RecipientCannotResumePauseBySender
         );
 
         require!(!self.has_stopped(at), StreamError::StreamHasStopped);
 
-        self.is_paused = false;
-        self.is_paused_by_sender = false;
+        self.set_flag(ZeroCopyStream::IS_PAUSED, false);
+        self.set_flag(ZeroCopyStream::IS_PAUSED_BY_SENDER, false);
 
         // Update last_resumed_at if there has been any flow till `at`.
-        if at > self.starts_at {
-            self.last_resumed_at = at;
+        if at > self.starts_at() {
+            self.set_last_resumed_at(at);
         }
 
         Ok(())
     }
 
+    fn close(&self, signer: Pubkey, escrow_amount: u64, at: u64) -> Result<()> {
+        require!(
+            signer == self.sender() || signer == self.recipient(),
+            StreamError::UserUnauthorizedToClose
+        );
+        require!(self.has_stopped(at), StreamError::StreamHasNotStopped);
+        require!(escrow_amount == 0, StreamError::EscrowNotEmpty);
+        Ok(())
+    }
+
     // --- Instruction functions --- END ---
 }
 
+impl<T: StreamLayout> StreamInstructions for T {}
+
+/// Seeds of a stream PDA after [`STREAM_ACCOUNT_SEED`](crate::STREAM_ACCOUNT_SEED), with which the stream signs for its
+/// escrow. Both stream layouts store them, see [`Stream::seeds`] and [`ZeroCopyStream::seeds`].
+pub(crate) struct StreamSeeds {
+    pub activity: Pubkey,
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    /// Seed of the name. See [`get_name_seed`].
+    pub name: Vec<u8>,
+    pub bump: u8,
+}
+
+/// Stake of a wallet in an activity. Each (activity, wallet) pair has a position PDA tracking the amount currently
+/// staked by the wallet across all its stakes in the activity, which is used to enforce
+/// [`Activity::max_stake_per_wallet`], the rewards accrued to it and its stake-seconds.
+///
+/// Positions are created on first use. Stakes created before positions existed are not included.
+#[account]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StakePosition {
+    /// Activity address.
+    pub activity: Pubkey,
+    /// Staker wallet address.
+    pub wallet: Pubkey,
+    /// The PDA bump.
+    pub bump: u8,
+    /// Amount currently staked by the wallet in the activity.
+    pub staked_amount: u64,
+    /// Sum of the weighted stakes of the wallet in the activity. Rewards and stake-seconds are based on it.
+    pub weighted_stake: u64,
+
+    /// Value of [`Activity::reward_per_token`] when the rewards of the position were last updated.
+    pub reward_per_token_paid: u128,
+    /// Rewards accrued to the position and not claimed yet.
+    pub pending_rewards: u64,
+    /// Total amount of rewards claimed by the wallet.
+    pub claimed_rewards: u64,
+    /// Total amount of rewards forfeited by the early unstakes of the wallet.
+    pub forfeited_rewards: u64,
+
+    /// Sum of the weighted stake multiplied by the seconds it was staked for, up to `stake_seconds_updated_at`. See
+    /// [`Activity::stake_seconds`].
+    pub stake_seconds: u128,
+    /// Time up to which `stake_seconds` is accumulated.
+    pub stake_seconds_updated_at: u64,
+}
+
+impl StakePosition {
+    /// Total size of a StakePosition account.
+    pub const LENGTH: usize = ANCHOR_DISCRIMINATOR_LENGTH
+        + 2 * PUBLIC_KEY_LENGTH // activity, wallet - 72
+        + 1 * U8_LENGTH         // bump - 73
+        + 1 * U64_LENGTH        // staked_amount - 81
+        + 1 * U128_LENGTH       // reward_per_token_paid - 97
+        + 2 * U64_LENGTH        // pending_rewards, claimed_rewards - 113
+        + 1 * U128_LENGTH       // stake_seconds - 129
+        + 1 * U64_LENGTH        // stake_seconds_updated_at - 137
+        + 1 * U64_LENGTH        // weighted_stake - 145
+        + 1 * U64_LENGTH        // forfeited_rewards - 153
+    ;
+
+    /// Accrue the rewards of the weighted stake up to `reward_per_token`. This needs to be called before the staked
+    /// amount changes.
+    pub(crate) fn update_rewards(&mut self, reward_per_token: u128) -> Result<()> {
+        let pending_rewards = u128::from(self.weighted_stake)
+            .checked_mul(reward_per_token.saturating_sub(self.reward_per_token_paid))
+            .map(|earned| earned / REWARD_PER_TOKEN_PRECISION)
+            .and_then(|earned| u64::try_from(earned).ok())
+            .and_then(|earned| self.pending_rewards.checked_add(earned))
+            .ok_or(StreamError::RewardAmountOutOfBounds)?;
+        self.pending_rewards = pending_rewards;
+        self.reward_per_token_paid = reward_per_token;
+        Ok(())
+    }
+
+    /// Accumulate the stake-seconds of the weighted stake up to time `at`. This needs to be called before the staked
+    /// amount changes.
+    pub(crate) fn update_stake_seconds(&mut self, at: u64) {
+        if at > self.stake_seconds_updated_at {
+            self.stake_seconds = self.stake_seconds_at(at);
+            self.stake_seconds_updated_at = at;
+        }
+    }
+
+    /// Get the stake-seconds up to time `at`, assuming the staked amount does not change until then.
+    pub fn stake_seconds_at(&self, at: u64) -> u128 {
+        let seconds = at.saturating_sub(self.stake_seconds_updated_at);
+        self.stake_seconds
+            .saturating_add(u128::from(self.weighted_stake) * u128::from(seconds))
+    }
+}
+
+/// Index of the streams of a wallet, so that clients can list them without scanning all program accounts. Each wallet
+/// has a registry PDA counting the streams appended to it, which are listed in fixed-size [`StreamRegistryPage`] PDAs.
+///
+/// A stream is appended to the registries of its sender and its recipient when it is created, and to the registry of
+/// the new sender or recipient when they change. Registries are append-only: a listed stream may since have been
+/// closed or moved to another wallet, and may be listed more than once, so clients need to check the stream accounts.
+/// Appending a stream never reads the previous pages, so it costs the same however many streams a wallet has.
+///
+/// Registries are created on first use. Streams created before registries existed or with the legacy `create*`
+/// instructions are not listed.
+#[account]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StreamRegistry {
+    /// Wallet address.
+    pub wallet: Pubkey,
+    /// The PDA bump.
+    pub bump: u8,
+    /// Number of streams appended to the registry. Stream `i` is listed in page `i / StreamRegistryPage::CAPACITY`.
+    pub stream_count: u64,
+}
+
+impl StreamRegistry {
+    /// Total size of a StreamRegistry account.
+    pub const LENGTH: usize = ANCHOR_DISCRIMINATOR_LENGTH
+        + 1 * PUBLIC_KEY_LENGTH // wallet - 40
+        + 1 * U8_LENGTH         // bump - 41
+        + 1 * U64_LENGTH        // stream_count - 49
+    ;
+
+    /// Get the index of the page the next stream is appended to. Its PDA needs to be passed to the instructions adding
+    /// streams to the registry.
+    pub fn next_page(&self) -> u64 {
+        self.stream_count / StreamRegistryPage::CAPACITY as u64
+    }
+
+    /// Get the number of pages listing streams.
+    pub fn page_count(&self) -> u64 {
+        match self.stream_count {
+            0 => 0,
+            stream_count => (stream_count - 1) / StreamRegistryPage::CAPACITY as u64 + 1,
+        }
+    }
+
+    /// Append a stream to `page`, which needs to be the page returned by [`next_page`](Self::next_page).
+    pub(crate) fn add(&mut self, page: &mut StreamRegistryPage, stream: Pubkey) -> Result<()> {
+        require!(
+            page.index == self.next_page() && page.streams.len() < StreamRegistryPage::CAPACITY,
+            StreamError::InvalidRegistryPage,
+        );
+        page.streams.push(stream);
+        self.stream_count = self
+            .stream_count
+            .checked_add(1)
+            .ok_or(StreamError::InvalidRegistryPage)?;
+        Ok(())
+    }
+}
+
+/// Page of a [`StreamRegistry`], listing up to [`CAPACITY`](Self::CAPACITY) streams in the order they were appended.
+#[account]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StreamRegistryPage {
+    /// Wallet address.
+    pub wallet: Pubkey,
+    /// Index of the page in the registry.
+    pub index: u64,
+    /// The PDA bump.
+    pub bump: u8,
+    /// Stream addresses.
+    pub streams: Vec<Pubkey>,
+}
+
+impl StreamRegistryPage {
+    /// Maximum number of streams listed in a page.
+    pub const CAPACITY: usize = 64;
+
+    /// Total size of a StreamRegistryPage account. The space for all streams is allocated when the page is created.
+    pub const LENGTH: usize = ANCHOR_DISCRIMINATOR_LENGTH
+        + 1 * PUBLIC_KEY_LENGTH                    // wallet - 40
+        + 1 * U64_LENGTH                           // index - 48
+        + 1 * U8_LENGTH                            // bump - 49
+        + VEC_LENGTH_PREFIX                        // streams length - 53
+        + Self::CAPACITY * PUBLIC_KEY_LENGTH       // streams - 2101
+    ;
+}
+
+/// Parameters for creating a new stream.
+///
+/// For more information on the fields, see fields of the [`Stream`] struct.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CreateStreamParams {
+    /// Seed of the stream PDA.
+    pub seed: u64,
+    /// Name of the stream.
+    pub name: String,
+    /// Recipient address.
+    pub recipient: Pubkey,
+    /// Start time of the stream. Ignored for activity streams which start on creation.
+    pub starts_at: u64,
+    /// End time of the stream. Ignored for activity streams which end after the activity duration.
+    pub ends_at: u64,
+    /// Amount available to the recipient once stream starts.
+    pub initial_amount: u64,
+    /// Flow interval is the interval in which flow payments are released.
+    pub flow_interval: u64,
+    /// Flow rate is the number of tokens to stream per interval.
+    pub flow_rate: u64,
+    /// Permissions of the stream parties.
+    pub permissions: StreamPermissions,
+    /// Notice period (in seconds) for cancellation.
+    pub notice_period: u64,
+    /// How the flow payments accrue.
+    pub accrual_mode: AccrualMode,
+}
+
+/// How the flow payments of a stream accrue.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccrualMode {
+    /// Flow payments accrue every second, pro rata of the flow rate.
+    Continuous,
+    /// The flow rate is released at once at the end of every whole flow interval, e.g. daily salary drops. Time
+    /// spent in an incomplete flow interval releases nothing.
+    Discrete,
+}
+
+// Not derived since `#[default]` on enum variants is not supported by the BPF toolchain.
+#[allow(clippy::derivable_impls)]
+impl Default for AccrualMode {
+    fn default() -> Self {
+        AccrualMode::Continuous
+    }
+}
+
+/// Permissions of the stream parties, set once on stream creation.
+///
+/// For more information on the fields, see fields of the [`Stream`] struct.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamPermissions {
+    /// True if a solvent stream can be cancelled by the sender.
+    pub sender_can_cancel: bool,
+    /// Time at which the sender is allowed to cancel a solvent stream.
+    pub sender_can_cancel_at: u64,
+    /// True if the sender can change the sender of the stream who will do the upcoming topups.
+    pub sender_can_change_sender: bool,
+    /// Time at which the sender is allowed to change the sender.
+    pub sender_can_change_sender_at: u64,
+    /// True if a stream can be paused by the sender.
+    pub sender_can_pause: bool,
+    /// Time at which the sender is allowed to pause a stream.
+    pub sender_can_pause_at: u64,
+    /// True if a stream can be resumed by the recipient if it was paused by the sender.
+    pub recipient_can_resume_pause_by_sender: bool,
+    /// Time at which the recipient is allowed to resume a stream which was paused by the sender.
+    pub recipient_can_resume_pause_by_sender_at: u64,
+    /// True if anyone can withdraw on behalf of the recipient. The amount will go in recipients' account.
+    pub anyone_can_withdraw_for_recipient: bool,
+    /// Time at which anyone can withdraw on behalf of the recipient.
+    pub anyone_can_withdraw_for_recipient_at: u64,
+}
+
+impl StreamPermissions {
+    /// Clamp the permission times to `at`, zeroing the times of the permissions which are not granted.
+    fn clamp(self, at: u64) -> Self {
+        let clamp_at = |can: bool, can_at: u64| if can { min(can_at, at) } else { 0 };
+        Self {
+            sender_can_cancel_at: clamp_at(self.sender_can_cancel, self.sender_can_cancel_at),
+            sender_can_change_sender_at: clamp_at(self.sender_can_change_sender, self.sender_can_change_sender_at),
+            sender_can_pause_at: clamp_at(self.sender_can_pause, self.sender_can_pause_at),
+            recipient_can_resume_pause_by_sender_at: clamp_at(
+                self.recipient_can_resume_pause_by_sender,
+                self.recipient_can_resume_pause_by_sender_at,
+            ),
+            anyone_can_withdraw_for_recipient_at: clamp_at(
+                self.anyone_can_withdraw_for_recipient,
+                self.anyone_can_withdraw_for_recipient_at,
+            ),
+            ..self
+        }
+    }
+}
+
+/// Rights the sender can delegate to the stream manager. The manager acts as the sender, so all restrictions on the
+/// sender (e.g. `sender_can_pause`) apply to the manager as well.
+///
+/// Topups are not listed here since anyone, including the manager, can topup a stream.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ManagerPermissions {
+    /// True if the manager can cancel the stream.
+    pub can_cancel: bool,
+    /// True if the manager can change the sender of the stream.
+    pub can_change_sender: bool,
+    /// True if the manager can pause the stream.
+    pub can_pause: bool,
+    /// True if the manager can resume the stream.
+    pub can_resume: bool,
+}
+
+impl ManagerPermissions {
+    const LENGTH: usize = 4 * BOOL_LENGTH;
+}
+
+/// Snapshot of the amounts of a stream at a point in time. See [`Stream::quote`].
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamQuote {
+    /// Time at which the quote was computed.
+    pub at: u64,
+    /// Total amount owed to the recipient.
+    pub amount_owed: u64,
+    /// Amount the recipient can withdraw. This is 0 until the stream has ended.
+    pub amount_available_to_withdraw: u64,
+    /// Amount refunded to the sender if the stream is cancelled.
+    pub amount_refundable: u64,
+    /// If true, the stream has ended and the recipient can withdraw.
+    pub is_withdrawable: bool,
+    /// If true, the total topup amount covers the amount owed.
+    pub is_solvent: bool,
+    /// Time at which the stream stops. If the stream has no end time and is not cancelled, this is 0.
+    pub stops_at: u64,
+    /// If true, the stream has stopped.
+    pub has_stopped: bool,
+    /// If true, there is no limit on the topup amount.
+    pub has_unlimited_topup: bool,
+    /// Maximum acceptable topup amount if the topup amount is limited.
+    pub max_acceptable_topup_amount: u64,
+}
+
+/// Lock duration allowed for the stakes of an activity and its reward multiplier. See
+/// [`Activity::lock_multipliers`].
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockMultiplier {
+    /// Lock duration in seconds.
+    pub duration: u64,
+    /// Weight of the stakes locked for `duration`, in basis points of the staked amount. See [`BASE_STAKE_WEIGHT`].
+    pub multiplier: u64,
+}
+
+impl LockMultiplier {
+    /// Size of a serialized lock multiplier.
+    pub const LENGTH: usize = 2 * U64_LENGTH;
+}
+
+/// Stake totals of an activity. See [`Activity::totals`].
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ActivityTotals {
+    /// Total amount ever staked in the activity.
+    pub total_staked: u64,
+    /// Total amount ever withdrawn or refunded from the stakes of the activity.
+    pub total_unstaked: u64,
+    /// Amount currently staked in the activity.
+    pub staked_amount: u64,
+    /// Sum of the weighted stakes currently in the activity.
+    pub weighted_stake: u64,
+    /// Number of wallets currently staking in the activity.
+    pub participant_count: u64,
+    /// Number of open streams of the activity.
+    pub stream_count: u64,
+}
+
 /// Record of funds to be transferred once a stream is cancelled.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct CancelTransferParams {
     /// Transfer fund amount to the stream sender.
     pub transfer_amount_sender: u64,
@@ -1124,3 +2484,929 @@ pub struct CancelTransferParams {
     /// Transfer fund amount to the stream recipient.
     pub transfer_amount_recipient: u64,
 }
+
+impl CancelTransferParams {
+    /// Total amount transferred out of the escrow.
+    pub fn total_amount(&self) -> u64 {
+        self.transfer_amount_sender + self.transfer_amount_signer + self.transfer_amount_recipient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream() -> Stream {
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        Stream {
+            is_prepaid: false,
+            mint: key(1),
+            sender: key(2),
+            recipient: key(3),
+            created_at: 1,
+            starts_at: 2,
+            ends_at: 3,
+            initial_amount: 4,
+            flow_interval: 5,
+            flow_rate: 6,
+            is_cancelled: true,
+            is_cancelled_before_start: false,
+            is_cancelled_by_sender: true,
+            cancelled_at: 7,
+            sender_can_cancel: true,
+            sender_can_cancel_at: 8,
+            sender_can_change_sender: false,
+            sender_can_change_sender_at: 9,
+            is_paused: true,
+            is_paused_by_sender: false,
+            sender_can_pause: true,
+            sender_can_pause_at: 10,
+            recipient_can_resume_pause_by_sender: false,
+            recipient_can_resume_pause_by_sender_at: 11,
+            anyone_can_withdraw_for_recipient: true,
+            anyone_can_withdraw_for_recipient_at: 12,
+            last_resumed_at: 13,
+            accumulated_active_time: 14,
+            total_withdrawn_amount: 15,
+            last_withdrawn_at: 16,
+            last_withdrawn_amount: 17,
+            total_topup_amount: 18,
+            last_topup_at: 19,
+            last_topup_amount: 20,
+            deposit_needed: 21,
+            stake_weight: BASE_STAKE_WEIGHT,
+            beneficiary: key(8),
+            version: STREAM_VERSION,
+            reserved: [22; 10],
+            seed: 23,
+            bump: 24,
+            activity: key(4),
+            manager: key(5),
+            manager_permissions: ManagerPermissions {
+                can_cancel: true,
+                can_change_sender: false,
+                can_pause: true,
+                can_resume: false,
+            },
+            notice_period: 25,
+            cancellation_scheduled_at: 26,
+            is_cancellation_requested_by_sender: true,
+            creator: key(6),
+            accrual_mode: AccrualMode::Discrete,
+            rent_payer: key(7),
+            is_stake: true,
+            name: "zero-copy stream".to_string(),
+        }
+    }
+
+    #[test]
+    fn zero_copy_stream_round_trips() {
+        let stream = stream();
+        assert_eq!(ZeroCopyStream::from_stream(&stream).to_stream(), stream);
+
+        let stream = Stream {
+            is_prepaid: true,
+            is_cancelled: false,
+            accrual_mode: AccrualMode::Continuous,
+            name: "n".repeat(MAX_STREAM_NAME_LENGTH),
+            ..self::stream()
+        };
+        assert_eq!(ZeroCopyStream::from_stream(&stream).to_stream(), stream);
+    }
+
+    #[test]
+    fn zero_copy_stream_has_no_padding() {
+        assert_eq!(ZeroCopyStream::LENGTH, 664);
+        assert_eq!(
+            ZeroCopyStream::LENGTH - ANCHOR_DISCRIMINATOR_LENGTH,
+            26 * U64_LENGTH + 10 * U64_LENGTH + 8 * PUBLIC_KEY_LENGTH + 4 + 2 * U8_LENGTH + MAX_STREAM_NAME_LENGTH + 6
+        );
+    }
+
+    #[test]
+    fn stream_version_is_in_the_original_reserved_space() {
+        let stream = Stream {
+            version: u64::MAX,
+            ..stream()
+        };
+        let data = stream.try_to_vec().unwrap();
+        assert_eq!(data.len() + ANCHOR_DISCRIMINATOR_LENGTH, Stream::space(&stream.name));
+
+        // The original layout reserved 16 u64s after deposit_needed, i.e. bytes 283 to 411 of the account, which are
+        // zeroes in the streams created with it.
+        let version_offset = 323;
+        assert!(version_offset >= 283 && version_offset + U64_LENGTH <= 411);
+        let data_offset = version_offset - ANCHOR_DISCRIMINATOR_LENGTH;
+        assert_eq!(data[data_offset..data_offset + U64_LENGTH], u64::MAX.to_le_bytes());
+    }
+
+    #[test]
+    fn stream_is_owned_by_its_beneficiary() {
+        let stream = stream();
+        assert_eq!(stream.staker(), stream.beneficiary);
+        assert_eq!(stream.payee(), stream.beneficiary);
+        assert_eq!(stream.refundee(), stream.beneficiary);
+        let zero_copy = ZeroCopyStream::from_stream(&stream);
+        assert_eq!(zero_copy.staker(), stream.beneficiary);
+        assert_eq!(zero_copy.payee(), stream.beneficiary);
+        assert_eq!(zero_copy.refundee(), stream.beneficiary);
+
+        let stream = Stream {
+            beneficiary: Pubkey::default(),
+            ..stream
+        };
+        assert_eq!(stream.staker(), stream.creator);
+        assert_eq!(stream.payee(), stream.recipient);
+        assert_eq!(stream.refundee(), stream.sender);
+    }
+
+    /// Non-prepaid stream started at 100, streaming 1 token per second with 1000 tokens topped up and a notice period
+    /// of 50 seconds.
+    fn running_stream() -> Stream {
+        Stream {
+            starts_at: 100,
+            ends_at: 0,
+            initial_amount: 0,
+            flow_interval: 1,
+            flow_rate: 1,
+            is_cancelled: false,
+            is_cancelled_by_sender: false,
+            cancelled_at: 0,
+            sender_can_cancel_at: 0,
+            is_paused: false,
+            last_resumed_at: 0,
+            accumulated_active_time: 0,
+            total_withdrawn_amount: 0,
+            total_topup_amount: 1000,
+            deposit_needed: 0,
+            beneficiary: Pubkey::default(),
+            notice_period: 50,
+            cancellation_scheduled_at: 0,
+            is_cancellation_requested_by_sender: false,
+            accrual_mode: AccrualMode::Continuous,
+            is_stake: false,
+            ..stream()
+        }
+    }
+
+    #[test]
+    fn requested_cancellation_takes_effect_after_the_notice_period() {
+        let mut stream = running_stream();
+        let (key, sender, recipient) = (Pubkey::default(), stream.sender, stream.recipient);
+        let other = Pubkey::new_from_array([9; 32]);
+
+        // Requesting the cancellation resumes a stream paused by the sender, so that the notice period is paid.
+        stream.pause_non_prepaid(sender, 110).unwrap();
+        assert_eq!(
+            stream.request_cancel(other, 120).unwrap_err(),
+            error!(StreamError::UserUnauthorizedToCancel)
+        );
+        stream.request_cancel(sender, 120).unwrap();
+        assert_eq!(stream.cancellation_scheduled_at, 170);
+        assert!(stream.is_cancellation_requested_by_sender);
+        assert!(!stream.is_paused);
+        assert_eq!(stream.last_resumed_at, 120);
+        assert_eq!(
+            stream.request_cancel(recipient, 130).unwrap_err(),
+            error!(StreamError::CancellationAlreadyRequested)
+        );
+        assert_eq!(
+            stream.pause_non_prepaid(sender, 130).unwrap_err(),
+            error!(StreamError::SenderCannotPause)
+        );
+
+        // Only the recipient can cancel during the notice period.
+        let mut cancelled = stream.clone();
+        assert_eq!(
+            cancelled.cancel(key, other, recipient, 160).unwrap_err(),
+            error!(StreamError::UserUnauthorizedToCancel)
+        );
+        let mut cancelled = stream.clone();
+        assert_eq!(
+            cancelled.cancel(key, sender, recipient, 160).unwrap_err(),
+            error!(StreamError::NoticePeriodNotOver)
+        );
+        let mut cancelled = stream.clone();
+        let params = cancelled.cancel(key, recipient, recipient, 160).unwrap();
+        assert_eq!(params.transfer_amount_recipient, 50);
+        assert!(!cancelled.is_cancelled_by_sender);
+
+        // Anyone can settle once the notice period is over. The cancellation takes effect at the scheduled time.
+        let params = stream.cancel(key, other, recipient, 180).unwrap();
+        assert!(stream.is_cancelled);
+        assert!(stream.is_cancelled_by_sender);
+        assert_eq!(stream.cancelled_at, 170);
+        assert_eq!(params.transfer_amount_recipient, 60);
+        assert_eq!(params.transfer_amount_sender, 940);
+        assert_eq!(params.transfer_amount_signer, 0);
+    }
+
+    #[test]
+    fn request_cancel_checks_the_cancellation_time() {
+        let mut stream = Stream {
+            notice_period: u64::MAX,
+            ..running_stream()
+        };
+        assert_eq!(
+            stream.request_cancel(stream.recipient, 120).unwrap_err(),
+            error!(StreamError::CancellationScheduledAtOutOfBounds)
+        );
+
+        // The notice period needs to be funded.
+        let mut stream = Stream {
+            total_topup_amount: 60,
+            ..running_stream()
+        };
+        assert_eq!(
+            stream.request_cancel(stream.recipient, 120).unwrap_err(),
+            error!(StreamError::NoticePeriodNotFunded)
+        );
+    }
+
+    #[test]
+    fn manager_acts_as_the_sender_with_its_permissions() {
+        let stream = Stream {
+            ends_at: 400,
+            notice_period: 0,
+            sender_can_change_sender: true,
+            sender_can_change_sender_at: 0,
+            sender_can_pause_at: 0,
+            ..running_stream()
+        };
+        let (key, sender, recipient, manager) = (Pubkey::default(), stream.sender, stream.recipient, stream.manager);
+        let new_sender = Pubkey::new_from_array([9; 32]);
+        let no_permissions = ManagerPermissions::default();
+        let all_permissions = ManagerPermissions {
+            can_cancel: true,
+            can_change_sender: true,
+            can_pause: true,
+            can_resume: true,
+        };
+
+        // Each permission only allows the manager to act as the sender in its own instruction.
+        for permissions in [
+            no_permissions,
+            ManagerPermissions {
+                can_cancel: true,
+                ..no_permissions
+            },
+            ManagerPermissions {
+                can_change_sender: true,
+                ..no_permissions
+            },
+            ManagerPermissions {
+                can_pause: true,
+                ..no_permissions
+            },
+            ManagerPermissions {
+                can_resume: true,
+                ..no_permissions
+            },
+        ] {
+            let mut stream = stream.clone();
+            stream.set_manager(sender, manager, permissions, 110).unwrap();
+            let cancel = stream.clone().cancel(key, manager, recipient, 120);
+            assert_eq!(cancel.is_ok(), permissions.can_cancel);
+            let change_sender = stream.clone().change_sender_non_prepaid(manager, new_sender, 120);
+            assert_eq!(change_sender.is_ok(), permissions.can_change_sender);
+            let pause = stream.clone().pause_non_prepaid(manager, 120);
+            assert_eq!(pause.is_ok(), permissions.can_pause);
+            let mut paused = stream.clone();
+            paused.pause_non_prepaid(sender, 115).unwrap();
+            assert_eq!(paused.resume_non_prepaid(manager, 120).is_ok(), permissions.can_resume);
+            // Only the sender can set the manager.
+            assert_eq!(
+                stream.set_manager(manager, manager, all_permissions, 120).unwrap_err(),
+                error!(StreamError::InvalidSender)
+            );
+        }
+
+        // The manager cannot be the recipient, nor the sender.
+        let mut stream = stream;
+        for manager in [recipient, sender] {
+            assert_eq!(
+                stream.set_manager(sender, manager, all_permissions, 110).unwrap_err(),
+                error!(StreamError::InvalidManager)
+            );
+        }
+        stream.set_manager(sender, manager, all_permissions, 110).unwrap();
+        assert_eq!(
+            stream
+                .clone()
+                .withdraw_and_change_recipient(recipient, recipient, manager, 450)
+                .unwrap_err(),
+            error!(StreamError::InvalidRecipient)
+        );
+
+        // Revoking the permissions or the manager takes effect immediately.
+        stream.set_manager(sender, manager, no_permissions, 120).unwrap();
+        assert_eq!(
+            stream.clone().pause_non_prepaid(manager, 130).unwrap_err(),
+            error!(StreamError::UserUnauthorizedToPause)
+        );
+        stream.set_manager(sender, manager, all_permissions, 130).unwrap();
+        stream.set_manager(sender, key, all_permissions, 140).unwrap();
+        assert_eq!(stream.manager, key);
+        assert_eq!(stream.manager_permissions, no_permissions);
+        assert_eq!(
+            stream.clone().cancel(key, manager, recipient, 150).unwrap_err(),
+            error!(StreamError::UserUnauthorizedToCancel)
+        );
+
+        // The manager cannot make itself the sender, but the sender can hand the stream over to the manager.
+        stream.set_manager(sender, manager, all_permissions, 150).unwrap();
+        assert_eq!(
+            stream
+                .clone()
+                .change_sender_non_prepaid(manager, manager, 160)
+                .unwrap_err(),
+            error!(StreamError::InvalidNewSender)
+        );
+        stream.clone().change_sender_non_prepaid(sender, manager, 160).unwrap();
+
+        // Changing the sender removes the manager delegated by the previous sender.
+        stream.change_sender_non_prepaid(manager, new_sender, 160).unwrap();
+        assert_eq!(stream.sender, new_sender);
+        assert_eq!(stream.manager, Pubkey::default());
+        assert_eq!(
+            stream.pause_non_prepaid(manager, 170).unwrap_err(),
+            error!(StreamError::UserUnauthorizedToPause)
+        );
+    }
+
+    #[test]
+    fn quote_matches_what_withdraw_and_cancel_transfer() {
+        let stream = Stream {
+            ends_at: 200,
+            total_topup_amount: 150,
+            deposit_needed: 10,
+            ..running_stream()
+        };
+        let (key, recipient) = (Pubkey::default(), stream.recipient);
+        let withdraw = |mut stream: Stream, at| stream.withdraw_and_change_recipient(recipient, recipient, key, at);
+
+        // Nothing can be withdrawn until the stream has ended.
+        let quote = stream.quote(150).unwrap();
+        assert_eq!(quote.amount_owed, 50);
+        assert!(quote.is_solvent);
+        assert!(!quote.is_withdrawable);
+        assert_eq!(quote.amount_available_to_withdraw, 0);
+        assert_eq!(quote.amount_refundable, 110);
+        assert_eq!(quote.stops_at, 200);
+        assert!(!quote.has_stopped);
+        assert!(withdraw(stream.clone(), 150).is_err());
+        let params = stream.clone().cancel(key, recipient, recipient, 150).unwrap();
+        assert_eq!(params.transfer_amount_sender, quote.amount_refundable);
+
+        // Solvent stream which has ended.
+        let quote = stream.quote(250).unwrap();
+        assert_eq!(quote.amount_owed, 100);
+        assert!(quote.is_solvent);
+        assert!(quote.is_withdrawable);
+        assert_eq!(quote.amount_available_to_withdraw, 100);
+        assert_eq!(quote.amount_refundable, 60);
+        assert!(quote.has_stopped);
+        assert_eq!(withdraw(stream.clone(), 250).unwrap(), 100);
+
+        // An insolvent stream also owes the deposit and refunds nothing.
+        let insolvent = Stream {
+            total_topup_amount: 30,
+            ..stream.clone()
+        };
+        let quote = insolvent.quote(250).unwrap();
+        assert!(!quote.is_solvent);
+        assert_eq!(quote.amount_available_to_withdraw, 40);
+        assert_eq!(quote.amount_refundable, 0);
+        assert_eq!(withdraw(insolvent.clone(), 250).unwrap(), 40);
+        assert_eq!(insolvent.quote(150).unwrap().amount_available_to_withdraw, 0);
+
+        // The amounts of a cancelled stream stop at the cancellation, which has paid out the recipient already.
+        let mut cancelled = stream;
+        cancelled.cancel(key, recipient, recipient, 150).unwrap();
+        let quote = cancelled.quote(250).unwrap();
+        assert_eq!(quote.amount_owed, 50);
+        assert!(quote.is_solvent);
+        assert!(quote.is_withdrawable);
+        assert_eq!(quote.amount_available_to_withdraw, 0);
+        assert_eq!(quote.amount_refundable, 0);
+        assert_eq!(quote.stops_at, 150);
+        assert!(quote.has_stopped);
+    }
+
+    #[test]
+    fn zero_copy_stream_matches_stream() {
+        // Run an instruction function on both layouts and check that they give the same result and stay identical,
+        // including when the instruction fails.
+        macro_rules! check {
+            ($stream:ident, $zero_copy:ident, $($call:tt)*) => {{
+                let result = $stream.$($call)*;
+                assert_eq!($zero_copy.$($call)*, result);
+                assert_eq!($zero_copy.to_stream(), $stream);
+                result
+            }};
+        }
+
+        let mut stream = Stream {
+            ends_at: 400,
+            deposit_needed: 10,
+            sender_can_change_sender: true,
+            sender_can_change_sender_at: 0,
+            sender_can_pause_at: 0,
+            ..running_stream()
+        };
+        let mut zero_copy = ZeroCopyStream::from_stream(&stream);
+        let key = Pubkey::default();
+        let (sender, recipient, manager) = (stream.sender, stream.recipient, stream.manager);
+        let (new_manager, new_sender) = (Pubkey::new_from_array([9; 32]), Pubkey::new_from_array([10; 32]));
+        let all_permissions = ManagerPermissions {
+            can_cancel: true,
+            can_change_sender: true,
+            can_pause: true,
+            can_resume: true,
+        };
+
+        check!(stream, zero_copy, topup_non_prepaid(0, 150)).unwrap_err();
+        check!(stream, zero_copy, topup_non_prepaid(100, 150)).unwrap();
+        check!(stream, zero_copy, pause_non_prepaid(manager, 150)).unwrap();
+        check!(stream, zero_copy, resume_non_prepaid(recipient, 160)).unwrap_err();
+        check!(stream, zero_copy, resume_non_prepaid(manager, 160)).unwrap_err();
+        check!(stream, zero_copy, resume_non_prepaid(sender, 170)).unwrap();
+        check!(
+            stream,
+            zero_copy,
+            set_manager(manager, new_manager, all_permissions, 180)
+        )
+        .unwrap_err();
+        check!(
+            stream,
+            zero_copy,
+            set_manager(sender, new_manager, all_permissions, 180)
+        )
+        .unwrap();
+        check!(
+            stream,
+            zero_copy,
+            change_sender_non_prepaid(new_manager, new_manager, 190)
+        )
+        .unwrap_err();
+        check!(
+            stream,
+            zero_copy,
+            change_sender_non_prepaid(new_manager, new_sender, 190)
+        )
+        .unwrap();
+        check!(stream, zero_copy, pause_non_prepaid(new_sender, 195)).unwrap();
+        check!(stream, zero_copy, request_cancel(recipient, 200)).unwrap();
+        check!(stream, zero_copy, pause_non_prepaid(new_sender, 210)).unwrap_err();
+        check!(
+            stream,
+            zero_copy,
+            withdraw_and_change_recipient(recipient, recipient, key, 220)
+        )
+        .unwrap_err();
+        check!(stream, zero_copy, cancel(key, key, recipient, 260)).unwrap();
+        check!(stream, zero_copy, withdraw_excess_topup_non_prepaid_ended(500)).unwrap_err();
+        check!(
+            stream,
+            zero_copy,
+            withdraw_and_change_recipient(key, recipient, key, 500)
+        )
+        .unwrap();
+
+        // An insolvent stream is cancelled by the withdrawal, and a solvent one can change its recipient.
+        let new_recipient = Pubkey::new_from_array([11; 32]);
+        for total_topup_amount in [100, 1000] {
+            let mut stream = Stream {
+                ends_at: 400,
+                deposit_needed: 10,
+                total_topup_amount,
+                ..running_stream()
+            };
+            let mut zero_copy = ZeroCopyStream::from_stream(&stream);
+            check!(
+                stream,
+                zero_copy,
+                withdraw_and_change_recipient(recipient, recipient, new_recipient, 450)
+            )
+            .unwrap();
+            check!(stream, zero_copy, withdraw_excess_topup_non_prepaid_ended(500)).ok();
+            check!(stream, zero_copy, cancel(key, key, stream.recipient, 500)).ok();
+        }
+
+        // Only the staker can unstake early, once.
+        let mut stream = Stream {
+            ends_at: 400,
+            is_stake: true,
+            ..running_stream()
+        };
+        let mut zero_copy = ZeroCopyStream::from_stream(&stream);
+        let staker = stream.creator;
+        check!(stream, zero_copy, unstake_early(recipient, 150)).unwrap_err();
+        check!(stream, zero_copy, unstake_early(staker, 150)).unwrap();
+        check!(stream, zero_copy, unstake_early(staker, 160)).unwrap_err();
+    }
+
+    #[test]
+    fn stream_registry_appends_streams_to_pages() {
+        let mut registry = StreamRegistry::default();
+        let mut first = StreamRegistryPage::default();
+        let mut second = StreamRegistryPage {
+            index: 1,
+            ..StreamRegistryPage::default()
+        };
+        assert_eq!((registry.next_page(), registry.page_count()), (0, 0));
+
+        for i in 0..StreamRegistryPage::CAPACITY {
+            assert!(registry.add(&mut second, Pubkey::new_from_array([1; 32])).is_err());
+            registry.add(&mut first, Pubkey::new_from_array([i as u8; 32])).unwrap();
+        }
+        assert_eq!((registry.next_page(), registry.page_count()), (1, 1));
+        assert!(registry.add(&mut first, Pubkey::new_from_array([1; 32])).is_err());
+        assert_eq!(
+            first.try_to_vec().unwrap().len() + ANCHOR_DISCRIMINATOR_LENGTH,
+            StreamRegistryPage::LENGTH
+        );
+
+        // Streams can be listed more than once, e.g. when the sender changes back.
+        registry.add(&mut second, Pubkey::new_from_array([0; 32])).unwrap();
+        assert_eq!(second.streams, vec![Pubkey::new_from_array([0; 32])]);
+        assert_eq!(
+            (registry.stream_count, registry.next_page(), registry.page_count()),
+            (65, 1, 2)
+        );
+        assert_eq!(
+            registry.try_to_vec().unwrap().len() + ANCHOR_DISCRIMINATOR_LENGTH,
+            StreamRegistry::LENGTH
+        );
+    }
+
+    #[test]
+    fn activity_tracks_stake_totals() {
+        let mut activity = Activity::default();
+        let mut first = StakePosition::default();
+        let mut second = StakePosition::default();
+        activity.stake(&mut first, 100, BASE_STAKE_WEIGHT, 0).unwrap();
+        activity.stake(&mut first, 50, BASE_STAKE_WEIGHT, 0).unwrap();
+        activity.stake(&mut second, 40, BASE_STAKE_WEIGHT, 0).unwrap();
+        activity.unstake(Some(&mut first), stream().stake(), 30, 0).unwrap();
+        let not_a_stake = Stream {
+            is_stake: false,
+            ..stream()
+        };
+        activity.unstake(Some(&mut first), not_a_stake.stake(), 20, 0).unwrap();
+
+        assert_eq!(first.staked_amount, 120);
+        assert_eq!(
+            activity.totals(),
+            ActivityTotals {
+                total_staked: 190,
+                total_unstaked: 30,
+                staked_amount: 160,
+                weighted_stake: 160,
+                participant_count: 2,
+                stream_count: 0,
+            }
+        );
+
+        activity.unstake(Some(&mut second), stream().stake(), 40, 0).unwrap();
+        assert_eq!(second.staked_amount, 0);
+        assert_eq!(activity.participant_count, 1);
+    }
+
+    #[test]
+    fn activity_enforces_stake_caps() {
+        let creator = Pubkey::new_from_array([1; 32]);
+        let mut activity = Activity {
+            is_active: true,
+            creator,
+            ..Activity::default()
+        };
+        let other = Pubkey::new_from_array([2; 32]);
+        assert!(activity.set_stake_caps(other, 100, 60).is_err());
+        activity.set_stake_caps(creator, 100, 60).unwrap();
+
+        let mut first = StakePosition::default();
+        let mut second = StakePosition::default();
+        activity.stake(&mut first, 60, BASE_STAKE_WEIGHT, 0).unwrap();
+        assert!(activity.stake(&mut first, 1, BASE_STAKE_WEIGHT, 0).is_err());
+        assert!(activity.stake(&mut second, 41, BASE_STAKE_WEIGHT, 0).is_err());
+        activity.stake(&mut second, 40, BASE_STAKE_WEIGHT, 0).unwrap();
+        assert!(activity.stake(&mut second, 1, BASE_STAKE_WEIGHT, 0).is_err());
+
+        // Unstaking frees up capacity.
+        activity.unstake(Some(&mut first), stream().stake(), 10, 0).unwrap();
+        activity.stake(&mut second, 10, BASE_STAKE_WEIGHT, 0).unwrap();
+        assert_eq!(activity.totals().staked_amount, 100);
+    }
+
+    #[test]
+    fn activity_accrues_rewards_per_token() {
+        let mut activity = Activity {
+            starts_at: 100,
+            ends_at: 200,
+            flow_rate: 10,
+            ..Activity::default()
+        };
+        let mut first = StakePosition::default();
+        let mut second = StakePosition::default();
+        activity.fund_rewards(600, 50).unwrap();
+        activity.stake(&mut first, 100, BASE_STAKE_WEIGHT, 100).unwrap();
+        // 100 rewards accrue to the first stake alone.
+        activity.stake(&mut second, 300, BASE_STAKE_WEIGHT, 110).unwrap();
+        // 200 rewards are shared 1:3 until the first stake is withdrawn.
+        activity.unstake(Some(&mut first), stream().stake(), 100, 130).unwrap();
+        assert_eq!(activity.claim_rewards(&mut first, 130).unwrap(), 150);
+        assert_eq!(first.claimed_rewards, 150);
+
+        // Emissions stop at the end of the activity and once the funded rewards are used up.
+        assert_eq!(activity.claim_rewards(&mut second, 250).unwrap(), 450);
+        assert_eq!(activity.claim_rewards(&mut first, 250).unwrap(), 0);
+        activity.fund_rewards(100, 250).unwrap();
+        assert_eq!(activity.claim_rewards(&mut second, 300).unwrap(), 0);
+        assert_eq!(activity.total_reward_funded, 700);
+        assert_eq!(activity.total_reward_accrued, 600);
+        assert_eq!(activity.total_reward_claimed, 600);
+    }
+
+    #[test]
+    fn position_is_closed_once_unstaked_and_claimed() {
+        let wallet = Pubkey::new_from_array([1; 32]);
+        let mut activity = Activity {
+            starts_at: 100,
+            ends_at: 200,
+            flow_rate: 10,
+            ..Activity::default()
+        };
+        let mut position = StakePosition {
+            wallet,
+            ..StakePosition::default()
+        };
+        activity.open_position().unwrap();
+        activity.fund_rewards(1000, 100).unwrap();
+        activity.stake(&mut position, 100, BASE_STAKE_WEIGHT, 100).unwrap();
+        assert!(activity.close_position(wallet, &mut position, 110).is_err());
+
+        // The rewards accrued to the position need to be claimed first.
+        let stake = stream().stake();
+        activity.unstake(Some(&mut position), stake, 100, 110).unwrap();
+        assert!(activity.close_position(wallet, &mut position, 110).is_err());
+        assert_eq!(activity.claim_rewards(&mut position, 110).unwrap(), 100);
+        assert!(activity.close_position(Pubkey::default(), &mut position, 110).is_err());
+        activity.close_position(wallet, &mut position, 120).unwrap();
+        assert_eq!(activity.position_count, 0);
+        assert!(activity.close_position(wallet, &mut position, 120).is_err());
+    }
+
+    #[test]
+    fn position_accumulates_stake_seconds_until_the_activity_ends() {
+        let mut activity = Activity {
+            starts_at: 100,
+            ends_at: 200,
+            ..Activity::default()
+        };
+        let mut position = StakePosition::default();
+        activity.stake(&mut position, 10, BASE_STAKE_WEIGHT, 100).unwrap();
+        activity.stake(&mut position, 20, BASE_STAKE_WEIGHT, 150).unwrap();
+        assert_eq!(position.stake_seconds, 500);
+        assert_eq!(activity.stake_seconds(&position, 160), 800);
+
+        let stake = stream().stake();
+        activity.unstake(Some(&mut position), stake, 10, 190).unwrap();
+        assert_eq!(position.stake_seconds, 1700);
+        // Stake-seconds are frozen at the end of the activity.
+        assert_eq!(activity.stake_seconds(&position, 300), 1900);
+        activity.unstake(Some(&mut position), stake, 20, 300).unwrap();
+        assert_eq!(position.stake_seconds, 1900);
+        assert_eq!(position.stake_seconds_updated_at, 200);
+    }
+
+    #[test]
+    fn activity_weights_stakes_by_lock_duration() {
+        let creator = Pubkey::new_from_array([1; 32]);
+        let mut activity = Activity {
+            is_active: true,
+            creator,
+            starts_at: 100,
+            ends_at: 200,
+            flow_rate: 10,
+            ..Activity::default()
+        };
+        assert_eq!(activity.stake_weight(7).unwrap(), BASE_STAKE_WEIGHT);
+
+        let lock = |duration, multiplier| LockMultiplier { duration, multiplier };
+        let other = Pubkey::new_from_array([2; 32]);
+        assert!(activity.set_lock_multipliers(other, vec![lock(10, 10_000)]).is_err());
+        assert!(activity.set_lock_multipliers(creator, vec![lock(0, 10_000)]).is_err());
+        assert!(activity.set_lock_multipliers(creator, vec![lock(10, 0)]).is_err());
+        assert!(activity
+            .set_lock_multipliers(creator, vec![lock(20, 10_000), lock(10, 20_000)])
+            .is_err());
+        activity
+            .set_lock_multipliers(creator, vec![lock(10, 10_000), lock(20, 30_000)])
+            .unwrap();
+        assert!(activity.stake_weight(15).is_err());
+        assert_eq!(activity.stake_weight(20).unwrap(), 30_000);
+
+        // The second stake is locked 3 times longer and earns 3 times more rewards per staked token.
+        let mut first = StakePosition::default();
+        let mut second = StakePosition::default();
+        activity.fund_rewards(1000, 100).unwrap();
+        activity.stake(&mut first, 100, 10_000, 100).unwrap();
+        activity.stake(&mut second, 100, 30_000, 100).unwrap();
+        assert_eq!(activity.totals().weighted_stake, 400);
+        assert_eq!(activity.claim_rewards(&mut first, 120).unwrap(), 50);
+        assert_eq!(activity.claim_rewards(&mut second, 120).unwrap(), 150);
+        assert_eq!(activity.stake_seconds(&second, 120), 6000);
+
+        let locked_stake = Stream {
+            stake_weight: 30_000,
+            ..stream()
+        };
+        activity
+            .unstake(Some(&mut second), locked_stake.stake(), 50, 120)
+            .unwrap();
+        assert_eq!(second.weighted_stake, 150);
+        assert_eq!(activity.total_weighted_stake, 250);
+    }
+
+    #[test]
+    fn activity_charges_early_unstake_penalties() {
+        let creator = Pubkey::new_from_array([1; 32]);
+        let treasury = Pubkey::new_from_array([2; 32]);
+        let mut activity = Activity {
+            is_active: true,
+            creator,
+            reward_mint: Pubkey::new_from_array([3; 32]),
+            starts_at: 100,
+            ends_at: 200,
+            flow_rate: 10,
+            ..Activity::default()
+        };
+        // Penalties can only go to the reward vault if they are paid in the reward mint.
+        assert!(activity
+            .set_early_unstake_penalty(creator, 1000, Pubkey::default())
+            .is_err());
+        assert!(activity
+            .set_early_unstake_penalty(creator, MAX_EARLY_UNSTAKE_PENALTY + 1, treasury)
+            .is_err());
+        activity.set_early_unstake_penalty(creator, 1000, treasury).unwrap();
+        activity.reward_mint = activity.stake_mint;
+        activity
+            .set_early_unstake_penalty(creator, 1000, Pubkey::default())
+            .unwrap();
+
+        let mut stake = Stream {
+            is_cancelled: false,
+            ends_at: 200,
+            total_topup_amount: 100,
+            total_withdrawn_amount: 0,
+            ..stream()
+        };
+        assert!(stake.unstake_early(stake.sender, 120).is_err());
+        assert!(stake.unstake_early(stake.beneficiary, 200).is_err());
+        assert_eq!(stake.unstake_early(stake.beneficiary, 120).unwrap(), 100);
+        assert!(stake.is_cancelled);
+        assert!(stake.unstake_early(stake.beneficiary, 120).is_err());
+
+        // The first position unstakes half of its stake early, forfeiting half of its rewards so far, which are emitted
+        // again along with the penalty.
+        let mut first = StakePosition::default();
+        let mut second = StakePosition::default();
+        activity.fund_rewards(1000, 100).unwrap();
+        activity.stake(&mut first, 100, BASE_STAKE_WEIGHT, 100).unwrap();
+        activity.stake(&mut second, 100, BASE_STAKE_WEIGHT, 100).unwrap();
+        let penalty = activity
+            .unstake_early(Some(&mut first), stake.stake(), 50, 120)
+            .unwrap();
+        assert_eq!(penalty, 5);
+        assert_eq!(first.staked_amount, 50);
+        assert_eq!(first.pending_rewards, 50);
+        assert_eq!(first.forfeited_rewards, 50);
+        assert_eq!(activity.total_unstaked, 50);
+        assert_eq!(activity.total_reward_forfeited, 50);
+        assert_eq!(activity.total_early_unstake_penalty, 5);
+        assert_eq!(activity.total_reward_funded, 1005);
+        assert_eq!(activity.claim_rewards(&mut first, 130).unwrap(), 83);
+        assert_eq!(activity.claim_rewards(&mut second, 130).unwrap(), 166);
+
+        // Without a penalty, nothing is charged and no penalty token account is needed.
+        activity
+            .set_early_unstake_penalty(creator, 0, Pubkey::default())
+            .unwrap();
+        let penalty = activity
+            .unstake_early(Some(&mut second), stake.stake(), 50, 130)
+            .unwrap();
+        assert_eq!(penalty, 0);
+        assert_eq!(second.staked_amount, 50);
+        assert_eq!(activity.total_early_unstake_penalty, 5);
+
+        // With the maximum penalty, the whole stake is charged and nothing is returned to the staker.
+        activity
+            .set_early_unstake_penalty(creator, MAX_EARLY_UNSTAKE_PENALTY, Pubkey::default())
+            .unwrap();
+        let penalty = activity
+            .unstake_early(Some(&mut second), stake.stake(), 50, 140)
+            .unwrap();
+        assert_eq!(penalty, 50);
+        assert_eq!(second.staked_amount, 0);
+        assert_eq!(activity.total_early_unstake_penalty, 55);
+    }
+
+    #[test]
+    fn activity_verifies_stakers_against_the_allowlist() {
+        let creator = Pubkey::new_from_array([1; 32]);
+        let allowed = Pubkey::new_from_array([2; 32]);
+        let other = Pubkey::new_from_array([3; 32]);
+        let mut activity = Activity {
+            is_active: true,
+            creator,
+            ..Activity::default()
+        };
+        assert!(activity.verify_staker(other, vec![]).is_ok());
+
+        let allowed_leaf = keccak::hashv(&[&allowed.to_bytes()]).0;
+        let creator_leaf = keccak::hashv(&[&creator.to_bytes()]).0;
+        let root = keccak::hashv(&[&min(allowed_leaf, creator_leaf), &max(allowed_leaf, creator_leaf)]).0;
+        assert!(activity.set_staker_allowlist_root(other, root).is_err());
+        activity.set_staker_allowlist_root(creator, root).unwrap();
+        assert!(activity.verify_staker(allowed, vec![creator_leaf]).is_ok());
+        assert!(activity.verify_staker(creator, vec![allowed_leaf]).is_ok());
+        assert!(activity.verify_staker(other, vec![allowed_leaf]).is_err());
+        assert!(activity.verify_staker(allowed, vec![]).is_err());
+
+        activity.set_staker_allowlist_root(creator, [0; 32]).unwrap();
+        assert!(activity.verify_staker(other, vec![]).is_ok());
+    }
+
+    #[test]
+    fn activity_validates_its_flow_rate() {
+        let initialize = |flow_rate| {
+            let key = Pubkey::new_from_array([1; 32]);
+            let name = "activity".to_string();
+            Activity::default().initialize(true, key, key, key, key, 10, 20, 30, 1, 1, flow_rate, 0, 0, name, 5)
+        };
+        assert!(initialize(0).is_err());
+        assert!(initialize(u64::MAX / 10 + 1).is_err());
+        initialize(u64::MAX / 10).unwrap();
+        initialize(1).unwrap();
+    }
+
+    #[test]
+    fn activity_is_managed_by_its_creator_and_validates_stakes() {
+        let creator = Pubkey::new_from_array([1; 32]);
+        let other = Pubkey::new_from_array([2; 32]);
+        let mut activity = Activity {
+            is_active: true,
+            creator,
+            starts_at: 10,
+            ends_at: 20,
+            reward_expires_at: 30,
+            min_amount: 1,
+            name: "activity".to_string(),
+            ..Activity::default()
+        };
+        assert_eq!(
+            activity.try_to_vec().unwrap().len() + ANCHOR_DISCRIMINATOR_LENGTH,
+            Activity::space(&activity.name, activity.lock_multipliers.len())
+        );
+
+        assert!(activity.update(other, 40, 50, 2, 3, 15).is_err());
+        assert!(activity.update(creator, 14, 50, 2, 3, 15).is_err());
+        assert!(activity.update(creator, 40, 39, 2, 3, 15).is_err());
+        assert!(activity.update(creator, 40, 50, 0, 3, 15).is_err());
+        // The flow rate has the same bounds as at creation.
+        assert!(activity.update(creator, 40, 50, 2, 0, 15).is_err());
+        assert!(activity.update(creator, 40, 50, 2, u64::MAX / 30 + 1, 15).is_err());
+        activity.update(creator, 40, 50, 2, u64::MAX / 30, 15).unwrap();
+        activity.update(creator, 40, 50, 2, 3, 15).unwrap();
+        assert_eq!(activity.ends_at, 40);
+        assert_eq!(activity.reward_expires_at, 50);
+        assert_eq!(activity.min_amount, 2);
+        assert_eq!(activity.flow_rate, 3);
+
+        activity.stream_count = 1;
+        assert!(activity.close(creator).is_err());
+        activity.stream_count = 0;
+        activity.position_count = 1;
+        assert!(activity.close(creator).is_err());
+        activity.position_count = 0;
+        activity.is_migrated = true;
+        assert!(activity.close(creator).is_err());
+        activity.is_migrated = false;
+        assert!(activity.close(other).is_err());
+        activity.close(creator).unwrap();
+
+        assert!(activity.validate_stake(1, 9).is_err());
+        assert!(activity.validate_stake(1, 41).is_err());
+        assert!(activity.validate_stake(1, 10).is_err());
+        activity.validate_stake(2, 10).unwrap();
+        activity.validate_stake(2, 40).unwrap();
+
+        assert!(activity.deactivate(other).is_err());
+        activity.deactivate(creator).unwrap();
+        assert!(!activity.is_active);
+        assert!(activity.deactivate(creator).is_err());
+        assert!(activity.update(creator, 40, 50, 2, 3, 15).is_err());
+        assert!(activity.validate_stake(2, 10).is_err());
+    }
+}
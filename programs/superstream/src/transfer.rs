@@ -1,7 +1,9 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+use anchor_spl::token::{close_account, transfer, CloseAccount, Token, TokenAccount, Transfer};
 
-use crate::{error::StreamError, Stream, STREAM_ACCOUNT_SEED};
+use crate::{
+    error::StreamError, get_name_seed, state::StreamSeeds, Activity, Stream, ACTIVITY_ACCOUNT_SEED, STREAM_ACCOUNT_SEED,
+};
 
 pub(crate) fn transfer_to_escrow<'info>(
     sender: &Signer<'info>,
@@ -30,10 +32,26 @@ pub(crate) fn transfer_from_escrow<'info>(
     destination_token: &Account<'info, TokenAccount>,
     escrow_token: &Account<'info, TokenAccount>,
     token_program: &Program<'info, Token>,
-    seed: u64,
-    mint: &Pubkey,
-    name: &str,
-    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    transfer_from_stream_escrow(
+        stream.to_account_info(),
+        &stream.seeds(),
+        destination_token,
+        escrow_token,
+        token_program,
+        amount,
+    )
+}
+
+/// Transfer funds from the escrow of a stream, signing with the stream PDA `stream_info` and its `seeds`. The seeds are
+/// passed separately so that zero-copy streams can use this as well.
+pub(crate) fn transfer_from_stream_escrow<'info>(
+    stream_info: AccountInfo<'info>,
+    seeds: &StreamSeeds,
+    destination_token: &Account<'info, TokenAccount>,
+    escrow_token: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
     amount: u64,
 ) -> Result<()> {
     if amount == 0 {
@@ -45,7 +63,7 @@ pub(crate) fn transfer_from_escrow<'info>(
     let cpi_accounts = Transfer {
         from: escrow_token.to_account_info(),
         to: destination_token.to_account_info(),
-        authority: stream.to_account_info(),
+        authority: stream_info,
     };
 
     transfer(
@@ -54,12 +72,109 @@ pub(crate) fn transfer_from_escrow<'info>(
             cpi_accounts,
             &[&[
                 STREAM_ACCOUNT_SEED,
-                seed.to_le_bytes().as_ref(),
-                mint.as_ref(),
-                name.as_bytes(),
-                &[bump],
+                seeds.activity.as_ref(),
+                seeds.mint.as_ref(),
+                seeds.creator.as_ref(),
+                seeds.name.as_ref(),
+                &[seeds.bump],
+            ]],
+        ),
+        amount,
+    )
+}
+
+/// Transfer rewards from the reward vault of an activity, signing with the activity PDA `activity_info`.
+pub(crate) fn transfer_from_activity_vault<'info>(
+    activity_info: AccountInfo<'info>,
+    activity: &Activity,
+    destination_token: &Account<'info, TokenAccount>,
+    reward_vault: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    require!(reward_vault.amount >= amount, StreamError::EscrowInsufficientFunds);
+    let cpi_program = token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: reward_vault.to_account_info(),
+        to: destination_token.to_account_info(),
+        authority: activity_info,
+    };
+
+    transfer(
+        CpiContext::new_with_signer(
+            cpi_program,
+            cpi_accounts,
+            &[&[
+                ACTIVITY_ACCOUNT_SEED,
+                activity.seed.to_le_bytes().as_ref(),
+                activity.stake_mint.as_ref(),
+                get_name_seed(&activity.name).as_ref(),
+                &[activity.bump],
             ]],
         ),
         amount,
     )
 }
+
+/// Close the empty escrow of a stream, signing with the stream PDA `stream_info` and its `seeds`, and send its rent to
+/// `destination`.
+pub(crate) fn close_stream_escrow<'info>(
+    stream_info: AccountInfo<'info>,
+    seeds: &StreamSeeds,
+    escrow_token: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let cpi_program = token_program.to_account_info();
+    let cpi_accounts = CloseAccount {
+        account: escrow_token.to_account_info(),
+        destination,
+        authority: stream_info,
+    };
+
+    close_account(CpiContext::new_with_signer(
+        cpi_program,
+        cpi_accounts,
+        &[&[
+            STREAM_ACCOUNT_SEED,
+            seeds.activity.as_ref(),
+            seeds.mint.as_ref(),
+            seeds.creator.as_ref(),
+            seeds.name.as_ref(),
+            &[seeds.bump],
+        ]],
+    ))
+}
+
+/// Close the empty reward vault of an activity, signing with the activity PDA `activity_info`, and send its rent to
+/// `destination`.
+pub(crate) fn close_activity_vault<'info>(
+    activity_info: AccountInfo<'info>,
+    activity: &Activity,
+    reward_vault: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let cpi_program = token_program.to_account_info();
+    let cpi_accounts = CloseAccount {
+        account: reward_vault.to_account_info(),
+        destination,
+        authority: activity_info,
+    };
+
+    close_account(CpiContext::new_with_signer(
+        cpi_program,
+        cpi_accounts,
+        &[&[
+            ACTIVITY_ACCOUNT_SEED,
+            activity.seed.to_le_bytes().as_ref(),
+            activity.stake_mint.as_ref(),
+            get_name_seed(&activity.name).as_ref(),
+            &[activity.bump],
+        ]],
+    ))
+}
@@ -0,0 +1,589 @@
+//! Stream accounting math of the Superstream program.
+//!
+//! This crate has no dependencies: nothing in it reads the `Clock` sysvar or depends on the Solana runtime. All amounts
+//! are computed at an explicit time `at` (unix timestamp in seconds), so off-chain services and tests can compute
+//! projections with exactly the code the program runs on-chain. The program re-exports this crate as
+//! `superstream::stream_math`.
+//!
+//! Flow payments are computed in fixed point. The flow rate of `flow_rate` tokens every `flow_interval` seconds is
+//! kept as an exact fraction, i.e. a per-second rate scaled by `flow_interval`, instead of being truncated to whole
+//! tokens per second. The accrued amount `active_time * flow_rate` is computed in u128, where the product of two u64
+//! can never overflow, and is divided by `flow_interval` only once at the end. The fraction of a token dropped by that
+//! last division is kept as the remainder of a [`FlowAccrual`].
+//!
+//! In discrete accrual mode, flow payments are only released at whole `flow_interval` boundaries. Active time spent in
+//! an incomplete flow interval releases nothing, so discrete flow amounts are always exact.
+//!
+//! Rounding always favors the escrow: amounts owed to recipients are rounded down and amounts senders need to pay
+//! upfront are rounded up, so the escrow never holds less than what it has to pay out.
+
+use std::{
+    cmp::{max, min},
+    fmt,
+};
+
+/// Deposit amount period (in seconds) for a non-prepaid stream. If a non-prepaid stream has unlimited lifetime or
+/// lifetime >= DEPOSIT_AMOUNT_PERIOD_IN_SECS, a security deposit is taken from the sender which would not be returned
+/// in case the stream becomes insolvent. This is done to make sure users keep topping up their streams on time.
+///
+/// If the stream has a notice period longer than this, the deposit covers the notice period instead.
+pub const DEPOSIT_AMOUNT_PERIOD_IN_SECS: u64 = 8 * 60 * 60; // 8 hrs
+
+/// Overflow errors of the stream math, naming the amount which is out of bounds. Each variant maps to the
+/// `*OutOfBounds` variant of `superstream::error::StreamError`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    PrepaidAmountNeeded,
+    DepositAmountNeeded,
+    AmountAvailableToWithdraw,
+    TopupAmount,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let amount = match self {
+            MathError::PrepaidAmountNeeded => "prepaid amount needed",
+            MathError::DepositAmountNeeded => "deposit amount needed",
+            MathError::AmountAvailableToWithdraw => "amount available to withdraw",
+            MathError::TopupAmount => "topup amount",
+        };
+        write!(f, "The {} is out of bounds", amount)
+    }
+}
+
+impl std::error::Error for MathError {}
+
+pub type MathResult<T> = std::result::Result<T, MathError>;
+
+/// Rounding direction of a flow amount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round towards zero. Used for amounts paid out to recipients.
+    Down,
+    /// Round away from zero. Used for amounts senders need to pay upfront.
+    Up,
+}
+
+/// Flow payments accrued over some active time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlowAccrual {
+    /// Whole tokens accrued.
+    pub amount: u128,
+    /// Fraction of a token accrued on top of `amount`, in units of `1 / flow_interval` tokens. Always less than
+    /// `flow_interval`.
+    pub remainder: u64,
+}
+
+impl FlowAccrual {
+    /// Get the accrued amount rounded in the given direction.
+    pub fn rounded(&self, rounding: Rounding) -> u128 {
+        match rounding {
+            Rounding::Up if self.remainder > 0 => self.amount + 1,
+            _ => self.amount,
+        }
+    }
+}
+
+/// The fields of a stream which determine its amounts. For more information on the fields, see fields of the
+/// `superstream::state::Stream` struct.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamTerms {
+    pub is_prepaid: bool,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub initial_amount: u64,
+    pub flow_interval: u64,
+    pub flow_rate: u64,
+    pub notice_period: u64,
+    pub cancelled_at: u64,
+    pub cancellation_scheduled_at: u64,
+    pub is_paused: bool,
+    pub last_resumed_at: u64,
+    pub accumulated_active_time: u64,
+    pub total_topup_amount: u64,
+    /// If true, flow payments accrue at once at the end of every whole flow interval instead of every second.
+    pub is_discrete: bool,
+}
+
+impl StreamTerms {
+    pub fn has_flow_payments(&self) -> bool {
+        self.flow_rate > 0 && (self.ends_at == 0 || self.ends_at > self.starts_at)
+    }
+
+    /// Calculate the amount of prepaid needed for a prepaid stream.
+    pub fn prepaid_amount_needed(&self) -> MathResult<u64> {
+        if !self.is_prepaid || self.ends_at == 0 {
+            Ok(0)
+        } else if !self.has_flow_payments() {
+            Ok(self.initial_amount)
+        } else {
+            // Rounded up so that the prepaid amount covers any amount owed during the stream.
+            self.initial_amount
+                .checked_add(
+                    self.flow_amount(self.ends_at - self.starts_at, Rounding::Up)
+                        .ok_or(MathError::PrepaidAmountNeeded)?,
+                )
+                .ok_or(MathError::PrepaidAmountNeeded)
+        }
+    }
+
+    /// Calculate the amount of deposit needed for the streaming payments excluding the initial amount.
+    pub fn deposit_needed(&self) -> MathResult<u64> {
+        Ok(if self.is_prepaid || !self.has_flow_payments() {
+            0
+        } else {
+            let deposit_period = max(DEPOSIT_AMOUNT_PERIOD_IN_SECS, self.notice_period);
+            // A discrete stream releases whole flow intervals, so the deposit covers every flow interval ending within
+            // the deposit period, and at least one.
+            let deposit_period = if self.is_discrete {
+                // deposit_period > 0, rounded up to whole flow intervals.
+                let intervals = (deposit_period - 1) / self.flow_interval + 1;
+                intervals
+                    .checked_mul(self.flow_interval)
+                    .ok_or(MathError::DepositAmountNeeded)?
+            } else {
+                deposit_period
+            };
+            let deposit_period = if self.ends_at == 0 {
+                deposit_period
+            } else {
+                min(deposit_period, self.ends_at - self.starts_at)
+            };
+            let deposit_needed = self
+                .flow_amount(deposit_period, Rounding::Up)
+                .ok_or(MathError::DepositAmountNeeded)?;
+
+            if deposit_needed >= 10 {
+                deposit_needed
+            } else {
+                deposit_needed + 1
+            }
+        })
+    }
+
+    /// Get the time at which the stream stops - the earliest of the end time, the cancellation time and the scheduled
+    /// cancellation time. If none of them is set, this is 0.
+    pub fn stops_at(&self) -> u64 {
+        let min_non_zero = |a: u64, b: u64| {
+            if a == 0 {
+                b
+            } else if b == 0 {
+                a
+            } else {
+                min(a, b)
+            }
+        };
+        min_non_zero(
+            min_non_zero(self.ends_at, self.cancelled_at),
+            self.cancellation_scheduled_at,
+        )
+    }
+
+    /// Check if a requested cancellation has taken effect.
+    pub fn is_notice_over(&self, at: u64) -> bool {
+        self.cancellation_scheduled_at > 0 && self.cancellation_scheduled_at <= at
+    }
+
+    /// Check if the stream has stopped.
+    pub fn has_stopped(&self, at: u64) -> bool {
+        let stops_at = self.stops_at();
+        stops_at > 0 && at > stops_at
+    }
+
+    /// Get the total amount owed to the recipient. The flow payments are rounded down.
+    pub fn amount_owed(&self, at: u64) -> MathResult<u64> {
+        let at = self.min_with_stopped_at(at);
+
+        Ok(if at < self.starts_at {
+            0
+        } else {
+            let accrued = u64::try_from(self.accrued(at)?.rounded(Rounding::Down))
+                .map_err(|_| MathError::AmountAvailableToWithdraw)?;
+            self.initial_amount
+                .checked_add(accrued)
+                .ok_or(MathError::AmountAvailableToWithdraw)?
+        })
+    }
+
+    /// Get the flow payments accrued till `at`, excluding the initial amount, along with the fraction of a token not
+    /// owed to the recipient yet.
+    pub fn accrued(&self, at: u64) -> MathResult<FlowAccrual> {
+        let at = self.min_with_stopped_at(at);
+
+        Ok(if at < self.starts_at || !self.has_flow_payments() {
+            FlowAccrual::default()
+        } else {
+            self.flow_accrual(self.unsafe_get_active_time_after_start(at)?)
+        })
+    }
+
+    /// Get the maximum acceptable topup amount. The first value is true if there is no limit on the topup amount.
+    pub fn max_acceptable_topup_amount(&self, at: u64) -> MathResult<(bool, u64)> {
+        Ok(if self.is_prepaid || !self.has_flow_payments() {
+            (false, 0)
+        } else {
+            // Streams: non-prepaid, with flow payments.
+            let stops_at = self.stops_at();
+            if stops_at == 0 {
+                // Streams: non-prepaid, with flow payments, non-cancelled with no end time.
+                (true, 0)
+            } else if stops_at < self.starts_at {
+                // Streams: non-prepaid, with flow payments, stopped before start.
+                (false, 0)
+            } else {
+                // Streams: non-prepaid, with flow payments, cancelled after start time and/or with set end time after
+                // start time.
+                let total_possible_active_time = if at < self.starts_at {
+                    // Streams: non-prepaid, with flow payments, not-started, with set end time after start time. The
+                    // stream cannot be cancelled before current time.
+                    self.ends_at - self.starts_at
+                } else {
+                    // Streams: non-prepaid, with flow payments, started, cancelled after start time and/or with set end
+                    // time after start time.
+                    if stops_at <= at {
+                        // Streams: non-prepaid, with flow payments, started, stopped after start time.
+                        self.unsafe_get_active_time_after_start(stops_at)?
+                    } else {
+                        // Streams: non-prepaid, with flow payments, started, not already stopped => with set end time
+                        // after start time in the future.
+                        self.unsafe_get_active_time_after_start(at)?
+                            .checked_add(stops_at - at)
+                            .ok_or(MathError::TopupAmount)?
+                    }
+                };
+
+                // Rounded down like the amount owed, as topping up more than what can ever be owed is pointless.
+                let total_possible_topup = self
+                    .initial_amount
+                    .checked_add(
+                        self.flow_amount(total_possible_active_time, Rounding::Down)
+                            .ok_or(MathError::TopupAmount)?,
+                    )
+                    .ok_or(MathError::TopupAmount)?;
+
+                (false, total_possible_topup.saturating_sub(self.total_topup_amount))
+            }
+        })
+    }
+
+    /// Get the active time of the stream till `at`, accounting for pauses.
+    ///
+    /// INVARIANT: (stops_at == 0 || at <= stops_at) && at >= self.starts_at && self.has_flow_payments()
+    pub fn unsafe_get_active_time_after_start(&self, at: u64) -> MathResult<u64> {
+        Ok(if self.is_paused {
+            // INVARIANT: The stream is paused => accumulated time is the total time.
+            self.accumulated_active_time
+        } else if self.last_resumed_at == 0 {
+            // INVARIANT: The stream is not paused and was never resumed => stream was never paused.
+            at - self.starts_at
+        } else {
+            // SAFETY: INVARIANT: last_resumed_at != 0 =>
+            //     last_resumed_at >= starts_at && (ends_at == 0 || < ends_at) =>
+            //     last_resumed_at will never be > ends_at if ends_at > 0
+            (at - self.last_resumed_at)
+                .checked_add(self.accumulated_active_time)
+                .ok_or(MathError::AmountAvailableToWithdraw)?
+        })
+    }
+
+    fn min_with_stopped_at(&self, at: u64) -> u64 {
+        let stops_at = self.stops_at();
+        if stops_at > 0 && at > stops_at {
+            // If the stream has been stopped for some reason - either ending or being cancelled - make at = stopped_at
+            // if the stream stopped before at. This will make sure, the amount is calculated only till the time the
+            // stream was active.
+            stops_at
+        } else {
+            at
+        }
+    }
+
+    /// Flow payments released over `active_time` seconds.
+    fn flow_accrual(&self, active_time: u64) -> FlowAccrual {
+        let active_time = if self.is_discrete {
+            // Only whole flow intervals release payments.
+            active_time - active_time % self.flow_interval
+        } else {
+            active_time
+        };
+        // Cannot overflow: (2^64 - 1)^2 < 2^128.
+        let scaled_amount = active_time as u128 * self.flow_rate as u128;
+        let flow_interval = self.flow_interval as u128;
+        FlowAccrual {
+            amount: scaled_amount / flow_interval,
+            remainder: (scaled_amount % flow_interval) as u64,
+        }
+    }
+
+    /// Amount released by the flow payments over `active_time` seconds, rounded in the given direction. Returns `None`
+    /// if the amount does not fit in u64.
+    fn flow_amount(&self, active_time: u64, rounding: Rounding) -> Option<u64> {
+        u64::try_from(self.flow_accrual(active_time).rounded(rounding)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{collection::vec, prelude::*};
+
+    use super::*;
+
+    prop_compose! {
+        /// A stream which has started at `starts_at` and ends `lifetime` seconds later, with amounts small enough to
+        /// never overflow.
+        fn stream_terms(is_prepaid: bool)(
+            starts_at in 1..1_000_000_000u64,
+            lifetime in 1..10_000_000u64,
+            initial_amount in 0..1_000_000_000_000u64,
+            flow_interval in 1..100_000u64,
+            flow_rate in 0..1_000_000_000u64,
+            is_discrete in any::<bool>(),
+        ) -> StreamTerms {
+            StreamTerms {
+                is_prepaid,
+                starts_at,
+                ends_at: starts_at + lifetime,
+                initial_amount,
+                flow_interval,
+                flow_rate,
+                is_discrete,
+                ..StreamTerms::default()
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn amount_owed_never_decreases(terms in stream_terms(false), a in 0..20_000_000u64, b in 0..20_000_000u64) {
+            let (a, b) = (terms.starts_at - 1 + min(a, b), terms.starts_at - 1 + max(a, b));
+            prop_assert!(terms.amount_owed(a)? <= terms.amount_owed(b)?);
+        }
+
+        #[test]
+        fn amount_owed_stops_growing_after_stop(terms in stream_terms(false), after in 1..10_000_000u64) {
+            let stops_at = terms.stops_at();
+            prop_assert_eq!(terms.amount_owed(stops_at)?, terms.amount_owed(stops_at + after)?);
+            prop_assert!(terms.has_stopped(stops_at + after));
+            prop_assert!(!terms.has_stopped(stops_at));
+        }
+
+        #[test]
+        fn prepaid_amount_covers_amount_owed(terms in stream_terms(true), at in 0..2_000_000_000u64) {
+            let prepaid_amount_needed = terms.prepaid_amount_needed()?;
+            prop_assert!(terms.amount_owed(at)? <= prepaid_amount_needed);
+            // At most the fraction of a token rounded away is left in the escrow.
+            prop_assert!(prepaid_amount_needed - terms.amount_owed(terms.ends_at)? <= 1);
+        }
+
+        #[test]
+        fn cancellation_stops_the_stream(terms in stream_terms(false), cancelled_after in 0..10_000_000u64, at in 0..2_000_000_000u64) {
+            let cancelled_at = terms.starts_at + min(cancelled_after, terms.ends_at - terms.starts_at);
+            let cancelled = StreamTerms { cancelled_at, ..terms };
+            prop_assert_eq!(cancelled.amount_owed(at)?, terms.amount_owed(min(at, cancelled_at))?);
+        }
+
+        #[test]
+        fn paused_stream_does_not_accrue(terms in stream_terms(false), paused_after in 0..10_000_000u64, at in 0..10_000_000u64) {
+            let active_time = min(paused_after, terms.ends_at - terms.starts_at);
+            let paused = StreamTerms { is_paused: true, accumulated_active_time: active_time, ..terms };
+            let at = terms.starts_at + active_time + at;
+            prop_assert_eq!(paused.amount_owed(at)?, terms.amount_owed(terms.starts_at + active_time)?);
+        }
+
+        #[test]
+        fn max_topup_funds_the_stream_till_the_end(terms in stream_terms(false), at in 0..2_000_000_000u64) {
+            prop_assume!(terms.has_flow_payments() && at <= terms.ends_at);
+            let (has_unlimited_topup, max_acceptable_topup_amount) = terms.max_acceptable_topup_amount(at)?;
+            prop_assert!(!has_unlimited_topup);
+            prop_assert_eq!(max_acceptable_topup_amount, terms.amount_owed(terms.ends_at)?);
+
+            let funded = StreamTerms { total_topup_amount: max_acceptable_topup_amount, ..terms };
+            prop_assert_eq!(funded.max_acceptable_topup_amount(at)?, (false, 0));
+        }
+
+        #[test]
+        fn deposit_covers_at_most_the_lifetime(terms in stream_terms(false), notice_period in 0..100_000u64) {
+            prop_assume!(terms.has_flow_payments() && !terms.is_discrete);
+            let terms = StreamTerms { notice_period, ..terms };
+            let deposit_period = min(max(DEPOSIT_AMOUNT_PERIOD_IN_SECS, notice_period), terms.ends_at - terms.starts_at);
+            let flow_amount = (deposit_period * terms.flow_rate).div_ceil(terms.flow_interval);
+            let deposit_needed = terms.deposit_needed()?;
+            prop_assert!(deposit_needed >= flow_amount && deposit_needed <= flow_amount + 1);
+        }
+
+        #[test]
+        fn accrual_is_exact(terms in stream_terms(false), at in 0..20_000_000u64) {
+            let at = terms.starts_at + at;
+            let active_time = min(at, terms.ends_at) - terms.starts_at;
+            let active_time = if terms.is_discrete { active_time - active_time % terms.flow_interval } else { active_time };
+            let active_time = active_time as u128;
+            let accrued = terms.accrued(at)?;
+            prop_assert!((accrued.remainder as u128) < terms.flow_interval as u128);
+            prop_assert_eq!(
+                accrued.amount * terms.flow_interval as u128 + accrued.remainder as u128,
+                active_time * terms.flow_rate as u128
+            );
+            prop_assert_eq!(terms.amount_owed(at)? as u128, terms.initial_amount as u128 + accrued.amount);
+        }
+
+        #[test]
+        fn discrete_stream_releases_whole_flow_intervals(terms in stream_terms(false), at in 0..20_000_000u64) {
+            let discrete = StreamTerms { is_discrete: true, ..terms };
+            let continuous = StreamTerms { is_discrete: false, ..terms };
+            let at = terms.starts_at + at;
+            let stopped_at = min(at, terms.ends_at);
+            let last_boundary = stopped_at - (stopped_at - terms.starts_at) % terms.flow_interval;
+            prop_assert_eq!(discrete.amount_owed(at)?, discrete.amount_owed(last_boundary)?);
+            prop_assert_eq!(discrete.amount_owed(last_boundary)?, continuous.amount_owed(last_boundary)?);
+            prop_assert!(discrete.amount_owed(at)? <= continuous.amount_owed(at)?);
+            prop_assert_eq!(discrete.accrued(at)?.remainder, 0);
+        }
+
+        #[test]
+        fn discrete_deposit_covers_whole_flow_intervals(terms in stream_terms(false), notice_period in 0..100_000u64) {
+            prop_assume!(terms.has_flow_payments());
+            let terms = StreamTerms { is_discrete: true, notice_period, ..terms };
+            let deposit_period = max(DEPOSIT_AMOUNT_PERIOD_IN_SECS, notice_period);
+            let lifetime = terms.ends_at - terms.starts_at;
+            let intervals = min(deposit_period.div_ceil(terms.flow_interval), lifetime / terms.flow_interval);
+            let flow_amount = intervals * terms.flow_rate;
+            let deposit_needed = terms.deposit_needed()?;
+            prop_assert_eq!(deposit_needed, if flow_amount >= 10 { flow_amount } else { flow_amount + 1 });
+        }
+
+        #[test]
+        fn prepaid_escrow_never_goes_short(terms in stream_terms(true), withdrawn_at in vec(0..20_000_000u64, 1..20)) {
+            // Withdraw everything owed at increasing times from an escrow holding the prepaid amount.
+            let mut escrow = terms.prepaid_amount_needed()?;
+            let mut total_withdrawn = 0;
+            let mut withdrawn_at: Vec<u64> = withdrawn_at.into_iter().map(|at| terms.starts_at + at).collect();
+            withdrawn_at.sort_unstable();
+            withdrawn_at.push(terms.ends_at);
+            for at in withdrawn_at {
+                let amount = terms.amount_owed(at)? - total_withdrawn;
+                prop_assert!(amount <= escrow);
+                escrow -= amount;
+                total_withdrawn += amount;
+            }
+            prop_assert!(escrow <= 1);
+        }
+
+        #[test]
+        fn non_prepaid_escrow_never_goes_short(
+            terms in stream_terms(false),
+            periods in vec((0..1_000_000u64, 0..1_000_000u64), 1..10),
+        ) {
+            prop_assume!(terms.has_flow_payments());
+            // Fund the stream with the most it accepts, then alternate active and paused periods, withdrawing everything
+            // owed at the end of each active period.
+            let (_, total_topup_amount) = terms.max_acceptable_topup_amount(terms.starts_at)?;
+            let mut terms = StreamTerms { total_topup_amount, ..terms };
+            let mut escrow = total_topup_amount + terms.deposit_needed()?;
+            let mut total_withdrawn = 0;
+            let mut at = terms.starts_at;
+            for (active_time, paused_time) in periods {
+                at += active_time;
+                let amount_owed = terms.amount_owed(at)?;
+                prop_assert!(amount_owed <= terms.total_topup_amount);
+                prop_assert!(amount_owed - total_withdrawn <= escrow);
+                escrow -= amount_owed - total_withdrawn;
+                total_withdrawn = amount_owed;
+
+                if terms.has_stopped(at) {
+                    break;
+                }
+                // Pause and resume the same way the program does.
+                if at > terms.starts_at {
+                    terms.accumulated_active_time = terms.unsafe_get_active_time_after_start(at)?;
+                }
+                terms.is_paused = true;
+                at += paused_time;
+                if terms.has_stopped(at) {
+                    break;
+                }
+                terms.is_paused = false;
+                if at > terms.starts_at {
+                    terms.last_resumed_at = at;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn high_decimal_tokens_do_not_overflow() {
+        // 1000 tokens with 12 decimals per day for 10 years. The u64 product of the active time and the flow rate would
+        // overflow while the amount itself fits.
+        let lifetime = 10 * 365 * 24 * 60 * 60;
+        let terms = StreamTerms {
+            is_prepaid: true,
+            starts_at: 1,
+            ends_at: 1 + lifetime,
+            flow_interval: 24 * 60 * 60,
+            flow_rate: 1_000_000_000_000_000,
+            ..StreamTerms::default()
+        };
+        assert!(lifetime.checked_mul(terms.flow_rate).is_none());
+        assert_eq!(terms.amount_owed(terms.ends_at), Ok(3650 * terms.flow_rate));
+        assert_eq!(terms.prepaid_amount_needed(), Ok(3650 * terms.flow_rate));
+    }
+
+    #[test]
+    fn fractional_accrual_is_tracked() {
+        // 1 token every 3 seconds.
+        let terms = StreamTerms {
+            is_prepaid: true,
+            starts_at: 10,
+            ends_at: 20,
+            flow_interval: 3,
+            flow_rate: 1,
+            ..StreamTerms::default()
+        };
+        assert_eq!(
+            terms.accrued(11),
+            Ok(FlowAccrual {
+                amount: 0,
+                remainder: 1
+            })
+        );
+        assert_eq!(
+            terms.accrued(14),
+            Ok(FlowAccrual {
+                amount: 1,
+                remainder: 1
+            })
+        );
+        assert_eq!(
+            terms.accrued(16),
+            Ok(FlowAccrual {
+                amount: 2,
+                remainder: 0
+            })
+        );
+        // Recipients are owed 3 tokens after 10 seconds while senders prepay 4.
+        assert_eq!(terms.amount_owed(20), Ok(3));
+        assert_eq!(terms.prepaid_amount_needed(), Ok(4));
+    }
+
+    #[test]
+    fn overflow_is_reported() {
+        let terms = StreamTerms {
+            starts_at: 1,
+            ends_at: u64::MAX,
+            initial_amount: 1,
+            flow_interval: 1,
+            flow_rate: u64::MAX,
+            ..StreamTerms::default()
+        };
+        assert_eq!(terms.amount_owed(10), Err(MathError::AmountAvailableToWithdraw));
+        assert_eq!(
+            StreamTerms {
+                is_prepaid: true,
+                ..terms
+            }
+            .prepaid_amount_needed(),
+            Err(MathError::PrepaidAmountNeeded)
+        );
+        assert_eq!(terms.deposit_needed(), Err(MathError::DepositAmountNeeded));
+        assert_eq!(terms.max_acceptable_topup_amount(10), Err(MathError::TopupAmount));
+    }
+}
@@ -0,0 +1,464 @@
+import { ok, strictEqual } from "assert";
+
+import { AnchorProvider, BN, Program, setProvider, Spl, utils as anchorUtils, web3, workspace } from "@project-serum/anchor";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccountInstruction,
+  createInitializeMintInstruction,
+  createMintToInstruction,
+  getAssociatedTokenAddress,
+  getMinimumBalanceForRentExemptMint,
+  MINT_SIZE,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+import keccak256 = require("keccak256");
+import { Superstream } from "../target/types/superstream";
+import { SuperstreamCpiExample } from "../target/types/superstream_cpi_example";
+
+const STREAM_ACCOUNT_SEED = "stream";
+const ACTIVITY_ACCOUNT_SEED = "activity";
+const DISTRIBUTOR_ACCOUNT_SEED = "distributor";
+const STATUS_ACCOUNT_SEED = "status";
+const REGISTRY_ACCOUNT_SEED = "registry";
+const POSITION_ACCOUNT_SEED = "position";
+const AUTHORITY_ACCOUNT_SEED = "authority";
+
+const REGISTRY_PAGE_CAPACITY = 64;
+
+describe("superstream-cpi-example", () => {
+  const provider = AnchorProvider.env();
+  setProvider(provider);
+
+  const superstream = workspace.Superstream as Program<Superstream>;
+  const program = workspace.SuperstreamCpiExample as Program<SuperstreamCpiExample>;
+
+  const owner = provider.wallet;
+  const [authority] = anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(AUTHORITY_ACCOUNT_SEED), owner.publicKey.toBuffer()],
+    program.programId,
+  );
+
+  const tokenProgram = Spl.token(provider);
+  const fetchTokenAmount = async (publicKey: web3.PublicKey): Promise<BN> => {
+    return (await tokenProgram.account.token.fetch(publicKey)).amount;
+  };
+  // The example forwards the return data of superstream, so both programs must have returned the same data.
+  const getReturnData = async (signature: string) => {
+    const transaction = await provider.connection.getTransaction(signature, { commitment: "confirmed" });
+    const logs = transaction?.meta?.logMessages;
+    ok(logs, "Transaction logs not found");
+    const getLastReturnData = (programId: web3.PublicKey) => {
+      const returnData = logs
+        .map((log) => log.match(`^Program return: ${programId.toBase58()} (\\S+)$`))
+        .filter((match) => match !== null)
+        .map((match) => Buffer.from(match[1], "base64"));
+      ok(returnData.length > 0, `Return data log of ${programId.toBase58()} not found`);
+      return returnData[returnData.length - 1];
+    };
+    const returnData = getLastReturnData(program.programId);
+    ok(returnData.equals(getLastReturnData(superstream.programId)));
+    return returnData;
+  };
+
+  const seed = new BN(0);
+  let mint = web3.PublicKey.default;
+  let activity = web3.PublicKey.default;
+  let ownerToken = web3.PublicKey.default;
+  let authorityToken = web3.PublicKey.default;
+
+  before(async () => {
+    mint = await createMint(provider);
+    ownerToken = await createAssociatedTokenAccount(provider, mint, owner.publicKey);
+    authorityToken = await createAssociatedTokenAccount(provider, mint, authority);
+    await mintTo(provider, mint, ownerToken, 1e10);
+    await mintTo(provider, mint, authorityToken, 1e10);
+
+    // The authority pays rent for the stream and claim status accounts.
+    await provider.sendAndConfirm(
+      new web3.Transaction().add(
+        web3.SystemProgram.transfer({
+          fromPubkey: owner.publicKey,
+          toPubkey: authority,
+          lamports: web3.LAMPORTS_PER_SOL,
+        }),
+      ),
+    );
+
+    const now = Math.floor(Date.now() / 1000);
+    const activityName = "cpi";
+    [activity] = anchorUtils.publicKey.findProgramAddressSync(
+      [Buffer.from(ACTIVITY_ACCOUNT_SEED), seed.toBuffer("le", 8), mint.toBuffer(), getNameSeed(activityName)],
+      superstream.programId,
+    );
+    await superstream.methods
+      .createActivity(seed, activityName, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity,
+        creator: owner.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+  });
+
+  it("Creates, tops up, pauses, resumes and cancels a stream with a PDA sender", async () => {
+    const name = "pda-sender";
+    const recipient = web3.Keypair.generate().publicKey;
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient);
+    const stream = getStreamPublicKey(superstream.programId, activity, mint, authority, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, stream);
+
+    await program.methods
+      .createNonPrepaid(createStreamParams(name, recipient, new BN(0)), new BN(1e6))
+      .accounts({
+        owner: owner.publicKey,
+        authority,
+        stream,
+        activity,
+        mint,
+        authorityToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        superstreamProgram: superstream.programId,
+        senderRegistry: getRegistryPublicKey(superstream.programId, authority),
+        recipientRegistry: getRegistryPublicKey(superstream.programId, recipient),
+        senderRegistryPage: await getNextRegistryPagePublicKey(superstream, authority),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(superstream, recipient),
+      })
+      .rpc();
+    let streamAccount = await superstream.account.stream.fetch(stream);
+    ok(streamAccount.sender.equals(authority));
+    ok(streamAccount.creator.equals(authority));
+
+    const totalTopupAmount = streamAccount.totalTopupAmount;
+    await program.methods
+      .topupNonPrepaid(seed, name, new BN(1000))
+      .accounts({
+        owner: owner.publicKey,
+        authority,
+        stream,
+        mint,
+        authorityToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        superstreamProgram: superstream.programId,
+      })
+      .rpc();
+    streamAccount = await superstream.account.stream.fetch(stream);
+    strictEqual(streamAccount.totalTopupAmount.toString(), totalTopupAmount.addn(1000).toString());
+
+    const pauseOrResumeAccounts = {
+      owner: owner.publicKey,
+      authority,
+      stream,
+      mint,
+      superstreamProgram: superstream.programId,
+    };
+    await program.methods.pauseNonPrepaid(seed, name).accounts(pauseOrResumeAccounts).rpc();
+    streamAccount = await superstream.account.stream.fetch(stream);
+    ok(streamAccount.isPaused);
+    ok(streamAccount.isPausedBySender);
+
+    await program.methods.resumeNonPrepaid(seed, name).accounts(pauseOrResumeAccounts).rpc();
+    streamAccount = await superstream.account.stream.fetch(stream);
+    ok(!streamAccount.isPaused);
+
+    const authorityAmount = await fetchTokenAmount(authorityToken);
+    const recipientAmount = await fetchTokenAmount(recipientToken);
+    const cancelSig = await program.methods
+      .cancel(seed, name, recipient)
+      .accounts({
+        owner: owner.publicKey,
+        authority,
+        stream,
+        activity,
+        position: getPositionPublicKey(superstream.programId, activity, authority),
+        sender: authority,
+        mint,
+        authorityToken,
+        senderToken: authorityToken,
+        recipientToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        superstreamProgram: superstream.programId,
+      })
+      .rpc({ commitment: "confirmed" });
+    streamAccount = await superstream.account.stream.fetch(stream);
+    ok(streamAccount.isCancelled);
+    ok(streamAccount.isCancelledBySender);
+    ok((await fetchTokenAmount(authorityToken)).gt(authorityAmount));
+
+    // The authority is both the sender and the signer of the cancellation.
+    const transfers = superstream.coder.types.decode("CancelTransferParams", await getReturnData(cancelSig));
+    strictEqual(
+      (await fetchTokenAmount(authorityToken)).sub(authorityAmount).toString(),
+      transfers.transferAmountSender.add(transfers.transferAmountSigner).toString(),
+    );
+    strictEqual(
+      (await fetchTokenAmount(recipientToken)).sub(recipientAmount).toString(),
+      transfers.transferAmountRecipient.toString(),
+    );
+  });
+
+  it("Withdraws from a stream with a PDA recipient", async () => {
+    const name = "pda-recipient";
+    const stream = getStreamPublicKey(superstream.programId, activity, mint, owner.publicKey, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, stream);
+
+    const params = createStreamParams(name, authority, new BN(Math.floor(Date.now() / 1000) + 2));
+    await superstream.methods
+      .createPrepaidV2(params)
+      .accounts({
+        stream,
+        activity,
+        sender: owner.publicKey,
+        mint,
+        senderToken: ownerToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(superstream.programId, owner.publicKey),
+        recipientRegistry: getRegistryPublicKey(superstream.programId, authority),
+        senderRegistryPage: await getNextRegistryPagePublicKey(superstream, owner.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(superstream, authority),
+        beneficiary: owner.publicKey,
+        payer: owner.publicKey,
+      })
+      .rpc();
+
+    // Withdrawals are only allowed once the stream has ended.
+    await sleep(4000);
+
+    const authorityAmount = await fetchTokenAmount(authorityToken);
+    const withdrawSig = await program.methods
+      .withdraw(name, owner.publicKey)
+      .accounts({
+        owner: owner.publicKey,
+        authority,
+        stream,
+        activity,
+        position: getPositionPublicKey(superstream.programId, activity, owner.publicKey),
+        mint,
+        authorityToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        superstreamProgram: superstream.programId,
+      })
+      .rpc({ commitment: "confirmed" });
+    const streamAccount = await superstream.account.stream.fetch(stream);
+    const authorityAmountAfter = await fetchTokenAmount(authorityToken);
+    strictEqual(authorityAmountAfter.sub(authorityAmount).toString(), streamAccount.totalWithdrawnAmount.toString());
+    const withdrawnAmount = new BN(await getReturnData(withdrawSig), "le");
+    strictEqual(withdrawnAmount.toString(), streamAccount.totalWithdrawnAmount.toString());
+    ok(streamAccount.totalWithdrawnAmount.gtn(0));
+  });
+
+  it("Claims distributor rewards with a PDA claimer", async () => {
+    const amount = new BN(500);
+    // A merkle tree with a single leaf has the leaf as the root and an empty proof.
+    const leaf = Buffer.from(
+      keccak256(
+        Buffer.concat([new BN(0).toArrayLike(Buffer, "le", 8), authority.toBuffer(), amount.toArrayLike(Buffer, "le", 8)]),
+      ),
+    );
+    const [distributor, distributorBump] = anchorUtils.publicKey.findProgramAddressSync(
+      [Buffer.from(DISTRIBUTOR_ACCOUNT_SEED), activity.toBuffer(), mint.toBuffer()],
+      superstream.programId,
+    );
+    const [status] = anchorUtils.publicKey.findProgramAddressSync(
+      [Buffer.from(STATUS_ACCOUNT_SEED), distributor.toBuffer(), authority.toBuffer()],
+      superstream.programId,
+    );
+    const rewardEscrowToken = await createAssociatedTokenAccount(provider, mint, distributor);
+
+    await superstream.methods
+      .createDistributor(distributorBump, Array.from(leaf), amount)
+      .accounts({
+        distributor,
+        activity,
+        creator: owner.publicKey,
+        senderToken: ownerToken,
+        mint,
+        rewardEscrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        payer: owner.publicKey,
+      })
+      .rpc();
+
+    const authorityAmount = await fetchTokenAmount(authorityToken);
+    const claimSig = await program.methods
+      .claim(new BN(0), amount, Array.from(leaf), [])
+      .accounts({
+        owner: owner.publicKey,
+        authority,
+        distributor,
+        status,
+        mint,
+        authorityToken,
+        escrowToken: rewardEscrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        superstreamProgram: superstream.programId,
+      })
+      .rpc({ commitment: "confirmed" });
+    strictEqual((await fetchTokenAmount(authorityToken)).sub(authorityAmount).toString(), amount.toString());
+    strictEqual(new BN(await getReturnData(claimSig), "le").toString(), amount.toString());
+
+    const statusAccount = await superstream.account.status.fetch(status);
+    ok(statusAccount.isClaimed);
+    ok(statusAccount.claimer.equals(authority));
+  });
+});
+
+function createStreamParams(name: string, recipient: web3.PublicKey, endsAt: BN) {
+  return {
+    seed: new BN(0),
+    name,
+    recipient,
+    startsAt: new BN(0),
+    endsAt,
+    initialAmount: new BN(100),
+    flowInterval: new BN(1),
+    flowRate: new BN(10),
+    permissions: {
+      senderCanCancel: true,
+      senderCanCancelAt: new BN(0),
+      senderCanChangeSender: false,
+      senderCanChangeSenderAt: new BN(0),
+      senderCanPause: true,
+      senderCanPauseAt: new BN(0),
+      recipientCanResumePauseBySender: false,
+      recipientCanResumePauseBySenderAt: new BN(0),
+      anyoneCanWithdrawForRecipient: false,
+      anyoneCanWithdrawForRecipientAt: new BN(0),
+    },
+    noticePeriod: new BN(0),
+    accrualMode: { continuous: {} },
+  };
+}
+
+function getStreamPublicKey(
+  programId: web3.PublicKey,
+  activity: web3.PublicKey,
+  mint: web3.PublicKey,
+  sender: web3.PublicKey,
+  name: string,
+): web3.PublicKey {
+  const [publicKey] = anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(STREAM_ACCOUNT_SEED), activity.toBuffer(), mint.toBuffer(), sender.toBuffer(), getNameSeed(name)],
+    programId,
+  );
+  return publicKey;
+}
+
+function getRegistryPublicKey(programId: web3.PublicKey, wallet: web3.PublicKey): web3.PublicKey {
+  const [publicKey] = anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(REGISTRY_ACCOUNT_SEED), wallet.toBuffer()],
+    programId,
+  );
+  return publicKey;
+}
+
+function getRegistryPagePublicKey(programId: web3.PublicKey, wallet: web3.PublicKey, page: number): web3.PublicKey {
+  const [publicKey] = anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(REGISTRY_ACCOUNT_SEED), wallet.toBuffer(), new BN(page).toArrayLike(Buffer, "le", 8)],
+    programId,
+  );
+  return publicKey;
+}
+
+async function getNextRegistryPagePublicKey(
+  superstream: Program<Superstream>,
+  wallet: web3.PublicKey,
+): Promise<web3.PublicKey> {
+  const registry = await superstream.account.streamRegistry.fetchNullable(
+    getRegistryPublicKey(superstream.programId, wallet),
+  );
+  const page = registry === null ? 0 : registry.streamCount.divn(REGISTRY_PAGE_CAPACITY).toNumber();
+  return getRegistryPagePublicKey(superstream.programId, wallet, page);
+}
+
+function getPositionPublicKey(
+  programId: web3.PublicKey,
+  activity: web3.PublicKey,
+  wallet: web3.PublicKey,
+): web3.PublicKey {
+  const [publicKey] = anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(POSITION_ACCOUNT_SEED), activity.toBuffer(), wallet.toBuffer()],
+    programId,
+  );
+  return publicKey;
+}
+
+function getNameSeed(name: string): Buffer {
+  // PDA seeds hold at most 32 bytes, longer names are seeded on their SHA-256 hash.
+  const nameBytes = Buffer.from(name);
+  return nameBytes.length <= 32 ? nameBytes : Buffer.from(anchorUtils.sha256.hash(name), "hex");
+}
+
+async function createMint(provider: AnchorProvider): Promise<web3.PublicKey> {
+  const authority = provider.wallet.publicKey;
+  const mint = web3.Keypair.generate();
+  const lamports = await getMinimumBalanceForRentExemptMint(provider.connection);
+
+  const transaction = new web3.Transaction().add(
+    web3.SystemProgram.createAccount({
+      fromPubkey: provider.wallet.publicKey,
+      newAccountPubkey: mint.publicKey,
+      space: MINT_SIZE,
+      lamports,
+      programId: TOKEN_PROGRAM_ID,
+    }),
+    createInitializeMintInstruction(mint.publicKey, 9, authority, authority, TOKEN_PROGRAM_ID),
+  );
+
+  await provider.sendAndConfirm(transaction, [mint]);
+  return mint.publicKey;
+}
+
+async function createAssociatedTokenAccount(
+  provider: AnchorProvider,
+  mint: web3.PublicKey,
+  owner: web3.PublicKey,
+): Promise<web3.PublicKey> {
+  const associatedToken = await getAssociatedTokenAddress(
+    mint,
+    owner,
+    true,
+    TOKEN_PROGRAM_ID,
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+  );
+  await provider.sendAndConfirm(
+    new web3.Transaction().add(
+      createAssociatedTokenAccountInstruction(
+        provider.wallet.publicKey,
+        associatedToken,
+        owner,
+        mint,
+        TOKEN_PROGRAM_ID,
+        ASSOCIATED_TOKEN_PROGRAM_ID,
+      ),
+    ),
+  );
+  return associatedToken;
+}
+
+async function mintTo(
+  provider: AnchorProvider,
+  mint: web3.PublicKey,
+  destination: web3.PublicKey,
+  amount: number,
+): Promise<void> {
+  const transaction = new web3.Transaction().add(
+    createMintToInstruction(mint, destination, provider.wallet.publicKey, amount),
+  );
+  await provider.sendAndConfirm(transaction);
+}
+
+function sleep(ms: number): Promise<void> {
+  return new Promise((resolve) => setTimeout(resolve, ms));
+}
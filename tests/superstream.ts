@@ -34,6 +34,10 @@ const STREAM_ACCOUNT_SEED = "stream";
 const ACTIVITY_ACCOUNT_SEED = "activity";
 const DISTRIBUTOR_ACCOUNT_SEED = "distributor";
 const STATUS_ACCOUNT_SEED = "status";
+const REGISTRY_ACCOUNT_SEED = "registry";
+const POSITION_ACCOUNT_SEED = "position";
+
+const REGISTRY_PAGE_CAPACITY = 64;
 
 export class Claimer {
   pubKey!: web3.PublicKey;
@@ -100,6 +104,33 @@ describe("superstream", () => {
   const fetchTokenAccount = async (publicKey: web3.PublicKey) => {
     return await tokenProgram.account.token.fetch(publicKey);
   };
+  const getComputeUnits = async (signature: Promise<string> | string) => {
+    const transaction = await provider.connection.getTransaction(await signature, { commitment: "confirmed" });
+    invariant(transaction?.meta?.logMessages, "Transaction logs not found");
+    // The last compute units log of the program is the one of the top-level instruction.
+    const computeUnits = transaction.meta.logMessages
+      .map((log) => log.match(`^Program ${program.programId.toBase58()} consumed (\\d+) of`))
+      .filter((match) => match !== null)
+      .map((match) => Number(match[1]));
+    invariant(computeUnits.length > 0, "Compute units log not found");
+    return computeUnits[computeUnits.length - 1];
+  };
+  const getReturnData = async (signature: string) => {
+    const transaction = await provider.connection.getTransaction(signature, { commitment: "confirmed" });
+    invariant(transaction?.meta?.logMessages, "Transaction logs not found");
+    // The last return data log of the program is the one of the top-level instruction.
+    const returnData = transaction.meta.logMessages
+      .map((log) => log.match(`^Program return: ${program.programId.toBase58()} (\\S+)$`))
+      .filter((match) => match !== null)
+      .map((match) => Buffer.from(match[1], "base64"));
+    invariant(returnData.length > 0, "Return data log not found");
+    return returnData[returnData.length - 1];
+  };
+  const getReturnedAmount = async (signature: string) => {
+    const returnData = await getReturnData(signature);
+    strictEqual(returnData.length, 8);
+    return new BN(returnData, "le");
+  };
 
   let mint = web3.PublicKey.default;
   let reward_mint = web3.PublicKey.default;
@@ -107,6 +138,7 @@ describe("superstream", () => {
   let senderToken = web3.PublicKey.default;
   let senderToken2 = web3.PublicKey.default;
   let senderTokenAmount = new BN(1e10);
+
   const seed = new BN(0);
   const name = "s1";
   const name2 = "s2";
@@ -153,7 +185,7 @@ describe("superstream", () => {
     console.log("recipient.publicKey: " + recipient.publicKey);
     console.log("activityPublicKey: " + activityPublicKey);
     let sig = await program.methods
-      .createActivity(seed, name, new BN(0), new BN(startAt), new BN(endsAt), new BN(4), new BN(1000), new BN(0))
+      .createActivity(seed, name, new BN(0), new BN(startAt), new BN(endsAt), new BN(4), new BN(1000), new BN(1))
       .accounts({
         activity: activityPublicKey,
         creator: sender.publicKey,
@@ -195,6 +227,7 @@ describe("superstream", () => {
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
       })
+      .remainingAccounts([positionAccount(program.programId, activityPublicKey, sender.publicKey)])
       .rpc();
     //.catch((error) => console.error(error));
     console.log("createStream sig is " + sig);
@@ -228,6 +261,7 @@ describe("superstream", () => {
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
       })
+      .remainingAccounts([positionAccount(program.programId, activityPublicKey, sender.publicKey)])
       .rpc();
     //.catch((error) => console.error(error));
     console.log("createStream 2 sig is " + sig);
@@ -260,10 +294,15 @@ describe("superstream", () => {
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
       })
+      .remainingAccounts([positionAccount(program.programId, activityPublicKey, sender2.publicKey)])
       .signers([sender2])
       .rpc();
     //.catch((error) => console.error(error));
     console.log("createStream 3 sig is " + sig);
+    const activityTotals = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activityTotals.totalStaked.toNumber(), 5000);
+    strictEqual(activityTotals.totalUnstaked.toNumber(), 0);
+    strictEqual(activityTotals.participantCount.toNumber(), 2);
     await sleep(3000);
     const streamFilter = {
       activity: activityPublicKey,
@@ -419,6 +458,7 @@ describe("superstream", () => {
         rewardEscrowToken: rewardEscrowToken,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
+        payer: sender.publicKey,
       })
       .rpc();
     console.log("createDistributor sig is " + sig);
@@ -460,11 +500,13 @@ describe("superstream", () => {
         status: statusAddress,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
+        payer: recipient.publicKey,
       })
       .signers([recipient])
-      .rpc();
+      .rpc({ commitment: "confirmed" });
     //  .catch((error) => console.error(error));
     console.log("claim sig is " + sig);
+    strictEqualBN(await getReturnedAmount(sig), new BN(10));
 
     await sleep(1000);
     escrowRewardTokenAccount = await fetchTokenAccount(rewardEscrowToken);
@@ -489,6 +531,7 @@ describe("superstream", () => {
         status: statusAddress,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
+        payer: recipient.publicKey,
       })
       .signers([recipient])
       .rpc()
@@ -523,6 +566,8 @@ describe("superstream", () => {
       .withdraw(name, recipient.publicKey, sender.publicKey)
       .accounts({
         stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
         signer: recipient.publicKey,
         mint,
         recipientToken,
@@ -540,6 +585,8 @@ describe("superstream", () => {
       .cancel(seed, name, recipient.publicKey)
       .accounts({
         stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
         signer: sender.publicKey,
         sender: sender.publicKey,
         mint,
@@ -567,6 +614,8 @@ describe("superstream", () => {
       .withdraw(name, recipient.publicKey, sender.publicKey)
       .accounts({
         stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
         signer: sender.publicKey,
         mint,
         recipientToken,
@@ -715,6 +764,8 @@ describe("superstream", () => {
       .withdraw(seed, name, recipient.publicKey)
       .accounts({
         stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
         signer: sender.publicKey,
         mint,
         recipientToken,
@@ -737,6 +788,8 @@ describe("superstream", () => {
         .cancel(seed, name, recipient.publicKey)
         .accounts({
           stream: streamPublicKey,
+          activity: activityPublicKey,
+          position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
           signer: randomSigner.publicKey,
           sender: sender.publicKey,
           mint,
@@ -757,6 +810,8 @@ describe("superstream", () => {
       .cancel(seed, name, recipient.publicKey)
       .accounts({
         stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
         signer: sender.publicKey,
         sender: sender.publicKey,
         mint,
@@ -781,6 +836,8 @@ describe("superstream", () => {
       .withdraw(seed, name, recipient.publicKey)
       .accounts({
         stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
         signer: sender.publicKey,
         mint,
         recipientToken,
@@ -794,67 +851,2304 @@ describe("superstream", () => {
     recipientTokenAccount = await fetchTokenAccount(recipientToken);
     approximatelyEqualBN(recipientTokenAccount.amount, new BN(1000 + diffOnCancel * 10));
   });
-});
 
-function strictEqualBN(actual: BN, expected: BN) {
-  if (!actual.eq(expected)) {
-    strictEqual(actual, expected);
-  }
-}
+  it("Creates an activity and a stream with names longer than 32 bytes", async () => {
+    const longName = "a human readable name which does not fit in a PDA seed";
+    ok(Buffer.from(longName).length > 32);
 
-const DELTA = new BN(15);
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, longName);
+    await program.methods
+      .createActivity(
+        seed,
+        longName,
+        new BN(now),
+        new BN(now + 3600),
+        new BN(now + 7200),
+        new BN(1),
+        new BN(1),
+        new BN(1),
+      )
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    strictEqual((await program.account.activity.fetch(activityPublicKey)).name, longName);
 
-function approximatelyEqualBN(actual: BN, expected: BN) {
-  if (actual.lt(expected.sub(DELTA)) || actual.gt(expected.add(DELTA))) {
-    strictEqual(actual.toString(), expected.toString());
-  }
-}
+    const recipient = web3.Keypair.generate();
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient.publicKey);
+    const [streamPublicKey] = getStreamPublicKey(
+      program.programId,
+      activityPublicKey,
+      mint,
+      sender.publicKey,
+      longName,
+    );
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    await program.methods
+      .createPrepaidV2({
+        seed,
+        name: longName,
+        recipient: recipient.publicKey,
+        startsAt: new BN(0),
+        endsAt: new BN(now + 2),
+        initialAmount: new BN(1000),
+        flowInterval: new BN(1),
+        flowRate: new BN(1),
+        permissions: {
+          senderCanCancel: true,
+          senderCanCancelAt: new BN(0),
+          senderCanChangeSender: false,
+          senderCanChangeSenderAt: new BN(0),
+          senderCanPause: false,
+          senderCanPauseAt: new BN(0),
+          recipientCanResumePauseBySender: false,
+          recipientCanResumePauseBySenderAt: new BN(0),
+          anyoneCanWithdrawForRecipient: true,
+          anyoneCanWithdrawForRecipientAt: new BN(0),
+        },
+        noticePeriod: new BN(0),
+        accrualMode: { continuous: {} },
+      })
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .rpc();
+    strictEqual((await program.account.stream.fetch(streamPublicKey)).name, longName);
 
-function getStreamPublicKey(
-  programId: web3.PublicKey,
-  activity: web3.PublicKey,
-  mint: web3.PublicKey,
-  sender: web3.PublicKey,
-  name: string,
-): [web3.PublicKey, number] {
-  return anchorUtils.publicKey.findProgramAddressSync(
-    [Buffer.from(STREAM_ACCOUNT_SEED), activity.toBuffer(), mint.toBuffer(), sender.toBuffer(), Buffer.from(name)],
-    programId,
-  );
-}
+    // Escrow transfers are signed with the hashed name seed. Withdrawals need the stream to have ended.
+    await sleep(4000);
+    await program.methods
+      .withdraw(longName, recipient.publicKey, sender.publicKey)
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+        signer: sender.publicKey,
+        mint,
+        recipientToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+    ok((await fetchTokenAccount(recipientToken)).amount.gten(1000));
+  });
 
-function getActivityPublicKey(
-  programId: web3.PublicKey,
-  seed: BN,
-  mint: web3.PublicKey,
-  name: string,
-): [web3.PublicKey, number] {
-  return anchorUtils.publicKey.findProgramAddressSync(
-    [Buffer.from(ACTIVITY_ACCOUNT_SEED), seed.toBuffer("le", 8), mint.toBuffer(), Buffer.from(name)],
-    programId,
-  );
-}
+  it("Lists streams in the registries of the sender and the recipient", async () => {
+    const name = "registry";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
 
-function getDistributorPublicKey(
-  programId: web3.PublicKey,
-  activity: web3.PublicKey,
-  mint: web3.PublicKey,
-): [web3.PublicKey, number] {
-  return anchorUtils.publicKey.findProgramAddressSync(
-    [Buffer.from(DISTRIBUTOR_ACCOUNT_SEED), activity.toBuffer(), mint.toBuffer()],
-    programId,
-  );
-}
+    // The recipient pays for the registry of the new recipient when changing the recipient.
+    const recipient = web3.Keypair.generate();
+    const newRecipient = web3.Keypair.generate();
+    await provider.sendAndConfirm(
+      new web3.Transaction().add(
+        web3.SystemProgram.transfer({
+          fromPubkey: sender.publicKey,
+          toPubkey: recipient.publicKey,
+          lamports: web3.LAMPORTS_PER_SOL,
+        }),
+      ),
+    );
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient.publicKey);
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    const [senderRegistry] = getRegistryPublicKey(program.programId, sender.publicKey);
+    const [recipientRegistry] = getRegistryPublicKey(program.programId, recipient.publicKey);
+    const [newRecipientRegistry] = getRegistryPublicKey(program.programId, newRecipient.publicKey);
+    const fetchRegistryPage = (wallet: web3.PublicKey, page: number) =>
+      program.account.streamRegistryPage.fetch(getRegistryPagePublicKey(program.programId, wallet, page)[0]);
+    const isListed = async (wallet: web3.PublicKey) =>
+      (await fetchRegistryPage(wallet, 0)).streams.some((stream) => stream.equals(streamPublicKey));
 
-function getStatusPublicKey(
-  programId: web3.PublicKey,
-  distributor: web3.PublicKey,
-  claimer: web3.PublicKey,
-): [web3.PublicKey, number] {
-  return anchorUtils.publicKey.findProgramAddressSync(
-    [Buffer.from(STATUS_ACCOUNT_SEED), distributor.toBuffer(), claimer.toBuffer()],
-    programId,
-  );
+    await program.methods
+      .createPrepaidV2({
+        seed,
+        name,
+        recipient: recipient.publicKey,
+        startsAt: new BN(0),
+        endsAt: new BN(now + 2),
+        initialAmount: new BN(1000),
+        flowInterval: new BN(1),
+        flowRate: new BN(1),
+        permissions: {
+          senderCanCancel: true,
+          senderCanCancelAt: new BN(0),
+          senderCanChangeSender: false,
+          senderCanChangeSenderAt: new BN(0),
+          senderCanPause: false,
+          senderCanPauseAt: new BN(0),
+          recipientCanResumePauseBySender: false,
+          recipientCanResumePauseBySenderAt: new BN(0),
+          anyoneCanWithdrawForRecipient: true,
+          anyoneCanWithdrawForRecipientAt: new BN(0),
+        },
+        noticePeriod: new BN(0),
+        accrualMode: { continuous: {} },
+      })
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry,
+        recipientRegistry,
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .rpc();
+    ok(await isListed(sender.publicKey));
+    const registry = await program.account.streamRegistry.fetch(recipientRegistry);
+    ok(registry.wallet.equals(recipient.publicKey));
+    strictEqualBN(registry.streamCount, new BN(1));
+    const page = await fetchRegistryPage(recipient.publicKey, 0);
+    ok(page.wallet.equals(recipient.publicKey));
+    strictEqualBN(page.index, new BN(0));
+    strictEqual(page.streams.length, 1);
+    ok(page.streams[0].equals(streamPublicKey));
+
+    // Changing the recipient appends the stream to the registry of the new recipient. The registries are append-only,
+    // so the old recipient still lists it.
+    await sleep(4000);
+    const recipientAmount = (await fetchTokenAccount(recipientToken)).amount;
+    const changeRecipientSig = await program.methods
+      .withdrawAndChangeRecipient(name, recipient.publicKey, sender.publicKey, newRecipient.publicKey)
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+        signer: recipient.publicKey,
+        mint,
+        recipientToken,
+        escrowToken,
+        newRecipientRegistry,
+        newRecipientRegistryPage: await getNextRegistryPagePublicKey(program, newRecipient.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([recipient])
+      .rpc({ commitment: "confirmed" });
+    strictEqualBN(
+      await getReturnedAmount(changeRecipientSig),
+      (await fetchTokenAccount(recipientToken)).amount.sub(recipientAmount),
+    );
+    ok(await isListed(recipient.publicKey));
+    ok(await isListed(newRecipient.publicKey));
+
+    // The stream is fully withdrawn, so it can be closed.
+    await program.methods
+      .closeStream()
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        signer: sender.publicKey,
+        rentPayer: sender.publicKey,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+    strictEqual(await program.account.stream.fetchNullable(streamPublicKey), null);
+    strictEqual(await provider.connection.getAccountInfo(escrowToken), null);
+    // Closing a stream does not touch the registries.
+    ok(await isListed(sender.publicKey));
+    ok(await isListed(newRecipient.publicKey));
+  });
+
+  it("Settles a requested cancellation after the notice period", async () => {
+    const name = "notice period";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient.publicKey);
+    const randomSigner = web3.Keypair.generate();
+    const randomSignerToken = await createAssociatedTokenAccount(provider, mint, randomSigner.publicKey);
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    await program.methods
+      .createNonPrepaidV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(0),
+          initialAmount: new BN(0),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: false,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: true,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(2),
+          accrualMode: { continuous: {} },
+        },
+        new BN(1e6),
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .rpc();
+    await program.methods
+      .pauseNonPrepaid(seed, name)
+      .accounts({ stream: streamPublicKey, signer: sender.publicKey, mint })
+      .rpc();
+
+    // Only the sender, the manager or the recipient can request the cancellation. Requesting it resumes the stream
+    // paused by the sender, so that the recipient is paid through the notice period.
+    try {
+      await program.methods
+        .requestCancel()
+        .accounts({ stream: streamPublicKey, signer: randomSigner.publicKey })
+        .signers([randomSigner])
+        .rpc();
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6027);
+    }
+    await program.methods.requestCancel().accounts({ stream: streamPublicKey, signer: sender.publicKey }).rpc();
+    let stream = await program.account.stream.fetch(streamPublicKey);
+    ok(!stream.isPaused);
+    ok(stream.isCancellationRequestedBySender);
+    ok(stream.cancellationScheduledAt.gtn(0));
+
+    const cancelAccounts = {
+      stream: streamPublicKey,
+      activity: activityPublicKey,
+      position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+      signer: sender.publicKey,
+      sender: sender.publicKey,
+      mint,
+      signerToken: senderToken,
+      senderToken,
+      recipientToken,
+      escrowToken,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    for (const [signer, errorCode] of [
+      [sender, 6050],
+      [randomSigner, 6027],
+    ] as const) {
+      try {
+        await program.methods
+          .cancel(seed, name, recipient.publicKey)
+          .accounts({
+            ...cancelAccounts,
+            signer: signer.publicKey,
+            signerToken: signer === sender ? senderToken : randomSignerToken,
+          })
+          .signers(signer === sender ? [] : [randomSigner])
+          .rpc();
+        ok(false);
+      } catch (e) {
+        ok(e instanceof AnchorError);
+        strictEqual(e.error.errorCode.number, errorCode);
+      }
+    }
+
+    // Once the notice period is over, anyone can settle the cancellation, which takes effect at the scheduled time.
+    await sleep(4000);
+    const cancelSig = await program.methods
+      .cancel(seed, name, recipient.publicKey)
+      .accounts({ ...cancelAccounts, signer: randomSigner.publicKey, signerToken: randomSignerToken })
+      .signers([randomSigner])
+      .rpc({ commitment: "confirmed" });
+    stream = await program.account.stream.fetch(streamPublicKey);
+    ok(stream.isCancelled);
+    ok(stream.isCancelledBySender);
+    strictEqual(stream.cancelledAt.toString(), stream.cancellationScheduledAt.toString());
+    strictEqual((await fetchTokenAccount(randomSignerToken)).amount.toNumber(), 0);
+
+    // The transferred amounts are returned to the caller.
+    const transfers = program.coder.types.decode("CancelTransferParams", await getReturnData(cancelSig));
+    strictEqualBN(transfers.transferAmountSigner, new BN(0));
+    ok(transfers.transferAmountRecipient.gtn(0));
+    strictEqualBN(transfers.transferAmountRecipient, (await fetchTokenAccount(recipientToken)).amount);
+    strictEqualBN(transfers.transferAmountRecipient, stream.totalWithdrawnAmount);
+    ok(transfers.transferAmountSender.gtn(0));
+
+    // The quote of the cancelled stream is read-only, so it can be simulated.
+    const quote = await program.methods.quoteStream(stream.cancelledAt).accounts({ stream: streamPublicKey }).view();
+    strictEqualBN(quote.at, stream.cancelledAt);
+    strictEqualBN(quote.amountOwed, transfers.transferAmountRecipient);
+    strictEqualBN(quote.stopsAt, stream.cancelledAt);
+    ok(quote.hasStopped);
+    ok(quote.isSolvent);
+  });
+
+  it("Lets a manager act as the sender with its permissions", async () => {
+    const name = "manager";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    // The manager pays for the registry of the new sender when changing the sender.
+    const recipient = web3.Keypair.generate();
+    const manager = web3.Keypair.generate();
+    const newSender = web3.Keypair.generate();
+    await provider.sendAndConfirm(
+      new web3.Transaction().add(
+        web3.SystemProgram.transfer({
+          fromPubkey: sender.publicKey,
+          toPubkey: manager.publicKey,
+          lamports: web3.LAMPORTS_PER_SOL,
+        }),
+      ),
+    );
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient.publicKey);
+    const managerToken = await createAssociatedTokenAccount(provider, mint, manager.publicKey);
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    await program.methods
+      .createNonPrepaidV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(0),
+          initialAmount: new BN(0),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: true,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: true,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(0),
+          accrualMode: { continuous: {} },
+        },
+        new BN(1e6),
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .rpc();
+
+    const rejectsWith = async (transaction: Promise<string>, errorCode: number) => {
+      try {
+        await transaction;
+        ok(false);
+      } catch (e) {
+        ok(e instanceof AnchorError);
+        strictEqual(e.error.errorCode.number, errorCode);
+      }
+    };
+    const setManager = (managerPublicKey: web3.PublicKey, permissions: Partial<Record<string, boolean>>) =>
+      program.methods
+        .setManager(managerPublicKey, {
+          canCancel: false,
+          canChangeSender: false,
+          canPause: false,
+          canResume: false,
+          ...permissions,
+        })
+        .accounts({ stream: streamPublicKey, sender: sender.publicKey })
+        .rpc();
+    const pause = () =>
+      program.methods
+        .pauseNonPrepaid(seed, name)
+        .accounts({ stream: streamPublicKey, signer: manager.publicKey, mint })
+        .signers([manager])
+        .rpc();
+    const resume = () =>
+      program.methods
+        .resumeNonPrepaid(seed, name)
+        .accounts({ stream: streamPublicKey, signer: manager.publicKey, mint })
+        .signers([manager])
+        .rpc();
+    const changeSender = async (newSenderPublicKey = newSender.publicKey) =>
+      program.methods
+        .changeSenderNonPrepaid(seed, name, newSenderPublicKey)
+        .accounts({
+          stream: streamPublicKey,
+          sender: manager.publicKey,
+          mint,
+          newSenderRegistry: getRegistryPublicKey(program.programId, newSenderPublicKey)[0],
+          newSenderRegistryPage: await getNextRegistryPagePublicKey(program, newSenderPublicKey),
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .signers([manager])
+        .rpc();
+    const cancel = () =>
+      program.methods
+        .cancel(seed, name, recipient.publicKey)
+        .accounts({
+          stream: streamPublicKey,
+          activity: activityPublicKey,
+          position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+          signer: manager.publicKey,
+          sender: sender.publicKey,
+          mint,
+          signerToken: managerToken,
+          senderToken,
+          recipientToken,
+          escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([manager])
+        .rpc();
+
+    // The manager can be neither the recipient nor the sender, and only the sender can set it.
+    await rejectsWith(setManager(recipient.publicKey, { canPause: true }), 6048);
+    await rejectsWith(setManager(sender.publicKey, { canPause: true }), 6048);
+    await rejectsWith(
+      program.methods
+        .setManager(manager.publicKey, { canCancel: true, canChangeSender: true, canPause: true, canResume: true })
+        .accounts({ stream: streamPublicKey, sender: manager.publicKey })
+        .signers([manager])
+        .rpc(),
+      2001,
+    );
+
+    // Each permission only lets the manager act as the sender in its own instruction.
+    await setManager(manager.publicKey, { canPause: true });
+    await rejectsWith(cancel(), 6027);
+    await rejectsWith(changeSender(), 6005);
+    await pause();
+    let stream = await program.account.stream.fetch(streamPublicKey);
+    ok(stream.manager.equals(manager.publicKey));
+    ok(stream.isPaused);
+    ok(stream.isPausedBySender);
+    await rejectsWith(resume(), 6038);
+    await setManager(manager.publicKey, { canResume: true });
+    await resume();
+    ok(!(await program.account.stream.fetch(streamPublicKey)).isPaused);
+
+    // Revoking the manager takes effect immediately.
+    await setManager(manager.publicKey, { canPause: true });
+    await setManager(web3.PublicKey.default, { canPause: true });
+    stream = await program.account.stream.fetch(streamPublicKey);
+    ok(stream.manager.equals(web3.PublicKey.default));
+    ok(!stream.managerPermissions.canPause);
+    await rejectsWith(pause(), 6037);
+
+    // The manager cannot make itself the sender. Changing the sender removes the manager, which was delegated by the
+    // previous sender.
+    await setManager(manager.publicKey, { canChangeSender: true });
+    await rejectsWith(changeSender(manager.publicKey), 6004);
+    await changeSender();
+    stream = await program.account.stream.fetch(streamPublicKey);
+    ok(stream.sender.equals(newSender.publicKey));
+    ok(stream.manager.equals(web3.PublicKey.default));
+    await rejectsWith(pause(), 6037);
+  });
+
+  it("Lets a manager cancel a stream on behalf of the sender", async () => {
+    const name = "manager cancel";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const manager = web3.Keypair.generate();
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient.publicKey);
+    const managerToken = await createAssociatedTokenAccount(provider, mint, manager.publicKey);
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    await program.methods
+      .createNonPrepaidV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(0),
+          initialAmount: new BN(0),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: false,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: false,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(0),
+          accrualMode: { continuous: {} },
+        },
+        new BN(1e6),
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .rpc();
+    await program.methods
+      .setManager(manager.publicKey, { canCancel: true, canChangeSender: false, canPause: false, canResume: false })
+      .accounts({ stream: streamPublicKey, sender: sender.publicKey })
+      .rpc();
+
+    // The manager cancels as the sender: the refund goes to the sender and nothing to the manager.
+    await program.methods
+      .cancel(seed, name, recipient.publicKey)
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+        signer: manager.publicKey,
+        sender: sender.publicKey,
+        mint,
+        signerToken: managerToken,
+        senderToken,
+        recipientToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([manager])
+      .rpc();
+    const stream = await program.account.stream.fetch(streamPublicKey);
+    ok(stream.isCancelled);
+    ok(stream.isCancelledBySender);
+    strictEqual((await fetchTokenAccount(managerToken)).amount.toNumber(), 0);
+    strictEqual((await fetchTokenAccount(escrowToken)).amount.toNumber(), 0);
+  });
+
+  it("Lets a sponsor pay the rent of a stream", async () => {
+    const name = "sponsored";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    // The customer only holds tokens, the provider wallet pays the rent and the transaction fees.
+    const customer = web3.Keypair.generate();
+    const recipient = web3.Keypair.generate();
+    const customerToken = await createAssociatedTokenAccount(provider, mint, customer.publicKey);
+    await mintTo(provider, mint, customerToken, 1000);
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient.publicKey);
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, customer.publicKey, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    const [senderRegistry] = getRegistryPublicKey(program.programId, customer.publicKey);
+    const [recipientRegistry] = getRegistryPublicKey(program.programId, recipient.publicKey);
+
+    await program.methods
+      .createPrepaidV2({
+        seed,
+        name,
+        recipient: recipient.publicKey,
+        startsAt: new BN(0),
+        endsAt: new BN(now + 2),
+        initialAmount: new BN(1000),
+        flowInterval: new BN(1),
+        flowRate: new BN(1),
+        permissions: {
+          senderCanCancel: true,
+          senderCanCancelAt: new BN(0),
+          senderCanChangeSender: false,
+          senderCanChangeSenderAt: new BN(0),
+          senderCanPause: false,
+          senderCanPauseAt: new BN(0),
+          recipientCanResumePauseBySender: false,
+          recipientCanResumePauseBySenderAt: new BN(0),
+          anyoneCanWithdrawForRecipient: true,
+          anyoneCanWithdrawForRecipientAt: new BN(0),
+        },
+        noticePeriod: new BN(0),
+        accrualMode: { continuous: {} },
+      })
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: customer.publicKey,
+        mint,
+        senderToken: customerToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry,
+        recipientRegistry,
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, customer.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: customer.publicKey,
+        payer: sender.publicKey,
+      })
+      .signers([customer])
+      .rpc();
+    const streamAccount = await program.account.stream.fetch(streamPublicKey);
+    ok(streamAccount.sender.equals(customer.publicKey));
+    ok(streamAccount.rentPayer.equals(sender.publicKey));
+    strictEqual(await provider.connection.getBalance(customer.publicKey), 0);
+
+    await sleep(4000);
+    await program.methods
+      .withdraw(name, recipient.publicKey, customer.publicKey)
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: getPositionPublicKey(program.programId, activityPublicKey, customer.publicKey)[0],
+        signer: sender.publicKey,
+        mint,
+        recipientToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // Closing the stream refunds the rent of the stream and the escrow account to the sponsor.
+    const rent =
+      (await provider.connection.getBalance(streamPublicKey)) + (await provider.connection.getBalance(escrowToken));
+    const balanceBefore = await provider.connection.getBalance(sender.publicKey);
+    await program.methods
+      .closeStream()
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        signer: customer.publicKey,
+        rentPayer: sender.publicKey,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([customer])
+      .rpc();
+    const balanceAfter = await provider.connection.getBalance(sender.publicKey);
+    ok(balanceAfter - balanceBefore > rent - web3.LAMPORTS_PER_SOL / 1000);
+    strictEqual(await provider.connection.getBalance(customer.publicKey), 0);
+  });
+
+  it("Updates, deactivates and closes an activity", async () => {
+    const name = "admin";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    // The flow rate has the same bounds as at creation.
+    try {
+      await program.methods
+        .updateActivity(new BN(now + 7200), new BN(now + 10800), new BN(10), new BN(0))
+        .accounts({ activity: activityPublicKey, creator: sender.publicKey })
+        .rpc();
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6077);
+    }
+    await program.methods
+      .updateActivity(new BN(now + 7200), new BN(now + 10800), new BN(10), new BN(2))
+      .accounts({ activity: activityPublicKey, creator: sender.publicKey })
+      .rpc();
+    let activity = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activity.endsAt.toNumber(), now + 7200);
+    strictEqual(activity.rewardExpiresAt.toNumber(), now + 10800);
+    strictEqual(activity.minAmount.toNumber(), 10);
+    strictEqual(activity.flowRate.toNumber(), 2);
+
+    await program.methods
+      .deactivateActivity()
+      .accounts({ activity: activityPublicKey, creator: sender.publicKey })
+      .rpc();
+    activity = await program.account.activity.fetch(activityPublicKey);
+    ok(!activity.isActive);
+
+    // A deactivated activity does not accept new streams.
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    try {
+      await program.methods
+        .createStreamV2(
+          {
+            seed,
+            name,
+            recipient: recipient.publicKey,
+            startsAt: new BN(0),
+            endsAt: new BN(now + 3600),
+            initialAmount: new BN(1000),
+            flowInterval: new BN(1),
+            flowRate: new BN(1),
+            permissions: {
+              senderCanCancel: true,
+              senderCanCancelAt: new BN(0),
+              senderCanChangeSender: false,
+              senderCanChangeSenderAt: new BN(0),
+              senderCanPause: false,
+              senderCanPauseAt: new BN(0),
+              recipientCanResumePauseBySender: false,
+              recipientCanResumePauseBySenderAt: new BN(0),
+              anyoneCanWithdrawForRecipient: true,
+              anyoneCanWithdrawForRecipientAt: new BN(0),
+            },
+            noticePeriod: new BN(0),
+            accrualMode: { continuous: {} },
+          },
+          new BN(1),
+          [],
+        )
+        .accounts({
+          stream: streamPublicKey,
+          activity: activityPublicKey,
+          sender: sender.publicKey,
+          mint,
+          senderToken,
+          escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+          senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+          recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+          senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+          recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+          beneficiary: sender.publicKey,
+          payer: sender.publicKey,
+        })
+        .remainingAccounts([positionAccount(program.programId, activityPublicKey, sender.publicKey)])
+        .rpc();
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6059);
+    }
+
+    // Closing the activity refunds the rewards left in the reward vault to the creator and closes the vault.
+    const rewardVault = await createAssociatedTokenAccount(provider, mint, activityPublicKey);
+    await program.methods
+      .closeActivity()
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        rewardMint: mint,
+        creatorToken: senderToken,
+        rewardVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+    strictEqual(await program.account.activity.fetchNullable(activityPublicKey), null);
+    strictEqual(await provider.connection.getAccountInfo(rewardVault), null);
+  });
+
+  it("Enforces the stake caps of an activity", async () => {
+    const name = "caps";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    const stake = async () =>
+      program.methods
+        .createStreamV2(
+          {
+            seed,
+            name,
+            recipient: recipient.publicKey,
+            startsAt: new BN(0),
+            endsAt: new BN(now + 3600),
+            initialAmount: new BN(1000),
+            flowInterval: new BN(1),
+            flowRate: new BN(1),
+            permissions: {
+              senderCanCancel: true,
+              senderCanCancelAt: new BN(0),
+              senderCanChangeSender: false,
+              senderCanChangeSenderAt: new BN(0),
+              senderCanPause: false,
+              senderCanPauseAt: new BN(0),
+              recipientCanResumePauseBySender: false,
+              recipientCanResumePauseBySenderAt: new BN(0),
+              anyoneCanWithdrawForRecipient: true,
+              anyoneCanWithdrawForRecipientAt: new BN(0),
+            },
+            noticePeriod: new BN(0),
+            accrualMode: { continuous: {} },
+          },
+          new BN(1),
+          [],
+        )
+        .accounts({
+          stream: streamPublicKey,
+          activity: activityPublicKey,
+          sender: sender.publicKey,
+          mint,
+          senderToken,
+          escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+          senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+          recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+          senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+          recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+          beneficiary: sender.publicKey,
+          payer: sender.publicKey,
+        })
+        .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+        .rpc();
+    const setStakeCaps = (maxTotalStake: number, maxStakePerWallet: number) =>
+      program.methods
+        .setStakeCaps(new BN(maxTotalStake), new BN(maxStakePerWallet))
+        .accounts({ activity: activityPublicKey, creator: sender.publicKey })
+        .rpc();
+
+    await setStakeCaps(1, 0);
+    try {
+      await stake();
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6064);
+    }
+
+    await setStakeCaps(0, 1);
+    try {
+      await stake();
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6065);
+    }
+
+    // A cap of zero means no cap.
+    await setStakeCaps(0, 0);
+    await stake();
+    const position = await program.account.stakePosition.fetch(positionPublicKey);
+    ok(position.activity.equals(activityPublicKey));
+    ok(position.wallet.equals(sender.publicKey));
+    strictEqual(position.stakedAmount.toNumber(), 1000);
+    const activity = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activity.totalStaked.toString(), position.stakedAmount.toString());
+    strictEqual(activity.participantCount.toNumber(), 1);
+  });
+
+  it("Accrues and claims activity rewards", async () => {
+    const name = "rewards";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(10))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    const rewardVault = await createAssociatedTokenAccount(provider, mint, activityPublicKey);
+    await program.methods
+      .fundActivityRewards(new BN(1000))
+      .accounts({
+        activity: activityPublicKey,
+        funder: sender.publicKey,
+        rewardMint: mint,
+        funderToken: senderToken,
+        rewardVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    await program.methods
+      .createStreamV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(now + 3600),
+          initialAmount: new BN(1000),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: false,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: false,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(0),
+          accrualMode: { continuous: {} },
+        },
+        new BN(1),
+        [],
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+      .rpc();
+
+    // The only staker earns all the rewards emitted since the stake was created.
+    await sleep(3000);
+    const senderAmount = (await fetchTokenAccount(senderToken)).amount;
+    const claimSig = await program.methods
+      .claimActivityRewards()
+      .accounts({
+        activity: activityPublicKey,
+        position: positionPublicKey,
+        staker: sender.publicKey,
+        rewardMint: mint,
+        stakerToken: senderToken,
+        rewardVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+    const claimedAmount = (await fetchTokenAccount(senderToken)).amount.sub(senderAmount);
+    strictEqualBN(await getReturnedAmount(claimSig), claimedAmount);
+    ok(claimedAmount.gtn(0));
+    ok(claimedAmount.lten(1000));
+    const position = await program.account.stakePosition.fetch(positionPublicKey);
+    strictEqual(position.claimedRewards.toString(), claimedAmount.toString());
+    strictEqual(position.pendingRewards.toNumber(), 0);
+    ok(position.stakeSeconds.gt(position.stakedAmount));
+    const activity = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activity.totalRewardFunded.toNumber(), 1000);
+    strictEqual(activity.totalRewardClaimed.toString(), claimedAmount.toString());
+    strictEqual(activity.flowRate.toNumber(), 10);
+
+    // The totals are returned by a read-only instruction, so they can be simulated.
+    const totals = await program.methods.quoteActivity().accounts({ activity: activityPublicKey }).view();
+    strictEqualBN(totals.totalStaked, activity.totalStaked);
+    strictEqualBN(totals.totalUnstaked, activity.totalUnstaked);
+    strictEqualBN(totals.stakedAmount, activity.totalStaked.sub(activity.totalUnstaked));
+    strictEqualBN(totals.weightedStake, activity.totalWeightedStake);
+    strictEqualBN(totals.participantCount, activity.participantCount);
+    strictEqualBN(totals.streamCount, activity.streamCount);
+    strictEqualBN(activity.positionCount, new BN(1));
+
+    // The position still holds the stake, so it cannot be closed yet.
+    try {
+      await program.methods
+        .closePosition()
+        .accounts({ activity: activityPublicKey, position: positionPublicKey, staker: sender.publicKey })
+        .rpc();
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6081);
+    }
+  });
+
+  it("Weights stakes by lock duration", async () => {
+    const name = "locks";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    const stake = async (lockDuration: number) =>
+      program.methods
+        .createStreamV2(
+          {
+            seed,
+            name,
+            recipient: recipient.publicKey,
+            startsAt: new BN(0),
+            endsAt: new BN(now + 3600),
+            initialAmount: new BN(1000),
+            flowInterval: new BN(1),
+            flowRate: new BN(1),
+            permissions: {
+              senderCanCancel: true,
+              senderCanCancelAt: new BN(0),
+              senderCanChangeSender: false,
+              senderCanChangeSenderAt: new BN(0),
+              senderCanPause: false,
+              senderCanPauseAt: new BN(0),
+              recipientCanResumePauseBySender: false,
+              recipientCanResumePauseBySenderAt: new BN(0),
+              anyoneCanWithdrawForRecipient: true,
+              anyoneCanWithdrawForRecipientAt: new BN(0),
+            },
+            noticePeriod: new BN(0),
+            accrualMode: { continuous: {} },
+          },
+          new BN(lockDuration),
+          [],
+        )
+        .accounts({
+          stream: streamPublicKey,
+          activity: activityPublicKey,
+          sender: sender.publicKey,
+          mint,
+          senderToken,
+          escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+          senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+          recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+          senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+          recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+          beneficiary: sender.publicKey,
+          payer: sender.publicKey,
+        })
+        .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+        .rpc();
+    const setLockMultipliers = (lockMultipliers: { duration: BN; multiplier: BN }[]) =>
+      program.methods
+        .setLockMultipliers(lockMultipliers)
+        .accounts({
+          activity: activityPublicKey,
+          creator: sender.publicKey,
+          systemProgram: web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    // Lock durations need to be strictly increasing.
+    try {
+      await setLockMultipliers([
+        { duration: new BN(60), multiplier: new BN(10000) },
+        { duration: new BN(60), multiplier: new BN(20000) },
+      ]);
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6067);
+    }
+
+    await setLockMultipliers([
+      { duration: new BN(1), multiplier: new BN(10000) },
+      { duration: new BN(60), multiplier: new BN(25000) },
+    ]);
+    let activity = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activity.lockMultipliers.length, 2);
+    strictEqual(activity.lockMultipliers[1].multiplier.toNumber(), 25000);
+
+    // Stakes need a lock duration > 0 which is one of the lock durations of the activity.
+    for (const lockDuration of [0, 30]) {
+      try {
+        await stake(lockDuration);
+        ok(false);
+      } catch (e) {
+        ok(e instanceof AnchorError);
+        strictEqual(e.error.errorCode.number, 6068);
+      }
+    }
+
+    await stake(60);
+    const stream = await program.account.stream.fetch(streamPublicKey);
+    strictEqual(stream.stakeWeight.toNumber(), 25000);
+    strictEqual(stream.endsAt.sub(stream.startsAt).toNumber(), 60);
+    const position = await program.account.stakePosition.fetch(positionPublicKey);
+    strictEqual(position.stakedAmount.toNumber(), 1000);
+    strictEqual(position.weightedStake.toNumber(), 2500);
+    activity = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activity.totalWeightedStake.toNumber(), 2500);
+  });
+
+  it("Restricts stakes to the allowlist of an activity", async () => {
+    const name = "allowlist";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    const leaves = [sender.publicKey, web3.Keypair.generate().publicKey].map((wallet) =>
+      Buffer.from(keccak256(wallet.toBuffer())),
+    );
+    const merkleTree = new MerkleTree(leaves);
+    await program.methods
+      .setStakerAllowlistRoot(toBytes32Array(merkleTree.root().hash))
+      .accounts({ activity: activityPublicKey, creator: sender.publicKey })
+      .rpc();
+    const stake = async (proof: number[][]) =>
+      program.methods
+        .createStreamV2(
+          {
+            seed,
+            name,
+            recipient: recipient.publicKey,
+            startsAt: new BN(0),
+            endsAt: new BN(now + 3600),
+            initialAmount: new BN(1000),
+            flowInterval: new BN(1),
+            flowRate: new BN(1),
+            permissions: {
+              senderCanCancel: true,
+              senderCanCancelAt: new BN(0),
+              senderCanChangeSender: false,
+              senderCanChangeSenderAt: new BN(0),
+              senderCanPause: false,
+              senderCanPauseAt: new BN(0),
+              recipientCanResumePauseBySender: false,
+              recipientCanResumePauseBySenderAt: new BN(0),
+              anyoneCanWithdrawForRecipient: true,
+              anyoneCanWithdrawForRecipientAt: new BN(0),
+            },
+            noticePeriod: new BN(0),
+            accrualMode: { continuous: {} },
+          },
+          new BN(1),
+          proof,
+        )
+        .accounts({
+          stream: streamPublicKey,
+          activity: activityPublicKey,
+          sender: sender.publicKey,
+          mint,
+          senderToken,
+          escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: web3.SystemProgram.programId,
+          senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+          recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+          senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+          recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+          beneficiary: sender.publicKey,
+          payer: sender.publicKey,
+        })
+        .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+        .rpc();
+    try {
+      await stake([]);
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 6072);
+    }
+
+    await stake(getProof(merkleTree, 0).map((p) => toBytes32Array(p)));
+    const position = await program.account.stakePosition.fetch(positionPublicKey);
+    strictEqual(position.stakedAmount.toNumber(), 1000);
+  });
+
+  it("Unstakes early with a penalty", async () => {
+    const name = "early unstake";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    const rewardVault = await createAssociatedTokenAccount(provider, mint, activityPublicKey);
+    await program.methods
+      .setEarlyUnstakePenalty(new BN(1000), web3.PublicKey.default)
+      .accounts({ activity: activityPublicKey, creator: sender.publicKey })
+      .rpc();
+    await program.methods
+      .createStreamV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(now + 3600),
+          initialAmount: new BN(1000),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: false,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: false,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(0),
+          accrualMode: { continuous: {} },
+        },
+        new BN(60),
+        [],
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+      .rpc();
+
+    // The penalty of 10% goes to the reward vault and is funded as rewards.
+    const senderAmount = (await fetchTokenAccount(senderToken)).amount;
+    const unstakeSig = await program.methods
+      .unstakeEarly()
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: positionPublicKey,
+        staker: sender.publicKey,
+        stakerToken: senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([{ pubkey: rewardVault, isSigner: false, isWritable: true }])
+      .rpc({ commitment: "confirmed" });
+    strictEqual((await getReturnedAmount(unstakeSig)).toNumber(), 900);
+    strictEqual((await fetchTokenAccount(senderToken)).amount.sub(senderAmount).toNumber(), 900);
+    strictEqual((await fetchTokenAccount(rewardVault)).amount.toNumber(), 100);
+    strictEqual((await fetchTokenAccount(escrowToken)).amount.toNumber(), 0);
+    const stream = await program.account.stream.fetch(streamPublicKey);
+    ok(stream.isCancelled);
+    const position = await program.account.stakePosition.fetch(positionPublicKey);
+    strictEqual(position.stakedAmount.toNumber(), 0);
+    const activity = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activity.totalEarlyUnstakePenalty.toNumber(), 100);
+    strictEqual(activity.totalRewardFunded.toNumber(), 100);
+    strictEqual(activity.participantCount.toNumber(), 0);
+  });
+
+  it("Unstakes early with a penalty of the whole stake", async () => {
+    const name = "full penalty";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    const rewardVault = await createAssociatedTokenAccount(provider, mint, activityPublicKey);
+    await program.methods
+      .setEarlyUnstakePenalty(new BN(10000), web3.PublicKey.default)
+      .accounts({ activity: activityPublicKey, creator: sender.publicKey })
+      .rpc();
+    await program.methods
+      .createStreamV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(now + 3600),
+          initialAmount: new BN(1000),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: false,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: false,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(0),
+          accrualMode: { continuous: {} },
+        },
+        new BN(60),
+        [],
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+      .rpc();
+
+    // The penalty of 100% takes the whole stake, so nothing is returned to the staker.
+    const senderAmount = (await fetchTokenAccount(senderToken)).amount;
+    const unstakeSig = await program.methods
+      .unstakeEarly()
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: positionPublicKey,
+        staker: sender.publicKey,
+        stakerToken: senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([{ pubkey: rewardVault, isSigner: false, isWritable: true }])
+      .rpc({ commitment: "confirmed" });
+    strictEqual((await getReturnedAmount(unstakeSig)).toNumber(), 0);
+    ok((await fetchTokenAccount(senderToken)).amount.eq(senderAmount));
+    strictEqual((await fetchTokenAccount(rewardVault)).amount.toNumber(), 1000);
+    strictEqual((await fetchTokenAccount(escrowToken)).amount.toNumber(), 0);
+    ok((await program.account.stream.fetch(streamPublicKey)).isCancelled);
+    const activity = await program.account.activity.fetch(activityPublicKey);
+    strictEqual(activity.totalEarlyUnstakePenalty.toNumber(), 1000);
+    strictEqual(activity.totalRewardFunded.toNumber(), 1000);
+  });
+
+  it("Unstakes early without a penalty", async () => {
+    const name = "free unstake";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    await program.methods
+      .createStreamV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(now + 3600),
+          initialAmount: new BN(1000),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: false,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: false,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(0),
+          accrualMode: { continuous: {} },
+        },
+        new BN(60),
+        [],
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: sender.publicKey,
+        payer: sender.publicKey,
+      })
+      .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+      .rpc();
+
+    // The activity has no penalty and no reward vault, so no penalty token account is passed.
+    const senderAmount = (await fetchTokenAccount(senderToken)).amount;
+    const unstakeSig = await program.methods
+      .unstakeEarly()
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        position: positionPublicKey,
+        staker: sender.publicKey,
+        stakerToken: senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+    strictEqual((await getReturnedAmount(unstakeSig)).toNumber(), 1000);
+    strictEqual((await fetchTokenAccount(senderToken)).amount.sub(senderAmount).toNumber(), 1000);
+    strictEqual((await fetchTokenAccount(escrowToken)).amount.toNumber(), 0);
+    ok((await program.account.stream.fetch(streamPublicKey)).isCancelled);
+    strictEqual((await program.account.activity.fetch(activityPublicKey)).totalEarlyUnstakePenalty.toNumber(), 0);
+  });
+
+  it("Stakes on behalf of a beneficiary", async () => {
+    const name = "beneficiary";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, name);
+    await program.methods
+      .createActivity(seed, name, new BN(now), new BN(now + 3600), new BN(now + 7200), new BN(1), new BN(1), new BN(1))
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const recipient = web3.Keypair.generate();
+    const beneficiary = web3.Keypair.generate();
+    const [streamPublicKey] = getStreamPublicKey(program.programId, activityPublicKey, mint, sender.publicKey, name);
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, beneficiary.publicKey);
+    const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+    await program.methods
+      .createStreamV2(
+        {
+          seed,
+          name,
+          recipient: recipient.publicKey,
+          startsAt: new BN(0),
+          endsAt: new BN(now + 3600),
+          initialAmount: new BN(1000),
+          flowInterval: new BN(1),
+          flowRate: new BN(1),
+          permissions: {
+            senderCanCancel: true,
+            senderCanCancelAt: new BN(0),
+            senderCanChangeSender: false,
+            senderCanChangeSenderAt: new BN(0),
+            senderCanPause: false,
+            senderCanPauseAt: new BN(0),
+            recipientCanResumePauseBySender: false,
+            recipientCanResumePauseBySenderAt: new BN(0),
+            anyoneCanWithdrawForRecipient: true,
+            anyoneCanWithdrawForRecipientAt: new BN(0),
+          },
+          noticePeriod: new BN(0),
+          accrualMode: { continuous: {} },
+        },
+        new BN(2),
+        [],
+      )
+      .accounts({
+        stream: streamPublicKey,
+        activity: activityPublicKey,
+        sender: sender.publicKey,
+        mint,
+        senderToken,
+        escrowToken,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+        senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+        recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+        senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+        recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+        beneficiary: beneficiary.publicKey,
+        payer: sender.publicKey,
+      })
+      .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+      .rpc();
+
+    // The sender funds the stake, the beneficiary owns the position and receives the withdrawals.
+    const stream = await program.account.stream.fetch(streamPublicKey);
+    ok(stream.beneficiary.equals(beneficiary.publicKey));
+    ok(stream.sender.equals(sender.publicKey));
+    const position = await program.account.stakePosition.fetch(positionPublicKey);
+    ok(position.wallet.equals(beneficiary.publicKey));
+    strictEqual(position.stakedAmount.toNumber(), 1000);
+
+    // The stake belongs to the beneficiary, so the sender cannot take the principal back when cancelling.
+    const beneficiaryToken = await createAssociatedTokenAccount(provider, mint, beneficiary.publicKey);
+    const cancelAccounts = {
+      stream: streamPublicKey,
+      activity: activityPublicKey,
+      position: positionPublicKey,
+      signer: sender.publicKey,
+      sender: sender.publicKey,
+      mint,
+      signerToken: senderToken,
+      senderToken,
+      recipientToken: beneficiaryToken,
+      escrowToken,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    try {
+      await program.methods.cancel(seed, name, recipient.publicKey).accounts(cancelAccounts).rpc();
+      ok(false);
+    } catch (e) {
+      ok(e instanceof AnchorError);
+      strictEqual(e.error.errorCode.number, 2003);
+    }
+
+    const senderAmount = (await fetchTokenAccount(senderToken)).amount;
+    await program.methods
+      .cancel(seed, name, recipient.publicKey)
+      .accounts({ ...cancelAccounts, senderToken: beneficiaryToken })
+      .rpc();
+    ok((await program.account.stream.fetch(streamPublicKey)).isCancelled);
+    strictEqual((await fetchTokenAccount(beneficiaryToken)).amount.toString(), "1000");
+    strictEqual((await fetchTokenAccount(senderToken)).amount.toString(), senderAmount.toString());
+  });
+
+  it("Measures compute units of zero-copy streams", async () => {
+    const activityName = "compute units";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, activityName);
+    await program.methods
+      .createActivity(
+        seed,
+        activityName,
+        new BN(now),
+        new BN(now + 3600),
+        new BN(now + 7200),
+        new BN(1),
+        new BN(1),
+        new BN(1),
+      )
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    // Two identical non-prepaid streams of 10 tokens per second for 8 seconds. The deposit covers the whole lifetime,
+    // so the minimum topup of 2 deposits on creation already funds the streams till the end.
+    const recipient = web3.Keypair.generate();
+    const recipientToken = await createAssociatedTokenAccount(provider, mint, recipient.publicKey);
+    const endsAt = Math.floor(Date.now() / 1000) + 8;
+    const streams = await Promise.all(
+      ["cu-borsh", "cu-zero-copy"].map(async (name) => {
+        const [streamPublicKey] = getStreamPublicKey(
+          program.programId,
+          activityPublicKey,
+          mint,
+          sender.publicKey,
+          name,
+        );
+        const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+        await program.methods
+          .createNonPrepaidV2(
+            {
+              seed,
+              name,
+              recipient: recipient.publicKey,
+              startsAt: new BN(0),
+              endsAt: new BN(endsAt),
+              initialAmount: new BN(0),
+              flowInterval: new BN(1),
+              flowRate: new BN(10),
+              permissions: {
+                senderCanCancel: true,
+                senderCanCancelAt: new BN(0),
+                senderCanChangeSender: false,
+                senderCanChangeSenderAt: new BN(0),
+                senderCanPause: false,
+                senderCanPauseAt: new BN(0),
+                recipientCanResumePauseBySender: false,
+                recipientCanResumePauseBySenderAt: new BN(0),
+                anyoneCanWithdrawForRecipient: true,
+                anyoneCanWithdrawForRecipientAt: new BN(0),
+              },
+              noticePeriod: new BN(0),
+              accrualMode: { continuous: {} },
+            },
+            new BN(160),
+          )
+          .accounts({
+            stream: streamPublicKey,
+            activity: activityPublicKey,
+            sender: sender.publicKey,
+            mint,
+            senderToken,
+            escrowToken,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: web3.SystemProgram.programId,
+            senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+            recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+            senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+            recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+            beneficiary: sender.publicKey,
+            payer: sender.publicKey,
+          })
+          .rpc();
+        return { name, streamPublicKey, escrowToken };
+      }),
+    );
+    const [borshStream, zeroCopyStream] = streams;
+
+    await program.methods
+      .migrateStream(null)
+      .accounts({
+        stream: zeroCopyStream.streamPublicKey,
+        activity: activityPublicKey,
+        signer: sender.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    const migrated = await program.account.zeroCopyStream.fetch(zeroCopyStream.streamPublicKey);
+    ok(migrated.recipient.equals(recipient.publicKey));
+    strictEqualBN(migrated.totalTopupAmount, new BN(80));
+    strictEqual(Buffer.from(migrated.name.slice(0, migrated.nameLength)).toString(), zeroCopyStream.name);
+
+    const computeUnits: Record<string, number[]> = {};
+    computeUnits.topupNonPrepaid = [
+      await getComputeUnits(
+        program.methods
+          .topupNonPrepaid(seed, borshStream.name, new BN(10))
+          .accounts({
+            stream: borshStream.streamPublicKey,
+            signer: sender.publicKey,
+            mint,
+            signerToken: senderToken,
+            escrowToken: borshStream.escrowToken,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc({ commitment: "confirmed" }),
+      ),
+      await getComputeUnits(
+        program.methods
+          .topupNonPrepaidZeroCopy(new BN(10))
+          .accounts({
+            stream: zeroCopyStream.streamPublicKey,
+            signer: sender.publicKey,
+            mint,
+            signerToken: senderToken,
+            escrowToken: zeroCopyStream.escrowToken,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc({ commitment: "confirmed" }),
+      ),
+    ];
+
+    // Withdrawals need the streams to have ended.
+    await sleep((endsAt + 2) * 1000 - Date.now());
+    const withdrawSignatures = [
+      await program.methods
+        .withdraw(borshStream.name, recipient.publicKey, sender.publicKey)
+        .accounts({
+          stream: borshStream.streamPublicKey,
+          activity: activityPublicKey,
+          position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+          signer: sender.publicKey,
+          mint,
+          recipientToken,
+          escrowToken: borshStream.escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" }),
+      await program.methods
+        .withdrawZeroCopy()
+        .accounts({
+          stream: zeroCopyStream.streamPublicKey,
+          activity: activityPublicKey,
+          position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+          signer: sender.publicKey,
+          mint,
+          recipientToken,
+          escrowToken: zeroCopyStream.escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" }),
+    ];
+    computeUnits.withdraw = await Promise.all(withdrawSignatures.map(getComputeUnits));
+    computeUnits.withdrawExcessTopupNonPrepaidEnded = [
+      await getComputeUnits(
+        program.methods
+          .withdrawExcessTopupNonPrepaidEnded(seed, borshStream.name)
+          .accounts({
+            stream: borshStream.streamPublicKey,
+            signer: sender.publicKey,
+            sender: sender.publicKey,
+            mint,
+            senderToken,
+            escrowToken: borshStream.escrowToken,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc({ commitment: "confirmed" }),
+      ),
+      await getComputeUnits(
+        program.methods
+          .withdrawExcessTopupNonPrepaidEndedZeroCopy()
+          .accounts({
+            stream: zeroCopyStream.streamPublicKey,
+            signer: sender.publicKey,
+            sender: sender.publicKey,
+            mint,
+            senderToken,
+            escrowToken: zeroCopyStream.escrowToken,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc({ commitment: "confirmed" }),
+      ),
+    ];
+    const cancelSignatures = [
+      await program.methods
+        .cancel(seed, borshStream.name, recipient.publicKey)
+        .accounts({
+          stream: borshStream.streamPublicKey,
+          activity: activityPublicKey,
+          position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+          signer: sender.publicKey,
+          sender: sender.publicKey,
+          mint,
+          signerToken: senderToken,
+          senderToken,
+          recipientToken,
+          escrowToken: borshStream.escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" }),
+      await program.methods
+        .cancelZeroCopy()
+        .accounts({
+          stream: zeroCopyStream.streamPublicKey,
+          activity: activityPublicKey,
+          position: getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey)[0],
+          signer: sender.publicKey,
+          sender: sender.publicKey,
+          mint,
+          signerToken: senderToken,
+          senderToken,
+          recipientToken,
+          escrowToken: zeroCopyStream.escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" }),
+    ];
+    computeUnits.cancel = await Promise.all(cancelSignatures.map(getComputeUnits));
+
+    console.table(
+      Object.entries(computeUnits).map(([instruction, [borshUnits, zeroCopyUnits]]) => ({
+        instruction,
+        borsh: borshUnits,
+        zeroCopy: zeroCopyUnits,
+        saved: borshUnits - zeroCopyUnits,
+      })),
+    );
+    for (const [instruction, [borshUnits, zeroCopyUnits]] of Object.entries(computeUnits)) {
+      ok(zeroCopyUnits < borshUnits, `${instruction} should use less compute units on a zero-copy stream`);
+    }
+
+    const [borshAccount, zeroCopyAccount] = await Promise.all([
+      program.account.stream.fetch(borshStream.streamPublicKey),
+      program.account.zeroCopyStream.fetch(zeroCopyStream.streamPublicKey),
+    ]);
+    strictEqualBN(zeroCopyAccount.totalWithdrawnAmount, borshAccount.totalWithdrawnAmount);
+    strictEqualBN(zeroCopyAccount.totalTopupAmount, borshAccount.totalTopupAmount);
+    ok(borshAccount.isCancelled);
+    ok((zeroCopyAccount.flags & 0b10) !== 0, "zero-copy stream should be cancelled");
+
+    // Both layouts return the same data to the caller.
+    const [borshWithdrawn, zeroCopyWithdrawn] = await Promise.all(withdrawSignatures.map(getReturnedAmount));
+    strictEqualBN(borshWithdrawn, borshAccount.totalWithdrawnAmount);
+    strictEqualBN(zeroCopyWithdrawn, borshWithdrawn);
+    const [borshTransfers, zeroCopyTransfers] = await Promise.all(
+      cancelSignatures.map(async (signature) =>
+        program.coder.types.decode("CancelTransferParams", await getReturnData(signature)),
+      ),
+    );
+    strictEqualBN(borshTransfers.transferAmountRecipient, new BN(0));
+    for (const field of ["transferAmountSender", "transferAmountSigner", "transferAmountRecipient"] as const) {
+      strictEqualBN(zeroCopyTransfers[field], borshTransfers[field]);
+    }
+    const at = new BN(Math.floor(Date.now() / 1000));
+    const [borshQuote, zeroCopyQuote] = await Promise.all([
+      program.methods.quoteStream(at).accounts({ stream: borshStream.streamPublicKey }).view(),
+      program.methods.quoteZeroCopyStream(at).accounts({ stream: zeroCopyStream.streamPublicKey }).view(),
+    ]);
+    for (const field of ["at", "amountOwed", "amountAvailableToWithdraw", "amountRefundable", "stopsAt"] as const) {
+      strictEqualBN(zeroCopyQuote[field], borshQuote[field]);
+    }
+    ok(borshQuote.hasStopped && zeroCopyQuote.hasStopped);
+  });
+
+  it("Unstakes a zero-copy stream early", async () => {
+    const activityName = "zero-copy unstake";
+    const now = Math.floor(Date.now() / 1000);
+    const [activityPublicKey] = getActivityPublicKey(program.programId, seed, mint, activityName);
+    await program.methods
+      .createActivity(
+        seed,
+        activityName,
+        new BN(now),
+        new BN(now + 3600),
+        new BN(now + 7200),
+        new BN(1),
+        new BN(1),
+        new BN(1),
+      )
+      .accounts({
+        activity: activityPublicKey,
+        creator: sender.publicKey,
+        stakeMint: mint,
+        rewardMint: mint,
+        optRewardMint: mint,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    // Two identical stakes, one of which is migrated to the zero-copy layout.
+    const recipient = web3.Keypair.generate();
+    const [positionPublicKey] = getPositionPublicKey(program.programId, activityPublicKey, sender.publicKey);
+    const streams = await Promise.all(
+      ["unstake-borsh", "unstake-zero-copy"].map(async (name) => {
+        const [streamPublicKey] = getStreamPublicKey(
+          program.programId,
+          activityPublicKey,
+          mint,
+          sender.publicKey,
+          name,
+        );
+        const escrowToken = await createAssociatedTokenAccount(provider, mint, streamPublicKey);
+        await program.methods
+          .createStreamV2(
+            {
+              seed,
+              name,
+              recipient: recipient.publicKey,
+              startsAt: new BN(0),
+              endsAt: new BN(now + 3600),
+              initialAmount: new BN(1000),
+              flowInterval: new BN(1),
+              flowRate: new BN(1),
+              permissions: {
+                senderCanCancel: true,
+                senderCanCancelAt: new BN(0),
+                senderCanChangeSender: false,
+                senderCanChangeSenderAt: new BN(0),
+                senderCanPause: false,
+                senderCanPauseAt: new BN(0),
+                recipientCanResumePauseBySender: false,
+                recipientCanResumePauseBySenderAt: new BN(0),
+                anyoneCanWithdrawForRecipient: true,
+                anyoneCanWithdrawForRecipientAt: new BN(0),
+              },
+              noticePeriod: new BN(0),
+              accrualMode: { continuous: {} },
+            },
+            new BN(60),
+            [],
+          )
+          .accounts({
+            stream: streamPublicKey,
+            activity: activityPublicKey,
+            sender: sender.publicKey,
+            mint,
+            senderToken,
+            escrowToken,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: web3.SystemProgram.programId,
+            senderRegistry: getRegistryPublicKey(program.programId, sender.publicKey)[0],
+            recipientRegistry: getRegistryPublicKey(program.programId, recipient.publicKey)[0],
+            senderRegistryPage: await getNextRegistryPagePublicKey(program, sender.publicKey),
+            recipientRegistryPage: await getNextRegistryPagePublicKey(program, recipient.publicKey),
+            beneficiary: sender.publicKey,
+            payer: sender.publicKey,
+          })
+          .remainingAccounts([{ pubkey: positionPublicKey, isSigner: false, isWritable: true }])
+          .rpc();
+        return { streamPublicKey, escrowToken };
+      }),
+    );
+    const [borshStream, zeroCopyStream] = streams;
+    await program.methods
+      .migrateStream(null)
+      .accounts({
+        stream: zeroCopyStream.streamPublicKey,
+        activity: activityPublicKey,
+        signer: sender.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const unstakeSignatures = [
+      await program.methods
+        .unstakeEarly()
+        .accounts({
+          stream: borshStream.streamPublicKey,
+          activity: activityPublicKey,
+          position: positionPublicKey,
+          staker: sender.publicKey,
+          stakerToken: senderToken,
+          escrowToken: borshStream.escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" }),
+      await program.methods
+        .unstakeEarlyZeroCopy()
+        .accounts({
+          stream: zeroCopyStream.streamPublicKey,
+          activity: activityPublicKey,
+          position: positionPublicKey,
+          staker: sender.publicKey,
+          stakerToken: senderToken,
+          escrowToken: zeroCopyStream.escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" }),
+    ];
+    const [borshUnits, zeroCopyUnits] = await Promise.all(unstakeSignatures.map(getComputeUnits));
+    console.table([
+      { instruction: "unstakeEarly", borsh: borshUnits, zeroCopy: zeroCopyUnits, saved: borshUnits - zeroCopyUnits },
+    ]);
+    ok(zeroCopyUnits < borshUnits, "unstakeEarly should use less compute units on a zero-copy stream");
+
+    // Both layouts return the whole stake and leave the stream cancelled with an empty escrow.
+    const [borshReturned, zeroCopyReturned] = await Promise.all(unstakeSignatures.map(getReturnedAmount));
+    strictEqual(borshReturned.toNumber(), 1000);
+    strictEqualBN(zeroCopyReturned, borshReturned);
+    strictEqual((await fetchTokenAccount(zeroCopyStream.escrowToken)).amount.toNumber(), 0);
+    const zeroCopyAccount = await program.account.zeroCopyStream.fetch(zeroCopyStream.streamPublicKey);
+    ok((zeroCopyAccount.flags & 0b10) !== 0, "zero-copy stream should be cancelled");
+    await rejectsWith(
+      program.methods
+        .unstakeEarlyZeroCopy()
+        .accounts({
+          stream: zeroCopyStream.streamPublicKey,
+          activity: activityPublicKey,
+          position: positionPublicKey,
+          staker: sender.publicKey,
+          stakerToken: senderToken,
+          escrowToken: zeroCopyStream.escrowToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc(),
+      6026,
+    );
+  });
+});
+
+function strictEqualBN(actual: BN, expected: BN) {
+  if (!actual.eq(expected)) {
+    strictEqual(actual, expected);
+  }
+}
+
+const DELTA = new BN(15);
+
+function approximatelyEqualBN(actual: BN, expected: BN) {
+  if (actual.lt(expected.sub(DELTA)) || actual.gt(expected.add(DELTA))) {
+    strictEqual(actual.toString(), expected.toString());
+  }
+}
+
+function getStreamPublicKey(
+  programId: web3.PublicKey,
+  activity: web3.PublicKey,
+  mint: web3.PublicKey,
+  sender: web3.PublicKey,
+  name: string,
+): [web3.PublicKey, number] {
+  return anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(STREAM_ACCOUNT_SEED), activity.toBuffer(), mint.toBuffer(), sender.toBuffer(), getNameSeed(name)],
+    programId,
+  );
+}
+
+function getActivityPublicKey(
+  programId: web3.PublicKey,
+  seed: BN,
+  mint: web3.PublicKey,
+  name: string,
+): [web3.PublicKey, number] {
+  return anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(ACTIVITY_ACCOUNT_SEED), seed.toBuffer("le", 8), mint.toBuffer(), getNameSeed(name)],
+    programId,
+  );
+}
+
+function getNameSeed(name: string): Buffer {
+  // PDA seeds hold at most 32 bytes, longer names are seeded on their SHA-256 hash.
+  const nameBytes = Buffer.from(name);
+  return nameBytes.length <= 32 ? nameBytes : Buffer.from(anchorUtils.sha256.hash(name), "hex");
+}
+
+function getDistributorPublicKey(
+  programId: web3.PublicKey,
+  activity: web3.PublicKey,
+  mint: web3.PublicKey,
+): [web3.PublicKey, number] {
+  return anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(DISTRIBUTOR_ACCOUNT_SEED), activity.toBuffer(), mint.toBuffer()],
+    programId,
+  );
+}
+
+function getStatusPublicKey(
+  programId: web3.PublicKey,
+  distributor: web3.PublicKey,
+  claimer: web3.PublicKey,
+): [web3.PublicKey, number] {
+  return anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(STATUS_ACCOUNT_SEED), distributor.toBuffer(), claimer.toBuffer()],
+    programId,
+  );
+}
+
+function getRegistryPublicKey(programId: web3.PublicKey, wallet: web3.PublicKey): [web3.PublicKey, number] {
+  return anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(REGISTRY_ACCOUNT_SEED), wallet.toBuffer()],
+    programId,
+  );
+}
+
+function getRegistryPagePublicKey(
+  programId: web3.PublicKey,
+  wallet: web3.PublicKey,
+  page: number,
+): [web3.PublicKey, number] {
+  return anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(REGISTRY_ACCOUNT_SEED), wallet.toBuffer(), new BN(page).toArrayLike(Buffer, "le", 8)],
+    programId,
+  );
+}
+
+// Streams are appended to the last page of a registry, which is the next page to create once it is full.
+async function getNextRegistryPagePublicKey(
+  program: Program<Superstream>,
+  wallet: web3.PublicKey,
+): Promise<web3.PublicKey> {
+  const [registryPublicKey] = getRegistryPublicKey(program.programId, wallet);
+  const registry = await program.account.streamRegistry.fetchNullable(registryPublicKey);
+  const page = registry === null ? 0 : registry.streamCount.divn(REGISTRY_PAGE_CAPACITY).toNumber();
+  return getRegistryPagePublicKey(program.programId, wallet, page)[0];
+}
+
+function getPositionPublicKey(
+  programId: web3.PublicKey,
+  activity: web3.PublicKey,
+  wallet: web3.PublicKey,
+): [web3.PublicKey, number] {
+  return anchorUtils.publicKey.findProgramAddressSync(
+    [Buffer.from(POSITION_ACCOUNT_SEED), activity.toBuffer(), wallet.toBuffer()],
+    programId,
+  );
+}
+
+// Activity stakes take the stake position of the beneficiary as their first remaining account.
+function positionAccount(
+  programId: web3.PublicKey,
+  activity: web3.PublicKey,
+  wallet: web3.PublicKey,
+): web3.AccountMeta {
+  return { pubkey: getPositionPublicKey(programId, activity, wallet)[0], isSigner: false, isWritable: true };
 }
 
 async function createMint(provider: AnchorProvider): Promise<web3.PublicKey> {